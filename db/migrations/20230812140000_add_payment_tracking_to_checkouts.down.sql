@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE checkouts
+    DROP COLUMN payment_hash,
+    DROP COLUMN amount_received;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE checkouts
+    ADD COLUMN payment_hash VARCHAR(255),
+    ADD COLUMN amount_received BIGINT NOT NULL DEFAULT 0;
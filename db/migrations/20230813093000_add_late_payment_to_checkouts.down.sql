@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE checkouts DROP COLUMN late_payment_at;
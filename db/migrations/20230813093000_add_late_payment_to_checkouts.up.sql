@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE checkouts ADD COLUMN late_payment_at TIMESTAMP;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TABLE webhook_delivery_attempts;
+
+DROP TABLE webhook_deliveries;
+
+DROP TABLE webhooks;
+
+DROP type webhook_delivery_status;
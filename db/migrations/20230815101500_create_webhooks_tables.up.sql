@@ -0,0 +1,41 @@
+-- Add up migration script here
+CREATE type webhook_delivery_status as enum (
+    'pending', 'delivered', 'failed'
+);
+
+CREATE TABLE webhooks (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    store_uuid VARCHAR(255) references stores(uuid) NOT NULL,
+    url TEXT NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    events TEXT[] NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    deleted_at TIMESTAMP
+);
+
+CREATE TABLE webhook_deliveries (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    webhook_uuid VARCHAR(255) references webhooks(uuid) NOT NULL,
+    event VARCHAR(255) NOT NULL,
+    payload JSONB NOT NULL,
+    status webhook_delivery_status NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    delivered_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX webhook_deliveries_due_idx ON webhook_deliveries (status, next_attempt_at);
+
+CREATE TABLE webhook_delivery_attempts (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    delivery_uuid VARCHAR(255) references webhook_deliveries(uuid) NOT NULL,
+    response_code INTEGER,
+    response_body TEXT,
+    error TEXT,
+    duration_ms BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TABLE api_keys (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    user_uuid VARCHAR(255) references users(uuid) NOT NULL,
+    store_uuid VARCHAR(255) references stores(uuid) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    key_prefix VARCHAR(16) NOT NULL,
+    key_hash VARCHAR(255) UNIQUE NOT NULL,
+    last_used_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP TABLE fee_ledger;
+DROP TABLE user_fee_overrides;
+
+ALTER TABLE checkouts
+    DROP COLUMN fee_amount,
+    DROP COLUMN net_amount;
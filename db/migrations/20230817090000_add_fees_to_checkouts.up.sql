@@ -0,0 +1,32 @@
+-- Add up migration script here
+ALTER TABLE checkouts
+    ADD COLUMN fee_amount BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN net_amount BIGINT NOT NULL DEFAULT 0;
+
+UPDATE checkouts SET net_amount = amount;
+
+-- Per-user pricing; a NULL column falls back to the [pricing] config.
+-- Rates are in basis points (1% = 100) so partners can get fractional discounts.
+CREATE TABLE user_fee_overrides (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    user_uuid VARCHAR(255) UNIQUE references users(uuid) ON DELETE CASCADE NOT NULL,
+    base_fee_sat BIGINT,
+    fee_rate_bps BIGINT,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE fee_ledger (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    user_uuid VARCHAR(255) references users(uuid) NOT NULL,
+    checkout_uuid VARCHAR(255) UNIQUE references checkouts(uuid) ON DELETE CASCADE NOT NULL,
+    gross_amount BIGINT NOT NULL,
+    fee_amount BIGINT NOT NULL,
+    net_amount BIGINT NOT NULL,
+    base_fee_sat BIGINT NOT NULL,
+    fee_rate_bps BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX fee_ledger_user_uuid_created_at_idx ON fee_ledger (user_uuid, created_at);
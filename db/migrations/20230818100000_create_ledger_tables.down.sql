@@ -0,0 +1,8 @@
+-- Add down migration script here
+DROP TRIGGER ledger_entries_balanced ON ledger_entries;
+DROP FUNCTION check_ledger_transaction_balanced;
+DROP TABLE ledger_entries;
+DROP TABLE ledger_transactions;
+DROP TABLE ledger_accounts;
+DROP type ledger_transaction_kind;
+DROP type ledger_account_kind;
@@ -0,0 +1,60 @@
+-- Add up migration script here
+CREATE type ledger_account_kind as enum (
+    'user', 'store', 'platform_fees', 'hot_wallet'
+);
+
+CREATE type ledger_transaction_kind as enum (
+    'checkout_payment', 'withdrawal', 'refund'
+);
+
+-- Balances are the sum of an account's entries. Money the platform owes
+-- (user, store and fee accounts) is positive, so the hot wallet that holds
+-- it carries the matching negative balance.
+CREATE TABLE ledger_accounts (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    kind ledger_account_kind NOT NULL,
+    user_uuid VARCHAR(255) references users(uuid),
+    store_uuid VARCHAR(255) references stores(uuid),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX ledger_accounts_owner_idx
+    ON ledger_accounts (kind, COALESCE(user_uuid, ''), COALESCE(store_uuid, ''));
+CREATE INDEX ledger_accounts_user_uuid_idx ON ledger_accounts (user_uuid);
+
+CREATE TABLE ledger_transactions (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    kind ledger_transaction_kind NOT NULL,
+    reference_uuid VARCHAR(255) NOT NULL,
+    description TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (kind, reference_uuid)
+);
+
+CREATE TABLE ledger_entries (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    transaction_uuid VARCHAR(255) references ledger_transactions(uuid) NOT NULL,
+    account_uuid VARCHAR(255) references ledger_accounts(uuid) NOT NULL,
+    amount BIGINT NOT NULL CHECK (amount <> 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX ledger_entries_account_uuid_created_at_idx
+    ON ledger_entries (account_uuid, created_at, uuid);
+CREATE INDEX ledger_entries_transaction_uuid_idx ON ledger_entries (transaction_uuid);
+
+-- Checked at commit so a transaction's entries can be inserted one by one.
+CREATE FUNCTION check_ledger_transaction_balanced() RETURNS TRIGGER AS $$
+BEGIN
+    IF (SELECT COALESCE(SUM(amount), 0) FROM ledger_entries
+        WHERE transaction_uuid = NEW.transaction_uuid) <> 0 THEN
+        RAISE EXCEPTION 'ledger transaction % is not balanced', NEW.transaction_uuid;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER ledger_entries_balanced
+    AFTER INSERT OR UPDATE ON ledger_entries
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION check_ledger_transaction_balanced();
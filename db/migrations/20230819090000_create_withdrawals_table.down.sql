@@ -0,0 +1,6 @@
+-- Add down migration script here
+-- Postgres can't drop enum values, so the ledger kinds added in the up
+-- migration stay behind.
+DROP TABLE withdrawals;
+DROP type withdrawal_status;
+DROP type withdrawal_kind;
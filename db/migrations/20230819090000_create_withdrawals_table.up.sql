@@ -0,0 +1,31 @@
+-- Add up migration script here
+ALTER TYPE ledger_account_kind ADD VALUE 'withdrawal_reserve';
+ALTER TYPE ledger_transaction_kind ADD VALUE 'withdrawal_settlement';
+ALTER TYPE ledger_transaction_kind ADD VALUE 'withdrawal_reversal';
+
+CREATE type withdrawal_kind as enum ('lightning');
+
+CREATE type withdrawal_status as enum ('pending', 'completed', 'failed');
+
+CREATE TABLE withdrawals (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    user_uuid VARCHAR(255) references users(uuid) ON DELETE CASCADE NOT NULL,
+    store_uuid VARCHAR(255) references stores(uuid),
+    kind withdrawal_kind NOT NULL,
+    status withdrawal_status NOT NULL DEFAULT 'pending',
+    destination TEXT NOT NULL,
+    payment_request TEXT,
+    amount BIGINT NOT NULL CHECK (amount > 0),
+    fee_limit BIGINT NOT NULL CHECK (fee_limit >= 0),
+    fee_amount BIGINT,
+    payment_hash VARCHAR(255),
+    payment_preimage VARCHAR(255),
+    failure_reason TEXT,
+    idempotency_key VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP,
+    UNIQUE (user_uuid, idempotency_key)
+);
+
+CREATE INDEX withdrawals_user_uuid_created_at_idx ON withdrawals (user_uuid, created_at, uuid);
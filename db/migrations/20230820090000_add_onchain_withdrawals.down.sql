@@ -0,0 +1,4 @@
+-- Add down migration script here
+-- Postgres can't drop enum values, so 'onchain' and 'processing' stay behind.
+DROP INDEX withdrawals_kind_status_idx;
+ALTER TABLE withdrawals DROP COLUMN txid;
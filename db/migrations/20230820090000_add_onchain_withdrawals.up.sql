@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TYPE withdrawal_kind ADD VALUE 'onchain';
+-- Claimed by the payout batcher and about to be broadcast.
+ALTER TYPE withdrawal_status ADD VALUE 'processing';
+
+ALTER TABLE withdrawals ADD COLUMN txid VARCHAR(255);
+
+CREATE INDEX withdrawals_kind_status_idx ON withdrawals (kind, status);
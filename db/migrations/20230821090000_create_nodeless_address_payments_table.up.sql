@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE nodeless_address_payments (
+    uuid VARCHAR(255) UNIQUE PRIMARY KEY,
+    nodeless_address_uuid VARCHAR(255) references nodeless_addresses(uuid) ON DELETE CASCADE NOT NULL,
+    checkout_uuid VARCHAR(255) UNIQUE references checkouts(uuid) ON DELETE CASCADE NOT NULL,
+    comment TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX nodeless_address_payments_address_idx ON nodeless_address_payments (nodeless_address_uuid);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX nodeless_address_payments_unpublished_zaps_idx;
+
+ALTER TABLE nodeless_address_payments
+    DROP COLUMN zap_request,
+    DROP COLUMN zap_receipt_id,
+    DROP COLUMN zap_receipt_published_at;
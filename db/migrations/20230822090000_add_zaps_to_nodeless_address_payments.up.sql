@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE nodeless_address_payments
+    ADD COLUMN zap_request TEXT,
+    ADD COLUMN zap_receipt_id VARCHAR(64),
+    ADD COLUMN zap_receipt_published_at TIMESTAMP;
+
+CREATE INDEX nodeless_address_payments_unpublished_zaps_idx ON nodeless_address_payments (created_at)
+    WHERE zap_request IS NOT NULL AND zap_receipt_published_at IS NULL;
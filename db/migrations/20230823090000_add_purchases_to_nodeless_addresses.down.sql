@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP INDEX nodeless_addresses_user_uuid_idx;
+
+ALTER TABLE nodeless_addresses
+    DROP COLUMN status,
+    DROP COLUMN checkout_uuid,
+    DROP COLUMN reserved_until,
+    DROP COLUMN activated_at;
+
+DROP TYPE nodeless_address_status;
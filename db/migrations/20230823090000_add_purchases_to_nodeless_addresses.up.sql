@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TYPE nodeless_address_status AS ENUM ('pending', 'active');
+
+ALTER TABLE nodeless_addresses
+    ADD COLUMN status nodeless_address_status NOT NULL DEFAULT 'active',
+    ADD COLUMN checkout_uuid VARCHAR(255) UNIQUE REFERENCES checkouts(uuid),
+    ADD COLUMN reserved_until TIMESTAMP,
+    ADD COLUMN activated_at TIMESTAMP;
+
+CREATE INDEX nodeless_addresses_user_uuid_idx ON nodeless_addresses (user_uuid);
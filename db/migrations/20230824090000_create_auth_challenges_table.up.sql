@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE auth_challenges (
+    challenge VARCHAR(64) PRIMARY KEY,
+    method VARCHAR(32) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX auth_challenges_expires_at_idx ON auth_challenges (expires_at);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE auth_challenges
+    DROP COLUMN user_uuid,
+    DROP COLUMN authenticated_at;
+
+ALTER TABLE users ALTER COLUMN identifier TYPE VARCHAR(50);
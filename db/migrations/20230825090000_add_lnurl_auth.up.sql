@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- LNURL-auth linking keys are hex compressed public keys.
+ALTER TABLE users ALTER COLUMN identifier TYPE VARCHAR(66);
+
+ALTER TABLE auth_challenges
+    ADD COLUMN user_uuid VARCHAR(255) REFERENCES users(uuid) ON DELETE CASCADE,
+    ADD COLUMN authenticated_at TIMESTAMP;
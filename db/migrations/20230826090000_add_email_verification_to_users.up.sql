@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP;
+
+-- Accounts created before registration existed were set up by hand.
+UPDATE users SET email_verified_at = created_at WHERE email IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE users DROP COLUMN sessions_revoked_at;
+
+DROP TABLE password_reset_tokens;
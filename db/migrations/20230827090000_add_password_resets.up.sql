@@ -0,0 +1,12 @@
+-- Add up migration script here
+CREATE TABLE password_reset_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    user_uuid VARCHAR(255) NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX password_reset_tokens_user_uuid_idx ON password_reset_tokens (user_uuid);
+
+-- Tokens issued before this are no longer accepted.
+ALTER TABLE users ADD COLUMN sessions_revoked_at TIMESTAMP;
@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TABLE sessions (
+    uuid VARCHAR(255) PRIMARY KEY,
+    user_uuid VARCHAR(255) NOT NULL REFERENCES users(uuid) ON DELETE CASCADE,
+    device VARCHAR(255),
+    ip_hash VARCHAR(64) NOT NULL,
+    last_seen_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX sessions_user_uuid_idx ON sessions (user_uuid);
+
+-- Every refresh token a session was issued. Only the latest is unused, so
+-- presenting a used one means it was stolen.
+CREATE TABLE refresh_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY,
+    session_uuid VARCHAR(255) NOT NULL REFERENCES sessions(uuid) ON DELETE CASCADE,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX refresh_tokens_session_uuid_idx ON refresh_tokens (session_uuid);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Keys access tokens are signed with. The newest signs, and the few before
+-- it still verify tokens issued before a rotation. Private keys are
+-- encrypted with the app key.
+CREATE TABLE signing_keys (
+    kid VARCHAR(64) PRIMARY KEY,
+    algorithm VARCHAR(16) NOT NULL,
+    private_key TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
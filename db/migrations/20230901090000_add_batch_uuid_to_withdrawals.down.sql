@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX withdrawals_batch_uuid_idx;
+ALTER TABLE withdrawals DROP COLUMN batch_uuid;
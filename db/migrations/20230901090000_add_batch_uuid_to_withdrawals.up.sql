@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Payout batch an on-chain withdrawal was claimed into. The batch's wallet
+-- transaction is labelled with it, so a batch whose send failed or was never
+-- recorded can be looked up in the wallet before it is released or completed.
+ALTER TABLE withdrawals ADD COLUMN batch_uuid VARCHAR(255);
+
+CREATE INDEX withdrawals_batch_uuid_idx ON withdrawals (batch_uuid);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX checkouts_uncredited_idx;
+ALTER TABLE checkouts DROP COLUMN amount_credited;
+ALTER TABLE checkouts DROP COLUMN amount_confirmed;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+-- `amount_received` includes unconfirmed on-chain funds, which can still be
+-- replaced. Only `amount_confirmed` is credited to the ledger, and
+-- `amount_credited` records how much of it has been posted so far.
+ALTER TABLE checkouts ADD COLUMN amount_confirmed BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE checkouts ADD COLUMN amount_credited BIGINT NOT NULL DEFAULT 0;
+
+UPDATE checkouts SET amount_confirmed = amount_received
+WHERE status IN ('paid', 'overpaid');
+
+UPDATE checkouts SET amount_credited = amount_received
+WHERE EXISTS (
+    SELECT 1 FROM ledger_transactions
+    WHERE ledger_transactions.kind = 'checkout_payment'
+        AND ledger_transactions.reference_uuid = checkouts.uuid
+);
+
+CREATE INDEX checkouts_uncredited_idx ON checkouts (updated_at)
+WHERE amount_confirmed > amount_credited;
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE auth_challenges DROP COLUMN poll_secret_hash;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Hash of the secret the browser that started an LNURL-auth login polls
+-- with. The k1 is in the LNURL and can't be trusted to pick up the login.
+ALTER TABLE auth_challenges ADD COLUMN poll_secret_hash VARCHAR(64);
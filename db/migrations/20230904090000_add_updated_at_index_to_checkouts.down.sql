@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX checkouts_updated_at_idx;
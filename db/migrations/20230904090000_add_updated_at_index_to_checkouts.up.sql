@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- The webhook enqueuer catches up on checkouts updated while it lagged.
+CREATE INDEX checkouts_updated_at_idx ON checkouts (updated_at);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE users DROP COLUMN mfa_tokens_revoked_at;
+ALTER TABLE users DROP COLUMN mfa_failures;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Wrong codes submitted for pending two-factor logins. Too many revoke every
+-- pending login of the user.
+ALTER TABLE users ADD COLUMN mfa_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN mfa_tokens_revoked_at TIMESTAMP;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Revocations are compared with token issue times, which are UTC. Values
+-- were written with NOW(), so they convert back in the session time zone.
+ALTER TABLE users ALTER COLUMN sessions_revoked_at TYPE TIMESTAMPTZ;
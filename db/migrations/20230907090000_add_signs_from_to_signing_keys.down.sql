@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE signing_keys DROP COLUMN signs_from;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Rotated keys are published in the JWKS before they start signing.
+ALTER TABLE signing_keys ADD COLUMN signs_from TIMESTAMPTZ NOT NULL DEFAULT NOW();
+UPDATE signing_keys SET signs_from = created_at;
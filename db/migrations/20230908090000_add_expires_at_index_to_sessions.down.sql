@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX sessions_expires_at_idx;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
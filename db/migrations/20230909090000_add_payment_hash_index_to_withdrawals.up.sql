@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE UNIQUE INDEX withdrawals_payment_hash_idx ON withdrawals (payment_hash) WHERE status <> 'failed';
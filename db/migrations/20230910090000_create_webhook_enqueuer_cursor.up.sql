@@ -0,0 +1,5 @@
+-- Add up migration script here
+CREATE TABLE webhook_enqueuer_cursor (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    processed_until TIMESTAMP NOT NULL
+);
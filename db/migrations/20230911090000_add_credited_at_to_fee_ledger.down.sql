@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX fee_ledger_credited_at_idx;
+
+ALTER TABLE fee_ledger DROP COLUMN credited_at;
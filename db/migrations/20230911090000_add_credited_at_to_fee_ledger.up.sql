@@ -0,0 +1,10 @@
+-- Add up migration script here
+-- Fees are recorded when a checkout is created, but only collected once it
+-- is credited. Entries of checkouts that were never paid stay pending.
+ALTER TABLE fee_ledger ADD COLUMN credited_at TIMESTAMP;
+
+UPDATE fee_ledger SET credited_at = checkouts.updated_at
+FROM checkouts
+WHERE checkouts.uuid = fee_ledger.checkout_uuid AND checkouts.amount_credited > 0;
+
+CREATE INDEX fee_ledger_credited_at_idx ON fee_ledger (credited_at);
@@ -21,8 +21,8 @@ async fn seed_users(pool: &PgPool) -> Result<(), sqlx::Error> {
     let uuid = Uuid::new_v4().to_string();
     let result = sqlx::query!(
         r#"
-        INSERT INTO users (uuid, email, password)
-        VALUES ($1, $2, $3)
+        INSERT INTO users (uuid, email, password, email_verified_at)
+        VALUES ($1, $2, $3, NOW())
         "#,
         uuid,
         "admin@nodeless.io",
@@ -1,3 +1,8 @@
+use anyhow::{anyhow, Result};
+use lightning_cluster::{
+    cluster::{Cluster, Node, NodeClient, NodeLightningImpl, NodeNetwork},
+    lnd::LndClient,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -8,6 +13,14 @@ pub struct AppConfig {
     pub stores: StoresConfig,
     pub donation_pages: DonationPagesConfig,
     pub rate_limiter: RateLimiterConfig,
+    pub workers: WorkersConfig,
+    pub webhooks: WebhooksConfig,
+    pub withdrawals: WithdrawalsConfig,
+    pub email: EmailConfig,
+    pub lnurl: LnurlConfig,
+    pub nodeless_addresses: NodelessAddressesConfig,
+    pub nostr: NostrConfig,
+    pub cluster: ClusterConfig,
 }
 
 impl From<toml::Value> for AppConfig {
@@ -18,6 +31,14 @@ impl From<toml::Value> for AppConfig {
         let stores = value.get("stores").unwrap();
         let donation_pages = value.get("donation_pages").unwrap();
         let rate_limiter = value.get("rate_limiter").unwrap();
+        let workers = value.get("workers").unwrap();
+        let webhooks = value.get("webhooks").unwrap();
+        let withdrawals = value.get("withdrawals").unwrap();
+        let email = value.get("email").unwrap();
+        let lnurl = value.get("lnurl").unwrap();
+        let nodeless_addresses = value.get("nodeless_addresses").unwrap();
+        let nostr = value.get("nostr").unwrap();
+        let cluster = value.get("cluster").unwrap();
 
         AppConfig {
             meta: MetaConfig {
@@ -46,6 +67,53 @@ impl From<toml::Value> for AppConfig {
                     .unwrap()
                     .as_integer()
                     .unwrap() as u64,
+                refresh_token_expiry_seconds: auth
+                    .get("refresh_token_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                challenge_expiry_seconds: auth
+                    .get("challenge_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                mfa_token_expiry_seconds: auth
+                    .get("mfa_token_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                user_cache_seconds: auth
+                    .get("user_cache_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                password_memory_kib: auth
+                    .get("password_memory_kib")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u32,
+                password_iterations: auth
+                    .get("password_iterations")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u32,
+                password_parallelism: auth
+                    .get("password_parallelism")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u32,
+                signing_key_algorithm: auth
+                    .get("signing_key_algorithm")
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                signing_keys_kept: auth.get("signing_keys_kept").unwrap().as_integer().unwrap(),
+                signing_key_refresh_seconds: auth
+                    .get("signing_key_refresh_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
             },
             pricing: PricingConfig {
                 base_fee_sat: pricing.get("base_fee_sat").unwrap().as_integer().unwrap() as u32,
@@ -91,6 +159,260 @@ impl From<toml::Value> for AppConfig {
                     .as_integer()
                     .unwrap() as u32,
             },
+            workers: WorkersConfig {
+                payment_watcher_interval_seconds: workers
+                    .get("payment_watcher_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                checkout_expiry_interval_seconds: workers
+                    .get("checkout_expiry_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                late_payment_window_seconds: workers
+                    .get("late_payment_window_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                ledger_reconcile_interval_seconds: workers
+                    .get("ledger_reconcile_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                payout_batch_interval_seconds: workers
+                    .get("payout_batch_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                zap_receipt_interval_seconds: workers
+                    .get("zap_receipt_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                handle_activation_interval_seconds: workers
+                    .get("handle_activation_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                withdrawal_reconcile_interval_seconds: workers
+                    .get("withdrawal_reconcile_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                session_cleanup_interval_seconds: workers
+                    .get("session_cleanup_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+            },
+            webhooks: WebhooksConfig {
+                max_webhooks_per_store: webhooks
+                    .get("max_webhooks_per_store")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u32,
+                max_attempts: webhooks.get("max_attempts").unwrap().as_integer().unwrap() as i32,
+                initial_backoff_seconds: webhooks
+                    .get("initial_backoff_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                max_backoff_seconds: webhooks
+                    .get("max_backoff_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                request_timeout_seconds: webhooks
+                    .get("request_timeout_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                delivery_interval_seconds: webhooks
+                    .get("delivery_interval_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+            },
+            withdrawals: WithdrawalsConfig {
+                min_lightning_withdrawal_sat: withdrawals
+                    .get("min_lightning_withdrawal_sat")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                lightning_fee_limit_base_sat: withdrawals
+                    .get("lightning_fee_limit_base_sat")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                lightning_fee_limit_rate_bps: withdrawals
+                    .get("lightning_fee_limit_rate_bps")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                lightning_address_timeout_seconds: withdrawals
+                    .get("lightning_address_timeout_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                lightning_reconcile_after_seconds: withdrawals
+                    .get("lightning_reconcile_after_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                min_onchain_withdrawal_sat: withdrawals
+                    .get("min_onchain_withdrawal_sat")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                onchain_fee_sat: withdrawals
+                    .get("onchain_fee_sat")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                onchain_fee_from_amount: withdrawals
+                    .get("onchain_fee_from_amount")
+                    .unwrap()
+                    .as_bool()
+                    .unwrap(),
+                onchain_target_conf: withdrawals
+                    .get("onchain_target_conf")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as i32,
+                onchain_max_batch_size: withdrawals
+                    .get("onchain_max_batch_size")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                onchain_reconcile_after_seconds: withdrawals
+                    .get("onchain_reconcile_after_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+            },
+            email: EmailConfig {
+                from: email.get("from").unwrap().as_str().unwrap().to_string(),
+                smtp_host: email
+                    .get("smtp_host")
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                smtp_port: email.get("smtp_port").unwrap().as_integer().unwrap() as u16,
+                app_url: email.get("app_url").unwrap().as_str().unwrap().to_string(),
+                verification_expiry_seconds: email
+                    .get("verification_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                password_reset_expiry_seconds: email
+                    .get("password_reset_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+            },
+            lnurl: LnurlConfig {
+                domain: lnurl.get("domain").unwrap().as_str().unwrap().to_string(),
+                callback_base_url: lnurl
+                    .get("callback_base_url")
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                min_sendable_sat: lnurl.get("min_sendable_sat").unwrap().as_integer().unwrap(),
+                max_sendable_sat: lnurl.get("max_sendable_sat").unwrap().as_integer().unwrap(),
+                comment_allowed: lnurl.get("comment_allowed").unwrap().as_integer().unwrap()
+                    as usize,
+                invoice_expiry_seconds: lnurl
+                    .get("invoice_expiry_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+            },
+            nodeless_addresses: NodelessAddressesConfig {
+                price_sat: nodeless_addresses
+                    .get("price_sat")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                min_handle_length: nodeless_addresses
+                    .get("min_handle_length")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as usize,
+                max_handle_length: nodeless_addresses
+                    .get("max_handle_length")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as usize,
+                reservation_seconds: nodeless_addresses
+                    .get("reservation_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+                reserved_handles: nodeless_addresses
+                    .get("reserved_handles")
+                    .unwrap()
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|handle| handle.as_str().unwrap().to_string())
+                    .collect(),
+                max_pending_purchases: nodeless_addresses
+                    .get("max_pending_purchases")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap(),
+            },
+            nostr: NostrConfig {
+                relays: nostr
+                    .get("relays")
+                    .unwrap()
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|relay| relay.as_str().unwrap().to_string())
+                    .collect(),
+                relay_timeout_seconds: nostr
+                    .get("relay_timeout_seconds")
+                    .unwrap()
+                    .as_integer()
+                    .unwrap() as u64,
+                login_url: nostr
+                    .get("login_url")
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            },
+            cluster: ClusterConfig {
+                nodes: cluster
+                    .get("nodes")
+                    .unwrap()
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(ClusterNodeConfig::from)
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl From<&toml::Value> for ClusterNodeConfig {
+    fn from(node: &toml::Value) -> Self {
+        let field = |name: &str| node.get(name).unwrap().as_str().unwrap().to_string();
+
+        ClusterNodeConfig {
+            pubkey: field("pubkey"),
+            ip: field("ip"),
+            port: field("port"),
+            host: field("host"),
+            network: field("network"),
+            implementation: field("implementation"),
+            cert_path: field("cert_path"),
+            macaroon_path: field("macaroon_path"),
         }
     }
 }
@@ -102,7 +424,29 @@ pub struct AuthConfig {
     pub enable_email_auth: bool,
     pub enable_nost_auth: bool,
     pub enable_identifier_auth: bool,
+    /// Lifetime of access tokens. Sessions outlive them by refreshing.
     pub jwt_expiry_seconds: u64,
+    /// How long a session lasts without being refreshed.
+    pub refresh_token_expiry_seconds: u64,
+    /// How long a login challenge can be answered.
+    pub challenge_expiry_seconds: i64,
+    /// How long a password login can wait for its second factor.
+    pub mfa_token_expiry_seconds: u64,
+    /// How long authenticated requests reuse a loaded user or session.
+    pub user_cache_seconds: u64,
+    /// Argon2id cost parameters. Hashes made with other parameters are
+    /// replaced on the next login.
+    pub password_memory_kib: u32,
+    pub password_iterations: u32,
+    pub password_parallelism: u32,
+    /// Algorithm of signing keys, `EdDSA` or `ES256`, unless one is given
+    /// when rotating.
+    pub signing_key_algorithm: String,
+    /// How many of the newest signing keys verify access tokens. Rotating
+    /// more often than this within the access token lifetime logs users out.
+    pub signing_keys_kept: i64,
+    /// How often keys are reloaded to pick up rotations by other instances.
+    pub signing_key_refresh_seconds: u64,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -133,3 +477,254 @@ pub struct RateLimiterConfig {
     pub checkout_requests_per_minute: u32,
     pub auth_requests_per_hour: u32,
 }
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WorkersConfig {
+    pub payment_watcher_interval_seconds: u64,
+    pub checkout_expiry_interval_seconds: u64,
+    pub late_payment_window_seconds: i64,
+    pub ledger_reconcile_interval_seconds: u64,
+    pub payout_batch_interval_seconds: u64,
+    pub zap_receipt_interval_seconds: u64,
+    pub handle_activation_interval_seconds: u64,
+    pub withdrawal_reconcile_interval_seconds: u64,
+    pub session_cleanup_interval_seconds: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WithdrawalsConfig {
+    pub min_lightning_withdrawal_sat: i64,
+    pub lightning_fee_limit_base_sat: i64,
+    /// Routing fee budget on top of the base, in basis points of the amount.
+    pub lightning_fee_limit_rate_bps: i64,
+    pub lightning_address_timeout_seconds: u64,
+    /// Lightning withdrawals still pending after this long are looked up on
+    /// the node and settled or reversed.
+    pub lightning_reconcile_after_seconds: i64,
+    pub min_onchain_withdrawal_sat: i64,
+    /// Flat fee charged per on-chain payout, covering its share of the miner fee.
+    pub onchain_fee_sat: i64,
+    /// Take the fee out of the payout instead of charging it on top.
+    pub onchain_fee_from_amount: bool,
+    pub onchain_target_conf: i32,
+    pub onchain_max_batch_size: i64,
+    /// Payout batches still processing after this long are checked against
+    /// the wallet and completed or put back in the queue.
+    pub onchain_reconcile_after_seconds: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EmailConfig {
+    /// Sender of account emails, e.g. `Nodeless <noreply@nodeless.io>`.
+    pub from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// Base URL of the web app that links in emails open.
+    pub app_url: String,
+    pub verification_expiry_seconds: u64,
+    pub password_reset_expiry_seconds: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LnurlConfig {
+    /// Domain of `handle@domain` lightning addresses.
+    pub domain: String,
+    /// Public base URL the LNURL-pay callbacks are served from.
+    pub callback_base_url: String,
+    pub min_sendable_sat: i64,
+    pub max_sendable_sat: i64,
+    /// Longest payer comment accepted, 0 to disable comments.
+    pub comment_allowed: usize,
+    pub invoice_expiry_seconds: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NodelessAddressesConfig {
+    /// Price of a new handle, in sats.
+    pub price_sat: i64,
+    pub min_handle_length: usize,
+    pub max_handle_length: usize,
+    /// How long a handle is held for its buyer while the checkout is paid.
+    pub reservation_seconds: i64,
+    /// Handles nobody can buy.
+    pub reserved_handles: Vec<String>,
+    /// Handles a user can have reserved at once.
+    pub max_pending_purchases: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NostrConfig {
+    /// Relay hints returned with NIP-05 lookups, and where zap receipts go
+    /// when the zap request doesn't name any relays.
+    pub relays: Vec<String>,
+    pub relay_timeout_seconds: u64,
+    /// Public URL of the nostr login endpoint, which login events must name
+    /// in their NIP-98 `u` tag.
+    pub login_url: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhooksConfig {
+    pub max_webhooks_per_store: u32,
+    pub max_attempts: i32,
+    pub initial_backoff_seconds: i64,
+    pub max_backoff_seconds: i64,
+    pub request_timeout_seconds: u64,
+    pub delivery_interval_seconds: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClusterConfig {
+    pub nodes: Vec<ClusterNodeConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClusterNodeConfig {
+    pub pubkey: String,
+    pub ip: String,
+    pub port: String,
+    pub host: String,
+    pub network: String,
+    pub implementation: String,
+    pub cert_path: String,
+    pub macaroon_path: String,
+}
+
+impl ClusterNodeConfig {
+    pub fn network(&self) -> Result<NodeNetwork> {
+        match self.network.as_str() {
+            "mainnet" => Ok(NodeNetwork::Mainnet),
+            "testnet" => Ok(NodeNetwork::Testnet),
+            network => Err(anyhow!(
+                "Unknown network for node {}: {}",
+                self.pubkey,
+                network
+            )),
+        }
+    }
+
+    /// Panics on an unknown implementation so a bad config fails at startup.
+    pub fn lightning_impl(&self) -> NodeLightningImpl {
+        match self.implementation.as_str() {
+            "lnd" => NodeLightningImpl::Lnd,
+            implementation => panic!(
+                "Unknown lightning implementation for node {}: {}",
+                self.pubkey, implementation
+            ),
+        }
+    }
+}
+
+impl ClusterConfig {
+    /// Network the cluster runs on. Fails if a node's network is unknown or
+    /// the nodes disagree; `main` checks this once at startup.
+    pub fn network(&self) -> Result<NodeNetwork> {
+        let networks: Vec<&str> = self
+            .nodes
+            .iter()
+            .map(|node| node.network.as_str())
+            .collect();
+
+        match self.nodes.first() {
+            Some(node) if networks.iter().all(|network| *network == node.network) => node.network(),
+            Some(_) => Err(anyhow!(
+                "Cluster nodes are on different networks: {:?}",
+                networks
+            )),
+            None => Err(anyhow!("No cluster nodes configured")),
+        }
+    }
+
+    /// Panics on a node with an unknown network or implementation, so call
+    /// it at startup.
+    pub fn build(&self) -> Cluster {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| Node {
+                pubkey: node.pubkey.clone(),
+                ip: node.ip.clone(),
+                port: node.port.clone(),
+                network: node.network().unwrap_or_else(|e| panic!("{}", e)),
+                lightning_impl: node.lightning_impl(),
+                client: NodeClient::Lnd(LndClient::new(
+                    node.host.clone(),
+                    node.cert_path.clone(),
+                    node.macaroon_path.clone(),
+                )),
+            })
+            .collect();
+
+        Cluster::new(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_cluster::cluster::NodeNetwork;
+
+    use super::{ClusterConfig, ClusterNodeConfig};
+
+    #[test]
+    fn test_cluster_nodes_from_config() {
+        let value: toml::Value = r#"
+            [[cluster.nodes]]
+            pubkey = "node1"
+            ip = "10.0.0.1"
+            port = "9735"
+            host = "https://10.0.0.1:8080"
+            network = "mainnet"
+            implementation = "lnd"
+            cert_path = "/etc/nodeless/node1/tls.cert"
+            macaroon_path = "/etc/nodeless/node1/admin.macaroon"
+
+            [[cluster.nodes]]
+            pubkey = "node2"
+            ip = "10.0.0.2"
+            port = "9735"
+            host = "https://10.0.0.2:8080"
+            network = "testnet"
+            implementation = "lnd"
+            cert_path = "/etc/nodeless/node2/tls.cert"
+            macaroon_path = "/etc/nodeless/node2/admin.macaroon"
+        "#
+        .parse()
+        .unwrap();
+
+        let nodes: Vec<ClusterNodeConfig> = value["cluster"]["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(ClusterNodeConfig::from)
+            .collect();
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].pubkey, "node1");
+        assert_eq!(nodes[1].host, "https://10.0.0.2:8080");
+        assert!(matches!(nodes[0].network(), Ok(NodeNetwork::Mainnet)));
+        assert!(matches!(nodes[1].network(), Ok(NodeNetwork::Testnet)));
+
+        let cluster = ClusterConfig { nodes };
+        assert!(cluster.network().is_err());
+    }
+
+    #[test]
+    fn test_unknown_network_fails() {
+        let node = ClusterNodeConfig {
+            pubkey: "node1".to_string(),
+            ip: "10.0.0.1".to_string(),
+            port: "9735".to_string(),
+            host: "https://10.0.0.1:8080".to_string(),
+            network: "signet".to_string(),
+            implementation: "lnd".to_string(),
+            cert_path: "tls.cert".to_string(),
+            macaroon_path: "admin.macaroon".to_string(),
+        };
+
+        assert!(node
+            .network()
+            .unwrap_err()
+            .to_string()
+            .contains("Unknown network"));
+    }
+}
@@ -1 +1,146 @@
+use crate::handlers::frontend::fe_store_handlers::{CreateStoreInvoice, ListStoreInvoicesQuery};
+use crate::helpers::format::{DataResponse, ErrorResponse};
+use crate::helpers::pagination::PaginatedResponse;
+use crate::lightning::LightningBackend;
+use crate::middleware::api_middleware::ApiKeyAuth;
+use crate::repositories::checkout_repository::CheckoutRepository;
+use crate::repositories::store_repository::{StoreInvoiceRepository, StoreRepository};
+use crate::services::checkout_service::{CheckoutService, CreateCheckoutService};
+use crate::services::event_bus::{CheckoutEventKind, EventBus};
+use crate::services::fee_service::FeeService;
+use crate::services::store_service::StoreService;
+use actix_web::{web, HttpResponse, Responder};
 
+#[allow(clippy::too_many_arguments)]
+pub async fn create_store_invoice(
+    auth: ApiKeyAuth,
+    data: web::Json<CreateStoreInvoice>,
+    store_uuid: web::Path<String>,
+    store_repo: web::Data<StoreRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    store_invoice_repo: web::Data<StoreInvoiceRepository>,
+    events: web::Data<EventBus>,
+    lightning: web::Data<dyn LightningBackend>,
+    fees: web::Data<FeeService>,
+) -> impl Responder {
+    // Api keys are bound to a single store, so the store in the path must match it.
+    if auth.store_uuid() != store_uuid.as_str() {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Api key is not valid for this store".to_string(),
+        });
+    }
+
+    let service = StoreService::new(
+        store_repo.get_ref().clone(),
+        checkout_repo.get_ref().clone(),
+        store_invoice_repo.get_ref().clone(),
+    );
+
+    let invoice = service
+        .create_invoice(
+            &store_uuid,
+            data.clone().metadata,
+            CreateCheckoutService {
+                user_uuid: auth.user_uuid().to_string(),
+                amount: data.amount,
+                expiry: data.expiry,
+                memo: data.memo.clone(),
+                description_hash: None,
+            },
+            CheckoutService::new(lightning.into_inner(), fees.get_ref().clone()),
+        )
+        .await;
+
+    match invoice {
+        Ok(invoice) => {
+            events.publish(CheckoutEventKind::Created, invoice.checkout.clone());
+            HttpResponse::Created().json(DataResponse { data: invoice })
+        }
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    }
+}
+
+pub async fn get_store_invoices(
+    auth: ApiKeyAuth,
+    store_uuid: web::Path<String>,
+    query: web::Query<ListStoreInvoicesQuery>,
+    store_repo: web::Data<StoreRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    store_invoice_repo: web::Data<StoreInvoiceRepository>,
+) -> impl Responder {
+    if auth.store_uuid() != store_uuid.as_str() {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Api key is not valid for this store".to_string(),
+        });
+    }
+
+    let filter = match query.to_filter() {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse { error }),
+    };
+
+    let service = StoreService::new(
+        store_repo.get_ref().clone(),
+        checkout_repo.get_ref().clone(),
+        store_invoice_repo.get_ref().clone(),
+    );
+
+    match service.list_invoices(&store_uuid, filter).await {
+        Ok(page) => HttpResponse::Ok().json(PaginatedResponse {
+            data: page.invoices,
+            next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get invoices".to_string(),
+        }),
+    }
+}
+
+pub async fn get_store_invoice(
+    auth: ApiKeyAuth,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    store_invoice_repo: web::Data<StoreInvoiceRepository>,
+) -> impl Responder {
+    let (store_uuid, invoice_uuid) = path.into_inner();
+
+    if auth.store_uuid() != store_uuid {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Api key is not valid for this store".to_string(),
+        });
+    }
+
+    let service = StoreService::new(
+        store_repo.get_ref().clone(),
+        checkout_repo.get_ref().clone(),
+        store_invoice_repo.get_ref().clone(),
+    );
+
+    match service.get_invoice(&store_uuid, &invoice_uuid).await {
+        Ok(Some(invoice)) => HttpResponse::Ok().json(DataResponse { data: invoice }),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Invoice not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get invoice".to_string(),
+        }),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1/stores")
+            .route(
+                "/{store_uuid}/invoices",
+                web::post().to(create_store_invoice),
+            )
+            .route("/{store_uuid}/invoices", web::get().to(get_store_invoices))
+            .route(
+                "/{store_uuid}/invoices/{invoice_uuid}",
+                web::get().to(get_store_invoice),
+            ),
+    );
+}
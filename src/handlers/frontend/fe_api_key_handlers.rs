@@ -0,0 +1,113 @@
+use crate::helpers::crypto::sha256_hex;
+use crate::helpers::format::{random_text, DataResponse, ErrorResponse};
+use crate::middleware::api_middleware::API_KEY_PREFIX;
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::models::api_key::ApiKey;
+use crate::repositories::api_key_repository::{ApiKeyRepository, CreateApiKey};
+use crate::repositories::store_repository::StoreRepository;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use serde_derive::Deserialize;
+
+use super::fe_store_handlers::authorize_store;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyReq {
+    pub name: String,
+}
+
+/// Returned only once, when the key is created; afterwards only its hash is kept.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+pub async fn create_api_key(
+    auth: AuthenticatedUser,
+    store_uuid: web::Path<String>,
+    form: web::Json<CreateApiKeyReq>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<ApiKeyRepository>,
+) -> impl Responder {
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > 255 {
+        return HttpResponse::BadRequest().json(ErrorResponse {
+            error: "Name must be between 1 and 255 characters".to_string(),
+        });
+    }
+
+    let key = format!("{}{}", API_KEY_PREFIX, random_text(40).await);
+    let api_key = CreateApiKey {
+        user_uuid: auth.uuid().to_string(),
+        store_uuid: store_uuid.to_string(),
+        name: name.to_string(),
+        key_prefix: key[..API_KEY_PREFIX.len() + 6].to_string(),
+        key_hash: sha256_hex(&key),
+    };
+
+    match repo.create(api_key).await {
+        Ok(api_key) => HttpResponse::Created().json(DataResponse {
+            data: CreatedApiKey { api_key, key },
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to create api key".to_string(),
+        }),
+    }
+}
+
+pub async fn get_all_api_keys(
+    auth: AuthenticatedUser,
+    store_uuid: web::Path<String>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<ApiKeyRepository>,
+) -> impl Responder {
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.get_all(&store_uuid).await {
+        Ok(api_keys) => HttpResponse::Ok().json(DataResponse { data: api_keys }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get api keys".to_string(),
+        }),
+    }
+}
+
+pub async fn revoke_api_key(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<ApiKeyRepository>,
+) -> impl Responder {
+    let (store_uuid, api_key_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.revoke(&store_uuid, &api_key_uuid).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Api key not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to revoke api key".to_string(),
+        }),
+    }
+}
+
+/// Registered inside the `/stores` scope.
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/{store_uuid}/api-keys")
+            .route("", web::post().to(create_api_key))
+            .route("", web::get().to(get_all_api_keys))
+            .route("/{api_key_uuid}", web::delete().to(revoke_api_key)),
+    );
+}
@@ -1,11 +1,27 @@
 use crate::{
     config::AppConfig,
-    helpers::{crypto::sha256_hmac, format::ErrorResponse},
+    helpers::{
+        crypto::{app_key, sha256_hmac},
+        format::{DataResponse, ErrorResponse},
+        nostr::NostrEvent,
+        password::{hash_password, verify_password, PasswordCheck},
+    },
     middleware::{
-        jwt_middleware::generate_jwt_token,
+        jwt_middleware::{AuthCache, AuthenticatedUser},
         limiter_middleware::{guest_limiter, GuestLimiter},
     },
-    repositories::user_repository::UserRepository,
+    models::session::Session,
+    repositories::{
+        session_repository::SessionRepository,
+        user_repository::{UserRepository, UserRepositoryError},
+    },
+    services::{
+        account_service::{validate_email, AccountError, AccountService},
+        lnurl_auth_service::{LnurlAuthError, LnurlAuthService, LnurlAuthStatus},
+        nostr_auth_service::{NostrAuthError, NostrAuthService},
+        session_service::{SessionError, SessionService},
+        totp_service::{TotpError, TotpService},
+    },
 };
 use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
 use serde::{Deserialize, Serialize};
@@ -35,6 +51,10 @@ pub enum LoginError {
     LoginMethodDisabled,
     #[error("Too many requests")]
     TooManyRequests,
+    #[error("{0}")]
+    InvalidChallenge(String),
+    #[error("Internal server error")]
+    Internal,
 }
 
 impl ResponseError for LoginError {
@@ -58,13 +78,51 @@ impl ResponseError for LoginError {
             LoginError::TooManyRequests => HttpResponse::TooManyRequests().json(ErrorResponse {
                 error: self.to_string(),
             }),
+            LoginError::InvalidChallenge(_) => HttpResponse::Unauthorized().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            LoginError::Internal => HttpResponse::InternalServerError().json(ErrorResponse {
+                error: self.to_string(),
+            }),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct JwtResponse {
-    pub token: String,
+impl From<SessionError> for LoginError {
+    fn from(e: SessionError) -> Self {
+        eprintln!("failed to start session: {:?}", e);
+        LoginError::Internal
+    }
+}
+
+/// Starts a session for a user who just logged in and responds with its
+/// access and refresh tokens.
+async fn start_session(
+    user_uuid: &str,
+    req: &HttpRequest,
+    sessions: &SessionService,
+) -> Result<HttpResponse, LoginError> {
+    let tokens = sessions
+        .start(user_uuid, device(req), hashed_ip(req))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+fn device(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get("User-Agent")
+        .and_then(|h| h.to_str().ok())
+        .map(|agent| agent.chars().take(255).collect())
+}
+
+fn hashed_ip(req: &HttpRequest) -> String {
+    let user_ip = req
+        .connection_info()
+        .peer_addr()
+        .unwrap_or_default()
+        .to_string();
+    sha256_hmac(&user_ip, app_key())
 }
 
 pub async fn email(
@@ -73,6 +131,8 @@ pub async fn email(
     user_repo: web::Data<UserRepository>,
     config: web::Data<AppConfig>,
     limiter: web::Data<GuestLimiter>,
+    totp: web::Data<TotpService>,
+    sessions: web::Data<SessionService>,
 ) -> Result<HttpResponse, LoginError> {
     let login_data = data.into_inner();
 
@@ -86,7 +146,8 @@ pub async fn email(
         return Err(LoginError::LoginMethodDisabled);
     }
 
-    if !login_data.email.contains("@") {
+    let email = login_data.email.trim().to_lowercase();
+    if !email.contains("@") {
         return Err(LoginError::InvalidEmail);
     }
 
@@ -94,24 +155,187 @@ pub async fn email(
         return Err(LoginError::PasswordTooShort);
     }
 
-    let hashed_pwd = sha256_hmac(&login_data.password, &dotenvy::var("APP_KEY").unwrap());
-    let user = user_repo.get_user_by_email(&login_data.email).await;
+    // Unknown emails are still checked against a dummy hash, so they can't
+    // be told apart by how long the answer takes.
+    let user = match user_repo.get_user_by_email(&email).await {
+        Ok(user) => Some(user),
+        Err(UserRepositoryError::DatabaseError(sqlx::Error::RowNotFound)) => None,
+        Err(e) => {
+            eprintln!("the error was {:?}", e);
+            None
+        }
+    };
+    let hash = user.as_ref().and_then(|user| user.password.as_deref());
+
+    let check = verify_password(&login_data.password, hash, &config.auth).await;
+    let user = match (user, check) {
+        (Some(user), PasswordCheck::Valid | PasswordCheck::NeedsRehash) => user,
+        _ => return Err(LoginError::InvalidCredentials),
+    };
+
+    // Legacy or outdated hash, replace it now that we know the password.
+    if check == PasswordCheck::NeedsRehash {
+        let hash = user.password.as_deref().unwrap_or_default();
+        let rehashed = hash_password(&login_data.password, &config.auth).await;
+        let replaced = match rehashed {
+            Ok(new_hash) => user_repo
+                .replace_password_hash(&user.uuid, hash, &new_hash)
+                .await
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = replaced {
+            eprintln!("failed to rehash password for {}: {:?}", user.uuid, e);
+        }
+    }
+
+    // The JWT waits for a code from `/auth/email/mfa`.
+    if user.has_totp() {
+        return Ok(HttpResponse::Ok().json(totp.mfa_pending(&user).await?));
+    }
+
+    start_session(&user.uuid, &req, &sessions).await
+}
 
-    match user {
-        Ok(user) => {
-            if user.password != hashed_pwd {
-                return Err(LoginError::InvalidCredentials);
-            } else {
-                let response = JwtResponse {
-                    token: generate_jwt_token(&user.uuid, config.auth.jwt_expiry_seconds).unwrap(),
-                };
-                Ok(HttpResponse::Ok().json(response))
+// -----------------------------------------------------------------------------
+// Two-Factor Authentication
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize)]
+pub struct MfaLoginReq {
+    pub mfa_token: String,
+    /// A code from the authenticator app, or a recovery code.
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TotpCodeReq {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+impl From<TotpError> for LoginError {
+    fn from(e: TotpError) -> Self {
+        match e {
+            TotpError::Internal(e) => {
+                eprintln!("two-factor login failed: {:?}", e);
+                LoginError::Internal
             }
+            e => LoginError::InvalidChallenge(e.to_string()),
         }
-        Err(e) => {
-            eprintln!("the error was {:?}", e);
-            return Err(LoginError::InvalidCredentials);
+    }
+}
+
+impl ResponseError for TotpError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            TotpError::Internal(e) => {
+                eprintln!("two-factor request failed: {:?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Internal server error".to_string(),
+                })
+            }
+            TotpError::AlreadyEnabled | TotpError::NotEnabled => {
+                HttpResponse::Conflict().json(ErrorResponse {
+                    error: self.to_string(),
+                })
+            }
+            TotpError::NotFound => HttpResponse::NotFound().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            TotpError::TooManyAttempts => HttpResponse::Unauthorized().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+        }
+    }
+}
+
+/// Second step of an email login for users with two-factor authentication.
+pub async fn email_mfa(
+    data: web::Json<MfaLoginReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    totp: web::Data<TotpService>,
+    sessions: web::Data<SessionService>,
+) -> Result<HttpResponse, LoginError> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests);
+    }
+
+    let user = totp.login(&data.mfa_token, &data.code).await?;
+
+    start_session(&user.uuid, &req, &sessions).await
+}
+
+pub async fn enroll_totp(
+    auth: AuthenticatedUser,
+    totp: web::Data<TotpService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_uuid = auth.uuid();
+
+    let enrollment = totp.enroll(user_uuid).await?;
+
+    Ok(HttpResponse::Ok().json(DataResponse { data: enrollment }))
+}
+
+pub async fn confirm_totp(
+    auth: AuthenticatedUser,
+    data: web::Json<TotpCodeReq>,
+    totp: web::Data<TotpService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_uuid = auth.uuid();
+
+    let recovery_codes = totp.confirm(user_uuid, &data.code).await?;
+
+    Ok(HttpResponse::Ok().json(DataResponse {
+        data: RecoveryCodesResponse { recovery_codes },
+    }))
+}
+
+/// Needs a current code. Too many wrong ones end the session the request
+/// was made with, in case its token was stolen.
+#[allow(clippy::too_many_arguments)]
+pub async fn disable_totp(
+    auth: AuthenticatedUser,
+    data: web::Json<TotpCodeReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    totp: web::Data<TotpService>,
+    session_repo: web::Data<SessionRepository>,
+    cache: web::Data<AuthCache>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests.into());
+    }
+
+    let user_uuid = auth.uuid();
+
+    match totp.disable(user_uuid, &data.code).await {
+        Ok(()) => Ok(HttpResponse::NoContent().finish()),
+        Err(TotpError::TooManyAttempts) => {
+            if let Some(session_uuid) = &auth.session_uuid {
+                session_repo
+                    .revoke(user_uuid, session_uuid)
+                    .await
+                    .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to log out"))?;
+                cache.invalidate_session(session_uuid).await;
+            }
+            Err(TotpError::TooManyAttempts.into())
         }
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -119,20 +343,410 @@ pub async fn email(
 // Nostr Login
 // -----------------------------------------------------------------------------
 
+/// A NIP-98 event signing a challenge from `/auth/nostr/challenge`.
+#[derive(Serialize, Deserialize)]
 pub struct NostrLogin {
-    pub npub: String,
+    pub event: NostrEvent,
 }
 
-pub async fn nostr() -> impl Responder {
-    HttpResponse::Ok().body("nostr_login")
+impl From<NostrAuthError> for LoginError {
+    fn from(e: NostrAuthError) -> Self {
+        match e {
+            NostrAuthError::Internal(e) => {
+                eprintln!("nostr login failed: {:?}", e);
+                LoginError::Internal
+            }
+            e => LoginError::InvalidChallenge(e.to_string()),
+        }
+    }
+}
+
+pub async fn nostr_challenge(
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    nostr_auth: web::Data<NostrAuthService>,
+) -> Result<HttpResponse, LoginError> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests);
+    }
+
+    if !config.auth.enable_nost_auth {
+        return Err(LoginError::LoginMethodDisabled);
+    }
+
+    let challenge = nostr_auth.challenge().await?;
+
+    Ok(HttpResponse::Ok().json(DataResponse { data: challenge }))
+}
+
+pub async fn nostr(
+    data: web::Json<NostrLogin>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    nostr_auth: web::Data<NostrAuthService>,
+    sessions: web::Data<SessionService>,
+) -> Result<HttpResponse, LoginError> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests);
+    }
+
+    if !config.auth.enable_nost_auth {
+        return Err(LoginError::LoginMethodDisabled);
+    }
+
+    let user = nostr_auth.login(&data.event).await?;
+
+    start_session(&user.uuid, &req, &sessions).await
 }
 
 // -----------------------------------------------------------------------------
 // Identifier Login
 // -----------------------------------------------------------------------------
 
-pub async fn identifier() -> impl Responder {
-    HttpResponse::Ok().body("identifier_login")
+impl From<LnurlAuthError> for LoginError {
+    fn from(e: LnurlAuthError) -> Self {
+        match e {
+            LnurlAuthError::Internal(e) => {
+                eprintln!("LNURL-auth login failed: {:?}", e);
+                LoginError::Internal
+            }
+            e => LoginError::InvalidChallenge(e.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IdentifierStatusReq {
+    pub poll_secret: String,
+}
+
+/// Starts an LNURL-auth login. The browser shows the LNURL for the user's
+/// wallet to sign and polls `/auth/identifier/{k1}` with the login's poll
+/// secret for the token.
+pub async fn identifier(
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    lnurl_auth: web::Data<LnurlAuthService>,
+) -> Result<HttpResponse, LoginError> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests);
+    }
+
+    if !config.auth.enable_identifier_auth {
+        return Err(LoginError::LoginMethodDisabled);
+    }
+
+    let login = lnurl_auth.login().await?;
+
+    Ok(HttpResponse::Ok().json(DataResponse { data: login }))
+}
+
+pub async fn identifier_status(
+    k1: web::Path<String>,
+    data: web::Json<IdentifierStatusReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    lnurl_auth: web::Data<LnurlAuthService>,
+    sessions: web::Data<SessionService>,
+) -> Result<HttpResponse, LoginError> {
+    if !config.auth.enable_identifier_auth {
+        return Err(LoginError::LoginMethodDisabled);
+    }
+
+    match lnurl_auth.poll(&k1, &data.poll_secret).await? {
+        LnurlAuthStatus::Pending => {
+            Ok(HttpResponse::Accepted().json(serde_json::json!({ "status": "pending" })))
+        }
+        LnurlAuthStatus::Authenticated(user_uuid) => {
+            start_session(&user_uuid, &req, &sessions).await
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Registration
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize)]
+pub struct RegisterReq {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyEmailReq {
+    pub token: String,
+}
+
+impl ResponseError for AccountError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AccountError::Internal(e) => {
+                eprintln!("account request failed: {:?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Internal server error".to_string(),
+                })
+            }
+            AccountError::EmailTaken => HttpResponse::Conflict().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            AccountError::NotFound => HttpResponse::NotFound().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+        }
+    }
+}
+
+/// Signs up with an email and password. The account can log in straight
+/// away but can't create stores until the emailed link is followed.
+pub async fn register(
+    data: web::Json<RegisterReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    accounts: web::Data<AccountService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests.into());
+    }
+
+    if !config.auth.enable_email_auth {
+        return Err(LoginError::LoginMethodDisabled.into());
+    }
+
+    let user = accounts.register(&data.email, &data.password).await?;
+
+    Ok(HttpResponse::Created().json(DataResponse { data: user }))
+}
+
+pub async fn verify_email(
+    data: web::Json<VerifyEmailReq>,
+    accounts: web::Data<AccountService>,
+) -> Result<HttpResponse, AccountError> {
+    let user = accounts.verify_email(&data.token).await?;
+
+    Ok(HttpResponse::Ok().json(DataResponse { data: user }))
+}
+
+pub async fn resend_verification_email(
+    auth: AuthenticatedUser,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    accounts: web::Data<AccountService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_uuid = auth.uuid();
+
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests.into());
+    }
+
+    accounts.resend_verification(user_uuid).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// -----------------------------------------------------------------------------
+// Password Reset
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize)]
+pub struct ForgotPasswordReq {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ResetPasswordReq {
+    pub token: String,
+    pub password: String,
+}
+
+/// Emails a password reset link. Responds the same whether or not an
+/// account has the email, and before the email is sent, so neither the
+/// response nor its timing gives that away.
+pub async fn forgot_password(
+    data: web::Json<ForgotPasswordReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    accounts: web::Data<AccountService>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests.into());
+    }
+
+    if !config.auth.enable_email_auth {
+        return Err(LoginError::LoginMethodDisabled.into());
+    }
+
+    let email = validate_email(&data.email)?;
+    actix_web::rt::spawn(async move {
+        if let Err(e) = accounts.forgot_password(&email).await {
+            eprintln!("failed to send password reset email: {:?}", e);
+        }
+    });
+
+    Ok(HttpResponse::Accepted().finish())
+}
+
+pub async fn reset_password(
+    data: web::Json<ResetPasswordReq>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<GuestLimiter>,
+    accounts: web::Data<AccountService>,
+    cache: web::Data<AuthCache>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = guest_limiter(&req, limiter, config.rate_limiter.auth_requests_per_hour).await;
+
+    if !limit {
+        return Err(LoginError::TooManyRequests.into());
+    }
+
+    if !config.auth.enable_email_auth {
+        return Err(LoginError::LoginMethodDisabled.into());
+    }
+
+    let reset = accounts.reset_password(&data.token, &data.password).await?;
+    cache
+        .invalidate_user(&reset.user.uuid, &reset.revoked_sessions)
+        .await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// -----------------------------------------------------------------------------
+// Sessions
+// -----------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize)]
+pub struct RefreshReq {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+impl ResponseError for SessionError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SessionError::InvalidRefreshToken => HttpResponse::Unauthorized().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            SessionError::Internal(e) => {
+                eprintln!("session request failed: {:?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Internal server error".to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Swaps a refresh token for new tokens. Refresh tokens work once, and
+/// replaying one ends the session it was issued for.
+pub async fn refresh(
+    data: web::Json<RefreshReq>,
+    req: HttpRequest,
+    sessions: web::Data<SessionService>,
+) -> Result<HttpResponse, SessionError> {
+    let tokens = sessions
+        .refresh(&data.refresh_token, &hashed_ip(&req))
+        .await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Ends the session the request was made with.
+pub async fn logout(
+    auth: AuthenticatedUser,
+    repo: web::Data<SessionRepository>,
+    cache: web::Data<AuthCache>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let session_uuid = match &auth.session_uuid {
+        Some(session_uuid) => session_uuid,
+        None => return HttpResponse::NoContent().finish(),
+    };
+
+    match repo.revoke(user_uuid, session_uuid).await {
+        Ok(_) => {
+            cache.invalidate_session(session_uuid).await;
+            HttpResponse::NoContent().finish()
+        }
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to log out".to_string(),
+        }),
+    }
+}
+
+pub async fn get_sessions(
+    auth: AuthenticatedUser,
+    repo: web::Data<SessionRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.get_active_by_user(user_uuid).await {
+        Ok(sessions) => {
+            let sessions: Vec<SessionResponse> = sessions
+                .into_iter()
+                .map(|session| SessionResponse {
+                    current: auth.session_uuid.as_ref() == Some(&session.uuid),
+                    session,
+                })
+                .collect();
+            HttpResponse::Ok().json(DataResponse { data: sessions })
+        }
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get sessions".to_string(),
+        }),
+    }
+}
+
+pub async fn delete_session(
+    auth: AuthenticatedUser,
+    session_uuid: web::Path<String>,
+    repo: web::Data<SessionRepository>,
+    cache: web::Data<AuthCache>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.revoke(user_uuid, &session_uuid).await {
+        Ok(true) => {
+            cache.invalidate_session(&session_uuid).await;
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Session not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to revoke session".to_string(),
+        }),
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -143,7 +757,25 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/auth")
             .route("/email", web::post().to(email))
+            .route("/email/mfa", web::post().to(email_mfa))
+            .route("/totp", web::post().to(enroll_totp))
+            .route("/totp/confirm", web::post().to(confirm_totp))
+            .route("/totp/disable", web::post().to(disable_totp))
+            .route("/register", web::post().to(register))
+            .route("/verify-email", web::post().to(verify_email))
+            .route(
+                "/verify-email/resend",
+                web::post().to(resend_verification_email),
+            )
+            .route("/password/forgot", web::post().to(forgot_password))
+            .route("/password/reset", web::post().to(reset_password))
+            .route("/refresh", web::post().to(refresh))
+            .route("/logout", web::post().to(logout))
+            .route("/sessions", web::get().to(get_sessions))
+            .route("/sessions/{session_uuid}", web::delete().to(delete_session))
             .route("/nostr", web::post().to(nostr))
-            .route("/identifier", web::post().to(identifier)),
+            .route("/nostr/challenge", web::post().to(nostr_challenge))
+            .route("/identifier", web::post().to(identifier))
+            .route("/identifier/{k1}", web::post().to(identifier_status)),
     );
 }
@@ -0,0 +1,94 @@
+use crate::helpers::format::{DataResponse, ErrorResponse};
+use crate::helpers::pagination::{page_size, Cursor, PaginatedResponse};
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::models::ledger::LedgerAccountBalance;
+use crate::repositories::ledger_repository::LedgerRepository;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Serialize)]
+pub struct Balance {
+    /// Sum of the user's own account and all of their store accounts, in sats.
+    pub total: i64,
+    pub accounts: Vec<LedgerAccountBalance>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListBalanceTransactionsQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn get_balance(
+    auth: AuthenticatedUser,
+    repo: web::Data<LedgerRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.get_balances(user_uuid).await {
+        Ok(accounts) => HttpResponse::Ok().json(DataResponse {
+            data: Balance {
+                total: accounts.iter().map(|account| account.balance).sum(),
+                accounts,
+            },
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get balance".to_string(),
+        }),
+    }
+}
+
+pub async fn get_balance_transactions(
+    auth: AuthenticatedUser,
+    query: web::Query<ListBalanceTransactionsQuery>,
+    repo: web::Data<LedgerRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let cursor = match &query.cursor {
+        Some(cursor) => match Cursor::decode(cursor) {
+            Ok(cursor) => Some(cursor),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "Invalid cursor".to_string(),
+                })
+            }
+        },
+        None => None,
+    };
+    let limit = page_size(query.limit);
+
+    // One extra row tells us whether there is another page.
+    match repo
+        .get_user_entries(user_uuid, cursor.as_ref(), limit + 1)
+        .await
+    {
+        Ok(mut entries) => {
+            let next_cursor = if entries.len() as i64 > limit {
+                entries.truncate(limit as usize);
+                entries
+                    .last()
+                    .map(|entry| Cursor::new(entry.created_at, &entry.uuid).encode())
+            } else {
+                None
+            };
+
+            HttpResponse::Ok().json(PaginatedResponse {
+                data: entries,
+                next_cursor,
+            })
+        }
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get balance transactions".to_string(),
+        }),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/balance")
+            .route("", web::get().to(get_balance))
+            .route("/transactions", web::get().to(get_balance_transactions)),
+    );
+}
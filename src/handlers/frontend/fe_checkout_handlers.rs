@@ -0,0 +1,376 @@
+use std::time::Duration;
+
+use crate::config::AppConfig;
+use crate::helpers::format::{DataResponse, ErrorResponse};
+use crate::middleware::limiter_middleware::{checkout_limiter, CheckoutLimiter};
+use crate::models::checkout::{Checkout, CheckoutStatus};
+use crate::repositories::checkout_repository::CheckoutRepository;
+use crate::services::checkout_service::QrCodeCache;
+use crate::services::event_bus::{CheckoutEvent, EventBus};
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use futures::stream;
+use serde::Serialize;
+use tokio::{
+    sync::broadcast::{error::RecvError, Receiver},
+    time::Instant,
+};
+
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Streams are closed after this long, so an idle client can't hold one
+/// forever. Browsers reconnect on their own, which counts against the
+/// checkout rate limit.
+const SSE_MAX_LIFETIME: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Serialize)]
+pub struct PublicCheckout {
+    pub uuid: String,
+    pub amount: i64,
+    pub amount_received: i64,
+    pub status: CheckoutStatus,
+    pub payment_request: String,
+    pub bitcoin_address: String,
+    pub qr_unified: String,
+    pub qr_bitcoin: String,
+    pub qr_ln: String,
+    pub seconds_remaining: i64,
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckoutStatusEvent {
+    pub uuid: String,
+    pub status: CheckoutStatus,
+    pub amount_received: i64,
+    pub seconds_remaining: i64,
+}
+
+impl From<&Checkout> for CheckoutStatusEvent {
+    fn from(checkout: &Checkout) -> Self {
+        Self {
+            uuid: checkout.uuid.clone(),
+            status: checkout.status.clone(),
+            amount_received: checkout.amount_received,
+            seconds_remaining: seconds_remaining(checkout),
+        }
+    }
+}
+
+fn expires_at(checkout: &Checkout) -> chrono::NaiveDateTime {
+    checkout.created_at + chrono::Duration::seconds(checkout.expiry_seconds)
+}
+
+fn seconds_remaining(checkout: &Checkout) -> i64 {
+    if checkout.status != CheckoutStatus::New {
+        return 0;
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    (expires_at(checkout) - now).num_seconds().max(0)
+}
+
+/// Statuses after which no further status events will be sent.
+fn is_final(status: &CheckoutStatus) -> bool {
+    matches!(
+        status,
+        CheckoutStatus::Paid | CheckoutStatus::Overpaid | CheckoutStatus::Expired
+    )
+}
+
+fn sse_message(event: &str, data: &CheckoutStatusEvent) -> web::Bytes {
+    let data = serde_json::to_string(data).unwrap();
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+pub async fn get_checkout(
+    checkout_uuid: web::Path<String>,
+    req: HttpRequest,
+    repo: web::Data<CheckoutRepository>,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<CheckoutLimiter>,
+    qr_cache: web::Data<QrCodeCache>,
+) -> impl Responder {
+    let limit = checkout_limiter(
+        &req,
+        limiter,
+        config.rate_limiter.checkout_requests_per_minute,
+    )
+    .await;
+
+    if !limit {
+        return HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many requests".to_string(),
+        });
+    }
+
+    let checkout = match repo.get_by_uuid(checkout_uuid.into_inner()).await {
+        Ok(checkout) if checkout.deleted_at.is_none() => checkout,
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Checkout not found".to_string(),
+            })
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to get checkout".to_string(),
+            })
+        }
+    };
+
+    match qr_cache.get(&checkout).await {
+        Ok((qr_unified, qr_bitcoin, qr_ln)) => HttpResponse::Ok().json(DataResponse {
+            data: PublicCheckout {
+                seconds_remaining: seconds_remaining(&checkout),
+                expires_at: expires_at(&checkout),
+                uuid: checkout.uuid,
+                amount: checkout.amount,
+                amount_received: checkout.amount_received,
+                status: checkout.status,
+                payment_request: checkout.payment_request,
+                bitcoin_address: checkout.bitcoin_address,
+                qr_unified,
+                qr_bitcoin,
+                qr_ln,
+                created_at: checkout.created_at,
+            },
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    }
+}
+
+struct CheckoutStream {
+    receiver: Receiver<CheckoutEvent>,
+    repo: CheckoutRepository,
+    checkout: Checkout,
+    pending: Option<web::Bytes>,
+    done: bool,
+    closes_at: Instant,
+}
+
+pub async fn checkout_events(
+    checkout_uuid: web::Path<String>,
+    req: HttpRequest,
+    repo: web::Data<CheckoutRepository>,
+    events: web::Data<EventBus>,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<CheckoutLimiter>,
+) -> impl Responder {
+    let limit = checkout_limiter(
+        &req,
+        limiter,
+        config.rate_limiter.checkout_requests_per_minute,
+    )
+    .await;
+
+    if !limit {
+        return HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many requests".to_string(),
+        });
+    }
+
+    // Subscribe before loading so a change in between isn't missed.
+    let receiver = events.subscribe();
+
+    let checkout = match repo.get_by_uuid(checkout_uuid.into_inner()).await {
+        Ok(checkout) if checkout.deleted_at.is_none() => checkout,
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Checkout not found".to_string(),
+            })
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to get checkout".to_string(),
+            })
+        }
+    };
+
+    let state = CheckoutStream {
+        receiver,
+        repo: repo.get_ref().clone(),
+        pending: Some(sse_message("status", &CheckoutStatusEvent::from(&checkout))),
+        done: is_final(&checkout.status),
+        closes_at: Instant::now() + SSE_MAX_LIFETIME,
+        checkout,
+    };
+
+    let stream = stream::unfold(state, |mut state| async move {
+        if let Some(message) = state.pending.take() {
+            return Some((Ok::<_, actix_web::Error>(message), state));
+        }
+
+        if state.done {
+            return None;
+        }
+
+        loop {
+            tokio::select! {
+                event = state.receiver.recv() => match event {
+                    Ok(event) if event.checkout.uuid == state.checkout.uuid => {
+                        state.done = is_final(&event.checkout.status);
+                        let message = sse_message(
+                            &event.kind.to_string(),
+                            &CheckoutStatusEvent::from(&event.checkout),
+                        );
+                        state.checkout = event.checkout;
+                        return Some((Ok(message), state));
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(_)) => {
+                        // Events were dropped; fall back to the stored state.
+                        let checkout = match state.repo.get_by_uuid(state.checkout.uuid.clone()).await {
+                            Ok(checkout) => checkout,
+                            Err(_) => return None,
+                        };
+                        if checkout.status == state.checkout.status
+                            && checkout.amount_received == state.checkout.amount_received
+                        {
+                            continue;
+                        }
+                        state.done = is_final(&checkout.status);
+                        let message = sse_message("status", &CheckoutStatusEvent::from(&checkout));
+                        state.checkout = checkout;
+                        return Some((Ok(message), state));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = tokio::time::sleep(SSE_KEEP_ALIVE) => {
+                    return Some((Ok(web::Bytes::from_static(b": keep-alive\n\n")), state));
+                }
+                _ = tokio::time::sleep_until(state.closes_at) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, "text/event-stream"))
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(stream)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/checkouts")
+            .route("/{checkout_uuid}", web::get().to(get_checkout))
+            .route("/{checkout_uuid}/events", web::get().to(checkout_events)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::read_to_string, sync::Arc, time::Duration};
+
+    use actix_web::{http::StatusCode, test, web::Data, App};
+    use moka::future::Cache;
+
+    use super::configure_routes;
+    use crate::{
+        config::AppConfig,
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        middleware::limiter_middleware::CheckoutLimiter,
+        repositories::checkout_repository::{CheckoutRepository, CreateCheckout},
+        services::{checkout_service::QrCodeCache, event_bus::EventBus},
+    };
+
+    #[actix_web::test]
+    async fn test_get_checkout() {
+        let config = AppConfig::from(
+            toml::from_str::<toml::Value>(&read_to_string("Nodeless.toml").unwrap()).unwrap(),
+        );
+        let limit = config.rate_limiter.checkout_requests_per_minute;
+        let repo = CheckoutRepository::new(create_test_pool().await);
+        let user = create_test_user().await.unwrap();
+        let checkout = repo
+            .create(CreateCheckout {
+                user_uuid: user.uuid.clone(),
+                amount: 1000,
+                bitcoin_address: "test address".to_string(),
+                payment_request: "test payment request".to_string(),
+                payment_hash: None,
+                expiry_seconds: 3600,
+                fee: None,
+            })
+            .await
+            .unwrap();
+
+        let qr_cache = QrCodeCache::new(100, Duration::from_secs(60));
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(repo))
+                .app_data(Data::new(config))
+                .app_data(Data::new(CheckoutLimiter {
+                    cache: Arc::new(Cache::new(100)),
+                }))
+                .app_data(Data::new(qr_cache.clone()))
+                .configure(configure_routes),
+        )
+        .await;
+        let get = |uuid: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/checkouts/{}", uuid))
+                .peer_addr("203.0.113.7:1234".parse().unwrap())
+                .to_request()
+        };
+
+        let first: serde_json::Value =
+            test::call_and_read_body_json(&app, get(&checkout.uuid)).await;
+        assert_eq!(first["data"]["uuid"], checkout.uuid.as_str());
+        assert!(first["data"]["qr_unified"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/png;base64,"));
+        // The QR codes are rendered once and served from the cache after.
+        assert!(qr_cache.contains(&checkout.uuid));
+        let second: serde_json::Value =
+            test::call_and_read_body_json(&app, get(&checkout.uuid)).await;
+        assert_eq!(second["data"]["qr_ln"], first["data"]["qr_ln"]);
+
+        let missing = test::call_service(&app, get(&uuid::Uuid::new_v4().to_string())).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        // Each address gets a few requests a minute.
+        for _ in 3..limit {
+            test::call_service(&app, get(&checkout.uuid)).await;
+        }
+        let limited = test::call_service(&app, get(&checkout.uuid)).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        delete_test_user(&user.uuid).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_checkout_events_are_rate_limited() {
+        let config = AppConfig::from(
+            toml::from_str::<toml::Value>(&read_to_string("Nodeless.toml").unwrap()).unwrap(),
+        );
+        let limit = config.rate_limiter.checkout_requests_per_minute;
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(CheckoutRepository::new(create_test_pool().await)))
+                .app_data(Data::new(EventBus::new(16)))
+                .app_data(Data::new(config))
+                .app_data(Data::new(CheckoutLimiter {
+                    cache: Arc::new(Cache::new(100)),
+                }))
+                .configure(configure_routes),
+        )
+        .await;
+        let events = || {
+            test::TestRequest::get()
+                .uri(&format!("/checkouts/{}/events", uuid::Uuid::new_v4()))
+                .peer_addr("203.0.113.8:1234".parse().unwrap())
+                .to_request()
+        };
+
+        for _ in 0..limit {
+            let missing = test::call_service(&app, events()).await;
+            assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+        }
+        let limited = test::call_service(&app, events()).await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::helpers::format::{DataResponse, ErrorResponse};
-use crate::middleware::jwt_middleware::AuthorizationService;
+use crate::middleware::jwt_middleware::AuthenticatedUser;
 use crate::models::donation_page;
 use crate::repositories::donation_page_repository::{
     CreateDonationPage, DonationPageRepository, RepoError, UpdateDonationPage,
@@ -22,14 +22,11 @@ pub struct UpdateDonationPageReq {
 }
 
 pub async fn create_donation_page(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     form: web::Json<CreateDonationPageReq>,
     repo: web::Data<DonationPageRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
 
     let donation_page = CreateDonationPage {
         user_uuid: user_uuid.to_string(),
@@ -52,14 +49,11 @@ pub async fn create_donation_page(
 }
 
 pub async fn get_all_donation_pages(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     repo: web::Data<DonationPageRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
-    match repo.get_all_by_user_uuid(&user_uuid).await {
+    let user_uuid = auth.uuid();
+    match repo.get_all_by_user_uuid(user_uuid).await {
         Ok(donation_pages) => HttpResponse::Ok().json(DataResponse {
             data: donation_pages,
         }),
@@ -70,16 +64,13 @@ pub async fn get_all_donation_pages(
 }
 
 pub async fn get_one_by_uuid(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     donation_page_uuid: web::Path<String>,
     repo: web::Data<DonationPageRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo
-        .get_one_by_user_uuid(&user_uuid, &donation_page_uuid)
+        .get_one_by_user_uuid(user_uuid, &donation_page_uuid)
         .await
     {
         Ok(donation_page) => HttpResponse::Ok().json(DataResponse {
@@ -92,15 +83,12 @@ pub async fn get_one_by_uuid(
 }
 
 pub async fn update_donation_page(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     donation_page_uuid: web::Path<String>,
     form: web::Json<UpdateDonationPageReq>,
     repo: web::Data<DonationPageRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
 
     let update_donation_page = UpdateDonationPage {
         slug: form.slug.clone(),
@@ -125,14 +113,11 @@ pub async fn update_donation_page(
 }
 
 pub async fn delete_donation_page(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     donation_page_uuid: web::Path<String>,
     repo: web::Data<DonationPageRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo.delete_by_user(&donation_page_uuid, &user_uuid).await {
         Ok(true) => HttpResponse::NoContent().finish(),
         Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
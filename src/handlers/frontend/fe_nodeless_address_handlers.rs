@@ -0,0 +1,166 @@
+use crate::helpers::format::{DataResponse, ErrorResponse};
+use crate::lightning::LightningBackend;
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::repositories::nodeless_address_repository::NodelessAddressRepository;
+use crate::services::checkout_service::CheckoutService;
+use crate::services::event_bus::{CheckoutEventKind, EventBus};
+use crate::services::fee_service::FeeService;
+use crate::services::nodeless_address_service::{NodelessAddressError, NodelessAddressService};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct PurchaseNodelessAddressReq {
+    pub handle: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNodelessAddressReq {
+    /// Clears the npub when null.
+    pub npub: Option<String>,
+}
+
+impl ResponseError for NodelessAddressError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            NodelessAddressError::Internal(e) => {
+                eprintln!("nodeless address request failed: {:?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Internal server error".to_string(),
+                })
+            }
+            NodelessAddressError::NotFound => HttpResponse::NotFound().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            NodelessAddressError::Unavailable
+            | NodelessAddressError::NpubTaken
+            | NodelessAddressError::NotActive => HttpResponse::Conflict().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            NodelessAddressError::TooManyPending => {
+                HttpResponse::TooManyRequests().json(ErrorResponse {
+                    error: self.to_string(),
+                })
+            }
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+        }
+    }
+}
+
+pub async fn get_handle_availability(
+    _auth: AuthenticatedUser,
+    handle: web::Path<String>,
+    addresses: web::Data<NodelessAddressService>,
+) -> impl Responder {
+    match addresses.availability(&handle).await {
+        Ok(availability) => HttpResponse::Ok().json(DataResponse { data: availability }),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn purchase_nodeless_address(
+    auth: AuthenticatedUser,
+    data: web::Json<PurchaseNodelessAddressReq>,
+    addresses: web::Data<NodelessAddressService>,
+    events: web::Data<EventBus>,
+    lightning: web::Data<dyn LightningBackend>,
+    fees: web::Data<FeeService>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let purchase = addresses
+        .purchase(
+            user_uuid,
+            &data.handle,
+            CheckoutService::new(lightning.into_inner(), fees.get_ref().clone()),
+        )
+        .await;
+
+    match purchase {
+        Ok(purchase) => {
+            events.publish(CheckoutEventKind::Created, purchase.checkout.clone());
+            HttpResponse::Created().json(DataResponse { data: purchase })
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn get_nodeless_addresses(
+    auth: AuthenticatedUser,
+    repo: web::Data<NodelessAddressRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.get_all_by_user(user_uuid).await {
+        Ok(addresses) => HttpResponse::Ok().json(DataResponse { data: addresses }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get nodeless addresses".to_string(),
+        }),
+    }
+}
+
+pub async fn get_nodeless_address(
+    auth: AuthenticatedUser,
+    address_uuid: web::Path<String>,
+    repo: web::Data<NodelessAddressRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.get_by_user_and_uuid(user_uuid, &address_uuid).await {
+        Ok(Some(address)) => HttpResponse::Ok().json(DataResponse { data: address }),
+        Ok(None) => NodelessAddressError::NotFound.error_response(),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get nodeless address".to_string(),
+        }),
+    }
+}
+
+pub async fn update_nodeless_address(
+    auth: AuthenticatedUser,
+    address_uuid: web::Path<String>,
+    data: web::Json<UpdateNodelessAddressReq>,
+    addresses: web::Data<NodelessAddressService>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match addresses
+        .update_npub(user_uuid, &address_uuid, data.into_inner().npub)
+        .await
+    {
+        Ok(address) => HttpResponse::Ok().json(DataResponse { data: address }),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn delete_nodeless_address(
+    auth: AuthenticatedUser,
+    address_uuid: web::Path<String>,
+    repo: web::Data<NodelessAddressRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.delete_by_user(user_uuid, &address_uuid).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => NodelessAddressError::NotFound.error_response(),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to delete nodeless address".to_string(),
+        }),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/nodeless-addresses")
+            .route("", web::get().to(get_nodeless_addresses))
+            .route("", web::post().to(purchase_nodeless_address))
+            .route(
+                "/availability/{handle}",
+                web::get().to(get_handle_availability),
+            )
+            .route("/{address_uuid}", web::get().to(get_nodeless_address))
+            .route("/{address_uuid}", web::put().to(update_nodeless_address))
+            .route("/{address_uuid}", web::delete().to(delete_nodeless_address)),
+    );
+}
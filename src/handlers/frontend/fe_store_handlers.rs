@@ -1,11 +1,19 @@
 use crate::helpers::format::{DataResponse, ErrorResponse};
-use crate::init_cluster;
-use crate::middleware::jwt_middleware::AuthorizationService;
+use crate::helpers::pagination::{page_size, Cursor, PaginatedResponse, SortOrder};
+use crate::lightning::LightningBackend;
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::models::checkout::CheckoutStatus;
 use crate::repositories::checkout_repository::CheckoutRepository;
-use crate::repositories::store_repository::{StoreInvoiceRepository, StoreRepository};
+use crate::repositories::store_repository::{
+    StoreInvoiceFilter, StoreInvoiceRepository, StoreRepository,
+};
 use crate::services::checkout_service::{CheckoutService, CreateCheckoutService};
+use crate::services::event_bus::{CheckoutEventKind, EventBus};
+use crate::services::fee_service::FeeService;
 use crate::services::store_service::StoreService;
 use actix_web::{web, HttpResponse, Responder};
+
+use super::{fe_api_key_handlers, fe_webhook_handlers};
 use serde_derive::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -26,15 +34,75 @@ pub struct CreateStoreInvoice {
     pub metadata: Option<serde_json::Value>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ListStoreInvoicesQuery {
+    pub status: Option<CheckoutStatus>,
+    pub from: Option<chrono::NaiveDateTime>,
+    pub to: Option<chrono::NaiveDateTime>,
+    /// JSON object the invoice metadata must contain, e.g. `{"order_id":"1234"}`.
+    pub metadata: Option<String>,
+    pub sort: Option<SortOrder>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ListStoreInvoicesQuery {
+    pub fn to_filter(&self) -> Result<StoreInvoiceFilter, String> {
+        let metadata = match &self.metadata {
+            Some(metadata) => match serde_json::from_str::<serde_json::Value>(metadata) {
+                Ok(value) if value.is_object() => Some(value),
+                _ => return Err("Metadata filter must be a JSON object".to_string()),
+            },
+            None => None,
+        };
+
+        let cursor = match &self.cursor {
+            Some(cursor) => Some(Cursor::decode(cursor).map_err(|_| "Invalid cursor".to_string())?),
+            None => None,
+        };
+
+        Ok(StoreInvoiceFilter {
+            status: self.status.clone(),
+            from: self.from,
+            to: self.to,
+            metadata,
+            sort: self.sort.unwrap_or_default(),
+            cursor,
+            limit: page_size(self.limit),
+        })
+    }
+}
+
+/// Makes sure the store exists and belongs to the authenticated user.
+pub async fn authorize_store(
+    auth: &AuthenticatedUser,
+    store_uuid: &str,
+    store_repo: &StoreRepository,
+) -> Result<(), HttpResponse> {
+    match store_repo.get_by_uuid(auth.uuid(), store_uuid).await {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(HttpResponse::NotFound().json(ErrorResponse {
+            error: "Store not found".to_string(),
+        })),
+        Err(_) => Err(HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get store".to_string(),
+        })),
+    }
+}
+
 pub async fn create_store(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     form: web::Json<CreateStoreReq>,
     repo: web::Data<StoreRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
+
+    if !auth.user.is_verified() {
+        return HttpResponse::Forbidden().json(ErrorResponse {
+            error: "Verify your email before creating a store".to_string(),
+        });
+    }
+
     match repo.create(&user_uuid, &form.name).await {
         Ok(store) => HttpResponse::Created().json(DataResponse { data: store }),
         Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
@@ -44,13 +112,10 @@ pub async fn create_store(
 }
 
 pub async fn get_all_stores(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     repo: web::Data<StoreRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo.get_all(&user_uuid).await {
         Ok(stores) => HttpResponse::Ok().json(DataResponse { data: stores }),
         Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
@@ -60,14 +125,11 @@ pub async fn get_all_stores(
 }
 
 pub async fn get_store_by_uuid(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     store_uuid: web::Path<String>,
     repo: web::Data<StoreRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo.get_by_uuid(&user_uuid, &store_uuid).await {
         Ok(Some(store)) => HttpResponse::Ok().json(DataResponse { data: store }),
         Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
@@ -80,15 +142,12 @@ pub async fn get_store_by_uuid(
 }
 
 pub async fn update_store(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     store_uuid: web::Path<String>,
     form: web::Json<UpdateStoreReq>,
     repo: web::Data<StoreRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo.update(&user_uuid, &store_uuid, &form.name).await {
         Ok(Some(updated_store)) => HttpResponse::Ok().json(DataResponse {
             data: updated_store,
@@ -103,14 +162,11 @@ pub async fn update_store(
 }
 
 pub async fn delete_store(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     store_uuid: web::Path<String>,
     repo: web::Data<StoreRepository>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    let user_uuid = auth.uuid();
     match repo.delete(&user_uuid, &store_uuid).await {
         Ok(true) => HttpResponse::NoContent().finish(),
         Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
@@ -122,18 +178,23 @@ pub async fn delete_store(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_store_invoice(
-    auth: AuthorizationService,
+    auth: AuthenticatedUser,
     data: web::Json<CreateStoreInvoice>,
     store_uuid: web::Path<String>,
     store_repo: web::Data<StoreRepository>,
     checkout_repo: web::Data<CheckoutRepository>,
     store_invoice_repo: web::Data<StoreInvoiceRepository>,
+    events: web::Data<EventBus>,
+    lightning: web::Data<dyn LightningBackend>,
+    fees: web::Data<FeeService>,
 ) -> impl Responder {
-    let user_uuid = auth
-        .uuid()
-        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User UUID not found"))
-        .unwrap();
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    let user_uuid = auth.uuid();
 
     let service = StoreService::new(
         store_repo.get_ref().clone(),
@@ -150,22 +211,92 @@ pub async fn create_store_invoice(
                 amount: data.amount,
                 expiry: data.expiry,
                 memo: data.memo.clone(),
+                description_hash: None,
             },
-            CheckoutService::new(init_cluster().await),
+            CheckoutService::new(lightning.into_inner(), fees.get_ref().clone()),
         )
         .await;
 
     match invoice {
-        Ok(invoice) => HttpResponse::Created().json(DataResponse { data: invoice }),
+        Ok(invoice) => {
+            events.publish(CheckoutEventKind::Created, invoice.checkout.clone());
+            HttpResponse::Created().json(DataResponse { data: invoice })
+        }
         Err(e) => HttpResponse::InternalServerError().json(ErrorResponse {
             error: e.to_string(),
         }),
     }
 }
 
+pub async fn get_store_invoices(
+    auth: AuthenticatedUser,
+    store_uuid: web::Path<String>,
+    query: web::Query<ListStoreInvoicesQuery>,
+    store_repo: web::Data<StoreRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    store_invoice_repo: web::Data<StoreInvoiceRepository>,
+) -> impl Responder {
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    let filter = match query.to_filter() {
+        Ok(filter) => filter,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse { error }),
+    };
+
+    let service = StoreService::new(
+        store_repo.get_ref().clone(),
+        checkout_repo.get_ref().clone(),
+        store_invoice_repo.get_ref().clone(),
+    );
+
+    match service.list_invoices(&store_uuid, filter).await {
+        Ok(page) => HttpResponse::Ok().json(PaginatedResponse {
+            data: page.invoices,
+            next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get invoices".to_string(),
+        }),
+    }
+}
+
+pub async fn get_store_invoice(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    store_invoice_repo: web::Data<StoreInvoiceRepository>,
+) -> impl Responder {
+    let (store_uuid, invoice_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    let service = StoreService::new(
+        store_repo.get_ref().clone(),
+        checkout_repo.get_ref().clone(),
+        store_invoice_repo.get_ref().clone(),
+    );
+
+    match service.get_invoice(&store_uuid, &invoice_uuid).await {
+        Ok(Some(invoice)) => HttpResponse::Ok().json(DataResponse { data: invoice }),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Invoice not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get invoice".to_string(),
+        }),
+    }
+}
+
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/stores")
+            .configure(fe_webhook_handlers::configure_routes)
+            .configure(fe_api_key_handlers::configure_routes)
             .route("", web::post().to(create_store))
             .route("", web::get().to(get_all_stores))
             .route("/{store_uuid}", web::get().to(get_store_by_uuid))
@@ -174,6 +305,103 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
             .route(
                 "/{store_uuid}/invoices",
                 web::post().to(create_store_invoice),
+            )
+            .route("/{store_uuid}/invoices", web::get().to(get_store_invoices))
+            .route(
+                "/{store_uuid}/invoices/{invoice_uuid}",
+                web::get().to(get_store_invoice),
             ),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use actix_web::{http::StatusCode, test, web::Data, App};
+
+    use super::configure_routes;
+    use crate::{
+        config::PricingConfig,
+        helpers::tests::{create_test_pool, create_test_signing_keys, create_test_user},
+        lightning::{fake::FakeLightningBackend, LightningBackend},
+        middleware::jwt_middleware::{generate_jwt_token, AuthCache},
+        repositories::{
+            checkout_repository::CheckoutRepository,
+            fee_repository::FeeRepository,
+            session_repository::{CreateSession, SessionRepository},
+            store_repository::{StoreInvoiceRepository, StoreRepository},
+            user_repository::UserRepository,
+        },
+        services::{event_bus::EventBus, fee_service::FeeService},
+    };
+
+    #[actix_web::test]
+    async fn test_create_invoice_on_another_users_store() {
+        let pool = create_test_pool().await;
+        let keys = create_test_signing_keys().await;
+        let sessions = SessionRepository::new(pool.clone());
+        let owner = create_test_user().await.unwrap();
+        let other = create_test_user().await.unwrap();
+        let store = StoreRepository::new(pool.clone())
+            .create(&owner.uuid, "Test Store")
+            .await
+            .unwrap();
+
+        let lightning: Arc<dyn LightningBackend> = Arc::new(FakeLightningBackend::new());
+        let pricing = PricingConfig {
+            base_fee_sat: 100,
+            fee_rate_percent: 1,
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(Data::new(UserRepository::new(pool.clone())))
+                .app_data(Data::new(sessions.clone()))
+                .app_data(Data::new(AuthCache::new(Duration::from_secs(5))))
+                .app_data(Data::new(keys.clone()))
+                .app_data(Data::new(StoreRepository::new(pool.clone())))
+                .app_data(Data::new(CheckoutRepository::new(pool.clone())))
+                .app_data(Data::new(StoreInvoiceRepository::new(pool.clone())))
+                .app_data(Data::new(EventBus::new(16)))
+                .app_data(Data::from(lightning))
+                .app_data(Data::new(FeeService::new(
+                    &pricing,
+                    FeeRepository::new(pool.clone()),
+                )))
+                .configure(configure_routes),
+        )
+        .await;
+
+        let mut tokens = Vec::new();
+        for user in [&owner, &other] {
+            let session = sessions
+                .create(CreateSession {
+                    user_uuid: user.uuid.clone(),
+                    device: None,
+                    ip_hash: "test ip hash".to_string(),
+                    refresh_token_hash: uuid::Uuid::new_v4().to_string(),
+                    expiry_seconds: 3600,
+                })
+                .await
+                .unwrap();
+            tokens.push(
+                generate_jwt_token(&keys, &user.uuid, &session.uuid, 60)
+                    .await
+                    .unwrap(),
+            );
+        }
+        let create = |token: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/stores/{}/invoices", store.uuid))
+                .insert_header(("Authorization", format!("Bearer {}", token)))
+                .set_json(serde_json::json!({"amount": 1000, "expiry": 3600}))
+                .to_request()
+        };
+
+        let rejected = test::call_service(&app, create(&tokens[1])).await;
+        assert_eq!(rejected.status(), StatusCode::NOT_FOUND);
+
+        let created = test::call_service(&app, create(&tokens[0])).await;
+        assert_eq!(created.status(), StatusCode::CREATED);
+    }
+}
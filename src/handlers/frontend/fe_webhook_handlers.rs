@@ -0,0 +1,300 @@
+use crate::config::AppConfig;
+use crate::helpers::format::{random_text, DataResponse, ErrorResponse};
+use crate::helpers::outbound::resolve_public;
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::models::webhook::{Webhook, WebhookDelivery, WebhookDeliveryAttempt};
+use crate::repositories::store_repository::StoreRepository;
+use crate::repositories::webhook_repository::{
+    CreateWebhook, UpdateWebhook, WebhookDeliveryRepository, WebhookRepository,
+};
+use crate::workers::webhook_dispatcher::WEBHOOK_EVENTS;
+use actix_web::{web, HttpResponse, Responder};
+use reqwest::Url;
+use serde::Serialize;
+use serde_derive::Deserialize;
+
+use super::fe_store_handlers::authorize_store;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookReq {
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookReq {
+    pub url: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+}
+
+/// The signing secret is only returned when the webhook is created.
+#[derive(Debug, Serialize)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookDeliveryLog {
+    #[serde(flatten)]
+    pub delivery: WebhookDelivery,
+    pub attempts: Vec<WebhookDeliveryAttempt>,
+}
+
+/// Webhook urls must point at a public host. Deliveries check this again,
+/// since the host can be repointed after it is saved.
+async fn validate_webhook(url: &str, events: &[String]) -> Result<(), String> {
+    let url = match Url::parse(url) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => url,
+        _ => return Err("Webhook url must be an http(s) url".to_string()),
+    };
+
+    let host = url
+        .host_str()
+        .map(|host| host.trim_matches(|c| c == '[' || c == ']'))
+        .ok_or_else(|| "Webhook url must have a host".to_string())?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    if resolve_public(host, port).await.is_err() {
+        return Err("Webhook url must resolve to a public address".to_string());
+    }
+
+    if events.is_empty() {
+        return Err("At least one event is required".to_string());
+    }
+
+    if let Some(event) = events
+        .iter()
+        .find(|event| !WEBHOOK_EVENTS.contains(&event.as_str()))
+    {
+        return Err(format!("Unknown webhook event: {}", event));
+    }
+
+    Ok(())
+}
+
+pub async fn create_webhook(
+    auth: AuthenticatedUser,
+    store_uuid: web::Path<String>,
+    form: web::Json<CreateWebhookReq>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+    config: web::Data<AppConfig>,
+) -> impl Responder {
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    if let Err(error) = validate_webhook(&form.url, &form.events).await {
+        return HttpResponse::BadRequest().json(ErrorResponse { error });
+    }
+
+    match repo.count(&store_uuid).await {
+        Ok(count) if count >= config.webhooks.max_webhooks_per_store as i64 => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Maximum number of webhooks reached".to_string(),
+            })
+        }
+        Ok(_) => {}
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to create webhook".to_string(),
+            })
+        }
+    }
+
+    let webhook = CreateWebhook {
+        store_uuid: store_uuid.to_string(),
+        url: form.url.clone(),
+        secret: format!("whsec_{}", random_text(32).await),
+        events: form.events.clone(),
+    };
+
+    match repo.create(webhook).await {
+        Ok(webhook) => HttpResponse::Created().json(DataResponse {
+            data: CreatedWebhook {
+                secret: webhook.secret.clone(),
+                webhook,
+            },
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to create webhook".to_string(),
+        }),
+    }
+}
+
+pub async fn get_all_webhooks(
+    auth: AuthenticatedUser,
+    store_uuid: web::Path<String>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+) -> impl Responder {
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.get_all(&store_uuid).await {
+        Ok(webhooks) => HttpResponse::Ok().json(DataResponse { data: webhooks }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get webhooks".to_string(),
+        }),
+    }
+}
+
+pub async fn get_webhook(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+) -> impl Responder {
+    let (store_uuid, webhook_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.get_by_uuid(&store_uuid, &webhook_uuid).await {
+        Ok(Some(webhook)) => HttpResponse::Ok().json(DataResponse { data: webhook }),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Webhook not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get webhook".to_string(),
+        }),
+    }
+}
+
+pub async fn update_webhook(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    form: web::Json<UpdateWebhookReq>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+) -> impl Responder {
+    let (store_uuid, webhook_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    if let Err(error) = validate_webhook(&form.url, &form.events).await {
+        return HttpResponse::BadRequest().json(ErrorResponse { error });
+    }
+
+    let data = UpdateWebhook {
+        url: form.url.clone(),
+        events: form.events.clone(),
+        enabled: form.enabled,
+    };
+
+    match repo.update(&store_uuid, &webhook_uuid, data).await {
+        Ok(Some(webhook)) => HttpResponse::Ok().json(DataResponse { data: webhook }),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Webhook not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to update webhook".to_string(),
+        }),
+    }
+}
+
+pub async fn delete_webhook(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+) -> impl Responder {
+    let (store_uuid, webhook_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.delete(&store_uuid, &webhook_uuid).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Webhook not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to delete webhook".to_string(),
+        }),
+    }
+}
+
+pub async fn get_webhook_deliveries(
+    auth: AuthenticatedUser,
+    path: web::Path<(String, String)>,
+    store_repo: web::Data<StoreRepository>,
+    repo: web::Data<WebhookRepository>,
+    delivery_repo: web::Data<WebhookDeliveryRepository>,
+) -> impl Responder {
+    let (store_uuid, webhook_uuid) = path.into_inner();
+
+    if let Err(response) = authorize_store(&auth, &store_uuid, &store_repo).await {
+        return response;
+    }
+
+    match repo.get_by_uuid(&store_uuid, &webhook_uuid).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::NotFound().json(ErrorResponse {
+                error: "Webhook not found".to_string(),
+            })
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to get webhook deliveries".to_string(),
+            })
+        }
+    }
+
+    let deliveries = match delivery_repo.get_all_by_webhook(&webhook_uuid, 100).await {
+        Ok(deliveries) => deliveries,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to get webhook deliveries".to_string(),
+            })
+        }
+    };
+
+    let delivery_uuids: Vec<String> = deliveries.iter().map(|d| d.uuid.clone()).collect();
+    let attempts = match delivery_repo.get_attempts(&delivery_uuids).await {
+        Ok(attempts) => attempts,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to get webhook deliveries".to_string(),
+            })
+        }
+    };
+
+    let log: Vec<WebhookDeliveryLog> = deliveries
+        .into_iter()
+        .map(|delivery| WebhookDeliveryLog {
+            attempts: attempts
+                .iter()
+                .filter(|attempt| attempt.delivery_uuid == delivery.uuid)
+                .cloned()
+                .collect(),
+            delivery,
+        })
+        .collect();
+
+    HttpResponse::Ok().json(DataResponse { data: log })
+}
+
+/// Registered inside the `/stores` scope.
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/{store_uuid}/webhooks")
+            .route("", web::post().to(create_webhook))
+            .route("", web::get().to(get_all_webhooks))
+            .route("/{webhook_uuid}", web::get().to(get_webhook))
+            .route("/{webhook_uuid}", web::put().to(update_webhook))
+            .route("/{webhook_uuid}", web::delete().to(delete_webhook))
+            .route(
+                "/{webhook_uuid}/deliveries",
+                web::get().to(get_webhook_deliveries),
+            ),
+    );
+}
@@ -0,0 +1,218 @@
+use crate::helpers::format::{DataResponse, ErrorResponse};
+use crate::helpers::pagination::{page_size, Cursor, PaginatedResponse};
+use crate::middleware::jwt_middleware::AuthenticatedUser;
+use crate::repositories::store_repository::StoreRepository;
+use crate::repositories::withdrawal_repository::WithdrawalRepository;
+use crate::services::withdrawal_service::{
+    CreateLightningWithdrawal, CreateOnchainWithdrawal, WithdrawalError, WithdrawalService,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use serde_derive::Deserialize;
+
+use super::fe_store_handlers::authorize_store;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(Debug, Deserialize)]
+pub struct CreateLightningWithdrawalReq {
+    /// BOLT11 invoice or lightning address.
+    pub destination: String,
+    pub amount: Option<i64>,
+    /// Withdraw from this store's balance instead of the user's own.
+    pub store_uuid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOnchainWithdrawalReq {
+    pub address: String,
+    pub amount: i64,
+    /// Withdraw from this store's balance instead of the user's own.
+    pub store_uuid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListWithdrawalsQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ResponseError for WithdrawalError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            WithdrawalError::Internal(e) => {
+                eprintln!("withdrawal failed: {:?}", e);
+                HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Failed to create withdrawal".to_string(),
+                })
+            }
+            WithdrawalError::InsufficientBalance { .. } => HttpResponse::UnprocessableEntity()
+                .json(ErrorResponse {
+                    error: self.to_string(),
+                }),
+            WithdrawalError::AlreadyPaying => HttpResponse::Conflict().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+            _ => HttpResponse::BadRequest().json(ErrorResponse {
+                error: self.to_string(),
+            }),
+        }
+    }
+}
+
+fn idempotency_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim())
+        .filter(|key| !key.is_empty() && key.len() <= 255)
+        .map(|key| key.to_string())
+}
+
+pub async fn create_lightning_withdrawal(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    form: web::Json<CreateLightningWithdrawalReq>,
+    store_repo: web::Data<StoreRepository>,
+    withdrawals: web::Data<WithdrawalService>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let idempotency_key = match idempotency_key(&req) {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("{} header is required", IDEMPOTENCY_KEY_HEADER),
+            })
+        }
+    };
+
+    if let Some(store_uuid) = &form.store_uuid {
+        if let Err(response) = authorize_store(&auth, store_uuid, &store_repo).await {
+            return response;
+        }
+    }
+
+    let form = form.into_inner();
+    let request = CreateLightningWithdrawal {
+        user_uuid: user_uuid.to_string(),
+        store_uuid: form.store_uuid,
+        destination: form.destination,
+        amount: form.amount,
+        idempotency_key,
+    };
+
+    match withdrawals.withdraw_lightning(request).await {
+        Ok(withdrawal) => HttpResponse::Ok().json(DataResponse { data: withdrawal }),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn create_onchain_withdrawal(
+    req: HttpRequest,
+    auth: AuthenticatedUser,
+    form: web::Json<CreateOnchainWithdrawalReq>,
+    store_repo: web::Data<StoreRepository>,
+    withdrawals: web::Data<WithdrawalService>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let idempotency_key = match idempotency_key(&req) {
+        Some(key) => key,
+        None => {
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("{} header is required", IDEMPOTENCY_KEY_HEADER),
+            })
+        }
+    };
+
+    if let Some(store_uuid) = &form.store_uuid {
+        if let Err(response) = authorize_store(&auth, store_uuid, &store_repo).await {
+            return response;
+        }
+    }
+
+    let form = form.into_inner();
+    let request = CreateOnchainWithdrawal {
+        user_uuid: user_uuid.to_string(),
+        store_uuid: form.store_uuid,
+        address: form.address,
+        amount: form.amount,
+        idempotency_key,
+    };
+
+    match withdrawals.withdraw_onchain(request).await {
+        Ok(withdrawal) => HttpResponse::Ok().json(DataResponse { data: withdrawal }),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn get_withdrawals(
+    auth: AuthenticatedUser,
+    query: web::Query<ListWithdrawalsQuery>,
+    repo: web::Data<WithdrawalRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    let cursor = match &query.cursor {
+        Some(cursor) => match Cursor::decode(cursor) {
+            Ok(cursor) => Some(cursor),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "Invalid cursor".to_string(),
+                })
+            }
+        },
+        None => None,
+    };
+    let limit = page_size(query.limit);
+
+    // One extra row tells us whether there is another page.
+    match repo.get_all(user_uuid, cursor.as_ref(), limit + 1).await {
+        Ok(mut withdrawals) => {
+            let next_cursor = if withdrawals.len() as i64 > limit {
+                withdrawals.truncate(limit as usize);
+                withdrawals
+                    .last()
+                    .map(|withdrawal| Cursor::new(withdrawal.created_at, &withdrawal.uuid).encode())
+            } else {
+                None
+            };
+
+            HttpResponse::Ok().json(PaginatedResponse {
+                data: withdrawals,
+                next_cursor,
+            })
+        }
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get withdrawals".to_string(),
+        }),
+    }
+}
+
+pub async fn get_withdrawal(
+    auth: AuthenticatedUser,
+    withdrawal_uuid: web::Path<String>,
+    repo: web::Data<WithdrawalRepository>,
+) -> impl Responder {
+    let user_uuid = auth.uuid();
+
+    match repo.get_by_uuid(user_uuid, &withdrawal_uuid).await {
+        Ok(Some(withdrawal)) => HttpResponse::Ok().json(DataResponse { data: withdrawal }),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Withdrawal not found".to_string(),
+        }),
+        Err(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: "Failed to get withdrawal".to_string(),
+        }),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/withdrawals")
+            .route("", web::get().to(get_withdrawals))
+            .route("/lightning", web::post().to(create_lightning_withdrawal))
+            .route("/onchain", web::post().to(create_onchain_withdrawal))
+            .route("/{withdrawal_uuid}", web::get().to(get_withdrawal)),
+    );
+}
@@ -1,3 +1,9 @@
+pub mod fe_api_key_handlers;
 pub mod fe_auth_handlers;
+pub mod fe_balance_handlers;
+pub mod fe_checkout_handlers;
 pub mod fe_donation_page_handlers;
+pub mod fe_nodeless_address_handlers;
 pub mod fe_store_handlers;
+pub mod fe_webhook_handlers;
+pub mod fe_withdrawal_handlers;
@@ -0,0 +1,4 @@
+pub mod public_jwks_handlers;
+pub mod public_lnurl_auth_handlers;
+pub mod public_lnurl_handlers;
+pub mod public_nostr_handlers;
@@ -0,0 +1,17 @@
+use crate::services::signing_key_service::{SigningKeyService, JWKS_MAX_AGE};
+use actix_web::{web, HttpResponse, Responder};
+
+/// Public keys access tokens can be verified with, for other services.
+pub async fn get_jwks(keys: web::Data<SigningKeyService>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(("Access-Control-Allow-Origin", "*"))
+        .insert_header((
+            "Cache-Control",
+            format!("public, max-age={}", JWKS_MAX_AGE.as_secs()),
+        ))
+        .json(keys.jwks().await)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/.well-known/jwks.json", web::get().to(get_jwks));
+}
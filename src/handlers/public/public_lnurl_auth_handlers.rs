@@ -0,0 +1,51 @@
+use crate::config::AppConfig;
+use crate::services::lnurl_auth_service::{LnurlAuthError, LnurlAuthService};
+use crate::services::lnurl_service::LnurlErrorResponse;
+use actix_web::{web, HttpResponse, Responder};
+use serde_derive::Deserialize;
+
+/// LUD-04 callback parameters, added by the wallet to the LNURL.
+#[derive(Debug, Deserialize)]
+pub struct AuthCallbackQuery {
+    pub k1: String,
+    pub sig: String,
+    pub key: String,
+}
+
+fn error_response(e: LnurlAuthError) -> HttpResponse {
+    match e {
+        LnurlAuthError::Internal(e) => {
+            eprintln!("LNURL-auth callback failed: {}", e);
+            HttpResponse::InternalServerError().json(LnurlErrorResponse {
+                status: "ERROR",
+                reason: "Failed to log in".to_string(),
+            })
+        }
+        e => HttpResponse::BadRequest().json(LnurlErrorResponse {
+            status: "ERROR",
+            reason: e.to_string(),
+        }),
+    }
+}
+
+pub async fn auth_callback(
+    query: web::Query<AuthCallbackQuery>,
+    config: web::Data<AppConfig>,
+    lnurl_auth: web::Data<LnurlAuthService>,
+) -> impl Responder {
+    if !config.auth.enable_identifier_auth {
+        return HttpResponse::BadRequest().json(LnurlErrorResponse {
+            status: "ERROR",
+            reason: "Login method is disabled.".to_string(),
+        });
+    }
+
+    match lnurl_auth.callback(&query.k1, &query.sig, &query.key).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({ "status": "OK" })),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/lnurl-auth/callback", web::get().to(auth_callback));
+}
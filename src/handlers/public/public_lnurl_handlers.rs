@@ -0,0 +1,128 @@
+use crate::config::AppConfig;
+use crate::lightning::LightningBackend;
+use crate::middleware::limiter_middleware::{checkout_limiter, CheckoutLimiter};
+use crate::repositories::checkout_repository::CheckoutRepository;
+use crate::repositories::nodeless_address_repository::{
+    NodelessAddressPaymentRepository, NodelessAddressRepository,
+};
+use crate::services::checkout_service::CheckoutService;
+use crate::services::event_bus::{CheckoutEventKind, EventBus};
+use crate::services::fee_service::FeeService;
+use crate::services::lnurl_service::{LnurlError, LnurlErrorResponse, LnurlPayment, LnurlService};
+use crate::services::zap_service::ZapService;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct PayCallbackQuery {
+    /// Millisats.
+    pub amount: i64,
+    pub comment: Option<String>,
+    /// NIP-57 zap request.
+    pub nostr: Option<String>,
+}
+
+fn lnurl_service(
+    config: &AppConfig,
+    zaps: &ZapService,
+    address_repo: &NodelessAddressRepository,
+    payment_repo: &NodelessAddressPaymentRepository,
+    checkout_repo: &CheckoutRepository,
+) -> LnurlService {
+    LnurlService::new(
+        config.lnurl.clone(),
+        zaps.pubkey(),
+        address_repo.clone(),
+        payment_repo.clone(),
+        checkout_repo.clone(),
+    )
+}
+
+fn error_response(e: LnurlError) -> HttpResponse {
+    let body = LnurlErrorResponse::from(&e);
+
+    match e {
+        LnurlError::NotFound => HttpResponse::NotFound().json(body),
+        LnurlError::TooManyRequests => HttpResponse::TooManyRequests().json(body),
+        LnurlError::Internal(e) => {
+            eprintln!("LNURL-pay callback failed: {}", e);
+            HttpResponse::InternalServerError().json(body)
+        }
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+pub async fn get_pay_request(
+    handle: web::Path<String>,
+    config: web::Data<AppConfig>,
+    zaps: web::Data<ZapService>,
+    address_repo: web::Data<NodelessAddressRepository>,
+    payment_repo: web::Data<NodelessAddressPaymentRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+) -> impl Responder {
+    let service = lnurl_service(&config, &zaps, &address_repo, &payment_repo, &checkout_repo);
+
+    match service.pay_request(&handle).await {
+        Ok(pay_request) => HttpResponse::Ok().json(pay_request),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Creates a checkout for the payment, so it is limited like other public
+/// checkout requests.
+#[allow(clippy::too_many_arguments)]
+pub async fn pay_callback(
+    handle: web::Path<String>,
+    query: web::Query<PayCallbackQuery>,
+    req: HttpRequest,
+    config: web::Data<AppConfig>,
+    limiter: web::Data<CheckoutLimiter>,
+    zaps: web::Data<ZapService>,
+    address_repo: web::Data<NodelessAddressRepository>,
+    payment_repo: web::Data<NodelessAddressPaymentRepository>,
+    checkout_repo: web::Data<CheckoutRepository>,
+    events: web::Data<EventBus>,
+    lightning: web::Data<dyn LightningBackend>,
+    fees: web::Data<FeeService>,
+) -> impl Responder {
+    let limit = checkout_limiter(
+        &req,
+        limiter,
+        config.rate_limiter.checkout_requests_per_minute,
+    )
+    .await;
+
+    if !limit {
+        return error_response(LnurlError::TooManyRequests);
+    }
+
+    let service = lnurl_service(&config, &zaps, &address_repo, &payment_repo, &checkout_repo);
+
+    let payment = service
+        .pay(
+            &handle,
+            LnurlPayment {
+                amount: query.amount,
+                comment: query.comment.clone(),
+                zap_request: query.nostr.clone(),
+            },
+            CheckoutService::new(lightning.into_inner(), fees.get_ref().clone()),
+        )
+        .await;
+
+    match payment {
+        Ok((callback, checkout)) => {
+            events.publish(CheckoutEventKind::Created, checkout);
+            HttpResponse::Ok().json(callback)
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route(
+        "/.well-known/lnurlp/{handle}",
+        web::get().to(get_pay_request),
+    )
+    .route("/lnurlp/{handle}/callback", web::get().to(pay_callback));
+}
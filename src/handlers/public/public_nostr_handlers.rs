@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::config::AppConfig;
+use crate::helpers::format::ErrorResponse;
+use crate::helpers::nostr::npub_to_hex;
+use crate::repositories::nodeless_address_repository::NodelessAddressRepository;
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Nip05Query {
+    pub name: Option<String>,
+}
+
+/// NIP-05 `nostr.json`, mapping names to hex public keys.
+#[derive(Debug, Default, Serialize)]
+pub struct Nip05Response {
+    pub names: HashMap<String, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub relays: HashMap<String, Vec<String>>,
+}
+
+pub async fn get_nostr_json(
+    query: web::Query<Nip05Query>,
+    config: web::Data<AppConfig>,
+    repo: web::Data<NodelessAddressRepository>,
+) -> impl Responder {
+    let mut response = Nip05Response::default();
+
+    if let Some(name) = &query.name {
+        let name = name.to_lowercase();
+
+        let address = match repo.get_active_by_handle(&name).await {
+            Ok(address) => address,
+            Err(_) => {
+                return HttpResponse::InternalServerError()
+                    .insert_header(("Access-Control-Allow-Origin", "*"))
+                    .json(ErrorResponse {
+                        error: "Failed to get address".to_string(),
+                    })
+            }
+        };
+
+        if let Some(npub) = address.and_then(|address| address.npub) {
+            match npub_to_hex(&npub) {
+                Ok(pubkey) => {
+                    if !config.nostr.relays.is_empty() {
+                        response
+                            .relays
+                            .insert(pubkey.clone(), config.nostr.relays.clone());
+                    }
+                    response.names.insert(name, pubkey);
+                }
+                Err(_) => eprintln!("Nodeless address {} has an invalid npub", name),
+            }
+        }
+    }
+
+    HttpResponse::Ok()
+        .insert_header(("Access-Control-Allow-Origin", "*"))
+        .json(response)
+}
+
+pub fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/.well-known/nostr.json", web::get().to(get_nostr_json));
+}
@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+use bech32::{FromBase32, Variant};
+use lightning_cluster::cluster::NodeNetwork;
+use sha2::{Digest, Sha256};
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Checks that `address` is a well-formed bitcoin address for `network`:
+/// segwit (bech32 or bech32m) or legacy base58check P2PKH and P2SH.
+pub fn validate(address: &str, network: &NodeNetwork) -> Result<()> {
+    let lower = address.to_lowercase();
+    let segwit_hrp = match network {
+        NodeNetwork::Mainnet => "bc",
+        NodeNetwork::Testnet => "tb",
+    };
+
+    if lower.starts_with(&format!("{}1", segwit_hrp)) {
+        return validate_segwit(address, segwit_hrp);
+    }
+
+    let versions: [u8; 2] = match network {
+        NodeNetwork::Mainnet => [0x00, 0x05],
+        NodeNetwork::Testnet => [0x6f, 0xc4],
+    };
+    let payload = base58check_decode(address)?;
+
+    if payload.len() != 21 || !versions.contains(&payload[0]) {
+        return Err(anyhow!("Address is not valid for this network"));
+    }
+
+    Ok(())
+}
+
+fn validate_segwit(address: &str, expected_hrp: &str) -> Result<()> {
+    let invalid = || anyhow!("Invalid bitcoin address");
+
+    if address != address.to_lowercase() && address != address.to_uppercase() {
+        return Err(invalid());
+    }
+
+    let (hrp, data, variant) = bech32::decode(address).map_err(|_| invalid())?;
+    if hrp != expected_hrp || data.is_empty() {
+        return Err(invalid());
+    }
+
+    let version = data[0].to_u8();
+    let program = Vec::<u8>::from_base32(&data[1..]).map_err(|_| invalid())?;
+
+    let valid = match version {
+        0 => variant == Variant::Bech32 && (program.len() == 20 || program.len() == 32),
+        1..=16 => variant == Variant::Bech32m && (2..=40).contains(&program.len()),
+        _ => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Decodes a base58check string and returns the payload without the checksum.
+fn base58check_decode(address: &str) -> Result<Vec<u8>> {
+    let invalid = || anyhow!("Invalid bitcoin address");
+    let mut bytes: Vec<u8> = Vec::new();
+
+    for c in address.bytes() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or_else(invalid)? as u32;
+
+        for byte in bytes.iter_mut().rev() {
+            carry += *byte as u32 * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+
+        while carry > 0 {
+            bytes.insert(0, (carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    let leading_zeros = address.bytes().take_while(|&c| c == b'1').count();
+    let mut decoded = vec![0u8; leading_zeros];
+    decoded.extend(bytes);
+
+    if decoded.len() < 5 {
+        return Err(invalid());
+    }
+
+    let (payload, checksum) = decoded.split_at(decoded.len() - 4);
+    let hash = Sha256::digest(&Sha256::digest(payload));
+    if &hash[..4] != checksum {
+        return Err(invalid());
+    }
+
+    Ok(payload.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_cluster::cluster::NodeNetwork;
+
+    use super::validate;
+
+    #[test]
+    fn test_validate_bitcoin_address() {
+        let mainnet = [
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+            "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+        ];
+        for address in mainnet {
+            assert!(
+                validate(address, &NodeNetwork::Mainnet).is_ok(),
+                "{}",
+                address
+            );
+            assert!(
+                validate(address, &NodeNetwork::Testnet).is_err(),
+                "{}",
+                address
+            );
+        }
+
+        assert!(validate(
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            &NodeNetwork::Testnet
+        )
+        .is_ok());
+
+        let invalid = [
+            "",
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb",
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+            "bc1qw508d6qejxtdg4y5r3zarvaRY0c5xw7kv8f3t4",
+            // Version 1 program encoded with bech32 instead of bech32m.
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+            "lntb10u1notanaddress",
+        ];
+        for address in invalid {
+            assert!(
+                validate(address, &NodeNetwork::Mainnet).is_err(),
+                "{}",
+                address
+            );
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{anyhow, Result};
+use bech32::{u5, FromBase32};
+use lightning_cluster::cluster::NodeNetwork;
+
+/// Trailing 65 byte signature, in 5 bit groups.
+const SIGNATURE_LEN: usize = 104;
+
+/// Expiry of invoices without an `x` field, in seconds.
+const DEFAULT_EXPIRY: i64 = 3600;
+
+/// The parts of a BOLT11 invoice the API checks. Only the checksum and
+/// structure are verified, not the signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInvoice {
+    /// Currency prefix of the human readable part: bc, tb, bcrt, ...
+    pub currency: String,
+    /// Amount in sats, `None` for invoices without an amount.
+    pub amount_sat: Option<i64>,
+    /// Unix time the invoice was created.
+    pub timestamp: i64,
+    /// Seconds after `timestamp` the invoice stays payable.
+    pub expiry: i64,
+    /// Hex payment hash (`p` field).
+    pub payment_hash: Option<String>,
+    /// Hex description hash (`h` field).
+    pub description_hash: Option<String>,
+}
+
+impl DecodedInvoice {
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.timestamp.saturating_add(self.expiry)
+    }
+
+    /// Checks that the invoice is for `network` and still payable at `now`.
+    pub fn check_payable(&self, network: &NodeNetwork, now: i64) -> Result<()> {
+        if self.currency != currency_prefix(network) {
+            return Err(anyhow!("Invoice is not for this network"));
+        }
+
+        if self.is_expired(now) {
+            return Err(anyhow!("Invoice has expired"));
+        }
+
+        Ok(())
+    }
+}
+
+/// BOLT11 currency prefix of invoices on `network`.
+pub fn currency_prefix(network: &NodeNetwork) -> &'static str {
+    match network {
+        NodeNetwork::Mainnet => "bc",
+        NodeNetwork::Testnet => "tb",
+    }
+}
+
+pub fn decode(payment_request: &str) -> Result<DecodedInvoice> {
+    let invalid = || anyhow!("Invalid lightning invoice");
+
+    let payment_request = payment_request.trim().to_lowercase();
+    let payment_request = payment_request
+        .strip_prefix("lightning:")
+        .unwrap_or(&payment_request);
+
+    let (hrp, data, _) = bech32::decode(payment_request).map_err(|_| invalid())?;
+    let hrp = hrp.strip_prefix("ln").ok_or_else(invalid)?;
+
+    // The currency prefix (bc, tb, bcrt, ...) runs up to the first digit.
+    let (currency, amount) = match hrp.find(|c: char| c.is_ascii_digit()) {
+        Some(index) => (&hrp[..index], Some(&hrp[index..])),
+        None => (hrp, None),
+    };
+    if currency.is_empty() {
+        return Err(invalid());
+    }
+
+    // Tagged fields sit between the 7 group timestamp and the signature.
+    let fields_end = data.len().checked_sub(SIGNATURE_LEN).ok_or_else(invalid)?;
+    if fields_end < 7 {
+        return Err(invalid());
+    }
+
+    let mut invoice = DecodedInvoice {
+        currency: currency.to_string(),
+        amount_sat: amount.map(parse_amount).transpose()?,
+        timestamp: read_int(&data[..7]),
+        expiry: DEFAULT_EXPIRY,
+        payment_hash: None,
+        description_hash: None,
+    };
+
+    let mut index = 7;
+    while index + 3 <= fields_end {
+        let tag = data[index].to_u8();
+        let len = data[index + 1].to_u8() as usize * 32 + data[index + 2].to_u8() as usize;
+        let end = index + 3 + len;
+        if end > fields_end {
+            return Err(invalid());
+        }
+        let field = &data[index + 3..end];
+
+        match (tag, len) {
+            (1, 52) => {
+                let hash = Vec::<u8>::from_base32(field).map_err(|_| invalid())?;
+                invoice.payment_hash = Some(hex::encode(hash));
+            }
+            (23, 52) => {
+                let hash = Vec::<u8>::from_base32(field).map_err(|_| invalid())?;
+                invoice.description_hash = Some(hex::encode(hash));
+            }
+            (6, 1..=12) => invoice.expiry = read_int(field),
+            _ => {}
+        }
+
+        index = end;
+    }
+
+    Ok(invoice)
+}
+
+/// Big-endian integer from 5 bit groups.
+fn read_int(groups: &[u5]) -> i64 {
+    groups
+        .iter()
+        .fold(0i64, |value, group| (value << 5) | group.to_u8() as i64)
+}
+
+/// Amount part of the human readable prefix in sats.
+fn parse_amount(amount: &str) -> Result<i64> {
+    let (digits, multiplier) = match amount.chars().last() {
+        Some(c) if c.is_ascii_digit() => (amount, None),
+        Some(c) => (&amount[..amount.len() - 1], Some(c)),
+        None => return Err(anyhow!("Invalid lightning invoice amount")),
+    };
+
+    let value: i128 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid lightning invoice amount"))?;
+
+    // Amounts are in bitcoin, scaled by the multiplier; work in tenths of a
+    // millisat so pico amounts stay integers.
+    let per_btc: i128 = 100_000_000_000 * 10;
+    let tenth_msat = match multiplier {
+        None => value * per_btc,
+        Some('m') => value * per_btc / 1_000,
+        Some('u') => value * per_btc / 1_000_000,
+        Some('n') => value * per_btc / 1_000_000_000,
+        Some('p') => value * per_btc / 1_000_000_000_000,
+        Some(_) => return Err(anyhow!("Invalid lightning invoice amount")),
+    };
+
+    if tenth_msat % 10_000 != 0 {
+        return Err(anyhow!("Invoice amount must be a whole number of sats"));
+    }
+
+    i64::try_from(tenth_msat / 10_000).map_err(|_| anyhow!("Invalid lightning invoice amount"))
+}
+
+/// Amount of a BOLT11 invoice in sats, `None` for invoices without an amount.
+pub fn invoice_amount_sat(payment_request: &str) -> Result<Option<i64>> {
+    Ok(decode(payment_request)?.amount_sat)
+}
+
+/// Hex description hash (`h` field) of a BOLT11 invoice, if it has one.
+pub fn invoice_description_hash(payment_request: &str) -> Result<Option<String>> {
+    Ok(decode(payment_request)?.description_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use lightning_cluster::cluster::NodeNetwork;
+
+    use super::{decode, invoice_amount_sat, invoice_description_hash};
+    use crate::lightning::fake::{fake_bolt11, fake_bolt11_with_description_hash};
+
+    #[test]
+    fn test_invoice_amount_sat() {
+        for amount in [1, 150, 1_000, 2_500_000] {
+            let invoice = fake_bolt11(&[7; 32], amount, "test", 3600, 1_690_000_000);
+            assert_eq!(invoice_amount_sat(&invoice).unwrap(), Some(amount));
+            assert_eq!(
+                invoice_amount_sat(&format!("lightning:{}", invoice.to_uppercase())).unwrap(),
+                Some(amount)
+            );
+        }
+
+        assert!(invoice_amount_sat("lntb10u1notaninvoice").is_err());
+        assert!(invoice_amount_sat("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").is_err());
+    }
+
+    #[test]
+    fn test_invoice_description_hash() {
+        let invoice = fake_bolt11(&[7; 32], 21, "test", 3600, 1_690_000_000);
+        assert_eq!(invoice_description_hash(&invoice).unwrap(), None);
+
+        let invoice =
+            fake_bolt11_with_description_hash(&[7; 32], 21, &[9; 32], 3600, 1_690_000_000);
+        assert_eq!(
+            invoice_description_hash(&invoice).unwrap(),
+            Some(hex::encode([9; 32]))
+        );
+    }
+
+    #[test]
+    fn test_decode_checks_network_and_expiry() {
+        let invoice = decode(&fake_bolt11(&[7; 32], 21, "test", 600, 1_690_000_000)).unwrap();
+        assert_eq!(invoice.currency, "tb");
+        assert_eq!(invoice.timestamp, 1_690_000_000);
+        assert_eq!(invoice.expiry, 600);
+        assert_eq!(invoice.payment_hash, Some(hex::encode([7; 32])));
+
+        assert!(invoice
+            .check_payable(&NodeNetwork::Testnet, 1_690_000_599)
+            .is_ok());
+        assert!(invoice
+            .check_payable(&NodeNetwork::Testnet, 1_690_000_600)
+            .is_err());
+        assert!(invoice
+            .check_payable(&NodeNetwork::Mainnet, 1_690_000_000)
+            .is_err());
+    }
+}
@@ -1,5 +1,12 @@
 use hmac::{Hmac, Mac, NewMac};
-use sha2::Sha256;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// `APP_KEY` from the environment, read on first use.
+pub fn app_key() -> &'static str {
+    static APP_KEY: OnceLock<String> = OnceLock::new();
+    APP_KEY.get_or_init(|| dotenvy::var("APP_KEY").expect("APP_KEY must be set"))
+}
 
 pub fn sha256_hmac(data: &str, secret: &str) -> String {
     type HmacSha256 = Hmac<Sha256>;
@@ -12,3 +19,7 @@ pub fn sha256_hmac(data: &str, secret: &str) -> String {
 
     hex::encode(result_bytes)
 }
+
+pub fn sha256_hex(data: &str) -> String {
+    hex::encode(Sha256::digest(data.as_bytes()))
+}
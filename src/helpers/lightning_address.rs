@@ -0,0 +1,188 @@
+use std::{net::IpAddr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use serde::Deserialize;
+
+use super::outbound;
+
+/// First LNURL-pay step, served at `/.well-known/lnurlp/{name}`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayRequest {
+    callback: String,
+    /// Millisats.
+    min_sendable: i64,
+    /// Millisats.
+    max_sendable: i64,
+    tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayCallback {
+    pr: Option<String>,
+    status: Option<String>,
+    reason: Option<String>,
+}
+
+/// Splits `name@domain` into its parts. The domain must be a hostname
+/// without a port; IP addresses are not accepted.
+pub fn parse(address: &str) -> Option<(&str, &str)> {
+    let (name, domain) = address.trim().split_once('@')?;
+
+    let valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'));
+    let valid_domain = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain.parse::<IpAddr>().is_err()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'));
+
+    if valid_name && valid_domain {
+        Some((name, domain))
+    } else {
+        None
+    }
+}
+
+/// Callback URL for `amount_msat`. It must be https on the address' own
+/// domain so a remote server can't point us at another host.
+fn callback_url(callback: &str, domain: &str, amount_msat: i64) -> Result<Url> {
+    let mut url =
+        Url::parse(callback).map_err(|_| anyhow!("Invalid lightning address callback"))?;
+
+    let same_host = url.scheme() == "https"
+        && url.host_str() == Some(domain.to_lowercase().as_str())
+        && url.port_or_known_default() == Some(443)
+        && url.username().is_empty()
+        && url.password().is_none();
+    if !same_host {
+        return Err(anyhow!(
+            "Lightning address callback must be https on {}",
+            domain
+        ));
+    }
+
+    url.query_pairs_mut()
+        .append_pair("amount", &amount_msat.to_string());
+
+    Ok(url)
+}
+
+/// Asks the address' LNURL-pay endpoint for an invoice of `amount` sats.
+/// Both requests go to the address' domain, which must resolve to a public
+/// address.
+pub async fn fetch_invoice(address: &str, amount: i64, timeout: Duration) -> Result<String> {
+    let (name, domain) = parse(address).ok_or_else(|| anyhow!("Invalid lightning address"))?;
+    let amount_msat = amount
+        .checked_mul(1_000)
+        .ok_or_else(|| anyhow!("Amount is too large"))?;
+    let url = Url::parse(&format!("https://{}/.well-known/lnurlp/{}", domain, name))
+        .map_err(|_| anyhow!("Invalid lightning address"))?;
+    let client = outbound::pinned_client(&url, timeout).await?;
+
+    let pay_request: PayRequest = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if pay_request.tag != "payRequest" {
+        return Err(anyhow!("Lightning address does not accept payments"));
+    }
+
+    if amount_msat < pay_request.min_sendable || amount_msat > pay_request.max_sendable {
+        return Err(anyhow!(
+            "Lightning address accepts between {} and {} sats",
+            pay_request.min_sendable / 1_000,
+            pay_request.max_sendable / 1_000
+        ));
+    }
+
+    let callback = callback_url(&pay_request.callback, domain, amount_msat)?;
+
+    let response: PayCallback = client
+        .get(callback)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    match response {
+        PayCallback { pr: Some(pr), .. } => Ok(pr),
+        PayCallback {
+            status: Some(status),
+            reason,
+            ..
+        } if status == "ERROR" => Err(anyhow!(
+            "Lightning address returned an error: {}",
+            reason.unwrap_or_default()
+        )),
+        _ => Err(anyhow!("Lightning address returned no invoice")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{callback_url, fetch_invoice, parse};
+
+    #[test]
+    fn test_parse_lightning_address() {
+        assert_eq!(
+            parse("satoshi@nodeless.io"),
+            Some(("satoshi", "nodeless.io"))
+        );
+        assert_eq!(
+            parse(" tips+btc@pay.example.com "),
+            Some(("tips+btc", "pay.example.com"))
+        );
+        assert_eq!(parse("satoshi"), None);
+        assert_eq!(parse("@nodeless.io"), None);
+        assert_eq!(parse("satoshi@localhost"), None);
+        assert_eq!(parse("sat oshi@nodeless.io"), None);
+        assert_eq!(parse("satoshi@nodeless.io/path"), None);
+        assert_eq!(parse("satoshi@nodeless.io:8080"), None);
+        assert_eq!(parse("satoshi@127.0.0.1"), None);
+        assert_eq!(parse("satoshi@169.254.169.254"), None);
+    }
+
+    #[test]
+    fn test_callback_url() {
+        assert_eq!(
+            callback_url("https://nodeless.io/lnurl/cb?id=1", "nodeless.io", 21_000)
+                .unwrap()
+                .as_str(),
+            "https://nodeless.io/lnurl/cb?id=1&amount=21000"
+        );
+
+        for callback in [
+            "http://nodeless.io/cb",
+            "https://evil.example.com/cb",
+            "https://nodeless.io:8443/cb",
+            "https://169.254.169.254/latest/meta-data",
+            "https://user@nodeless.io/cb",
+            "not a url",
+        ] {
+            assert!(
+                callback_url(callback, "nodeless.io", 21_000).is_err(),
+                "{}",
+                callback
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_amount_overflow_is_rejected() {
+        let result = fetch_invoice("satoshi@nodeless.io", i64::MAX, Duration::from_secs(1)).await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Amount is too large");
+    }
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use bech32::{ToBase32, Variant};
+
+/// Encodes a URL as a LUD-01 bech32 LNURL, uppercased so QR codes can use
+/// alphanumeric mode.
+pub fn encode(url: &str) -> Result<String> {
+    let lnurl = bech32::encode("lnurl", url.as_bytes().to_base32(), Variant::Bech32)?;
+
+    Ok(lnurl.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode;
+
+    #[test]
+    fn test_encode_lnurl() {
+        // LUD-01 example.
+        assert_eq!(
+            encode("https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df").unwrap(),
+            "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS"
+        );
+    }
+}
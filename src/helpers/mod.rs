@@ -1,3 +1,15 @@
+pub mod bitcoin_address;
+pub mod bolt11;
 pub mod crypto;
 pub mod format;
+pub mod lightning_address;
+pub mod lnurl;
+pub mod nostr;
+pub mod outbound;
+pub mod pagination;
+pub mod password;
+pub mod qr;
+pub mod signed_token;
+pub mod signing_key;
 pub mod tests;
+pub mod totp;
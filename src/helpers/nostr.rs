@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Result};
+use bech32::{FromBase32, ToBase32, Variant};
+use secp256k1::{schnorr::Signature, KeyPair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A signed NIP-01 event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: i64,
+    pub kind: u32,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// Builds the event and signs it with `keys`.
+    pub fn sign(
+        keys: &KeyPair,
+        created_at: i64,
+        kind: u32,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Result<NostrEvent> {
+        let pubkey = keys.x_only_public_key().0.to_string();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+
+        let message = Message::from_slice(&hex::decode(&id)?)?;
+        let sig = Secp256k1::new().sign_schnorr_with_aux_rand(&message, keys, &rand::random());
+
+        Ok(NostrEvent {
+            id,
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        })
+    }
+
+    /// Checks that the id is the hash of the event and the signature is the
+    /// pubkey's signature over it.
+    pub fn verify(&self) -> Result<()> {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+        if id != self.id {
+            return Err(anyhow!("Event id does not match its content"));
+        }
+
+        let pubkey = XOnlyPublicKey::from_slice(&hex::decode(&self.pubkey)?)?;
+        let sig = Signature::from_slice(&hex::decode(&self.sig)?)?;
+        let message = Message::from_slice(&hex::decode(&self.id)?)?;
+
+        Secp256k1::new()
+            .verify_schnorr(&sig, &message, &pubkey)
+            .map_err(|_| anyhow!("Invalid event signature"))
+    }
+
+    /// Tags named `name`, each with the name stripped.
+    pub fn tags(&self, name: &str) -> impl Iterator<Item = &[String]> {
+        let name = name.to_string();
+
+        self.tags
+            .iter()
+            .filter(move |tag| tag.first() == Some(&name))
+            .map(|tag| &tag[1..])
+    }
+
+    /// First value of the first tag named `name`.
+    pub fn tag_value(&self, name: &str) -> Option<&str> {
+        self.tags(name)
+            .next()
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// NIP-01 event id: the SHA-256 of `[0, pubkey, created_at, kind, tags, content]`.
+fn event_id(
+    pubkey: &str,
+    created_at: i64,
+    kind: u32,
+    tags: &[Vec<String>],
+    content: &str,
+) -> String {
+    let serialized = serde_json::json!([0, pubkey, created_at, kind, tags, content]).to_string();
+
+    hex::encode(Sha256::digest(serialized.as_bytes()))
+}
+
+/// Parses a hex secret key, as the server's nostr key is configured.
+pub fn keypair_from_hex(secret_key: &str) -> Result<KeyPair> {
+    KeyPair::from_seckey_str(&Secp256k1::new(), secret_key.trim())
+        .map_err(|_| anyhow!("Invalid nostr secret key"))
+}
+
+/// Decodes a NIP-19 `npub` into the hex public key used by NIP-05 and events.
+pub fn npub_to_hex(npub: &str) -> Result<String> {
+    let invalid = || anyhow!("Invalid npub");
+
+    let (hrp, data, variant) = bech32::decode(npub.trim()).map_err(|_| invalid())?;
+    if hrp != "npub" || variant != Variant::Bech32 {
+        return Err(invalid());
+    }
+
+    let pubkey = Vec::<u8>::from_base32(&data).map_err(|_| invalid())?;
+    if pubkey.len() != 32 {
+        return Err(invalid());
+    }
+
+    Ok(hex::encode(pubkey))
+}
+
+/// Encodes a hex public key as a NIP-19 `npub`.
+pub fn hex_to_npub(pubkey: &str) -> Result<String> {
+    let pubkey = hex::decode(pubkey.trim()).map_err(|_| anyhow!("Invalid public key"))?;
+    if pubkey.len() != 32 {
+        return Err(anyhow!("Invalid public key"));
+    }
+
+    Ok(bech32::encode("npub", pubkey.to_base32(), Variant::Bech32)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex_to_npub, keypair_from_hex, npub_to_hex, NostrEvent};
+
+    #[test]
+    fn test_npub_to_hex() {
+        // NIP-19 test vector.
+        assert_eq!(
+            npub_to_hex("npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg").unwrap(),
+            "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e"
+        );
+
+        assert!(
+            npub_to_hex("nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5").is_err()
+        );
+        assert!(npub_to_hex("npub1notvalid").is_err());
+
+        assert_eq!(
+            hex_to_npub("7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e")
+                .unwrap(),
+            "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg"
+        );
+        assert!(hex_to_npub("7e7e").is_err());
+    }
+
+    #[test]
+    fn test_sign_and_verify_event() {
+        // BIP-340 test vector 1 key.
+        let keys =
+            keypair_from_hex("b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef")
+                .unwrap();
+        let tags = vec![vec!["p".to_string(), "ab".repeat(32)]];
+
+        let event = NostrEvent::sign(&keys, 1_690_000_000, 1, tags, "hello".to_string()).unwrap();
+        assert_eq!(
+            event.pubkey,
+            "dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659"
+        );
+        assert_eq!(event.tag_value("p"), Some("ab".repeat(32).as_str()));
+        assert!(event.verify().is_ok());
+
+        let mut tampered = event.clone();
+        tampered.content = "goodbye".to_string();
+        assert!(tampered.verify().is_err());
+
+        let mut forged = event;
+        forged.sig = "00".repeat(64);
+        assert!(forged.verify().is_err());
+    }
+}
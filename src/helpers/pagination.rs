@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 25;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Serialize, Deserialize)]
+pub struct PaginatedResponse<T> {
+    pub data: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Position of the last row of a page, ordered by `(created_at, uuid)`.
+/// Handed to clients as an opaque url-safe string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: chrono::NaiveDateTime,
+    pub uuid: String,
+}
+
+impl Cursor {
+    pub fn new(created_at: chrono::NaiveDateTime, uuid: &str) -> Self {
+        Self {
+            created_at,
+            uuid: uuid.to_string(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}:{}",
+            self.created_at.and_utc().timestamp_micros(),
+            self.uuid
+        );
+        base64::encode_config(raw, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self> {
+        let raw = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)?;
+        let raw = String::from_utf8(raw)?;
+        let (micros, uuid) = raw
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid cursor"))?;
+        let created_at = chrono::DateTime::from_timestamp_micros(micros.parse()?)
+            .ok_or_else(|| anyhow!("Invalid cursor"))?
+            .naive_utc();
+
+        Ok(Self::new(created_at, uuid))
+    }
+}
+
+/// Clamps a requested page size to `1..=MAX_PAGE_SIZE`.
+pub fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{page_size, Cursor, MAX_PAGE_SIZE};
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let created_at = chrono::NaiveDate::from_ymd_opt(2023, 8, 17)
+            .unwrap()
+            .and_hms_micro_opt(12, 30, 15, 123456)
+            .unwrap();
+        let cursor = Cursor::new(created_at, "7f1c5a0e-uuid");
+
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_cursor_rejects_garbage() {
+        assert!(Cursor::decode("not a cursor").is_err());
+        assert!(Cursor::decode(&base64::encode("no-separator")).is_err());
+    }
+
+    #[test]
+    fn test_page_size() {
+        assert_eq!(page_size(None), 25);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(1000)), MAX_PAGE_SIZE);
+    }
+}
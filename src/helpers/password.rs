@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use hmac::{Hmac, Mac, NewMac};
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use std::sync::OnceLock;
+
+use crate::{config::AuthConfig, helpers::crypto::app_key};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// The password is right but its hash is a legacy HMAC or uses other
+    /// cost parameters than configured, and should be replaced.
+    NeedsRehash,
+}
+
+/// Hash checked for logins of unknown users, so they take as long as
+/// logins with a wrong password.
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+fn argon2(config: &AuthConfig) -> Result<Argon2<'static>> {
+    let params = Params::new(
+        config.password_memory_kib,
+        config.password_iterations,
+        config.password_parallelism,
+        None,
+    )
+    .map_err(|e| anyhow!("invalid argon2 parameters: {}", e))?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Checks the configured Argon2 parameters, which would otherwise fail
+/// every login, and hashes the dummy password unknown users are checked
+/// against. Run once at startup.
+pub fn check_params(config: &AuthConfig) -> Result<()> {
+    argon2(config)?;
+    dummy_hash(config);
+
+    Ok(())
+}
+
+fn dummy_hash(config: &AuthConfig) -> &'static str {
+    DUMMY_HASH.get_or_init(|| hash_blocking("not a password", config).unwrap_or_default())
+}
+
+/// Hashes the password with Argon2id and a random salt, as a PHC string.
+/// Runs on the blocking thread pool.
+pub async fn hash_password(password: &str, config: &AuthConfig) -> Result<String> {
+    let password = password.to_string();
+    let config = config.clone();
+
+    tokio::task::spawn_blocking(move || hash_blocking(&password, &config)).await?
+}
+
+/// Checks the password against a stored Argon2id hash, or a legacy
+/// `sha256_hmac(password, APP_KEY)` one. Both compare in constant time.
+/// Without a hash, e.g. for an unknown user, a dummy one is checked so
+/// the answer takes as long. Runs on the blocking thread pool.
+pub async fn verify_password(
+    password: &str,
+    hash: Option<&str>,
+    config: &AuthConfig,
+) -> PasswordCheck {
+    let password = password.to_string();
+    let hash = hash.map(str::to_string);
+    let config = config.clone();
+
+    let checked = tokio::task::spawn_blocking(move || match hash {
+        Some(hash) => verify_blocking(&password, &hash, &config),
+        None => {
+            verify_blocking(&password, dummy_hash(&config), &config);
+            PasswordCheck::Invalid
+        }
+    })
+    .await;
+
+    checked.unwrap_or_else(|e| {
+        eprintln!("failed to verify password: {:?}", e);
+        PasswordCheck::Invalid
+    })
+}
+
+fn hash_blocking(password: &str, config: &AuthConfig) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = argon2(config)?
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("failed to hash password: {}", e))?;
+
+    Ok(hash.to_string())
+}
+
+fn verify_blocking(password: &str, hash: &str, config: &AuthConfig) -> PasswordCheck {
+    let parsed = match PasswordHash::new(hash) {
+        Ok(parsed) => parsed,
+        Err(_) => {
+            return match verify_legacy(password, hash) {
+                true => PasswordCheck::NeedsRehash,
+                false => PasswordCheck::Invalid,
+            }
+        }
+    };
+
+    let argon2 = match argon2(config) {
+        Ok(argon2) => argon2,
+        Err(_) => return PasswordCheck::Invalid,
+    };
+    if argon2
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordCheck::Invalid;
+    }
+
+    let wanted = argon2.params();
+    let current = parsed.algorithm == Algorithm::Argon2id.ident()
+        && Params::try_from(&parsed).is_ok_and(|params| {
+            params.m_cost() == wanted.m_cost()
+                && params.t_cost() == wanted.t_cost()
+                && params.p_cost() == wanted.p_cost()
+        });
+    match current {
+        true => PasswordCheck::Valid,
+        false => PasswordCheck::NeedsRehash,
+    }
+}
+
+fn verify_legacy(password: &str, hash: &str) -> bool {
+    let tag = match hex::decode(hash) {
+        Ok(tag) => tag,
+        Err(_) => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(app_key().as_bytes()).unwrap();
+    mac.update(password.as_bytes());
+    mac.verify(&tag).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_params, hash_password, verify_password, PasswordCheck};
+    use crate::{
+        config::AuthConfig,
+        helpers::crypto::{app_key, sha256_hmac},
+    };
+
+    fn auth_config(iterations: u32) -> AuthConfig {
+        AuthConfig {
+            min_password_length: 8,
+            require_strong_password: true,
+            enable_email_auth: true,
+            enable_nost_auth: true,
+            enable_identifier_auth: true,
+            jwt_expiry_seconds: 3600,
+            refresh_token_expiry_seconds: 3600,
+            challenge_expiry_seconds: 300,
+            mfa_token_expiry_seconds: 300,
+            user_cache_seconds: 30,
+            password_memory_kib: 1024,
+            password_iterations: iterations,
+            password_parallelism: 1,
+            signing_key_algorithm: "EdDSA".to_string(),
+            signing_keys_kept: 3,
+            signing_key_refresh_seconds: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_password() {
+        let config = auth_config(1);
+        let hash = hash_password("Correct1horse", &config).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        // Salts are per hash.
+        assert_ne!(hash, hash_password("Correct1horse", &config).await.unwrap());
+
+        assert_eq!(
+            verify_password("Correct1horse", Some(&hash), &config).await,
+            PasswordCheck::Valid
+        );
+        assert_eq!(
+            verify_password("Wrong1horse", Some(&hash), &config).await,
+            PasswordCheck::Invalid
+        );
+        // Raising the cost asks for a rehash.
+        assert_eq!(
+            verify_password("Correct1horse", Some(&hash), &auth_config(2)).await,
+            PasswordCheck::NeedsRehash
+        );
+
+        let legacy = sha256_hmac("Correct1horse", app_key());
+        assert_eq!(
+            verify_password("Correct1horse", Some(&legacy), &config).await,
+            PasswordCheck::NeedsRehash
+        );
+        assert_eq!(
+            verify_password("Wrong1horse", Some(&legacy), &config).await,
+            PasswordCheck::Invalid
+        );
+        assert_eq!(
+            verify_password("Correct1horse", Some("garbage"), &config).await,
+            PasswordCheck::Invalid
+        );
+        // Unknown users are checked against a dummy hash.
+        assert_eq!(
+            verify_password("Correct1horse", None, &config).await,
+            PasswordCheck::Invalid
+        );
+    }
+
+    #[test]
+    fn test_check_params() {
+        assert!(check_params(&auth_config(1)).is_ok());
+        assert!(check_params(&auth_config(0)).is_err());
+    }
+}
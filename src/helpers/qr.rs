@@ -0,0 +1,20 @@
+use actix_web::web;
+use anyhow::Result;
+use std::fs;
+
+/// Renders `data` as a PNG QR code and returns it as a base64 data URI.
+/// The rendering goes through a temporary file, so it runs on the blocking
+/// thread pool.
+pub async fn qr_data_uri(data: &str) -> Result<String> {
+    let data = data.to_string();
+
+    web::block(move || -> Result<String> {
+        let qr_name = format!("/tmp/{}.png", uuid::Uuid::new_v4());
+        qrcode_gen::qr_image(&data, qr_name.as_str());
+        let buffer = fs::read(qr_name.as_str())?;
+        let base64_image = base64::encode(&buffer);
+        fs::remove_file(qr_name)?;
+        Ok(format!("data:image/png;base64,{}", base64_image))
+    })
+    .await?
+}
@@ -0,0 +1,93 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::services::signing_key_service::SigningKeyService;
+
+/// Claims of a single-purpose token sent to users, e.g. in an email link.
+/// They are signed with the same keys as access tokens, whose claims they
+/// don't share, so neither kind passes for the other.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedTokenClaims {
+    pub sub: String,
+    /// What the token may be used for, so one kind can't stand in for another.
+    pub purpose: String,
+    /// Digest of the state the token was issued for. It stops matching once
+    /// that state changes, which makes the token unusable.
+    pub fingerprint: String,
+    pub exp: usize,
+}
+
+/// Short digest of `state` to bind a token to.
+pub fn fingerprint(state: &str) -> String {
+    hex::encode(&Sha256::digest(state.as_bytes())[..8])
+}
+
+/// Signs an expiring token for `sub` with the signing keys.
+pub async fn sign(
+    keys: &SigningKeyService,
+    purpose: &str,
+    sub: &str,
+    fingerprint: &str,
+    expiry_seconds: u64,
+) -> Result<String> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let claims = SignedTokenClaims {
+        sub: sub.to_string(),
+        purpose: purpose.to_string(),
+        fingerprint: fingerprint.to_string(),
+        exp: (now + expiry_seconds) as usize,
+    };
+
+    Ok(keys.sign(&claims).await?)
+}
+
+/// Checks the token's signature, expiry and purpose.
+pub async fn verify(
+    keys: &SigningKeyService,
+    purpose: &str,
+    token: &str,
+) -> Result<SignedTokenClaims> {
+    let claims = keys.verify::<SignedTokenClaims>(token).await?.claims;
+
+    if claims.purpose != purpose {
+        return Err(anyhow!("Token is not valid for {}", purpose));
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint, sign, verify};
+    use crate::{
+        helpers::tests::create_test_signing_keys,
+        middleware::jwt_middleware::{generate_jwt_token, verify_jwt_token},
+    };
+
+    #[tokio::test]
+    async fn test_signed_token() {
+        let keys = create_test_signing_keys().await;
+        let token = sign(&keys, "verify_email", "user", &fingerprint("a@b.c"), 60)
+            .await
+            .unwrap();
+
+        let claims = verify(&keys, "verify_email", &token).await.unwrap();
+        assert_eq!(claims.sub, "user");
+        assert_eq!(claims.fingerprint, fingerprint("a@b.c"));
+
+        assert!(verify(&keys, "reset_password", &token).await.is_err());
+        assert!(verify(&keys, "verify_email", &format!("{}x", token))
+            .await
+            .is_err());
+
+        // Access tokens and signed tokens don't pass for each other.
+        let access = generate_jwt_token(&keys, "user", "session", 60)
+            .await
+            .unwrap();
+        assert!(verify(&keys, "verify_email", &access).await.is_err());
+        assert!(verify_jwt_token(&keys, &token).await.is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac, NewMac};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::{
+    aead,
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::helpers::crypto::app_key;
+
+const NONCE_LEN: usize = 12;
+
+/// A public key as published in the JWKS, so other services can verify
+/// tokens (RFC 7517).
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+}
+
+/// A freshly generated key pair.
+pub struct KeyMaterial {
+    pub pkcs8: Vec<u8>,
+    pub public_key: Vec<u8>,
+}
+
+/// Parses the algorithm of a signing key. Only `EdDSA` (Ed25519) and
+/// `ES256` (P-256) are supported.
+pub fn parse_algorithm(name: &str) -> Result<Algorithm> {
+    match Algorithm::from_str(name) {
+        Ok(algorithm @ (Algorithm::EdDSA | Algorithm::ES256)) => Ok(algorithm),
+        _ => Err(anyhow!("Unsupported signing key algorithm: {}", name)),
+    }
+}
+
+pub fn algorithm_name(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::EdDSA => "EdDSA",
+        Algorithm::ES256 => "ES256",
+        _ => unreachable!("unsupported signing key algorithm"),
+    }
+}
+
+pub fn generate(algorithm: Algorithm) -> Result<KeyMaterial> {
+    let rng = SystemRandom::new();
+    fn unspecified<E>(_: E) -> anyhow::Error {
+        anyhow!("Failed to generate signing key")
+    }
+
+    let (pkcs8, public_key) = match algorithm {
+        Algorithm::EdDSA => {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(unspecified)?;
+            let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(unspecified)?;
+            (pkcs8.as_ref().to_vec(), pair.public_key().as_ref().to_vec())
+        }
+        Algorithm::ES256 => {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .map_err(unspecified)?;
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+                .map_err(unspecified)?;
+            (pkcs8.as_ref().to_vec(), pair.public_key().as_ref().to_vec())
+        }
+        _ => return Err(anyhow!("Unsupported signing key algorithm")),
+    };
+
+    Ok(KeyMaterial { pkcs8, public_key })
+}
+
+pub fn encoding_key(algorithm: Algorithm, pkcs8: &[u8]) -> EncodingKey {
+    match algorithm {
+        Algorithm::EdDSA => EncodingKey::from_ed_der(pkcs8),
+        _ => EncodingKey::from_ec_der(pkcs8),
+    }
+}
+
+pub fn decoding_key(algorithm: Algorithm, public_key: &[u8]) -> DecodingKey {
+    match algorithm {
+        Algorithm::EdDSA => DecodingKey::from_ed_der(public_key),
+        _ => DecodingKey::from_ec_der(public_key),
+    }
+}
+
+pub fn jwk(kid: &str, algorithm: Algorithm, public_key: &[u8]) -> Jwk {
+    let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+    let (kty, crv, x, y) = match algorithm {
+        Algorithm::EdDSA => ("OKP", "Ed25519", encode(public_key), None),
+        // Uncompressed point: 0x04 || x || y.
+        _ => (
+            "EC",
+            "P-256",
+            encode(&public_key[1..33]),
+            Some(encode(&public_key[33..])),
+        ),
+    };
+
+    Jwk {
+        kty: kty.to_string(),
+        crv: crv.to_string(),
+        x,
+        y,
+        kid: kid.to_string(),
+        alg: algorithm_name(algorithm).to_string(),
+        key_use: "sig".to_string(),
+    }
+}
+
+fn derive_sealing_key(secret: &str) -> aead::LessSafeKey {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(b"signing_keys");
+    let key = mac.finalize().into_bytes();
+
+    aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key).unwrap())
+}
+
+/// AES-256-GCM keys for private keys at rest, the one new keys are sealed
+/// with first. It is derived from `SIGNING_KEYS_SECRET`, so a leaked app
+/// key doesn't unlock the signing keys. Deployments without it, and keys
+/// sealed before it was set, use one derived from the app key.
+fn sealing_keys() -> &'static [aead::LessSafeKey] {
+    static SEALING_KEYS: OnceLock<Vec<aead::LessSafeKey>> = OnceLock::new();
+    SEALING_KEYS.get_or_init(|| {
+        let dedicated = dotenvy::var("SIGNING_KEYS_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+
+        dedicated
+            .iter()
+            .map(|secret| derive_sealing_key(secret))
+            .chain([derive_sealing_key(app_key())])
+            .collect()
+    })
+}
+
+/// Whether private keys are sealed with a dedicated secret rather than one
+/// derived from the app key.
+pub fn has_dedicated_secret() -> bool {
+    sealing_keys().len() > 1
+}
+
+/// Encrypts a private key for storage, bound to its kid.
+pub fn encrypt(kid: &str, pkcs8: &[u8]) -> Result<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("Failed to generate nonce"))?;
+
+    let mut sealed = pkcs8.to_vec();
+    sealing_keys()[0]
+        .seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(kid.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("Failed to encrypt signing key"))?;
+
+    Ok(base64::encode([&nonce[..], &sealed].concat()))
+}
+
+pub fn decrypt(kid: &str, encrypted: &str) -> Result<Vec<u8>> {
+    let mut sealed = base64::decode(encrypted)?;
+    if sealed.len() < NONCE_LEN {
+        return Err(anyhow!("Signing key {} is malformed", kid));
+    }
+    let in_out = sealed.split_off(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = sealed
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow!("Signing key {} is malformed", kid))?;
+
+    let pkcs8 = sealing_keys()
+        .iter()
+        .find_map(|key| {
+            let nonce = aead::Nonce::assume_unique_for_key(nonce);
+            let mut in_out = in_out.clone();
+            key.open_in_place(nonce, aead::Aad::from(kid.as_bytes()), &mut in_out)
+                .ok()
+                .map(|pkcs8| pkcs8.to_vec())
+        })
+        .ok_or_else(|| anyhow!("Failed to decrypt signing key {}", kid))?;
+
+    Ok(pkcs8)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+
+    use super::{decrypt, encrypt, generate, jwk};
+
+    #[test]
+    fn test_signing_key() {
+        let ed = generate(Algorithm::EdDSA).unwrap();
+        let encrypted = encrypt("a", &ed.pkcs8).unwrap();
+        assert_eq!(decrypt("a", &encrypted).unwrap(), ed.pkcs8);
+        // Keys can't be swapped between rows.
+        assert!(decrypt("b", &encrypted).is_err());
+
+        let jwk_ed = jwk("a", Algorithm::EdDSA, &ed.public_key);
+        assert_eq!(
+            (jwk_ed.kty.as_str(), jwk_ed.crv.as_str()),
+            ("OKP", "Ed25519")
+        );
+        assert_eq!(jwk_ed.x.len(), 43);
+        assert!(jwk_ed.y.is_none());
+
+        let ec = generate(Algorithm::ES256).unwrap();
+        let jwk_ec = jwk("b", Algorithm::ES256, &ec.public_key);
+        assert_eq!((jwk_ec.kty.as_str(), jwk_ec.crv.as_str()), ("EC", "P-256"));
+        assert_eq!(jwk_ec.x.len(), 43);
+        assert_eq!(jwk_ec.y.map(|y| y.len()), Some(43));
+    }
+}
@@ -6,9 +6,11 @@ use crate::{
     repositories::{
         checkout_repository::CheckoutRepository,
         donation_page_repository::DonationPageRepository,
+        signing_key_repository::SigningKeyRepository,
         store_repository::{StoreInvoiceRepository, StoreRepository},
         user_repository::{CreateUser, UserRepository, UserRepositoryError},
     },
+    services::signing_key_service::SigningKeyService,
 };
 use actix_web::{
     dev::{ServiceFactory, ServiceRequest},
@@ -16,16 +18,15 @@ use actix_web::{
     web::Data,
     App, Responder,
 };
-use lightning_cluster::{
-    cluster::{Cluster, Node, NodeClient, NodeLightningImpl, NodeNetwork},
-    lnd::LndClient,
-};
 use moka::future::Cache;
 use sqlx::PgPool;
 use std::{fs::read_to_string, sync::Arc, time::Duration};
 use toml::Value;
 
-use super::{crypto::sha256_hmac, format::random_text};
+use super::{
+    crypto::{app_key, sha256_hmac},
+    format::random_text,
+};
 
 pub async fn create_test_pool() -> PgPool {
     let pool = PgPool::connect(dotenvy::var("DATABASE_URL").unwrap().as_str())
@@ -34,16 +35,24 @@ pub async fn create_test_pool() -> PgPool {
     pool
 }
 
+pub async fn create_test_signing_keys() -> SigningKeyService {
+    let keys = SigningKeyService::new(
+        jsonwebtoken::Algorithm::EdDSA,
+        10,
+        Duration::from_secs(60),
+        SigningKeyRepository::new(create_test_pool().await),
+    );
+    keys.load().await.unwrap();
+    keys
+}
+
 pub async fn create_test_user() -> Result<User, UserRepositoryError> {
     let pool = create_test_pool().await;
     let user_repo = UserRepository::new(pool.clone());
     let random = random_text(10).await;
     let test_user = CreateUser {
         email: Some(String::from(format!("{}@nodeless.io", random))),
-        password: Some(sha256_hmac(
-            "password",
-            dotenvy::var("APP_KEY").unwrap().as_str(),
-        )),
+        password: Some(sha256_hmac("password", app_key())),
         npub: None,
         identifier: None,
     };
@@ -60,24 +69,3 @@ pub async fn delete_test_user(uuid: &str) -> Result<bool, UserRepositoryError> {
     let result = user_repo.hard_delete(uuid).await?;
     Ok(result)
 }
-
-pub async fn create_test_cluster() -> Cluster {
-    let node1 = Node {
-        pubkey: dotenvy::var("NODE1_PUBKEY").unwrap(),
-        ip: dotenvy::var("NODE1_IP").unwrap(),
-        port: dotenvy::var("NODE1_PORT").unwrap(),
-        network: NodeNetwork::Testnet,
-        lightning_impl: NodeLightningImpl::Lnd,
-        client: NodeClient::Lnd(LndClient::new(
-            dotenvy::var("NODE1_HOST").unwrap(),
-            dotenvy::var("NODE1_CERT_PATH").unwrap(),
-            dotenvy::var("NODE1_MACAROON_PATH").unwrap(),
-        )),
-    };
-
-    let nodes = vec![node1];
-    let cluster = Cluster::new(nodes);
-
-    cluster
-}
-
@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lightning_cluster::cluster::{
+    Cluster, ClusterAddInvoice, ClusterInvoiceState, ClusterPayInvoice, ClusterPaymentStatus,
+    ClusterSendMany,
+};
+
+use super::{
+    lnd::LndRestClient, AddInvoice, Invoice, InvoiceState, InvoiceStatus, LightningBackend,
+    Payment, PaymentError, PaymentStatus,
+};
+use crate::{config::ClusterConfig, helpers::bolt11};
+
+/// Payment errors LND only returns once a payment has failed for good.
+/// Anything else, such as a timeout or dropped connection, may still settle.
+const FINAL_PAYMENT_FAILURES: &[&str] = &[
+    "failure_reason_",
+    "no_route",
+    "unable to find a path",
+    "incorrect_payment_details",
+    "incorrect or unknown payment details",
+    "insufficient_balance",
+    "insufficient local balance",
+    "invoice expired",
+    "already paid",
+];
+
+fn payment_error(e: anyhow::Error) -> PaymentError {
+    let message = e.to_string().to_lowercase();
+
+    if FINAL_PAYMENT_FAILURES
+        .iter()
+        .any(|failure| message.contains(failure))
+    {
+        PaymentError::Failed(e.to_string())
+    } else {
+        PaymentError::Unknown(e)
+    }
+}
+
+/// The lightning `Cluster`, plus a REST client per node for what the
+/// cluster client can't do.
+pub struct ClusterBackend {
+    cluster: Cluster,
+    nodes: Vec<LndRestClient>,
+    next_node: AtomicUsize,
+}
+
+impl ClusterBackend {
+    pub fn from_config(config: &ClusterConfig) -> Result<Self> {
+        let nodes = config
+            .nodes
+            .iter()
+            .map(|node| LndRestClient::new(&node.host, &node.cert_path, &node.macaroon_path))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            cluster: config.build(),
+            nodes,
+            next_node: AtomicUsize::new(0),
+        })
+    }
+
+    /// Creates the invoice on the nodes in turn, and checks it really
+    /// commits to the description hash before handing it out.
+    async fn add_description_hash_invoice(
+        &self,
+        req: &AddInvoice,
+        description_hash: &str,
+    ) -> Result<Invoice> {
+        if self.nodes.is_empty() {
+            return Err(anyhow!("No cluster nodes configured"));
+        }
+        let node = &self.nodes[self.next_node.fetch_add(1, Ordering::Relaxed) % self.nodes.len()];
+
+        let invoice = node
+            .add_invoice(&req.memo, req.amount, req.expiry, description_hash)
+            .await?;
+
+        let committed = bolt11::invoice_description_hash(&invoice.payment_request)?;
+        if committed.as_deref() != Some(description_hash) {
+            return Err(anyhow!(
+                "Invoice {} does not commit to the description hash",
+                invoice.payment_hash
+            ));
+        }
+
+        Ok(invoice)
+    }
+}
+
+#[async_trait]
+impl LightningBackend for ClusterBackend {
+    async fn add_invoice(&self, req: AddInvoice) -> Result<Invoice> {
+        if let Some(description_hash) = &req.description_hash {
+            return self
+                .add_description_hash_invoice(&req, description_hash)
+                .await;
+        }
+
+        let req = ClusterAddInvoice {
+            pubkey: None,
+            memo: req.memo,
+            value: req.amount,
+            expiry: req.expiry,
+        };
+
+        let invoice = self.cluster.add_invoice(req, None).await?;
+
+        Ok(Invoice {
+            payment_hash: invoice.r_hash,
+            payment_request: invoice.payment_request,
+        })
+    }
+
+    async fn next_address(&self) -> Result<String> {
+        self.cluster.next_address(None).await
+    }
+
+    /// Invoices created over REST live on one node, so they are looked up
+    /// on each node if the cluster doesn't find them.
+    async fn lookup_invoice(&self, payment_hash: &str) -> Result<InvoiceStatus> {
+        let invoice = match self.cluster.lookup_invoice(payment_hash, None).await {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                for node in &self.nodes {
+                    if let Ok(status) = node.lookup_invoice(payment_hash).await {
+                        return Ok(status);
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        let state = match invoice.state {
+            ClusterInvoiceState::Open => InvoiceState::Open,
+            ClusterInvoiceState::Accepted => InvoiceState::Accepted,
+            ClusterInvoiceState::Settled => InvoiceState::Settled,
+            ClusterInvoiceState::Canceled => InvoiceState::Canceled,
+        };
+
+        Ok(InvoiceStatus {
+            payment_hash: payment_hash.to_string(),
+            state,
+            amount_paid: invoice.amt_paid_sat,
+        })
+    }
+
+    async fn address_received(&self, address: &str) -> Result<(i64, i64)> {
+        let received = self.addresses_received(&[address.to_string()]).await?;
+
+        Ok(received.get(address).copied().unwrap_or((0, 0)))
+    }
+
+    /// Reads each node's wallet history once, however many addresses are
+    /// asked for. Spent outputs still count, so a checkout's funds don't
+    /// drop when they are swept or used for a payout.
+    async fn addresses_received(
+        &self,
+        addresses: &[String],
+    ) -> Result<HashMap<String, (i64, i64)>> {
+        let mut received: HashMap<String, (i64, i64)> = addresses
+            .iter()
+            .map(|address| (address.clone(), (0, 0)))
+            .collect();
+
+        for node in &self.nodes {
+            node.add_received(&mut received).await?;
+        }
+
+        Ok(received)
+    }
+
+    async fn pay_invoice(
+        &self,
+        payment_request: &str,
+        fee_limit: i64,
+    ) -> Result<Payment, PaymentError> {
+        let req = ClusterPayInvoice {
+            payment_request: payment_request.to_string(),
+            fee_limit_sat: fee_limit,
+        };
+
+        let payment = self
+            .cluster
+            .pay_invoice(req, None)
+            .await
+            .map_err(payment_error)?;
+
+        Ok(Payment {
+            payment_hash: payment.payment_hash,
+            preimage: payment.payment_preimage,
+            fee: payment.fee_sat,
+        })
+    }
+
+    async fn lookup_payment(&self, payment_hash: &str) -> Result<PaymentStatus> {
+        let payment = match self.cluster.lookup_payment(payment_hash, None).await? {
+            Some(payment) => payment,
+            None => return Ok(PaymentStatus::NotFound),
+        };
+
+        let status = match payment.status {
+            ClusterPaymentStatus::InFlight => PaymentStatus::InFlight,
+            ClusterPaymentStatus::Succeeded => PaymentStatus::Succeeded(Payment {
+                payment_hash: payment.payment_hash,
+                preimage: payment.payment_preimage,
+                fee: payment.fee_sat,
+            }),
+            ClusterPaymentStatus::Failed => PaymentStatus::Failed(payment.failure_reason),
+        };
+
+        Ok(status)
+    }
+
+    async fn send_many(
+        &self,
+        outputs: &[(String, i64)],
+        target_conf: i32,
+        label: &str,
+    ) -> Result<String> {
+        let req = ClusterSendMany {
+            outputs: outputs.iter().cloned().collect(),
+            target_conf,
+            label: label.to_string(),
+        };
+
+        let response = self.cluster.send_many(req, None).await?;
+
+        Ok(response.txid)
+    }
+
+    async fn find_transaction(&self, label: &str) -> Result<Option<String>> {
+        let transactions = self.cluster.get_transactions(None).await?;
+
+        Ok(transactions
+            .into_iter()
+            .find(|transaction| transaction.label == label)
+            .map(|transaction| transaction.tx_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::payment_error;
+    use crate::lightning::PaymentError;
+
+    #[test]
+    fn test_only_final_failures_fail_the_payment() {
+        for error in [
+            "FAILURE_REASON_NO_ROUTE",
+            "unable to find a path to destination",
+            "invoice expired. Valid until 2023-08-01 00:00:00",
+            "invoice is already paid",
+        ] {
+            assert!(matches!(
+                payment_error(anyhow!(error)),
+                PaymentError::Failed(_)
+            ));
+        }
+
+        for error in [
+            "request timed out",
+            "transport error: connection reset",
+            "payment is in transition",
+        ] {
+            assert!(matches!(
+                payment_error(anyhow!(error)),
+                PaymentError::Unknown(_)
+            ));
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bech32::{u5, ToBase32, Variant};
+use futures::{stream, stream::BoxStream, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+
+use super::{
+    AddInvoice, Invoice, InvoiceState, InvoiceStatus, LightningBackend, Payment, PaymentError,
+    PaymentStatus,
+};
+use crate::helpers::bolt11;
+
+/// Invoices are stamped from this time on, one second apart, so the
+/// generated payment requests are the same on every run.
+const BASE_TIMESTAMP: u64 = 1_690_000_000;
+
+#[derive(Debug, Clone)]
+struct FakeInvoice {
+    amount: i64,
+    state: InvoiceState,
+    amount_paid: i64,
+}
+
+#[derive(Default)]
+struct FakeState {
+    counter: u64,
+    invoices: HashMap<String, FakeInvoice>,
+    addresses: HashMap<String, (i64, i64)>,
+    payments: Vec<String>,
+    outgoing: HashMap<String, PaymentStatus>,
+    onchain_sends: Vec<Vec<(String, i64)>>,
+    transactions: HashMap<String, String>,
+    payment_fee: i64,
+    payment_error: Option<String>,
+    payment_in_flight: bool,
+}
+
+/// Deterministic in-memory lightning node for tests. Generates testnet
+/// BOLT11 invoices and addresses and lets tests settle them.
+pub struct FakeLightningBackend {
+    state: Mutex<FakeState>,
+    updates: broadcast::Sender<InvoiceStatus>,
+}
+
+impl Default for FakeLightningBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeLightningBackend {
+    pub fn new() -> Self {
+        let (updates, _) = broadcast::channel(64);
+
+        Self {
+            state: Mutex::new(FakeState::default()),
+            updates,
+        }
+    }
+
+    /// Pays the invoice in full, or with `amount` sats if given.
+    pub fn settle(&self, payment_hash: &str, amount: Option<i64>) {
+        self.set_state(payment_hash, InvoiceState::Settled, amount);
+    }
+
+    pub fn cancel(&self, payment_hash: &str) {
+        self.set_state(payment_hash, InvoiceState::Canceled, Some(0));
+    }
+
+    /// Sets the funds seen at an on-chain address.
+    pub fn receive_onchain(&self, address: &str, confirmed: i64, unconfirmed: i64) {
+        self.state
+            .lock()
+            .unwrap()
+            .addresses
+            .insert(address.to_string(), (confirmed, unconfirmed));
+    }
+
+    /// Routing fee reported for the following payments.
+    pub fn set_payment_fee(&self, fee: i64) {
+        self.state.lock().unwrap().payment_fee = fee;
+    }
+
+    /// Makes the following payments and on-chain sends fail with the given
+    /// error, or succeed again with `None`.
+    pub fn set_payment_error(&self, error: Option<&str>) {
+        self.state.lock().unwrap().payment_error = error.map(|e| e.to_string());
+    }
+
+    /// Makes the following payments time out while still in flight, until
+    /// resolved with `resolve_payment`, and on-chain sends fail after the
+    /// transaction was broadcast.
+    pub fn set_payment_in_flight(&self, in_flight: bool) {
+        self.state.lock().unwrap().payment_in_flight = in_flight;
+    }
+
+    /// Settles an in-flight payment, or fails it with `failure`.
+    pub fn resolve_payment(&self, payment_hash: &str, failure: Option<&str>) {
+        let mut fake = self.state.lock().unwrap();
+
+        let status = match failure {
+            Some(failure) => PaymentStatus::Failed(failure.to_string()),
+            None => PaymentStatus::Succeeded(Payment {
+                payment_hash: payment_hash.to_string(),
+                preimage: hex::encode(sha256(payment_hash.as_bytes())),
+                fee: fake.payment_fee,
+            }),
+        };
+        fake.outgoing.insert(payment_hash.to_string(), status);
+    }
+
+    /// Payment requests paid through this backend, in order.
+    pub fn payments(&self) -> Vec<String> {
+        self.state.lock().unwrap().payments.clone()
+    }
+
+    /// Outputs of every on-chain send, one entry per transaction.
+    pub fn onchain_sends(&self) -> Vec<Vec<(String, i64)>> {
+        self.state.lock().unwrap().onchain_sends.clone()
+    }
+
+    fn set_state(&self, payment_hash: &str, state: InvoiceState, amount: Option<i64>) {
+        let status = {
+            let mut fake = self.state.lock().unwrap();
+            let invoice = fake
+                .invoices
+                .get_mut(payment_hash)
+                .expect("Unknown fake invoice");
+            invoice.state = state;
+            invoice.amount_paid = amount.unwrap_or(invoice.amount);
+
+            InvoiceStatus {
+                payment_hash: payment_hash.to_string(),
+                state,
+                amount_paid: invoice.amount_paid,
+            }
+        };
+
+        let _ = self.updates.send(status);
+    }
+
+    fn next_counter(&self) -> u64 {
+        let mut fake = self.state.lock().unwrap();
+        fake.counter += 1;
+        fake.counter
+    }
+}
+
+fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// Big-endian base32 digits of `value`, left padded to `min_len`.
+fn int_to_u5(mut value: u64, min_len: usize) -> Vec<u5> {
+    let mut digits = Vec::new();
+
+    while value > 0 || digits.len() < min_len {
+        digits.push(u5::try_from_u8((value & 31) as u8).unwrap());
+        value >>= 5;
+    }
+
+    digits.reverse();
+    digits
+}
+
+fn tagged_field(tag: u8, data: Vec<u5>) -> Vec<u5> {
+    let mut field = vec![u5::try_from_u8(tag).unwrap()];
+    field.extend(int_to_u5(data.len() as u64, 2));
+    field.extend(data);
+    field
+}
+
+/// Amount part of the human readable prefix, using the largest exact multiplier.
+fn bolt11_amount(sats: i64) -> String {
+    if sats % 100_000 == 0 {
+        format!("{}m", sats / 100_000)
+    } else if sats % 100 == 0 {
+        format!("{}u", sats / 100)
+    } else {
+        format!("{}n", sats * 10)
+    }
+}
+
+/// Builds a structurally valid testnet BOLT11 invoice. The signature is
+/// filler, so it will not pass signature verification.
+pub fn fake_bolt11(
+    payment_hash: &[u8],
+    amount: i64,
+    memo: &str,
+    expiry: i64,
+    timestamp: u64,
+) -> String {
+    let description = tagged_field(13, memo.as_bytes().to_base32());
+
+    build_bolt11(payment_hash, amount, description, expiry, timestamp)
+}
+
+/// Like `fake_bolt11`, but committing to a description hash instead of a memo.
+pub fn fake_bolt11_with_description_hash(
+    payment_hash: &[u8],
+    amount: i64,
+    description_hash: &[u8],
+    expiry: i64,
+    timestamp: u64,
+) -> String {
+    let description = tagged_field(23, description_hash.to_base32());
+
+    build_bolt11(payment_hash, amount, description, expiry, timestamp)
+}
+
+fn build_bolt11(
+    payment_hash: &[u8],
+    amount: i64,
+    description: Vec<u5>,
+    expiry: i64,
+    timestamp: u64,
+) -> String {
+    let mut data = int_to_u5(timestamp, 7);
+    data.extend(tagged_field(1, payment_hash.to_base32()));
+    data.extend(tagged_field(16, sha256(payment_hash).to_base32()));
+    data.extend(description);
+    data.extend(tagged_field(6, int_to_u5(expiry as u64, 0)));
+
+    let mut signature = sha256(&[payment_hash, b"r"].concat());
+    signature.extend(sha256(&[payment_hash, b"s"].concat()));
+    signature.push(0);
+    data.extend(signature.to_base32());
+
+    let hrp = format!("lntb{}", bolt11_amount(amount));
+    bech32::encode(&hrp, data, Variant::Bech32).unwrap()
+}
+
+/// Builds a testnet P2WPKH address from a 20 byte program.
+pub fn fake_address(program: &[u8]) -> String {
+    let mut data = vec![u5::try_from_u8(0).unwrap()];
+    data.extend(program.to_base32());
+    bech32::encode("tb", data, Variant::Bech32).unwrap()
+}
+
+#[async_trait]
+impl LightningBackend for FakeLightningBackend {
+    async fn add_invoice(&self, req: AddInvoice) -> Result<Invoice> {
+        let counter = self.next_counter();
+        let preimage = sha256(format!("fake-preimage-{}", counter).as_bytes());
+        let payment_hash = sha256(&preimage);
+        let payment_request = match &req.description_hash {
+            Some(description_hash) => fake_bolt11_with_description_hash(
+                &payment_hash,
+                req.amount,
+                &hex::decode(description_hash)?,
+                req.expiry,
+                BASE_TIMESTAMP + counter,
+            ),
+            None => fake_bolt11(
+                &payment_hash,
+                req.amount,
+                &req.memo,
+                req.expiry,
+                BASE_TIMESTAMP + counter,
+            ),
+        };
+        let payment_hash = hex::encode(payment_hash);
+
+        self.state.lock().unwrap().invoices.insert(
+            payment_hash.clone(),
+            FakeInvoice {
+                amount: req.amount,
+                state: InvoiceState::Open,
+                amount_paid: 0,
+            },
+        );
+
+        Ok(Invoice {
+            payment_hash,
+            payment_request,
+        })
+    }
+
+    async fn next_address(&self) -> Result<String> {
+        let counter = self.next_counter();
+        let program = sha256(format!("fake-address-{}", counter).as_bytes());
+
+        Ok(fake_address(&program[..20]))
+    }
+
+    async fn lookup_invoice(&self, payment_hash: &str) -> Result<InvoiceStatus> {
+        let fake = self.state.lock().unwrap();
+        let invoice = fake
+            .invoices
+            .get(payment_hash)
+            .ok_or_else(|| anyhow!("invoice not found"))?;
+
+        Ok(InvoiceStatus {
+            payment_hash: payment_hash.to_string(),
+            state: invoice.state,
+            amount_paid: invoice.amount_paid,
+        })
+    }
+
+    async fn address_received(&self, address: &str) -> Result<(i64, i64)> {
+        let fake = self.state.lock().unwrap();
+
+        Ok(fake.addresses.get(address).copied().unwrap_or((0, 0)))
+    }
+
+    async fn pay_invoice(
+        &self,
+        payment_request: &str,
+        fee_limit: i64,
+    ) -> Result<Payment, PaymentError> {
+        let payment_hash = bolt11::decode(payment_request)?
+            .payment_hash
+            .ok_or_else(|| anyhow!("invoice has no payment hash"))?;
+        let mut fake = self.state.lock().unwrap();
+
+        if let Some(error) = &fake.payment_error {
+            return Err(PaymentError::Failed(error.clone()));
+        }
+
+        if fake.payment_fee > fee_limit {
+            return Err(PaymentError::Failed(
+                "no route within fee limit".to_string(),
+            ));
+        }
+
+        fake.payments.push(payment_request.to_string());
+
+        if fake.payment_in_flight {
+            fake.outgoing.insert(payment_hash, PaymentStatus::InFlight);
+            return Err(anyhow!("payment timed out").into());
+        }
+
+        let payment = Payment {
+            preimage: hex::encode(sha256(payment_hash.as_bytes())),
+            payment_hash: payment_hash.clone(),
+            fee: fake.payment_fee,
+        };
+        fake.outgoing
+            .insert(payment_hash, PaymentStatus::Succeeded(payment.clone()));
+
+        Ok(payment)
+    }
+
+    async fn lookup_payment(&self, payment_hash: &str) -> Result<PaymentStatus> {
+        let fake = self.state.lock().unwrap();
+
+        Ok(fake
+            .outgoing
+            .get(payment_hash)
+            .cloned()
+            .unwrap_or(PaymentStatus::NotFound))
+    }
+
+    async fn send_many(
+        &self,
+        outputs: &[(String, i64)],
+        _target_conf: i32,
+        label: &str,
+    ) -> Result<String> {
+        let mut fake = self.state.lock().unwrap();
+
+        if let Some(error) = &fake.payment_error {
+            return Err(anyhow!(error.clone()));
+        }
+
+        fake.onchain_sends.push(outputs.to_vec());
+        let txid = hex::encode(sha256(
+            format!("fake-tx-{}", fake.onchain_sends.len()).as_bytes(),
+        ));
+        fake.transactions.insert(label.to_string(), txid.clone());
+
+        if fake.payment_in_flight {
+            return Err(anyhow!("connection reset"));
+        }
+
+        Ok(txid)
+    }
+
+    async fn find_transaction(&self, label: &str) -> Result<Option<String>> {
+        Ok(self.state.lock().unwrap().transactions.get(label).cloned())
+    }
+
+    fn subscribe_invoice(
+        self: Arc<Self>,
+        payment_hash: String,
+    ) -> BoxStream<'static, Result<InvoiceStatus>> {
+        let receiver = self.updates.subscribe();
+        let current = self
+            .state
+            .lock()
+            .unwrap()
+            .invoices
+            .get(&payment_hash)
+            .map(|invoice| InvoiceStatus {
+                payment_hash: payment_hash.clone(),
+                state: invoice.state,
+                amount_paid: invoice.amount_paid,
+            });
+
+        let current = match current {
+            Some(current) => current,
+            None => return stream::once(async { Err(anyhow!("invoice not found")) }).boxed(),
+        };
+
+        let done = current.state.is_final();
+        let updates = stream::unfold(
+            (receiver, payment_hash, done),
+            |(mut receiver, payment_hash, done)| async move {
+                if done {
+                    return None;
+                }
+
+                loop {
+                    match receiver.recv().await {
+                        Ok(status) if status.payment_hash == payment_hash => {
+                            let done = status.state.is_final();
+                            return Some((Ok(status), (receiver, payment_hash, done)));
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        );
+
+        stream::once(async { Ok(current) }).chain(updates).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+
+    use super::FakeLightningBackend;
+    use crate::lightning::{AddInvoice, InvoiceState, LightningBackend};
+
+    #[tokio::test]
+    async fn test_fake_invoices_are_valid_bolt11() {
+        let backend = FakeLightningBackend::new();
+        let invoice = backend
+            .add_invoice(AddInvoice {
+                memo: "Nodeless".to_string(),
+                amount: 1000,
+                expiry: 3600,
+                description_hash: None,
+            })
+            .await
+            .unwrap();
+
+        let (hrp, _, variant) = bech32::decode(&invoice.payment_request).unwrap();
+        assert_eq!(hrp, "lntb10u");
+        assert_eq!(variant, bech32::Variant::Bech32);
+        assert_eq!(invoice.payment_hash.len(), 64);
+
+        let again = FakeLightningBackend::new()
+            .add_invoice(AddInvoice {
+                memo: "Nodeless".to_string(),
+                amount: 1000,
+                expiry: 3600,
+                description_hash: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(again, invoice);
+
+        let address = backend.next_address().await.unwrap();
+        let (hrp, _, _) = bech32::decode(&address).unwrap();
+        assert_eq!(hrp, "tb");
+    }
+
+    #[tokio::test]
+    async fn test_fake_settlement() {
+        let backend = Arc::new(FakeLightningBackend::new());
+        let invoice = backend
+            .add_invoice(AddInvoice {
+                memo: "Nodeless".to_string(),
+                amount: 1234,
+                expiry: 3600,
+                description_hash: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            backend.settled_amount(&invoice.payment_hash).await.unwrap(),
+            None
+        );
+
+        let mut updates = backend
+            .clone()
+            .subscribe_invoice(invoice.payment_hash.clone());
+        let open = updates.next().await.unwrap().unwrap();
+        assert_eq!(open.state, InvoiceState::Open);
+
+        backend.settle(&invoice.payment_hash, None);
+
+        let settled = updates.next().await.unwrap().unwrap();
+        assert_eq!(settled.state, InvoiceState::Settled);
+        assert_eq!(settled.amount_paid, 1234);
+        assert!(updates.next().await.is_none());
+
+        assert_eq!(
+            backend.settled_amount(&invoice.payment_hash).await.unwrap(),
+            Some(1234)
+        );
+    }
+}
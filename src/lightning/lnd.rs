@@ -0,0 +1,230 @@
+use std::{collections::HashMap, fs};
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+
+use super::{Invoice, InvoiceState, InvoiceStatus};
+
+/// Client for the parts of LND's REST API the cluster client doesn't
+/// expose: invoices committing to a description hash, and the wallet's
+/// transaction history.
+#[derive(Clone)]
+pub struct LndRestClient {
+    host: String,
+    macaroon: String,
+    client: reqwest::Client,
+}
+
+#[derive(Serialize)]
+struct AddInvoiceRequest<'a> {
+    memo: &'a str,
+    value: i64,
+    expiry: i64,
+    /// Base64, as LND's REST API encodes bytes.
+    description_hash: String,
+}
+
+#[derive(Deserialize)]
+struct AddInvoiceResponse {
+    r_hash: String,
+    payment_request: String,
+}
+
+#[derive(Deserialize)]
+struct LookupInvoiceResponse {
+    state: String,
+    #[serde(default, deserialize_with = "int64")]
+    amt_paid_sat: i64,
+}
+
+#[derive(Deserialize)]
+struct TransactionsResponse {
+    #[serde(default)]
+    transactions: Vec<Transaction>,
+}
+
+#[derive(Deserialize)]
+struct Transaction {
+    #[serde(default)]
+    num_confirmations: i32,
+    #[serde(default)]
+    output_details: Vec<OutputDetail>,
+}
+
+#[derive(Deserialize)]
+struct OutputDetail {
+    #[serde(default)]
+    address: String,
+    #[serde(default, deserialize_with = "int64")]
+    amount: i64,
+    #[serde(default)]
+    is_our_address: bool,
+}
+
+/// LND's REST API encodes 64-bit integers as strings.
+fn int64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Int64 {
+        String(String),
+        Number(i64),
+    }
+
+    match Int64::deserialize(deserializer)? {
+        Int64::String(value) => value.parse().map_err(serde::de::Error::custom),
+        Int64::Number(value) => Ok(value),
+    }
+}
+
+impl LndRestClient {
+    /// Reads the node's TLS certificate and macaroon, so a bad path fails
+    /// at startup.
+    pub fn new(host: &str, cert_path: &str, macaroon_path: &str) -> Result<Self> {
+        let cert = fs::read(cert_path)
+            .map_err(|e| anyhow!("Could not read TLS cert {}: {}", cert_path, e))?;
+        let macaroon = fs::read(macaroon_path)
+            .map_err(|e| anyhow!("Could not read macaroon {}: {}", macaroon_path, e))?;
+
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&cert)?)
+            .build()?;
+
+        Ok(Self {
+            host: host.trim_end_matches('/').to_string(),
+            macaroon: hex::encode(macaroon),
+            client,
+        })
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let response = request
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("LND returned {}: {}", status, body));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Creates an invoice committing to `description_hash`, given in hex,
+    /// instead of the memo.
+    pub async fn add_invoice(
+        &self,
+        memo: &str,
+        amount: i64,
+        expiry: i64,
+        description_hash: &str,
+    ) -> Result<Invoice> {
+        let request = AddInvoiceRequest {
+            memo,
+            value: amount,
+            expiry,
+            description_hash: base64::encode(hex::decode(description_hash)?),
+        };
+
+        let invoice: AddInvoiceResponse = self
+            .send(
+                self.client
+                    .post(format!("{}/v1/invoices", self.host))
+                    .json(&request),
+            )
+            .await?;
+
+        Ok(Invoice {
+            payment_hash: hex::encode(base64::decode(&invoice.r_hash)?),
+            payment_request: invoice.payment_request,
+        })
+    }
+
+    pub async fn lookup_invoice(&self, payment_hash: &str) -> Result<InvoiceStatus> {
+        let invoice: LookupInvoiceResponse = self
+            .send(
+                self.client
+                    .get(format!("{}/v1/invoice/{}", self.host, payment_hash)),
+            )
+            .await?;
+
+        let state = match invoice.state.as_str() {
+            "OPEN" => InvoiceState::Open,
+            "ACCEPTED" => InvoiceState::Accepted,
+            "SETTLED" => InvoiceState::Settled,
+            "CANCELED" => InvoiceState::Canceled,
+            state => return Err(anyhow!("Unknown invoice state: {}", state)),
+        };
+
+        Ok(InvoiceStatus {
+            payment_hash: payment_hash.to_string(),
+            state,
+            amount_paid: invoice.amt_paid_sat,
+        })
+    }
+
+    /// Adds the sats every wallet transaction paid to each of the addresses
+    /// to its (confirmed, unconfirmed) totals. Outputs count once received,
+    /// whether or not they were spent since.
+    pub async fn add_received(&self, received: &mut HashMap<String, (i64, i64)>) -> Result<()> {
+        let history: TransactionsResponse = self
+            .send(self.client.get(format!("{}/v1/transactions", self.host)))
+            .await?;
+
+        for transaction in &history.transactions {
+            for output in &transaction.output_details {
+                if !output.is_our_address {
+                    continue;
+                }
+
+                if let Some((confirmed, unconfirmed)) = received.get_mut(&output.address) {
+                    if transaction.num_confirmations > 0 {
+                        *confirmed += output.amount;
+                    } else {
+                        *unconfirmed += output.amount;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LookupInvoiceResponse, TransactionsResponse};
+
+    #[test]
+    fn test_parse_int64_strings() {
+        let invoice: LookupInvoiceResponse =
+            serde_json::from_str(r#"{"state": "SETTLED", "amt_paid_sat": "2100"}"#).unwrap();
+        assert_eq!(invoice.amt_paid_sat, 2100);
+
+        let invoice: LookupInvoiceResponse =
+            serde_json::from_str(r#"{"state": "OPEN", "amt_paid_sat": 0}"#).unwrap();
+        assert_eq!(invoice.amt_paid_sat, 0);
+    }
+
+    #[test]
+    fn test_parse_transaction_history() {
+        let history: TransactionsResponse = serde_json::from_str(
+            r#"{"transactions": [{
+                "tx_hash": "ab",
+                "num_confirmations": 3,
+                "output_details": [
+                    {"address": "tb1qpaid", "amount": "5000", "is_our_address": true},
+                    {"address": "tb1qchange", "amount": "1200", "is_our_address": false}
+                ]
+            }]}"#,
+        )
+        .unwrap();
+
+        let output = &history.transactions[0].output_details[0];
+        assert_eq!(history.transactions[0].num_confirmations, 3);
+        assert_eq!(output.address, "tb1qpaid");
+        assert_eq!(output.amount, 5000);
+        assert!(output.is_our_address);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, stream::BoxStream, StreamExt};
+use thiserror::Error;
+
+pub mod cluster;
+#[cfg(test)]
+pub mod fake;
+pub mod lnd;
+
+const SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct AddInvoice {
+    pub memo: String,
+    /// Amount in sats.
+    pub amount: i64,
+    pub expiry: i64,
+    /// Hex SHA-256 of a description the invoice commits to instead of the
+    /// memo, as LNURL-pay and zaps require.
+    pub description_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    pub payment_hash: String,
+    pub payment_request: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvoiceState {
+    Open,
+    Accepted,
+    Settled,
+    Canceled,
+}
+
+impl InvoiceState {
+    /// Settled and canceled invoices never change state again.
+    pub fn is_final(&self) -> bool {
+        matches!(self, InvoiceState::Settled | InvoiceState::Canceled)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvoiceStatus {
+    pub payment_hash: String,
+    pub state: InvoiceState,
+    /// Amount paid in sats, zero until settled.
+    pub amount_paid: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payment {
+    pub payment_hash: String,
+    pub preimage: String,
+    /// Routing fee paid in sats.
+    pub fee: i64,
+}
+
+/// Why `pay_invoice` returned without a payment.
+#[derive(Error, Debug)]
+pub enum PaymentError {
+    /// The node gave up on the payment and has nothing left in flight, so it
+    /// will never settle.
+    #[error("{0}")]
+    Failed(String),
+    /// The call failed without a final answer, e.g. a timeout or dropped
+    /// connection. The payment may still be in flight or have settled.
+    #[error(transparent)]
+    Unknown(#[from] anyhow::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// The node has no record of a payment to this hash.
+    NotFound,
+    InFlight,
+    Succeeded(Payment),
+    Failed(String),
+}
+
+/// Everything the API needs from a lightning node. Implemented for the
+/// lightning cluster and, in tests, by an in-memory fake.
+#[async_trait]
+pub trait LightningBackend: Send + Sync + 'static {
+    async fn add_invoice(&self, req: AddInvoice) -> Result<Invoice>;
+
+    async fn next_address(&self) -> Result<String>;
+
+    async fn lookup_invoice(&self, payment_hash: &str) -> Result<InvoiceStatus>;
+
+    /// Returns (confirmed, unconfirmed) sats received at the address.
+    async fn address_received(&self, address: &str) -> Result<(i64, i64)>;
+
+    /// Returns (confirmed, unconfirmed) sats received at each of the
+    /// addresses. Backends that can should look them all up at once.
+    async fn addresses_received(
+        &self,
+        addresses: &[String],
+    ) -> Result<HashMap<String, (i64, i64)>> {
+        let mut received = HashMap::new();
+
+        for address in addresses {
+            received.insert(address.clone(), self.address_received(address).await?);
+        }
+
+        Ok(received)
+    }
+
+    /// Pays a BOLT11 invoice, paying at most `fee_limit` sats in routing fees.
+    async fn pay_invoice(
+        &self,
+        payment_request: &str,
+        fee_limit: i64,
+    ) -> Result<Payment, PaymentError>;
+
+    /// Status of an outgoing payment, for payments whose `pay_invoice` call
+    /// ended without a final answer.
+    async fn lookup_payment(&self, payment_hash: &str) -> Result<PaymentStatus>;
+
+    /// Sends one on-chain transaction paying every `(address, sats)` output,
+    /// aiming for confirmation within `target_conf` blocks, and labels it in
+    /// the wallet. Returns the txid.
+    async fn send_many(
+        &self,
+        outputs: &[(String, i64)],
+        target_conf: i32,
+        label: &str,
+    ) -> Result<String>;
+
+    /// Txid of the wallet transaction with `label`, if the wallet has one.
+    async fn find_transaction(&self, label: &str) -> Result<Option<String>>;
+
+    /// Amount paid to the invoice, once settled.
+    async fn settled_amount(&self, payment_hash: &str) -> Result<Option<i64>> {
+        let invoice = self.lookup_invoice(payment_hash).await?;
+
+        match invoice.state {
+            InvoiceState::Settled => Ok(Some(invoice.amount_paid)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Yields the invoice status every time it changes and ends once it is
+    /// settled or canceled. Backends without push updates poll `lookup_invoice`.
+    fn subscribe_invoice(
+        self: Arc<Self>,
+        payment_hash: String,
+    ) -> BoxStream<'static, Result<InvoiceStatus>> {
+        stream::unfold(
+            (self, payment_hash, None, false),
+            |(backend, payment_hash, last, done)| async move {
+                if done {
+                    return None;
+                }
+
+                loop {
+                    match backend.lookup_invoice(&payment_hash).await {
+                        Ok(status) if Some(status.state) != last => {
+                            let state = status.state;
+                            return Some((
+                                Ok(status),
+                                (backend, payment_hash, Some(state), state.is_final()),
+                            ));
+                        }
+                        Ok(_) => tokio::time::sleep(SUBSCRIPTION_POLL_INTERVAL).await,
+                        Err(e) => return Some((Err(e), (backend, payment_hash, last, true))),
+                    }
+                }
+            },
+        )
+        .boxed()
+    }
+}
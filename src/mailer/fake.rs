@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Email, Mailer};
+
+/// In-memory mailer stand-in that records what was sent.
+#[derive(Default)]
+pub struct FakeMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl FakeMailer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for FakeMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        self.sent.lock().unwrap().push(email);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+#[cfg(test)]
+pub mod fake;
+pub mod smtp;
+
+/// A plain text email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Sends account emails. Implemented over SMTP and, in tests, by an
+/// in-memory stand-in.
+#[async_trait]
+pub trait Mailer: Send + Sync + 'static {
+    async fn send(&self, email: Email) -> Result<()>;
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::{Email, Mailer};
+use crate::config::EmailConfig;
+
+pub struct SmtpMailer {
+    pub from: Mailbox,
+    pub transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailer {
+    /// Connects to the configured relay over STARTTLS.
+    pub fn new(config: &EmailConfig, username: String, password: String) -> Result<Self> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+            .port(config.smtp_port)
+            .credentials(Credentials::new(username, password))
+            .build();
+
+        Ok(Self {
+            from: config.from.parse()?,
+            transport,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)?;
+
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
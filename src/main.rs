@@ -1,28 +1,62 @@
 use actix_web::{middleware::Logger, web::Data, App, HttpServer};
-use handlers::frontend::*;
-use lightning_cluster::{
-    cluster::{Cluster, Node, NodeClient, NodeLightningImpl, NodeNetwork},
-    lnd::LndClient,
+use handlers::{api::*, frontend::*, public::*};
+use lightning::{cluster::ClusterBackend, LightningBackend};
+use lightning_cluster::cluster::NodeNetwork;
+use mailer::{smtp::SmtpMailer, Mailer};
+use middleware::{
+    jwt_middleware::AuthCache,
+    limiter_middleware::{ApiLimiter, CheckoutLimiter, GuestLimiter},
 };
-use middleware::limiter_middleware::{ApiLimiter, GuestLimiter};
 use moka::future::Cache;
+use nostr::{websocket::WebSocketRelayClient, RelayClient};
 use repositories::{
+    api_key_repository::ApiKeyRepository,
+    auth_challenge_repository::AuthChallengeRepository,
     checkout_repository::CheckoutRepository,
     donation_page_repository::{self, DonationPageRepository},
+    fee_repository::FeeRepository,
+    ledger_repository::LedgerRepository,
+    nodeless_address_repository::{NodelessAddressPaymentRepository, NodelessAddressRepository},
+    session_repository::SessionRepository,
+    signing_key_repository::SigningKeyRepository,
     store_repository::{StoreInvoiceRepository, StoreRepository},
     user_repository::UserRepository,
+    webhook_repository::{WebhookDeliveryRepository, WebhookRepository},
+    withdrawal_repository::WithdrawalRepository,
+};
+use services::{
+    account_service::AccountService, checkout_service::QrCodeCache, event_bus::EventBus,
+    fee_service::FeeService, ledger_service::LedgerService, lnurl_auth_service::LnurlAuthService,
+    nodeless_address_service::NodelessAddressService, nostr_auth_service::NostrAuthService,
+    session_service::SessionService, signing_key_service::SigningKeyService,
+    totp_service::TotpService, withdrawal_service::WithdrawalService, zap_service::ZapService,
 };
 use sqlx::PgPool;
 use std::{fs::read_to_string, sync::Arc, time::Duration};
 use toml::Value;
+use workers::{
+    checkout_expiry::CheckoutExpirySweeper,
+    handle_activator::HandleActivator,
+    ledger_poster::LedgerPoster,
+    payment_watcher::PaymentWatcher,
+    payout_batcher::PayoutBatcher,
+    session_sweeper::SessionSweeper,
+    webhook_dispatcher::{WebhookDeliverer, WebhookEnqueuer},
+    withdrawal_reconciler::WithdrawalReconciler,
+    zap_publisher::ZapPublisher,
+};
 
 pub mod config;
 pub mod handlers;
 pub mod helpers;
+pub mod lightning;
+pub mod mailer;
 pub mod middleware;
 pub mod models;
+pub mod nostr;
 pub mod repositories;
 pub mod services;
+pub mod workers;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -34,12 +68,120 @@ async fn main() -> std::io::Result<()> {
     let store_invoice_repo = StoreInvoiceRepository::new(pool.clone());
     let checkout_repository = CheckoutRepository::new(pool.clone());
     let donation_page_repository = DonationPageRepository::new(pool.clone());
+    let webhook_repository = WebhookRepository::new(pool.clone());
+    let webhook_delivery_repository = WebhookDeliveryRepository::new(pool.clone());
+    let api_key_repository = ApiKeyRepository::new(pool.clone());
+    let ledger_repository = LedgerRepository::new(pool.clone());
+    let withdrawal_repository = WithdrawalRepository::new(pool.clone());
+    let nodeless_address_repository = NodelessAddressRepository::new(pool.clone());
+    let nodeless_address_payment_repository = NodelessAddressPaymentRepository::new(pool.clone());
+    let session_repository = SessionRepository::new(pool.clone());
     let config_content = read_to_string("Nodeless.toml").expect("Failed to read Nodeless.toml");
     let toml_config: Value = config_content
         .parse()
         .expect("Failed to parse Nodeless.toml");
 
     let app_config = config::AppConfig::from(toml_config);
+    let network = app_config
+        .cluster
+        .network()
+        .unwrap_or_else(|e| panic!("Invalid cluster config: {}", e));
+    let allow_insecure_relays = !matches!(network, NodeNetwork::Mainnet);
+    helpers::password::check_params(&app_config.auth)
+        .unwrap_or_else(|e| panic!("Invalid auth config: {}", e));
+    if !helpers::signing_key::has_dedicated_secret() {
+        eprintln!("SIGNING_KEYS_SECRET is not set, signing keys are encrypted with the app key");
+    }
+    let signing_key_algorithm =
+        helpers::signing_key::parse_algorithm(&app_config.auth.signing_key_algorithm)
+            .expect("auth.signing_key_algorithm must be EdDSA or ES256");
+    let signing_key_service = SigningKeyService::new(
+        signing_key_algorithm,
+        app_config.auth.signing_keys_kept,
+        Duration::from_secs(app_config.auth.signing_key_refresh_seconds),
+        SigningKeyRepository::new(pool.clone()),
+    );
+
+    // `nodeless-api rotate-keys [EdDSA|ES256]` adds a signing key and exits.
+    // Running instances publish it within the refresh interval, and sign
+    // with it once cached copies of the JWKS without it have expired.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("rotate-keys") => {
+            let algorithm = match args
+                .get(1)
+                .map(|name| helpers::signing_key::parse_algorithm(name))
+            {
+                Some(Ok(algorithm)) => algorithm,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+                None => signing_key_algorithm,
+            };
+            let key = signing_key_service
+                .rotate(algorithm)
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            println!(
+                "Rotated signing keys, signing with {} ({}) from {}",
+                key.kid, key.algorithm, key.signs_from
+            );
+            return Ok(());
+        }
+        Some(command) => {
+            eprintln!("Unknown command: {}", command);
+            std::process::exit(2);
+        }
+        None => {}
+    }
+    signing_key_service
+        .load()
+        .await
+        .expect("Failed to load signing keys");
+
+    let nostr_auth_service = NostrAuthService::new(
+        app_config.auth.challenge_expiry_seconds,
+        app_config.nostr.login_url.clone(),
+        AuthChallengeRepository::new(pool.clone()),
+        user_repo.clone(),
+    );
+    let lnurl_auth_service = LnurlAuthService::new(
+        app_config.auth.challenge_expiry_seconds,
+        app_config.lnurl.callback_base_url.clone(),
+        AuthChallengeRepository::new(pool.clone()),
+        user_repo.clone(),
+    );
+    let auth_cache = AuthCache::new(Duration::from_secs(app_config.auth.user_cache_seconds));
+    let session_service = SessionService::new(
+        app_config.auth.jwt_expiry_seconds,
+        app_config.auth.refresh_token_expiry_seconds,
+        session_repository.clone(),
+        signing_key_service.clone(),
+        auth_cache.clone(),
+    );
+    let totp_service = TotpService::new(
+        app_config.meta.name.clone(),
+        app_config.auth.mfa_token_expiry_seconds,
+        user_repo.clone(),
+        signing_key_service.clone(),
+    );
+    let mailer: Arc<dyn Mailer> = Arc::new(
+        SmtpMailer::new(
+            &app_config.email,
+            dotenvy::var("SMTP_USERNAME").unwrap_or_default(),
+            dotenvy::var("SMTP_PASSWORD").unwrap_or_default(),
+        )
+        .expect("Failed to configure SMTP mailer"),
+    );
+    let account_service = AccountService::new(
+        app_config.auth.clone(),
+        app_config.email.clone(),
+        user_repo.clone(),
+        mailer,
+        signing_key_service.clone(),
+    );
+    let fee_service = FeeService::new(&app_config.pricing, FeeRepository::new(pool.clone()));
 
     let api_limiter_cache: Cache<String, u32> = Cache::builder()
         .time_to_live(Duration::from_secs(60))
@@ -59,6 +201,122 @@ async fn main() -> std::io::Result<()> {
         cache: guest_limiter_cache,
     };
 
+    let checkout_limiter = CheckoutLimiter {
+        cache: Arc::new(
+            Cache::builder()
+                .time_to_live(Duration::from_secs(60))
+                .build(),
+        ),
+    };
+    let qr_code_cache = QrCodeCache::new(10_000, Duration::from_secs(3600));
+
+    let lightning: Arc<dyn LightningBackend> = Arc::new(
+        ClusterBackend::from_config(&app_config.cluster)
+            .unwrap_or_else(|e| panic!("Invalid cluster config: {}", e)),
+    );
+    let event_bus = EventBus::new(1024);
+    let withdrawal_service = WithdrawalService::new(
+        lightning.clone(),
+        withdrawal_repository.clone(),
+        app_config.withdrawals.clone(),
+        network,
+    );
+
+    let payment_watcher = PaymentWatcher::new(
+        lightning.clone(),
+        checkout_repository.clone(),
+        event_bus.clone(),
+        Duration::from_secs(app_config.workers.payment_watcher_interval_seconds),
+        app_config.workers.late_payment_window_seconds,
+    );
+    actix_web::rt::spawn(payment_watcher.run());
+
+    let checkout_expiry_sweeper = CheckoutExpirySweeper::new(
+        checkout_repository.clone(),
+        event_bus.clone(),
+        Duration::from_secs(app_config.workers.checkout_expiry_interval_seconds),
+    );
+    actix_web::rt::spawn(checkout_expiry_sweeper.run());
+
+    let webhook_enqueuer = WebhookEnqueuer::new(
+        event_bus.clone(),
+        checkout_repository.clone(),
+        store_invoice_repo.clone(),
+        webhook_repository.clone(),
+        webhook_delivery_repository.clone(),
+    );
+    actix_web::rt::spawn(webhook_enqueuer.run());
+
+    let webhook_deliverer = WebhookDeliverer::new(
+        webhook_repository.clone(),
+        webhook_delivery_repository.clone(),
+        app_config.webhooks.clone(),
+    );
+    actix_web::rt::spawn(webhook_deliverer.run());
+
+    let ledger_poster = LedgerPoster::new(
+        event_bus.clone(),
+        LedgerService::new(
+            ledger_repository.clone(),
+            store_invoice_repo.clone(),
+            nodeless_address_repository.clone(),
+        ),
+        Duration::from_secs(app_config.workers.ledger_reconcile_interval_seconds),
+    );
+    actix_web::rt::spawn(ledger_poster.run());
+
+    let nostr_keys = helpers::nostr::keypair_from_hex(&dotenvy::var("NOSTR_PRIVATE_KEY").unwrap())
+        .expect("NOSTR_PRIVATE_KEY must be a hex secret key");
+    let relays: Arc<dyn RelayClient> = Arc::new(WebSocketRelayClient::new(
+        Duration::from_secs(app_config.nostr.relay_timeout_seconds),
+        allow_insecure_relays,
+    ));
+    let zap_service = ZapService::new(
+        nostr_keys,
+        relays,
+        app_config.nostr.relays.clone(),
+        nodeless_address_payment_repository.clone(),
+        checkout_repository.clone(),
+    );
+
+    let zap_publisher = ZapPublisher::new(
+        event_bus.clone(),
+        zap_service.clone(),
+        Duration::from_secs(app_config.workers.zap_receipt_interval_seconds),
+    );
+    actix_web::rt::spawn(zap_publisher.run());
+
+    let nodeless_address_service = NodelessAddressService::new(
+        app_config.nodeless_addresses.clone(),
+        nodeless_address_repository.clone(),
+        checkout_repository.clone(),
+    );
+
+    let handle_activator = HandleActivator::new(
+        event_bus.clone(),
+        nodeless_address_service.clone(),
+        Duration::from_secs(app_config.workers.handle_activation_interval_seconds),
+    );
+    actix_web::rt::spawn(handle_activator.run());
+
+    let payout_batcher = PayoutBatcher::new(
+        withdrawal_service.clone(),
+        Duration::from_secs(app_config.workers.payout_batch_interval_seconds),
+    );
+    actix_web::rt::spawn(payout_batcher.run());
+
+    let withdrawal_reconciler = WithdrawalReconciler::new(
+        withdrawal_service.clone(),
+        Duration::from_secs(app_config.workers.withdrawal_reconcile_interval_seconds),
+    );
+    actix_web::rt::spawn(withdrawal_reconciler.run());
+
+    let session_sweeper = SessionSweeper::new(
+        session_repository.clone(),
+        Duration::from_secs(app_config.workers.session_cleanup_interval_seconds),
+    );
+    actix_web::rt::spawn(session_sweeper.run());
+
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(user_repo.clone()))
@@ -66,35 +324,47 @@ async fn main() -> std::io::Result<()> {
             .app_data(Data::new(app_config.clone()))
             .app_data(Data::new(api_limiter.clone()))
             .app_data(Data::new(guest_limiter.clone()))
+            .app_data(Data::new(checkout_limiter.clone()))
+            .app_data(Data::new(qr_code_cache.clone()))
             .app_data(Data::new(store_invoice_repo.clone()))
             .app_data(Data::new(checkout_repository.clone()))
             .app_data(Data::new(donation_page_repository.clone()))
+            .app_data(Data::new(event_bus.clone()))
+            .app_data(Data::from(lightning.clone()))
+            .app_data(Data::new(fee_service.clone()))
+            .app_data(Data::new(webhook_repository.clone()))
+            .app_data(Data::new(webhook_delivery_repository.clone()))
+            .app_data(Data::new(api_key_repository.clone()))
+            .app_data(Data::new(ledger_repository.clone()))
+            .app_data(Data::new(withdrawal_repository.clone()))
+            .app_data(Data::new(withdrawal_service.clone()))
+            .app_data(Data::new(nodeless_address_repository.clone()))
+            .app_data(Data::new(nodeless_address_payment_repository.clone()))
+            .app_data(Data::new(zap_service.clone()))
+            .app_data(Data::new(nodeless_address_service.clone()))
+            .app_data(Data::new(nostr_auth_service.clone()))
+            .app_data(Data::new(lnurl_auth_service.clone()))
+            .app_data(Data::new(account_service.clone()))
+            .app_data(Data::new(totp_service.clone()))
+            .app_data(Data::new(session_repository.clone()))
+            .app_data(Data::new(session_service.clone()))
+            .app_data(Data::new(auth_cache.clone()))
+            .app_data(Data::new(signing_key_service.clone()))
             .wrap(Logger::new("%a %{User-Agent}i"))
             .configure(fe_auth_handlers::configure_routes)
             .configure(fe_store_handlers::configure_routes)
             .configure(fe_donation_page_handlers::configure_routes)
+            .configure(fe_checkout_handlers::configure_routes)
+            .configure(fe_balance_handlers::configure_routes)
+            .configure(fe_withdrawal_handlers::configure_routes)
+            .configure(fe_nodeless_address_handlers::configure_routes)
+            .configure(api_store_handlers::configure_routes)
+            .configure(public_jwks_handlers::configure_routes)
+            .configure(public_lnurl_handlers::configure_routes)
+            .configure(public_lnurl_auth_handlers::configure_routes)
+            .configure(public_nostr_handlers::configure_routes)
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
 }
-
-async fn init_cluster() -> Cluster {
-    let node1 = Node {
-        pubkey: dotenvy::var("NODE1_PUBKEY").unwrap(),
-        ip: dotenvy::var("NODE1_IP").unwrap(),
-        port: dotenvy::var("NODE1_PORT").unwrap(),
-        network: NodeNetwork::Testnet,
-        lightning_impl: NodeLightningImpl::Lnd,
-        client: NodeClient::Lnd(LndClient::new(
-            dotenvy::var("NODE1_HOST").unwrap(),
-            dotenvy::var("NODE1_CERT_PATH").unwrap(),
-            dotenvy::var("NODE1_MACAROON_PATH").unwrap(),
-        )),
-    };
-
-    let nodes = vec![node1];
-    let cluster = Cluster::new(nodes);
-
-    cluster
-}
@@ -1 +1,157 @@
+use actix_web::{dev, web, Error, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
 
+use crate::helpers::crypto::sha256_hex;
+use crate::models::api_key::ApiKey;
+use crate::repositories::api_key_repository::ApiKeyRepository;
+
+pub const API_KEY_PREFIX: &str = "nl_";
+
+/// Authenticates a request with a store API key sent either as
+/// `Authorization: Bearer nl_...` or in the `X-Api-Key` header.
+pub struct ApiKeyAuth {
+    pub api_key: ApiKey,
+}
+
+impl ApiKeyAuth {
+    pub fn store_uuid(&self) -> &str {
+        &self.api_key.store_uuid
+    }
+
+    pub fn user_uuid(&self) -> &str {
+        &self.api_key.user_uuid
+    }
+}
+
+fn extract_key(req: &HttpRequest) -> Option<String> {
+    let headers = req.headers();
+
+    if let Some(key) = headers.get("X-Api-Key").and_then(|h| h.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+
+    headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|key| key.trim().to_string())
+}
+
+impl FromRequest for ApiKeyAuth {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<ApiKeyAuth, Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
+        let key = extract_key(req);
+        let repo = req
+            .app_data::<web::Data<ApiKeyRepository>>()
+            .expect("Failed to get ApiKeyRepository from request")
+            .clone();
+
+        Box::pin(async move {
+            let key = match key {
+                Some(key) if key.starts_with(API_KEY_PREFIX) => key,
+                _ => return Err(actix_web::error::ErrorUnauthorized("Missing api key")),
+            };
+
+            let api_key = repo
+                .get_active_by_hash(&sha256_hex(&key))
+                .await
+                .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to get api key"))?
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid api key"))?;
+
+            if let Err(e) = repo.touch(&api_key.uuid).await {
+                eprintln!("Failed to update api key last use: {}", e);
+            }
+
+            Ok(ApiKeyAuth { api_key })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test::TestRequest, web, FromRequest, HttpRequest};
+
+    use super::ApiKeyAuth;
+    use crate::{
+        helpers::{
+            crypto::sha256_hex,
+            tests::{create_test_pool, create_test_user, delete_test_user},
+        },
+        repositories::{
+            api_key_repository::{ApiKeyRepository, CreateApiKey},
+            store_repository::StoreRepository,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_api_key_auth() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let store_repo = StoreRepository::new(pool.clone());
+        let store = store_repo.create(&user.uuid, "test store").await.unwrap();
+        let repo = ApiKeyRepository::new(pool.clone());
+        let secret = format!("nl_{}", user.uuid);
+
+        let key = repo
+            .create(CreateApiKey {
+                user_uuid: user.uuid.clone(),
+                store_uuid: store.uuid.clone(),
+                name: "backend".to_string(),
+                key_prefix: "nl_abcd".to_string(),
+                key_hash: sha256_hex(&secret),
+            })
+            .await
+            .unwrap();
+
+        let request = |header: (&str, String)| -> HttpRequest {
+            TestRequest::default()
+                .insert_header(header)
+                .app_data(web::Data::new(repo.clone()))
+                .to_http_request()
+        };
+        let bearer = || ("Authorization", format!("Bearer {}", secret));
+
+        let auth = ApiKeyAuth::extract(&request(bearer())).await.unwrap();
+        assert_eq!(auth.api_key.uuid, key.uuid);
+        let auth = ApiKeyAuth::extract(&request(("X-Api-Key", secret.clone())))
+            .await
+            .unwrap();
+        assert_eq!(auth.store_uuid(), store.uuid);
+
+        // Bearer tokens that aren't api keys, e.g. JWTs, are rejected.
+        assert!(
+            ApiKeyAuth::extract(&request(("Authorization", "Bearer eyJ.x.y".to_string())))
+                .await
+                .is_err()
+        );
+        assert!(
+            ApiKeyAuth::extract(&request(("Authorization", secret.clone())))
+                .await
+                .is_err()
+        );
+
+        // Keys stop working while their user is suspended or deleted.
+        sqlx::query("UPDATE users SET suspended_at = NOW() WHERE uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(ApiKeyAuth::extract(&request(bearer())).await.is_err());
+
+        sqlx::query("UPDATE users SET suspended_at = NULL, deleted_at = NOW() WHERE uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(ApiKeyAuth::extract(&request(bearer())).await.is_err());
+
+        repo.hard_delete(&key.uuid).await.unwrap();
+        let _ = store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -1,20 +1,35 @@
-use actix_web::{dev, Error, FromRequest, HttpRequest};
-use futures::future::{ready, Ready};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use actix_web::{dev, web, Error, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use moka::future::Cache;
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    models::user::User,
+    repositories::{session_repository::SessionRepository, user_repository::UserRepository},
+    services::signing_key_service::{SigningKeyError, SigningKeyService},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
-    sub: String,
-    exp: usize,
-    iat: usize,
+    pub sub: String,
+    pub exp: usize,
+    pub iat: usize,
+    /// Session the token was issued for. Tokens from before sessions
+    /// existed have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
-pub fn generate_jwt_token(
+pub async fn generate_jwt_token(
+    keys: &SigningKeyService,
     user_uuid: &str,
+    session_uuid: &str,
     exp_secs: u64,
-) -> Result<String, jsonwebtoken::errors::Error> {
+) -> Result<String, SigningKeyError> {
     let current_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -25,67 +40,184 @@ pub fn generate_jwt_token(
         sub: user_uuid.to_owned(),
         exp: expiration_time as usize,
         iat: current_time as usize,
+        sid: Some(session_uuid.to_owned()),
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(dotenvy::var("APP_KEY").unwrap().as_ref()),
-    )?;
-    Ok(token)
+    keys.sign(&claims).await
 }
 
-pub fn verify_jwt_token(
+pub async fn verify_jwt_token(
+    keys: &SigningKeyService,
     token: &str,
-) -> Result<jsonwebtoken::TokenData<Claims>, jsonwebtoken::errors::Error> {
-    let validation = Validation {
-        algorithms: vec![Algorithm::HS256],
-        ..Validation::default()
-    };
+) -> Result<jsonwebtoken::TokenData<Claims>, SigningKeyError> {
+    keys.verify::<Claims>(token).await
+}
 
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(dotenvy::var("APP_KEY").unwrap().as_ref()),
-        &validation,
-    )?;
-    Ok(token_data)
+/// Recently loaded users and session states, so authenticating a request
+/// doesn't always hit the database. Entries live for a few seconds, which
+/// is how long a deleted user or revoked session can go unnoticed unless
+/// its entry is invalidated.
+#[derive(Clone)]
+pub struct AuthCache {
+    pub users: Arc<Cache<String, User>>,
+    pub sessions: Arc<Cache<String, bool>>,
 }
 
-pub struct AuthorizationService {
-    user_uuid: Option<String>,
+impl AuthCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            users: Arc::new(Cache::builder().time_to_live(ttl).build()),
+            sessions: Arc::new(Cache::builder().time_to_live(ttl).build()),
+        }
+    }
+
+    pub async fn invalidate_session(&self, session_uuid: &str) {
+        self.sessions.invalidate(session_uuid).await;
+    }
+
+    /// Drops the user and the given sessions of theirs, e.g. after their
+    /// password was reset.
+    pub async fn invalidate_user(&self, user_uuid: &str, session_uuids: &[String]) {
+        self.users.invalidate(user_uuid).await;
+
+        for session_uuid in session_uuids {
+            self.invalidate_session(session_uuid).await;
+        }
+    }
 }
 
-impl AuthorizationService {
-    pub fn uuid(&self) -> Option<&String> {
-        self.user_uuid.as_ref()
+impl std::fmt::Debug for AuthCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthCache").finish_non_exhaustive()
     }
 }
 
-impl FromRequest for AuthorizationService {
+/// The user a request was made by, authenticated with a JWT sent as
+/// `Authorization: Bearer <token>`.
+pub struct AuthenticatedUser {
+    pub user: User,
+    /// Session the token was issued for. Tokens from before sessions
+    /// existed have none.
+    pub session_uuid: Option<String>,
+}
+
+impl AuthenticatedUser {
+    pub fn uuid(&self) -> &str {
+        &self.user.uuid
+    }
+}
+
+fn extract_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get("Authorization")?.to_str().ok()?.trim();
+    let token = header.strip_prefix("Bearer ").unwrap_or(header);
+
+    Some(token.trim().to_string())
+}
+
+async fn load_user(
+    uuid: &str,
+    cache: &AuthCache,
+    user_repo: &UserRepository,
+) -> Result<Option<User>, Error> {
+    if let Some(user) = cache.users.get(uuid) {
+        return Ok(Some(user));
+    }
+
+    let user = user_repo
+        .get_by_uuid(uuid)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to get user"))?;
+    if let Some(user) = &user {
+        cache.users.insert(uuid.to_string(), user.clone()).await;
+    }
+
+    Ok(user)
+}
+
+async fn is_session_active(
+    uuid: &str,
+    cache: &AuthCache,
+    session_repo: &SessionRepository,
+) -> Result<bool, Error> {
+    if let Some(active) = cache.sessions.get(uuid) {
+        return Ok(active);
+    }
+
+    let active = session_repo
+        .is_active(uuid)
+        .await
+        .map_err(|_| actix_web::error::ErrorInternalServerError("Failed to get session"))?;
+    cache.sessions.insert(uuid.to_string(), active).await;
+
+    Ok(active)
+}
+
+impl FromRequest for AuthenticatedUser {
     type Error = Error;
-    type Future = Ready<Result<AuthorizationService, Error>>;
+    type Future = LocalBoxFuture<'static, Result<AuthenticatedUser, Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
-        let auth_header = req.headers().get("Authorization");
-
-        if let Some(auth_header) = auth_header {
-            let secret = dotenvy::var("APP_KEY").unwrap();
-            let token = auth_header.to_str().unwrap_or("");
-            let decoding_key = DecodingKey::from_secret(secret.as_ref());
-
-            match decode::<Claims>(token, &decoding_key, &Validation::default()) {
-                Ok(token_data) => {
-                    // Extract the 'sub' field from the token and store it in AuthorizationService
-                    let user_uuid = token_data.claims.sub;
-                    let auth_service = AuthorizationService {
-                        user_uuid: Some(user_uuid),
-                    };
-                    ready(Ok(auth_service))
+        let token = extract_token(req);
+        let cache = req
+            .app_data::<web::Data<AuthCache>>()
+            .expect("Failed to get AuthCache from request")
+            .clone();
+        let user_repo = req
+            .app_data::<web::Data<UserRepository>>()
+            .expect("Failed to get UserRepository from request")
+            .clone();
+        let session_repo = req
+            .app_data::<web::Data<SessionRepository>>()
+            .expect("Failed to get SessionRepository from request")
+            .clone();
+        let keys = req
+            .app_data::<web::Data<SigningKeyService>>()
+            .expect("Failed to get SigningKeyService from request")
+            .clone();
+
+        Box::pin(async move {
+            let token =
+                token.ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing token"))?;
+            let claims = match verify_jwt_token(&keys, &token).await {
+                Ok(token_data) => token_data.claims,
+                Err(SigningKeyError::InvalidToken) => {
+                    return Err(actix_web::error::ErrorUnauthorized("Invalid token"))
                 }
-                Err(_) => ready(Err(actix_web::error::ErrorUnauthorized("Invalid token"))),
+                Err(e) => {
+                    eprintln!("Failed to verify token: {}", e);
+                    return Err(actix_web::error::ErrorInternalServerError(
+                        "Failed to verify token",
+                    ));
+                }
+            };
+
+            let user = match load_user(&claims.sub, &cache, &user_repo).await? {
+                Some(user) if user.deleted_at.is_none() => user,
+                _ => return Err(actix_web::error::ErrorUnauthorized("Invalid token")),
+            };
+            if user.suspended_at.is_some() {
+                return Err(actix_web::error::ErrorForbidden("Account suspended"));
             }
-        } else {
-            ready(Err(actix_web::error::ErrorUnauthorized("Missing token")))
-        }
+
+            // Tokens issued before the user's sessions were revoked, e.g. by
+            // a password reset, are no longer valid. `iat` is in whole
+            // seconds, so tokens from the same second are revoked too.
+            if let Some(revoked_at) = user.sessions_revoked_at {
+                if (claims.iat as i64) <= revoked_at.timestamp() {
+                    return Err(actix_web::error::ErrorUnauthorized("Session revoked"));
+                }
+            }
+
+            if let Some(sid) = &claims.sid {
+                if !is_session_active(sid, &cache, &session_repo).await? {
+                    return Err(actix_web::error::ErrorUnauthorized("Session revoked"));
+                }
+            }
+
+            Ok(AuthenticatedUser {
+                user,
+                session_uuid: claims.sid,
+            })
+        })
     }
 }
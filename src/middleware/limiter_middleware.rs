@@ -4,7 +4,7 @@ use futures_util::FutureExt;
 use moka::future::Cache;
 use std::sync::Arc;
 
-use crate::helpers::crypto::sha256_hmac;
+use crate::helpers::crypto::{app_key, sha256_hmac};
 
 #[derive(Clone)]
 pub struct ApiLimiter {
@@ -61,7 +61,7 @@ impl FromRequest for GuestLimiter {
     fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
         eprintln!("GuestLimiter");
         let user_ip = req.connection_info().peer_addr().unwrap().to_string();
-        let hashed_ip = sha256_hmac(user_ip.as_str(), dotenvy::var("APP_KEY").unwrap().as_str());
+        let hashed_ip = sha256_hmac(user_ip.as_str(), app_key());
         let guest_limiter = req
             .app_data::<GuestLimiter>()
             .expect("Failed to get GuestLimiter cache from request");
@@ -91,16 +91,35 @@ impl FromRequest for GuestLimiter {
 }
 
 pub async fn guest_limiter(req: &HttpRequest, cache: web::Data<GuestLimiter>, limit: u32) -> bool {
+    count_guest_request(req, &cache.cache, limit).await
+}
+
+/// Per-IP limit on public checkout requests, counted per minute and apart
+/// from the login attempts `GuestLimiter` counts.
+#[derive(Clone)]
+pub struct CheckoutLimiter {
+    pub cache: Arc<Cache<String, u32>>,
+}
+
+pub async fn checkout_limiter(
+    req: &HttpRequest,
+    cache: web::Data<CheckoutLimiter>,
+    limit: u32,
+) -> bool {
+    count_guest_request(req, &cache.cache, limit).await
+}
+
+async fn count_guest_request(req: &HttpRequest, cache: &Cache<String, u32>, limit: u32) -> bool {
     let user_ip = req.connection_info().peer_addr().unwrap().to_string();
-    let hashed_ip = sha256_hmac(user_ip.as_str(), dotenvy::var("APP_KEY").unwrap().as_str());
-    let count = cache.cache.get(&hashed_ip);
+    let hashed_ip = sha256_hmac(user_ip.as_str(), app_key());
+    let count = cache.get(&hashed_ip);
     if count.is_some() && count.unwrap() >= limit {
         return false;
     }
     if count.is_none() {
-        cache.cache.insert(hashed_ip, 1).await;
+        cache.insert(hashed_ip, 1).await;
     } else {
-        cache.cache.insert(hashed_ip, count.unwrap() + 1).await;
+        cache.insert(hashed_ip, count.unwrap() + 1).await;
     }
     true
 }
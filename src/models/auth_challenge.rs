@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+/// A single-use nonce a client proves control of a key with, e.g. by signing
+/// it in a nostr event.
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct AuthChallenge {
+    pub challenge: String,
+    /// Login method the challenge was issued for.
+    pub method: String,
+    pub expires_at: chrono::NaiveDateTime,
+    /// User the challenge was answered for, when it is answered out of band,
+    /// e.g. by a wallet for LNURL-auth.
+    #[serde(skip_serializing)]
+    pub user_uuid: Option<String>,
+    #[serde(skip_serializing)]
+    pub authenticated_at: Option<chrono::NaiveDateTime>,
+    /// SHA-256 of the secret an answered challenge is picked up with, for
+    /// challenges answered out of band.
+    #[serde(skip_serializing)]
+    pub poll_secret_hash: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
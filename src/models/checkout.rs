@@ -10,14 +10,24 @@ pub struct Checkout {
     pub status: CheckoutStatus,
     pub bitcoin_address: String,
     pub payment_request: String,
+    pub payment_hash: Option<String>,
+    /// Everything seen for the checkout, unconfirmed on-chain funds included.
+    pub amount_received: i64,
+    /// Lightning and confirmed on-chain funds, the part that can be credited.
+    pub amount_confirmed: i64,
+    /// How much of `amount_confirmed` has been posted to the ledger.
+    pub amount_credited: i64,
+    pub fee_amount: i64,
+    pub net_amount: i64,
     pub expiry_seconds: i64,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub expired_at: Option<chrono::NaiveDateTime>,
+    pub late_payment_at: Option<chrono::NaiveDateTime>,
     pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
-#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone)]
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "checkout_status", rename_all = "lowercase")]
 pub enum CheckoutStatus {
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct UserFeeOverride {
+    pub uuid: String,
+    pub user_uuid: String,
+    pub base_fee_sat: Option<i64>,
+    pub fee_rate_bps: Option<i64>,
+    pub note: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct FeeLedgerEntry {
+    pub uuid: String,
+    pub user_uuid: String,
+    pub checkout_uuid: String,
+    pub gross_amount: i64,
+    pub fee_amount: i64,
+    pub net_amount: i64,
+    pub base_fee_sat: i64,
+    pub fee_rate_bps: i64,
+    pub created_at: chrono::NaiveDateTime,
+    /// Unset until the checkout is credited, so the fee is not collected yet.
+    pub credited_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Fee charged on a single checkout and the policy it was computed with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CheckoutFee {
+    pub fee_amount: i64,
+    pub net_amount: i64,
+    pub base_fee_sat: i64,
+    pub fee_rate_bps: i64,
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct LedgerAccount {
+    pub uuid: String,
+    pub kind: LedgerAccountKind,
+    pub user_uuid: Option<String>,
+    pub store_uuid: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "ledger_account_kind", rename_all = "snake_case")]
+pub enum LedgerAccountKind {
+    User,
+    Store,
+    PlatformFees,
+    HotWallet,
+    /// Funds held for withdrawals that are still in flight.
+    WithdrawalReserve,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct LedgerTransaction {
+    pub uuid: String,
+    pub kind: LedgerTransactionKind,
+    pub reference_uuid: String,
+    pub description: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "ledger_transaction_kind", rename_all = "snake_case")]
+pub enum LedgerTransactionKind {
+    CheckoutPayment,
+    Withdrawal,
+    WithdrawalSettlement,
+    WithdrawalReversal,
+    Refund,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct LedgerEntry {
+    pub uuid: String,
+    pub transaction_uuid: String,
+    pub account_uuid: String,
+    /// Positive amounts credit the account, negative amounts debit it.
+    pub amount: i64,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// An entry on one of a user's accounts, with the transaction it belongs to.
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct UserLedgerEntry {
+    pub uuid: String,
+    pub transaction_uuid: String,
+    pub transaction_kind: LedgerTransactionKind,
+    pub reference_uuid: String,
+    pub description: Option<String>,
+    pub account_uuid: String,
+    pub store_uuid: Option<String>,
+    pub amount: i64,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct LedgerAccountBalance {
+    pub account_uuid: String,
+    pub kind: LedgerAccountKind,
+    pub store_uuid: Option<String>,
+    pub balance: i64,
+}
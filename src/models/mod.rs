@@ -1,5 +1,13 @@
+pub mod api_key;
+pub mod auth_challenge;
 pub mod checkout;
 pub mod donation_page;
+pub mod fee;
+pub mod ledger;
 pub mod nodeless_address;
+pub mod session;
+pub mod signing_key;
 pub mod store;
 pub mod user;
+pub mod webhook;
+pub mod withdrawal;
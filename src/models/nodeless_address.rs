@@ -1,13 +1,44 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
 pub struct NodelessAddress {
     pub uuid: String,
     pub user_uuid: String,
     pub handle: String,
     pub npub: Option<String>,
+    /// Price in sats the handle was bought for.
     pub price: i64,
+    pub status: NodelessAddressStatus,
+    /// Checkout the handle is being bought with.
+    pub checkout_uuid: Option<String>,
+    /// A pending handle is held for the buyer until then, or while its
+    /// checkout is being paid.
+    pub reserved_until: Option<chrono::NaiveDateTime>,
+    pub activated_at: Option<chrono::NaiveDateTime>,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub deleted_at: Option<chrono::NaiveDateTime>,
 }
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "nodeless_address_status", rename_all = "lowercase")]
+pub enum NodelessAddressStatus {
+    /// Reserved, waiting for its checkout to be paid.
+    Pending,
+    Active,
+}
+
+/// A payment made to a nodeless address over LNURL-pay.
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct NodelessAddressPayment {
+    pub uuid: String,
+    pub nodeless_address_uuid: String,
+    pub checkout_uuid: String,
+    pub comment: Option<String>,
+    /// The NIP-57 zap request, exactly as the sender signed it.
+    pub zap_request: Option<String>,
+    pub zap_receipt_id: Option<String>,
+    pub zap_receipt_published_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// A login on a device, kept alive by rotating refresh tokens.
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct Session {
+    pub uuid: String,
+    pub user_uuid: String,
+    /// User agent of the device that logged in.
+    pub device: Option<String>,
+    #[serde(skip_serializing)]
+    pub ip_hash: String,
+    pub last_seen_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+/// A key access tokens are signed with, identified in their `kid` header.
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct SigningKey {
+    pub kid: String,
+    /// `EdDSA` or `ES256`.
+    pub algorithm: String,
+    /// PKCS#8 document, encrypted with the signing keys secret.
+    #[serde(skip_serializing)]
+    pub private_key: String,
+    /// Raw public key, base64url encoded.
+    pub public_key: String,
+    /// When the key starts signing. Rotated keys are published in the JWKS
+    /// a while before, so cached copies of it know them by then.
+    pub signs_from: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::NaiveDateTime,
+}
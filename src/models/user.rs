@@ -1,15 +1,51 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize, sqlx::FromRow, Clone)]
 pub struct User {
     pub uuid: String,
-    pub email: String,
-    pub password: String,
+    /// Users who signed up with nostr or LNURL-auth have no email or password.
+    pub email: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
     pub npub: Option<String>,
     pub identifier: Option<String>,
+    pub email_verified_at: Option<NaiveDateTime>,
+    /// Tokens issued before this were revoked, e.g. by a password reset.
+    #[serde(skip_serializing)]
+    pub sessions_revoked_at: Option<DateTime<Utc>>,
+    /// Base32 TOTP secret, set once enrolment starts. Two-factor
+    /// authentication is only on once `totp_enabled_at` is set.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
+    /// Last time step a code was accepted for, so codes can't be replayed.
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>,
+    /// Wrong codes submitted for pending two-factor logins since the last
+    /// right one.
+    #[serde(skip_serializing)]
+    pub mfa_failures: i32,
+    /// Pending two-factor logins issued before this were revoked after too
+    /// many wrong codes.
+    #[serde(skip_serializing)]
+    pub mfa_tokens_revoked_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub last_login_at: NaiveDateTime,
     pub deleted_at: Option<NaiveDateTime>,
+    /// Suspended users can't use the API.
+    pub suspended_at: Option<NaiveDateTime>,
+}
+
+impl User {
+    /// Users who signed up with an email must confirm it. Users who signed up
+    /// with nostr or a lightning wallet have no email to confirm.
+    pub fn is_verified(&self) -> bool {
+        self.email.is_none() || self.email_verified_at.is_some()
+    }
+
+    pub fn has_totp(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
 }
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, sqlx::FromRow, Clone)]
+pub struct Withdrawal {
+    pub uuid: String,
+    pub user_uuid: String,
+    /// Store the funds are withdrawn from, or the user's own account if unset.
+    pub store_uuid: Option<String>,
+    pub kind: WithdrawalKind,
+    pub status: WithdrawalStatus,
+    /// What the user asked to pay: a BOLT11 invoice, lightning address or bitcoin address.
+    pub destination: String,
+    pub payment_request: Option<String>,
+    pub amount: i64,
+    /// Fee reserved on top of the amount: the routing fee limit for lightning,
+    /// the flat payout fee for on-chain.
+    pub fee_limit: i64,
+    pub fee_amount: Option<i64>,
+    pub payment_hash: Option<String>,
+    pub payment_preimage: Option<String>,
+    pub failure_reason: Option<String>,
+    /// Transaction that paid an on-chain withdrawal.
+    pub txid: Option<String>,
+    /// Payout batch an on-chain withdrawal was sent in, also the label of
+    /// the batch's wallet transaction.
+    pub batch_uuid: Option<String>,
+    pub idempotency_key: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub completed_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "withdrawal_kind", rename_all = "lowercase")]
+pub enum WithdrawalKind {
+    Lightning,
+    Onchain,
+}
+
+#[derive(Serialize, Deserialize, Debug, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "withdrawal_status", rename_all = "lowercase")]
+pub enum WithdrawalStatus {
+    Pending,
+    /// Claimed into an on-chain payout batch that is being, or may have
+    /// been, broadcast.
+    Processing,
+    Completed,
+    Failed,
+}
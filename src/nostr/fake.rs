@@ -0,0 +1,51 @@
+use std::{collections::HashSet, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::RelayClient;
+use crate::helpers::nostr::NostrEvent;
+
+/// In-memory relay stand-in that records what was published where.
+#[derive(Default)]
+pub struct FakeRelayClient {
+    published: Mutex<Vec<(String, NostrEvent)>>,
+    offline: Mutex<HashSet<String>>,
+}
+
+impl FakeRelayClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes publishing to `relay` fail until it is brought back online.
+    pub fn set_offline(&self, relay: &str, offline: bool) {
+        let mut relays = self.offline.lock().unwrap();
+
+        if offline {
+            relays.insert(relay.to_string());
+        } else {
+            relays.remove(relay);
+        }
+    }
+
+    pub fn published(&self) -> Vec<(String, NostrEvent)> {
+        self.published.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl RelayClient for FakeRelayClient {
+    async fn publish(&self, relay: &str, event: &NostrEvent) -> Result<()> {
+        if self.offline.lock().unwrap().contains(relay) {
+            return Err(anyhow!("relay {} is offline", relay));
+        }
+
+        self.published
+            .lock()
+            .unwrap()
+            .push((relay.to_string(), event.clone()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::helpers::nostr::NostrEvent;
+
+#[cfg(test)]
+pub mod fake;
+pub mod websocket;
+
+/// Publishes events to nostr relays. Implemented over websockets and, in
+/// tests, by an in-memory stand-in.
+#[async_trait]
+pub trait RelayClient: Send + Sync + 'static {
+    /// Sends the event to `relay`, succeeding once the relay accepts it.
+    async fn publish(&self, relay: &str, event: &NostrEvent) -> Result<()>;
+}
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{client_async_tls, tungstenite::Message};
+
+use super::RelayClient;
+use crate::helpers::{nostr::NostrEvent, outbound};
+
+pub struct WebSocketRelayClient {
+    pub timeout: Duration,
+    /// Whether plain `ws://` relays are allowed. Only off mainnet.
+    pub allow_insecure: bool,
+}
+
+impl WebSocketRelayClient {
+    pub fn new(timeout: Duration, allow_insecure: bool) -> Self {
+        Self {
+            timeout,
+            allow_insecure,
+        }
+    }
+
+    /// Relay urls come from zap requests, so the host is resolved once,
+    /// rejected unless it is public, and the connection pinned to it.
+    async fn connect(&self, relay: &str) -> Result<TcpStream> {
+        let url = Url::parse(relay).map_err(|_| anyhow!("Invalid relay url {}", relay))?;
+        match url.scheme() {
+            "wss" => {}
+            "ws" if self.allow_insecure => {}
+            _ => return Err(anyhow!("Invalid relay url {}", relay)),
+        }
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow!("Invalid relay url {}", relay))?
+            .trim_matches(|c| c == '[' || c == ']');
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| anyhow!("Invalid relay url {}", relay))?;
+        let addrs = outbound::resolve_public(host, port).await?;
+
+        Ok(TcpStream::connect(addrs[0]).await?)
+    }
+}
+
+#[async_trait]
+impl RelayClient for WebSocketRelayClient {
+    async fn publish(&self, relay: &str, event: &NostrEvent) -> Result<()> {
+        let publish = async {
+            let stream = self.connect(relay).await?;
+            let (mut socket, _) = client_async_tls(relay, stream).await?;
+            socket
+                .send(Message::Text(
+                    serde_json::json!(["EVENT", event]).to_string(),
+                ))
+                .await?;
+
+            // NIP-20: the relay answers with ["OK", <event id>, <accepted>, <message>].
+            while let Some(message) = socket.next().await {
+                let text = match message? {
+                    Message::Text(text) => text,
+                    _ => continue,
+                };
+
+                if let Ok((kind, id, accepted, reason)) =
+                    serde_json::from_str::<(String, String, bool, String)>(&text)
+                {
+                    if kind == "OK" && id == event.id {
+                        let _ = socket.close(None).await;
+
+                        return if accepted {
+                            Ok(())
+                        } else {
+                            Err(anyhow!("Relay rejected the event: {}", reason))
+                        };
+                    }
+                }
+            }
+
+            Err(anyhow!("Relay closed the connection"))
+        };
+
+        tokio::time::timeout(self.timeout, publish)
+            .await
+            .map_err(|_| anyhow!("Timed out publishing to {}", relay))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WebSocketRelayClient;
+    use crate::nostr::RelayClient;
+
+    #[tokio::test]
+    async fn test_relays_must_be_public() {
+        let event = serde_json::from_value(serde_json::json!({
+            "id": "id",
+            "pubkey": "pubkey",
+            "created_at": 0,
+            "kind": 9735,
+            "tags": [],
+            "content": "",
+            "sig": "sig",
+        }))
+        .unwrap();
+        let client = WebSocketRelayClient::new(Duration::from_secs(1), false);
+
+        for relay in [
+            "ws://relay.damus.io",
+            "wss://127.0.0.1:5432",
+            "wss://169.254.169.254",
+            "wss://[::1]",
+            "https://relay.damus.io",
+            "not a url",
+        ] {
+            assert!(client.publish(relay, &event).await.is_err(), "{}", relay);
+        }
+
+        let insecure = WebSocketRelayClient::new(Duration::from_secs(1), true);
+        assert!(insecure
+            .publish("ws://127.0.0.1:5432", &event)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("non-public"));
+    }
+}
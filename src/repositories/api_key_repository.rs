@@ -0,0 +1,173 @@
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use crate::models::api_key::ApiKey;
+
+#[derive(Debug, Clone)]
+pub struct ApiKeyRepository {
+    pool: PgPool,
+}
+
+pub struct CreateApiKey {
+    pub user_uuid: String,
+    pub store_uuid: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub key_hash: String,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, key: CreateApiKey) -> Result<ApiKey, Error> {
+        let uuid = Uuid::new_v4().to_string();
+        let key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            INSERT INTO api_keys (uuid, user_uuid, store_uuid, name, key_prefix, key_hash)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(&uuid)
+        .bind(key.user_uuid)
+        .bind(key.store_uuid)
+        .bind(key.name)
+        .bind(key.key_prefix)
+        .bind(key.key_hash)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    pub async fn get_all(&self, store_uuid: &str) -> Result<Vec<ApiKey>, Error> {
+        let keys = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE store_uuid = $1 ORDER BY created_at DESC",
+        )
+        .bind(store_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Looks up a key that has not been revoked by the hash of its secret.
+    /// Keys of deleted stores, and of deleted or suspended users, don't count.
+    pub async fn get_active_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, Error> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            SELECT api_keys.* FROM api_keys
+            INNER JOIN stores ON stores.uuid = api_keys.store_uuid
+            INNER JOIN users ON users.uuid = api_keys.user_uuid
+            WHERE api_keys.key_hash = $1
+                AND api_keys.revoked_at IS NULL
+                AND stores.deleted_at IS NULL
+                AND users.deleted_at IS NULL
+                AND users.suspended_at IS NULL
+            "#,
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    /// Records a use of the key, at most once a minute to spare the database.
+    pub async fn touch(&self, uuid: &str) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE api_keys SET last_used_at = NOW()
+            WHERE uuid = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+            "#,
+        )
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke(&self, store_uuid: &str, uuid: &str) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE api_keys SET revoked_at = NOW(), updated_at = NOW()
+            WHERE uuid = $1 AND store_uuid = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(uuid)
+        .bind(store_uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn hard_delete(&self, uuid: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE uuid = $1")
+            .bind(uuid)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        helpers::{
+            crypto::sha256_hex,
+            tests::{create_test_pool, create_test_user, delete_test_user},
+        },
+        repositories::store_repository::StoreRepository,
+    };
+
+    use super::{ApiKeyRepository, CreateApiKey};
+
+    #[tokio::test]
+    async fn test_api_key_lifecycle() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let store_repo = StoreRepository::new(pool.clone());
+        let store = store_repo.create(&user.uuid, "test store").await.unwrap();
+        let repo = ApiKeyRepository::new(pool.clone());
+        let key_hash = sha256_hex(&format!("nl_{}", user.uuid));
+
+        let key = repo
+            .create(CreateApiKey {
+                user_uuid: user.uuid.clone(),
+                store_uuid: store.uuid.clone(),
+                name: "backend".to_string(),
+                key_prefix: "nl_abcd".to_string(),
+                key_hash: key_hash.clone(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(key.store_uuid, store.uuid);
+        assert!(key.last_used_at.is_none());
+
+        let found = repo.get_active_by_hash(&key_hash).await.unwrap().unwrap();
+        assert_eq!(found.uuid, key.uuid);
+
+        repo.touch(&key.uuid).await.unwrap();
+        let found = repo.get_active_by_hash(&key_hash).await.unwrap().unwrap();
+        assert!(found.last_used_at.is_some());
+
+        assert_eq!(repo.get_all(&store.uuid).await.unwrap().len(), 1);
+
+        assert!(repo.revoke(&store.uuid, &key.uuid).await.unwrap());
+        assert!(!repo.revoke(&store.uuid, &key.uuid).await.unwrap());
+        assert!(repo.get_active_by_hash(&key_hash).await.unwrap().is_none());
+
+        assert!(repo.hard_delete(&key.uuid).await.unwrap());
+        let _ = store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+use rand::RngCore;
+use sqlx::PgPool;
+
+use crate::models::auth_challenge::AuthChallenge;
+
+#[derive(Debug, Clone)]
+pub struct AuthChallengeRepository {
+    pool: PgPool,
+}
+
+impl AuthChallengeRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Issues a random challenge for `method` that expires after
+    /// `expiry_seconds`. Expired challenges are cleaned up on the way.
+    pub async fn create(
+        &self,
+        method: &str,
+        expiry_seconds: i64,
+    ) -> Result<AuthChallenge, sqlx::Error> {
+        self.insert(method, expiry_seconds, None).await
+    }
+
+    /// Issues a challenge that is answered out of band, and can only be
+    /// picked up with the secret hashed to `poll_secret_hash`.
+    pub async fn create_polled(
+        &self,
+        method: &str,
+        expiry_seconds: i64,
+        poll_secret_hash: &str,
+    ) -> Result<AuthChallenge, sqlx::Error> {
+        self.insert(method, expiry_seconds, Some(poll_secret_hash))
+            .await
+    }
+
+    async fn insert(
+        &self,
+        method: &str,
+        expiry_seconds: i64,
+        poll_secret_hash: Option<&str>,
+    ) -> Result<AuthChallenge, sqlx::Error> {
+        sqlx::query("DELETE FROM auth_challenges WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        let mut challenge = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut challenge);
+
+        let challenge = sqlx::query_as::<_, AuthChallenge>(
+            r#"
+            INSERT INTO auth_challenges (challenge, method, expires_at, poll_secret_hash)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3), $4)
+            RETURNING *
+            "#,
+        )
+        .bind(hex::encode(challenge))
+        .bind(method)
+        .bind(expiry_seconds as f64)
+        .bind(poll_secret_hash)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    /// Uses up the challenge. Returns false if it was never issued for
+    /// `method`, has expired, or was already used.
+    pub async fn consume(&self, method: &str, challenge: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM auth_challenges
+            WHERE challenge = $1 AND method = $2 AND expires_at >= NOW()
+            "#,
+        )
+        .bind(challenge)
+        .bind(method)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the challenge if it was issued for `method` and hasn't expired.
+    pub async fn get(
+        &self,
+        method: &str,
+        challenge: &str,
+    ) -> Result<Option<AuthChallenge>, sqlx::Error> {
+        let challenge = sqlx::query_as::<_, AuthChallenge>(
+            r#"
+            SELECT * FROM auth_challenges
+            WHERE challenge = $1 AND method = $2 AND expires_at >= NOW()
+            "#,
+        )
+        .bind(challenge)
+        .bind(method)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(challenge)
+    }
+
+    /// Records that the challenge was answered for the user. Returns false if
+    /// it isn't live or was already answered.
+    pub async fn authenticate(
+        &self,
+        method: &str,
+        challenge: &str,
+        user_uuid: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE auth_challenges SET user_uuid = $1, authenticated_at = NOW()
+            WHERE challenge = $2 AND method = $3 AND expires_at >= NOW()
+                AND authenticated_at IS NULL
+            "#,
+        )
+        .bind(user_uuid)
+        .bind(challenge)
+        .bind(method)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Uses up an answered challenge and returns the user it was answered
+    /// for, or `None` if it hasn't been answered or `poll_secret_hash` isn't
+    /// the one it was issued with.
+    pub async fn consume_authenticated(
+        &self,
+        method: &str,
+        challenge: &str,
+        poll_secret_hash: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let user_uuid = sqlx::query_scalar::<_, String>(
+            r#"
+            DELETE FROM auth_challenges
+            WHERE challenge = $1 AND method = $2 AND expires_at >= NOW()
+                AND authenticated_at IS NOT NULL AND poll_secret_hash = $3
+            RETURNING user_uuid
+            "#,
+        )
+        .bind(challenge)
+        .bind(method)
+        .bind(poll_secret_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_uuid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuthChallengeRepository;
+    use crate::helpers::tests::create_test_pool;
+
+    #[tokio::test]
+    async fn test_challenges_are_single_use() {
+        let repo = AuthChallengeRepository::new(create_test_pool().await);
+
+        let challenge = repo.create("test", 60).await.unwrap();
+        assert_eq!(challenge.challenge.len(), 64);
+        assert!(!repo.consume("other", &challenge.challenge).await.unwrap());
+        assert!(repo.consume("test", &challenge.challenge).await.unwrap());
+        assert!(!repo.consume("test", &challenge.challenge).await.unwrap());
+
+        let expired = repo.create("test", -1).await.unwrap();
+        assert!(!repo.consume("test", &expired.challenge).await.unwrap());
+    }
+}
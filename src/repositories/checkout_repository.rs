@@ -1,6 +1,9 @@
 use sqlx::PgPool;
 
-use crate::models::checkout::{Checkout, CheckoutStatus};
+use crate::models::{
+    checkout::{Checkout, CheckoutStatus},
+    fee::CheckoutFee,
+};
 
 #[derive(Clone)]
 pub struct CheckoutRepository {
@@ -12,7 +15,11 @@ pub struct CreateCheckout {
     pub amount: i64,
     pub bitcoin_address: String,
     pub payment_request: String,
+    pub payment_hash: Option<String>,
     pub expiry_seconds: i64,
+    /// When set, the fee is stored on the checkout and recorded in the fee
+    /// ledger, pending until the checkout is credited.
+    pub fee: Option<CheckoutFee>,
 }
 
 impl CheckoutRepository {
@@ -22,10 +29,17 @@ impl CheckoutRepository {
 
     pub async fn create(&self, req: CreateCheckout) -> Result<Checkout, sqlx::Error> {
         let uuid = uuid::Uuid::new_v4().to_string();
+        let (fee_amount, net_amount) = match &req.fee {
+            Some(fee) => (fee.fee_amount, fee.net_amount),
+            None => (0, req.amount),
+        };
+
+        let mut tx = self.pool.begin().await?;
+
         let checkout = sqlx::query_as::<_, Checkout>(
             r#"
-            INSERT INTO checkouts (uuid, user_uuid, amount, status, bitcoin_address, payment_request, expiry_seconds)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO checkouts (uuid, user_uuid, amount, status, bitcoin_address, payment_request, payment_hash, expiry_seconds, fee_amount, net_amount)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             RETURNING *
             "#,
         )
@@ -35,10 +49,34 @@ impl CheckoutRepository {
         .bind(CheckoutStatus::New)
         .bind(req.bitcoin_address)
         .bind(req.payment_request)
+        .bind(req.payment_hash)
         .bind(req.expiry_seconds)
-        .fetch_one(&self.pool)
+        .bind(fee_amount)
+        .bind(net_amount)
+        .fetch_one(&mut *tx)
         .await?;
 
+        if let Some(fee) = req.fee {
+            sqlx::query(
+                r#"
+                INSERT INTO fee_ledger (uuid, user_uuid, checkout_uuid, gross_amount, fee_amount, net_amount, base_fee_sat, fee_rate_bps)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                "#,
+            )
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(&checkout.user_uuid)
+            .bind(&checkout.uuid)
+            .bind(checkout.amount)
+            .bind(fee.fee_amount)
+            .bind(fee.net_amount)
+            .bind(fee.base_fee_sat)
+            .bind(fee.fee_rate_bps)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
         Ok(checkout)
     }
 
@@ -55,6 +93,19 @@ impl CheckoutRepository {
         Ok(checkout)
     }
 
+    pub async fn get_by_uuids(&self, uuids: &[String]) -> Result<Vec<Checkout>, sqlx::Error> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            SELECT * FROM checkouts WHERE uuid = ANY($1)
+            "#,
+        )
+        .bind(uuids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
     pub async fn set_status(
         &self,
         uuid: &str,
@@ -62,10 +113,155 @@ impl CheckoutRepository {
     ) -> Result<Checkout, sqlx::Error> {
         let checkout = sqlx::query_as::<_, Checkout>(
             r#"
-            UPDATE checkouts SET status = $1 WHERE uuid = $2
+            UPDATE checkouts SET status = $1, updated_at = NOW() WHERE uuid = $2
+            RETURNING *
+            "#,
+        )
+        .bind(status)
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(checkout)
+    }
+
+    /// Checkouts created within the last `max_age_seconds` that can still
+    /// receive funds and need to be watched for payments, and paid ones with
+    /// on-chain funds still unconfirmed.
+    pub async fn get_unsettled(&self, max_age_seconds: i64) -> Result<Vec<Checkout>, sqlx::Error> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            SELECT * FROM checkouts
+            WHERE (status IN ('new', 'pendingconfirmation', 'underpaid')
+                    OR (status IN ('paid', 'overpaid') AND amount_confirmed < amount_received))
+                AND deleted_at IS NULL
+                AND created_at >= NOW() - make_interval(secs => $1)
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(max_age_seconds as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
+    /// Records what the checkout received, unless it moved on from
+    /// `expected`, e.g. because it expired in the meantime. Returns `None`
+    /// then. The recorded amounts never go down.
+    pub async fn set_payment_received(
+        &self,
+        uuid: &str,
+        expected: CheckoutStatus,
+        status: CheckoutStatus,
+        amount_received: i64,
+        amount_confirmed: i64,
+    ) -> Result<Option<Checkout>, sqlx::Error> {
+        let checkout = sqlx::query_as::<_, Checkout>(
+            r#"
+            UPDATE checkouts
+            SET status = $1, amount_received = GREATEST(amount_received, $2),
+                amount_confirmed = GREATEST(amount_confirmed, $3), updated_at = NOW()
+            WHERE uuid = $4 AND status = $5
+            RETURNING *
             "#,
         )
         .bind(status)
+        .bind(amount_received)
+        .bind(amount_confirmed)
+        .bind(uuid)
+        .bind(expected)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(checkout)
+    }
+
+    /// Checkouts for store invoices updated at or after `since`, oldest first.
+    pub async fn get_store_checkouts_updated_since(
+        &self,
+        since: chrono::NaiveDateTime,
+    ) -> Result<Vec<Checkout>, sqlx::Error> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            SELECT checkouts.* FROM checkouts
+            JOIN store_invoices ON store_invoices.checkout_uuid = checkouts.uuid
+            WHERE checkouts.updated_at >= $1
+            ORDER BY checkouts.updated_at ASC
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
+    /// Marks every `new` or `underpaid` checkout whose expiry has passed as
+    /// expired. Confirmed funds of underpaid ones are flagged as a late
+    /// payment, so they are still credited.
+    pub async fn expire_stale(&self) -> Result<Vec<Checkout>, sqlx::Error> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            UPDATE checkouts
+            SET status = $1, expired_at = NOW(), updated_at = NOW(),
+                late_payment_at = CASE WHEN amount_confirmed > 0 THEN NOW() END
+            WHERE status IN ($2, $3)
+                AND deleted_at IS NULL
+                AND created_at + expiry_seconds * INTERVAL '1 second' <= NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(CheckoutStatus::Expired)
+        .bind(CheckoutStatus::New)
+        .bind(CheckoutStatus::Underpaid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
+    /// Checkouts that expired within the last `window_seconds`, whose addresses
+    /// are still watched for late on-chain payments.
+    pub async fn get_recently_expired(
+        &self,
+        window_seconds: i64,
+    ) -> Result<Vec<Checkout>, sqlx::Error> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            SELECT * FROM checkouts
+            WHERE status = $1
+                AND deleted_at IS NULL
+                AND expired_at >= NOW() - $2 * INTERVAL '1 second'
+            ORDER BY expired_at ASC
+            "#,
+        )
+        .bind(CheckoutStatus::Expired)
+        .bind(window_seconds)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
+    pub async fn set_late_payment(
+        &self,
+        uuid: &str,
+        amount_received: i64,
+        amount_confirmed: i64,
+    ) -> Result<Checkout, sqlx::Error> {
+        let checkout = sqlx::query_as::<_, Checkout>(
+            r#"
+            UPDATE checkouts
+            SET amount_received = GREATEST(amount_received, $1),
+                amount_confirmed = GREATEST(amount_confirmed, $2), late_payment_at = NOW(),
+                updated_at = NOW()
+            WHERE uuid = $3
+            RETURNING *
+            "#,
+        )
+        .bind(amount_received)
+        .bind(amount_confirmed)
         .bind(uuid)
         .fetch_one(&self.pool)
         .await?;
@@ -97,7 +293,9 @@ mod tests {
             amount: 100,
             bitcoin_address: "test address".to_string(),
             payment_request: "test payment request".to_string(),
+            payment_hash: None,
             expiry_seconds: 100,
+            fee: None,
         };
 
         let checkout = checkout_repo.create(checkout).await.unwrap();
@@ -0,0 +1,151 @@
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use crate::models::fee::{FeeLedgerEntry, UserFeeOverride};
+
+#[derive(Debug, Clone)]
+pub struct FeeRepository {
+    pool: PgPool,
+}
+
+pub struct SetUserFeeOverride {
+    pub base_fee_sat: Option<i64>,
+    pub fee_rate_bps: Option<i64>,
+    pub note: Option<String>,
+}
+
+impl FeeRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_override(&self, user_uuid: &str) -> Result<Option<UserFeeOverride>, Error> {
+        let fee_override = sqlx::query_as::<_, UserFeeOverride>(
+            "SELECT * FROM user_fee_overrides WHERE user_uuid = $1",
+        )
+        .bind(user_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(fee_override)
+    }
+
+    pub async fn set_override(
+        &self,
+        user_uuid: &str,
+        data: SetUserFeeOverride,
+    ) -> Result<UserFeeOverride, Error> {
+        let uuid = Uuid::new_v4().to_string();
+        let fee_override = sqlx::query_as::<_, UserFeeOverride>(
+            r#"
+            INSERT INTO user_fee_overrides (uuid, user_uuid, base_fee_sat, fee_rate_bps, note)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_uuid) DO UPDATE SET
+                base_fee_sat = EXCLUDED.base_fee_sat,
+                fee_rate_bps = EXCLUDED.fee_rate_bps,
+                note = EXCLUDED.note,
+                updated_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(&uuid)
+        .bind(user_uuid)
+        .bind(data.base_fee_sat)
+        .bind(data.fee_rate_bps)
+        .bind(data.note)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(fee_override)
+    }
+
+    pub async fn delete_override(&self, user_uuid: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM user_fee_overrides WHERE user_uuid = $1")
+            .bind(user_uuid)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_ledger_entry(
+        &self,
+        checkout_uuid: &str,
+    ) -> Result<Option<FeeLedgerEntry>, Error> {
+        let entry = sqlx::query_as::<_, FeeLedgerEntry>(
+            "SELECT * FROM fee_ledger WHERE checkout_uuid = $1",
+        )
+        .bind(checkout_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(entry)
+    }
+
+    /// Sum of fees on checkouts that were credited in `[from, to)`.
+    pub async fn collected_fees(
+        &self,
+        from: chrono::NaiveDateTime,
+        to: chrono::NaiveDateTime,
+    ) -> Result<i64, Error> {
+        let total: Option<i64> = sqlx::query_scalar(
+            r#"
+            SELECT SUM(fee_amount)::BIGINT FROM fee_ledger
+            WHERE credited_at >= $1 AND credited_at < $2
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(total.unwrap_or(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::tests::{create_test_pool, create_test_user, delete_test_user};
+
+    use super::{FeeRepository, SetUserFeeOverride};
+
+    #[tokio::test]
+    async fn test_user_fee_override() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let repo = FeeRepository::new(pool.clone());
+
+        assert!(repo.get_override(&user.uuid).await.unwrap().is_none());
+
+        repo.set_override(
+            &user.uuid,
+            SetUserFeeOverride {
+                base_fee_sat: Some(0),
+                fee_rate_bps: None,
+                note: Some("partner".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let fee_override = repo
+            .set_override(
+                &user.uuid,
+                SetUserFeeOverride {
+                    base_fee_sat: Some(0),
+                    fee_rate_bps: Some(50),
+                    note: Some("partner".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(fee_override.fee_rate_bps, Some(50));
+
+        let fee_override = repo.get_override(&user.uuid).await.unwrap().unwrap();
+        assert_eq!(fee_override.base_fee_sat, Some(0));
+        assert_eq!(fee_override.fee_rate_bps, Some(50));
+
+        assert!(repo.delete_override(&user.uuid).await.unwrap());
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
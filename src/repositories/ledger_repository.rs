@@ -0,0 +1,495 @@
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::helpers::pagination::Cursor;
+use crate::models::checkout::Checkout;
+use crate::models::ledger::{
+    LedgerAccount, LedgerAccountBalance, LedgerAccountKind, LedgerTransaction,
+    LedgerTransactionKind, UserLedgerEntry,
+};
+
+#[derive(Error, Debug)]
+pub enum LedgerRepositoryError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("Ledger transaction {0} is not balanced")]
+    Unbalanced(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerRepository {
+    pool: PgPool,
+}
+
+/// Identifies an account by its owner; accounts are created on first use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountKey {
+    pub kind: LedgerAccountKind,
+    pub user_uuid: Option<String>,
+    pub store_uuid: Option<String>,
+}
+
+impl AccountKey {
+    pub fn user(user_uuid: &str) -> Self {
+        Self {
+            kind: LedgerAccountKind::User,
+            user_uuid: Some(user_uuid.to_string()),
+            store_uuid: None,
+        }
+    }
+
+    pub fn store(user_uuid: &str, store_uuid: &str) -> Self {
+        Self {
+            kind: LedgerAccountKind::Store,
+            user_uuid: Some(user_uuid.to_string()),
+            store_uuid: Some(store_uuid.to_string()),
+        }
+    }
+
+    pub fn platform_fees() -> Self {
+        Self {
+            kind: LedgerAccountKind::PlatformFees,
+            user_uuid: None,
+            store_uuid: None,
+        }
+    }
+
+    pub fn hot_wallet() -> Self {
+        Self {
+            kind: LedgerAccountKind::HotWallet,
+            user_uuid: None,
+            store_uuid: None,
+        }
+    }
+
+    pub fn withdrawal_reserve() -> Self {
+        Self {
+            kind: LedgerAccountKind::WithdrawalReserve,
+            user_uuid: None,
+            store_uuid: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewLedgerEntry {
+    pub account: AccountKey,
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewLedgerTransaction {
+    pub kind: LedgerTransactionKind,
+    pub reference_uuid: String,
+    pub description: Option<String>,
+    pub entries: Vec<NewLedgerEntry>,
+}
+
+impl LedgerRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn get_or_create_account(
+        conn: &mut PgConnection,
+        key: &AccountKey,
+    ) -> Result<LedgerAccount, sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO ledger_accounts (uuid, kind, user_uuid, store_uuid)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (kind, COALESCE(user_uuid, ''), COALESCE(store_uuid, '')) DO NOTHING
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(key.kind)
+        .bind(&key.user_uuid)
+        .bind(&key.store_uuid)
+        .execute(&mut *conn)
+        .await?;
+
+        let account = sqlx::query_as::<_, LedgerAccount>(
+            r#"
+            SELECT * FROM ledger_accounts
+            WHERE kind = $1
+                AND COALESCE(user_uuid, '') = COALESCE($2, '')
+                AND COALESCE(store_uuid, '') = COALESCE($3, '')
+            "#,
+        )
+        .bind(key.kind)
+        .bind(&key.user_uuid)
+        .bind(&key.store_uuid)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(account)
+    }
+
+    /// Locks the account until the surrounding transaction ends and returns
+    /// its balance, so concurrent debits can't both spend the same funds.
+    pub async fn lock_balance_with(
+        conn: &mut PgConnection,
+        key: &AccountKey,
+    ) -> Result<i64, sqlx::Error> {
+        let account = Self::get_or_create_account(conn, key).await?;
+
+        sqlx::query("SELECT uuid FROM ledger_accounts WHERE uuid = $1 FOR UPDATE")
+            .bind(&account.uuid)
+            .execute(&mut *conn)
+            .await?;
+
+        let balance: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0)::BIGINT FROM ledger_entries WHERE account_uuid = $1",
+        )
+        .bind(&account.uuid)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Posts a transaction on an open connection so callers can combine it
+    /// with their own writes. Returns `None` if the same kind and reference
+    /// was already posted.
+    pub async fn post_with(
+        conn: &mut PgConnection,
+        transaction: NewLedgerTransaction,
+    ) -> Result<Option<LedgerTransaction>, LedgerRepositoryError> {
+        let total: i64 = transaction.entries.iter().map(|entry| entry.amount).sum();
+        if total != 0 || transaction.entries.is_empty() {
+            return Err(LedgerRepositoryError::Unbalanced(
+                transaction.reference_uuid,
+            ));
+        }
+
+        let posted = sqlx::query_as::<_, LedgerTransaction>(
+            r#"
+            INSERT INTO ledger_transactions (uuid, kind, reference_uuid, description)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (kind, reference_uuid) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(transaction.kind)
+        .bind(&transaction.reference_uuid)
+        .bind(&transaction.description)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        let posted = match posted {
+            Some(posted) => posted,
+            None => return Ok(None),
+        };
+
+        for entry in transaction.entries.iter().filter(|entry| entry.amount != 0) {
+            let account = Self::get_or_create_account(conn, &entry.account).await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO ledger_entries (uuid, transaction_uuid, account_uuid, amount)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&posted.uuid)
+            .bind(&account.uuid)
+            .bind(entry.amount)
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        Ok(Some(posted))
+    }
+
+    /// Posts a transaction in its own database transaction.
+    pub async fn post(
+        &self,
+        transaction: NewLedgerTransaction,
+    ) -> Result<Option<LedgerTransaction>, LedgerRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+        let posted = Self::post_with(&mut tx, transaction).await?;
+        tx.commit().await?;
+
+        Ok(posted)
+    }
+
+    pub async fn get_transaction(
+        &self,
+        kind: LedgerTransactionKind,
+        reference_uuid: &str,
+    ) -> Result<Option<LedgerTransaction>, LedgerRepositoryError> {
+        let transaction = sqlx::query_as::<_, LedgerTransaction>(
+            "SELECT * FROM ledger_transactions WHERE kind = $1 AND reference_uuid = $2",
+        )
+        .bind(kind)
+        .bind(reference_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(transaction)
+    }
+
+    /// Posts a checkout's credit, moves its `amount_credited` up to what the
+    /// transaction was built from and marks its fee as collected, in one
+    /// database transaction. Returns
+    /// `None`, posting nothing, if the checkout was credited in the meantime.
+    pub async fn post_checkout_credit(
+        &self,
+        checkout: &Checkout,
+        transaction: NewLedgerTransaction,
+    ) -> Result<Option<LedgerTransaction>, LedgerRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE checkouts SET amount_credited = $1
+            WHERE uuid = $2 AND amount_credited = $3 AND amount_confirmed >= $1
+            "#,
+        )
+        .bind(checkout.amount_confirmed)
+        .bind(&checkout.uuid)
+        .bind(checkout.amount_credited)
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        // The fee is only collected once the checkout is credited.
+        sqlx::query(
+            r#"
+            UPDATE fee_ledger SET credited_at = NOW()
+            WHERE checkout_uuid = $1 AND credited_at IS NULL
+            "#,
+        )
+        .bind(&checkout.uuid)
+        .execute(&mut *tx)
+        .await?;
+
+        let posted = Self::post_with(&mut tx, transaction).await?;
+        if posted.is_some() {
+            tx.commit().await?;
+        }
+
+        Ok(posted)
+    }
+
+    /// Paid checkouts, late payments included, with confirmed funds that were
+    /// not credited yet.
+    pub async fn get_unposted_checkouts(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<Checkout>, LedgerRepositoryError> {
+        let checkouts = sqlx::query_as::<_, Checkout>(
+            r#"
+            SELECT * FROM checkouts
+            WHERE (status IN ('paid', 'overpaid') OR late_payment_at IS NOT NULL)
+                AND amount_confirmed > amount_credited
+                AND deleted_at IS NULL
+            ORDER BY updated_at ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(checkouts)
+    }
+
+    /// Balances of every account owned by the user, stores included.
+    pub async fn get_balances(
+        &self,
+        user_uuid: &str,
+    ) -> Result<Vec<LedgerAccountBalance>, LedgerRepositoryError> {
+        let balances = sqlx::query_as::<_, LedgerAccountBalance>(
+            r#"
+            SELECT
+                ledger_accounts.uuid AS account_uuid,
+                ledger_accounts.kind,
+                ledger_accounts.store_uuid,
+                COALESCE(SUM(ledger_entries.amount), 0)::BIGINT AS balance
+            FROM ledger_accounts
+            LEFT JOIN ledger_entries ON ledger_entries.account_uuid = ledger_accounts.uuid
+            WHERE ledger_accounts.user_uuid = $1
+            GROUP BY ledger_accounts.uuid
+            ORDER BY ledger_accounts.created_at ASC
+            "#,
+        )
+        .bind(user_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(balances)
+    }
+
+    pub async fn get_account_balance(
+        &self,
+        key: &AccountKey,
+    ) -> Result<i64, LedgerRepositoryError> {
+        let balance: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(ledger_entries.amount), 0)::BIGINT FROM ledger_entries
+            INNER JOIN ledger_accounts ON ledger_accounts.uuid = ledger_entries.account_uuid
+            WHERE ledger_accounts.kind = $1
+                AND COALESCE(ledger_accounts.user_uuid, '') = COALESCE($2, '')
+                AND COALESCE(ledger_accounts.store_uuid, '') = COALESCE($3, '')
+            "#,
+        )
+        .bind(key.kind)
+        .bind(&key.user_uuid)
+        .bind(&key.store_uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(balance)
+    }
+
+    /// Newest first page of entries on the user's accounts, after the cursor.
+    pub async fn get_user_entries(
+        &self,
+        user_uuid: &str,
+        cursor: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<UserLedgerEntry>, LedgerRepositoryError> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT
+                ledger_entries.uuid,
+                ledger_entries.transaction_uuid,
+                ledger_transactions.kind AS transaction_kind,
+                ledger_transactions.reference_uuid,
+                ledger_transactions.description,
+                ledger_entries.account_uuid,
+                ledger_accounts.store_uuid,
+                ledger_entries.amount,
+                ledger_entries.created_at
+            FROM ledger_entries
+            INNER JOIN ledger_accounts ON ledger_accounts.uuid = ledger_entries.account_uuid
+            INNER JOIN ledger_transactions
+                ON ledger_transactions.uuid = ledger_entries.transaction_uuid
+            WHERE ledger_accounts.user_uuid = "#,
+        );
+        query.push_bind(user_uuid);
+
+        if let Some(cursor) = cursor {
+            query
+                .push(" AND (ledger_entries.created_at, ledger_entries.uuid) < (")
+                .push_bind(cursor.created_at)
+                .push(", ")
+                .push_bind(cursor.uuid.clone())
+                .push(")");
+        }
+
+        query
+            .push(" ORDER BY ledger_entries.created_at DESC, ledger_entries.uuid DESC LIMIT ")
+            .push_bind(limit);
+
+        let entries = query
+            .build_query_as::<UserLedgerEntry>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        models::ledger::LedgerTransactionKind,
+    };
+
+    use super::{
+        AccountKey, LedgerRepository, LedgerRepositoryError, NewLedgerEntry, NewLedgerTransaction,
+    };
+
+    #[tokio::test]
+    async fn test_ledger_posting() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let repo = LedgerRepository::new(pool.clone());
+        let reference_uuid = uuid::Uuid::new_v4().to_string();
+
+        let transaction = NewLedgerTransaction {
+            kind: LedgerTransactionKind::Refund,
+            reference_uuid: reference_uuid.clone(),
+            description: Some("test".to_string()),
+            entries: vec![
+                NewLedgerEntry {
+                    account: AccountKey::hot_wallet(),
+                    amount: -1000,
+                },
+                NewLedgerEntry {
+                    account: AccountKey::user(&user.uuid),
+                    amount: 1000,
+                },
+            ],
+        };
+
+        let unbalanced = NewLedgerTransaction {
+            entries: vec![NewLedgerEntry {
+                account: AccountKey::user(&user.uuid),
+                amount: 1000,
+            }],
+            ..transaction.clone()
+        };
+        assert!(matches!(
+            repo.post(unbalanced).await,
+            Err(LedgerRepositoryError::Unbalanced(_))
+        ));
+
+        let posted = repo.post(transaction.clone()).await.unwrap().unwrap();
+        assert_eq!(posted.reference_uuid, reference_uuid);
+
+        // Posting the same reference twice is a no-op.
+        assert!(repo.post(transaction).await.unwrap().is_none());
+
+        let balances = repo.get_balances(&user.uuid).await.unwrap();
+        assert_eq!(balances.len(), 1);
+        assert_eq!(balances[0].balance, 1000);
+        assert_eq!(
+            repo.get_account_balance(&AccountKey::user(&user.uuid))
+                .await
+                .unwrap(),
+            1000
+        );
+
+        let entries = repo.get_user_entries(&user.uuid, None, 10).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].transaction_kind, LedgerTransactionKind::Refund);
+
+        // The database rejects unbalanced entries even when they bypass the repository.
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query(
+            "INSERT INTO ledger_entries (uuid, transaction_uuid, account_uuid, amount) VALUES ($1, $2, $3, 5)",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&posted.uuid)
+        .bind(&balances[0].account_uuid)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        assert!(tx.commit().await.is_err());
+
+        sqlx::query("DELETE FROM ledger_entries WHERE transaction_uuid = $1")
+            .bind(&posted.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM ledger_transactions WHERE uuid = $1")
+            .bind(&posted.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM ledger_accounts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
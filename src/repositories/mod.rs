@@ -1,5 +1,13 @@
+pub mod api_key_repository;
+pub mod auth_challenge_repository;
 pub mod checkout_repository;
 pub mod donation_page_repository;
+pub mod fee_repository;
+pub mod ledger_repository;
 pub mod nodeless_address_repository;
+pub mod session_repository;
+pub mod signing_key_repository;
 pub mod store_repository;
 pub mod user_repository;
+pub mod webhook_repository;
+pub mod withdrawal_repository;
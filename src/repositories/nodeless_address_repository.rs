@@ -1,12 +1,22 @@
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::models::nodeless_address::NodelessAddress;
+use crate::models::nodeless_address::{NodelessAddress, NodelessAddressPayment};
 
+/// Checkout statuses under which a reservation is kept past its expiry, as
+/// the payment may still settle.
+const SETTLING_CHECKOUT_STATUSES: &str = "('paid', 'overpaid', 'pendingconfirmation')";
+
+#[derive(Debug, Clone)]
 pub struct NodelessAddressRepository {
     pub pool: PgPool,
 }
 
+#[derive(Debug, Clone)]
+pub struct NodelessAddressPaymentRepository {
+    pub pool: PgPool,
+}
+
 pub struct CreateNodelessAddress {
     pub user_uuid: String,
     pub handle: String,
@@ -14,6 +24,16 @@ pub struct CreateNodelessAddress {
     pub price: i64,
 }
 
+/// Outcome of reserving a handle.
+#[derive(Debug)]
+pub enum Reservation {
+    Reserved(NodelessAddress),
+    /// Someone holds the handle.
+    Taken,
+    /// The user has as many live reservations as they may.
+    TooManyPending,
+}
+
 pub struct UpdateNodelessAddress {
     pub npub: Option<String>,
     pub price: Option<i64>,
@@ -62,6 +82,24 @@ impl NodelessAddressRepository {
         Ok(addr)
     }
 
+    /// Like `get_by_handle`, but soft-deleted and unpaid addresses don't resolve.
+    pub async fn get_active_by_handle(
+        &self,
+        handle: &str,
+    ) -> Result<Option<NodelessAddress>, sqlx::Error> {
+        let addr = sqlx::query_as::<_, NodelessAddress>(
+            r#"
+            SELECT * FROM nodeless_addresses
+            WHERE handle = $1 AND status = 'active' AND deleted_at IS NULL
+            "#,
+        )
+        .bind(handle)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(addr)
+    }
+
     pub async fn get_by_uuid(&self, uuid: &str) -> Result<NodelessAddress, sqlx::Error> {
         let addr = sqlx::query_as::<_, NodelessAddress>(
             r#"
@@ -84,7 +122,7 @@ impl NodelessAddressRepository {
         let addr = sqlx::query_as::<_, NodelessAddress>(
             r#"
             UPDATE nodeless_addresses
-            SET npub = $1, price = $2
+            SET npub = $1, price = COALESCE($2, price), updated_at = NOW()
             WHERE uuid = $3
             RETURNING *
             "#,
@@ -112,6 +150,247 @@ impl NodelessAddressRepository {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Reserves `handle` for the user as a pending address, unless they
+    /// have `max_pending` live reservations already. Expired reservations
+    /// of the handle are dropped first, unless their checkout is still
+    /// settling.
+    pub async fn reserve(
+        &self,
+        user_uuid: &str,
+        handle: &str,
+        price: i64,
+        reserved_until: chrono::NaiveDateTime,
+        max_pending: i64,
+    ) -> Result<Reservation, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        // Locks the user so concurrent purchases can't both pass the cap.
+        sqlx::query("SELECT 1 FROM users WHERE uuid = $1 FOR UPDATE")
+            .bind(user_uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        let pending = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM nodeless_addresses
+            WHERE user_uuid = $1 AND status = 'pending' AND reserved_until >= NOW()
+            "#,
+        )
+        .bind(user_uuid)
+        .fetch_one(&mut *tx)
+        .await?;
+        if pending >= max_pending {
+            return Ok(Reservation::TooManyPending);
+        }
+
+        sqlx::query(&format!(
+            r#"
+            DELETE FROM nodeless_addresses a
+            WHERE a.handle = $1 AND a.status = 'pending' AND a.reserved_until < NOW()
+                AND NOT EXISTS (
+                    SELECT 1 FROM checkouts c
+                    WHERE c.uuid = a.checkout_uuid AND c.status IN {}
+                )
+            "#,
+            SETTLING_CHECKOUT_STATUSES
+        ))
+        .bind(handle)
+        .execute(&mut *tx)
+        .await?;
+
+        let addr = sqlx::query_as::<_, NodelessAddress>(
+            r#"
+            INSERT INTO nodeless_addresses (uuid, user_uuid, handle, price, status, reserved_until)
+            VALUES ($1, $2, $3, $4, 'pending', $5)
+            ON CONFLICT (handle) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_uuid)
+        .bind(handle)
+        .bind(price)
+        .bind(reserved_until)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(match addr {
+            Some(addr) => Reservation::Reserved(addr),
+            None => Reservation::Taken,
+        })
+    }
+
+    /// Deletes pending addresses whose reservation expired, unless their
+    /// checkout is still settling, releasing the handles.
+    pub async fn delete_expired_reservations(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(&format!(
+            r#"
+            DELETE FROM nodeless_addresses a
+            WHERE a.status = 'pending' AND a.reserved_until < NOW()
+                AND NOT EXISTS (
+                    SELECT 1 FROM checkouts c
+                    WHERE c.uuid = a.checkout_uuid AND c.status IN {}
+                )
+            "#,
+            SETTLING_CHECKOUT_STATUSES
+        ))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Whether `handle` is held by an address, including soft-deleted ones
+    /// and reservations that are live or still settling.
+    pub async fn is_handle_taken(&self, handle: &str) -> Result<bool, sqlx::Error> {
+        let taken = sqlx::query_scalar::<_, bool>(&format!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM nodeless_addresses a
+                LEFT JOIN checkouts c ON c.uuid = a.checkout_uuid
+                WHERE a.handle = $1
+                    AND (a.status = 'active' OR a.reserved_until >= NOW() OR c.status IN {})
+            )
+            "#,
+            SETTLING_CHECKOUT_STATUSES
+        ))
+        .bind(handle)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(taken)
+    }
+
+    pub async fn set_checkout(
+        &self,
+        uuid: &str,
+        checkout_uuid: &str,
+    ) -> Result<NodelessAddress, sqlx::Error> {
+        let addr = sqlx::query_as::<_, NodelessAddress>(
+            r#"
+            UPDATE nodeless_addresses SET checkout_uuid = $1, updated_at = NOW()
+            WHERE uuid = $2
+            RETURNING *
+            "#,
+        )
+        .bind(checkout_uuid)
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(addr)
+    }
+
+    pub async fn get_by_checkout_uuid(
+        &self,
+        checkout_uuid: &str,
+    ) -> Result<Option<NodelessAddress>, sqlx::Error> {
+        let addr = sqlx::query_as::<_, NodelessAddress>(
+            "SELECT * FROM nodeless_addresses WHERE checkout_uuid = $1",
+        )
+        .bind(checkout_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(addr)
+    }
+
+    /// Activates pending addresses whose checkout was paid in full before it
+    /// expired, up to `limit`. Pass a checkout to only activate its address.
+    pub async fn activate_paid(
+        &self,
+        checkout_uuid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<NodelessAddress>, sqlx::Error> {
+        let addrs = sqlx::query_as::<_, NodelessAddress>(
+            r#"
+            UPDATE nodeless_addresses SET
+                status = 'active',
+                activated_at = NOW(),
+                reserved_until = NULL,
+                updated_at = NOW()
+            WHERE uuid IN (
+                SELECT a.uuid FROM nodeless_addresses a
+                JOIN checkouts c ON c.uuid = a.checkout_uuid
+                WHERE a.status = 'pending' AND a.deleted_at IS NULL
+                    AND c.status IN ('paid', 'overpaid')
+                    AND ($1::VARCHAR IS NULL OR c.uuid = $1)
+                LIMIT $2
+                FOR UPDATE OF a SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(checkout_uuid)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(addrs)
+    }
+
+    /// The user's addresses, active ones and reservations still awaiting
+    /// payment, newest first.
+    pub async fn get_all_by_user(
+        &self,
+        user_uuid: &str,
+    ) -> Result<Vec<NodelessAddress>, sqlx::Error> {
+        let addrs = sqlx::query_as::<_, NodelessAddress>(&format!(
+            r#"
+            SELECT a.* FROM nodeless_addresses a
+            LEFT JOIN checkouts c ON c.uuid = a.checkout_uuid
+            WHERE a.user_uuid = $1 AND a.deleted_at IS NULL
+                AND (a.status = 'active' OR a.reserved_until >= NOW() OR c.status IN {})
+            ORDER BY a.created_at DESC
+            "#,
+            SETTLING_CHECKOUT_STATUSES
+        ))
+        .bind(user_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(addrs)
+    }
+
+    pub async fn get_by_user_and_uuid(
+        &self,
+        user_uuid: &str,
+        uuid: &str,
+    ) -> Result<Option<NodelessAddress>, sqlx::Error> {
+        let addr = sqlx::query_as::<_, NodelessAddress>(
+            r#"
+            SELECT * FROM nodeless_addresses
+            WHERE user_uuid = $1 AND uuid = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(user_uuid)
+        .bind(uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(addr)
+    }
+
+    /// Soft deletes one of the user's addresses. The handle is not released,
+    /// so nobody else can take over a lightning or nostr address people
+    /// already know.
+    pub async fn delete_by_user(&self, user_uuid: &str, uuid: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE nodeless_addresses SET deleted_at = NOW(), updated_at = NOW()
+            WHERE user_uuid = $1 AND uuid = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(user_uuid)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn hard_delete(&self, uuid: &str) -> Result<bool, sqlx::Error> {
         let result = sqlx::query(
             r#"
@@ -127,6 +406,97 @@ impl NodelessAddressRepository {
     }
 }
 
+impl NodelessAddressPaymentRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        nodeless_address_uuid: &str,
+        checkout_uuid: &str,
+        comment: Option<&str>,
+        zap_request: Option<&str>,
+    ) -> Result<NodelessAddressPayment, sqlx::Error> {
+        let payment = sqlx::query_as::<_, NodelessAddressPayment>(
+            r#"
+            INSERT INTO nodeless_address_payments
+            (uuid, nodeless_address_uuid, checkout_uuid, comment, zap_request)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(nodeless_address_uuid)
+        .bind(checkout_uuid)
+        .bind(comment)
+        .bind(zap_request)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(payment)
+    }
+
+    pub async fn get_by_checkout_uuid(
+        &self,
+        checkout_uuid: &str,
+    ) -> Result<Option<NodelessAddressPayment>, sqlx::Error> {
+        let payment = sqlx::query_as::<_, NodelessAddressPayment>(
+            "SELECT * FROM nodeless_address_payments WHERE checkout_uuid = $1",
+        )
+        .bind(checkout_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(payment)
+    }
+
+    /// Paid zaps from the last day whose receipt hasn't been published yet,
+    /// oldest first.
+    pub async fn get_unpublished_zaps(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<NodelessAddressPayment>, sqlx::Error> {
+        let payments = sqlx::query_as::<_, NodelessAddressPayment>(
+            r#"
+            SELECT p.* FROM nodeless_address_payments p
+            JOIN checkouts c ON c.uuid = p.checkout_uuid
+            WHERE p.zap_request IS NOT NULL
+                AND p.zap_receipt_published_at IS NULL
+                AND p.created_at > NOW() - INTERVAL '1 day'
+                AND (c.status IN ('paid', 'overpaid') OR c.late_payment_at IS NOT NULL)
+            ORDER BY p.created_at ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(payments)
+    }
+
+    pub async fn set_zap_receipt_published(
+        &self,
+        uuid: &str,
+        zap_receipt_id: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE nodeless_address_payments
+            SET zap_receipt_id = $1, zap_receipt_published_at = NOW()
+            WHERE uuid = $2
+            "#,
+        )
+        .bind(zap_receipt_id)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use crate::helpers::tests::{create_test_pool, create_test_user, delete_test_user};
@@ -184,9 +554,12 @@ pub mod tests {
         assert_eq!(addr.npub, Some("test".to_string()));
         assert_eq!(addr.price, 100);
 
+        assert!(repo.get_active_by_handle("test").await.unwrap().is_some());
+
         let delete = repo.delete(&addr.uuid).await.unwrap();
 
         assert_eq!(delete, true);
+        assert!(repo.get_active_by_handle("test").await.unwrap().is_none());
 
         let hard_delete = repo.hard_delete(&addr.uuid).await.unwrap();
 
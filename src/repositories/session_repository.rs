@@ -0,0 +1,205 @@
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use crate::models::session::Session;
+
+#[derive(Debug, Clone)]
+pub struct SessionRepository {
+    pool: PgPool,
+}
+
+pub struct CreateSession {
+    pub user_uuid: String,
+    pub device: Option<String>,
+    pub ip_hash: String,
+    pub refresh_token_hash: String,
+    pub expiry_seconds: u64,
+}
+
+#[derive(Debug)]
+pub enum RefreshOutcome {
+    /// The token was swapped for the new one and the session extended.
+    Rotated(Session),
+    /// The token was already swapped before, so this session was revoked.
+    Reused(String),
+    /// The token was never issued, or its session ended.
+    Invalid,
+}
+
+impl SessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Starts a session with its first refresh token.
+    pub async fn create(&self, session: CreateSession) -> Result<Session, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let created = sqlx::query_as::<_, Session>(
+            r#"
+            INSERT INTO sessions (uuid, user_uuid, device, ip_hash, expires_at)
+            VALUES ($1, $2, $3, $4, NOW() + make_interval(secs => $5))
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&session.user_uuid)
+        .bind(&session.device)
+        .bind(&session.ip_hash)
+        .bind(session.expiry_seconds as f64)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, session_uuid) VALUES ($1, $2)")
+            .bind(&session.refresh_token_hash)
+            .bind(&created.uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
+    /// Deletes sessions, and their refresh tokens, that expired over
+    /// `retention_seconds` ago. Returns how many were deleted.
+    pub async fn delete_expired(&self, retention_seconds: u64) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "DELETE FROM sessions WHERE expires_at < NOW() - make_interval(secs => $1)",
+        )
+        .bind(retention_seconds as f64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Swaps a refresh token for a new one, extending its session. A token
+    /// that was already swapped revokes the session, since whoever holds it
+    /// isn't the only one.
+    pub async fn rotate(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+        ip_hash: &str,
+        expiry_seconds: u64,
+    ) -> Result<RefreshOutcome, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let token = sqlx::query_as::<_, (String, Option<chrono::NaiveDateTime>)>(
+            r#"
+            SELECT session_uuid, used_at
+            FROM refresh_tokens
+            WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let session_uuid = match token {
+            Some((session_uuid, None)) => session_uuid,
+            Some((session_uuid, Some(_))) => {
+                sqlx::query(
+                    r#"
+                    UPDATE sessions SET revoked_at = NOW()
+                    WHERE uuid = $1 AND revoked_at IS NULL
+                    "#,
+                )
+                .bind(&session_uuid)
+                .execute(&mut *tx)
+                .await?;
+                tx.commit().await?;
+
+                return Ok(RefreshOutcome::Reused(session_uuid));
+            }
+            None => return Ok(RefreshOutcome::Invalid),
+        };
+
+        let session = sqlx::query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = NOW(),
+                expires_at = NOW() + make_interval(secs => $3),
+                ip_hash = $2
+            WHERE uuid = $1 AND revoked_at IS NULL AND expires_at >= NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(&session_uuid)
+        .bind(ip_hash)
+        .bind(expiry_seconds as f64)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let session = match session {
+            Some(session) => session,
+            None => return Ok(RefreshOutcome::Invalid),
+        };
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, session_uuid) VALUES ($1, $2)")
+            .bind(new_token_hash)
+            .bind(&session.uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(RefreshOutcome::Rotated(session))
+    }
+
+    pub async fn is_active(&self, uuid: &str) -> Result<bool, Error> {
+        let active = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM sessions
+                WHERE uuid = $1 AND revoked_at IS NULL AND expires_at >= NOW()
+            )
+            "#,
+        )
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(active)
+    }
+
+    pub async fn get_active_by_user(&self, user_uuid: &str) -> Result<Vec<Session>, Error> {
+        let sessions = sqlx::query_as::<_, Session>(
+            r#"
+            SELECT * FROM sessions
+            WHERE user_uuid = $1 AND revoked_at IS NULL AND expires_at >= NOW()
+            ORDER BY last_seen_at DESC
+            "#,
+        )
+        .bind(user_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    /// Ends one of the user's sessions. Returns false if they have no such
+    /// active session.
+    pub async fn revoke(&self, user_uuid: &str, uuid: &str) -> Result<bool, Error> {
+        let rows = sqlx::query(
+            r#"
+            UPDATE sessions SET revoked_at = NOW()
+            WHERE uuid = $1 AND user_uuid = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(uuid)
+        .bind(user_uuid)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows > 0)
+    }
+}
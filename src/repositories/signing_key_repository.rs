@@ -0,0 +1,90 @@
+use sqlx::{Error, PgPool};
+
+use crate::models::signing_key::SigningKey;
+
+#[derive(Debug, Clone)]
+pub struct SigningKeyRepository {
+    pool: PgPool,
+}
+
+pub struct CreateSigningKey {
+    pub kid: String,
+    pub algorithm: String,
+    pub private_key: String,
+    pub public_key: String,
+    /// How long after it is created the key starts signing.
+    pub signs_in_seconds: u64,
+}
+
+impl SigningKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, key: CreateSigningKey) -> Result<SigningKey, Error> {
+        let created = sqlx::query_as::<_, SigningKey>(
+            r#"
+            INSERT INTO signing_keys (kid, algorithm, private_key, public_key, signs_from)
+            VALUES ($1, $2, $3, $4, NOW() + make_interval(secs => $5))
+            RETURNING *
+            "#,
+        )
+        .bind(&key.kid)
+        .bind(&key.algorithm)
+        .bind(&key.private_key)
+        .bind(&key.public_key)
+        .bind(key.signs_in_seconds as f64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(created)
+    }
+
+    /// The newest `limit` keys, newest first, and the newest one that signs
+    /// already in case those all start signing later.
+    pub async fn get_latest(&self, limit: i64) -> Result<Vec<SigningKey>, Error> {
+        let keys = sqlx::query_as::<_, SigningKey>(
+            r#"
+            SELECT * FROM signing_keys
+            WHERE kid IN (
+                SELECT kid FROM signing_keys ORDER BY created_at DESC, kid LIMIT $1
+            ) OR kid = (
+                SELECT kid FROM signing_keys
+                WHERE signs_from <= NOW()
+                ORDER BY created_at DESC, kid
+                LIMIT 1
+            )
+            ORDER BY created_at DESC, kid
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Deletes all but the newest `keep` keys, so tokens they signed stop
+    /// verifying. The newest key that signs already is kept too.
+    pub async fn prune(&self, keep: i64) -> Result<u64, Error> {
+        let rows = sqlx::query(
+            r#"
+            DELETE FROM signing_keys
+            WHERE kid NOT IN (
+                SELECT kid FROM signing_keys ORDER BY created_at DESC, kid LIMIT $1
+            ) AND kid IS DISTINCT FROM (
+                SELECT kid FROM signing_keys
+                WHERE signs_from <= NOW()
+                ORDER BY created_at DESC, kid
+                LIMIT 1
+            )
+            "#,
+        )
+        .bind(keep)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows)
+    }
+}
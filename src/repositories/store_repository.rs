@@ -1,6 +1,8 @@
-use sqlx::{Error, PgPool};
+use sqlx::{Error, PgPool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
+use crate::helpers::pagination::{Cursor, SortOrder};
+use crate::models::checkout::CheckoutStatus;
 use crate::models::store::{Store, StoreInvoice};
 
 #[derive(Debug, Clone)]
@@ -116,6 +118,18 @@ pub struct CreateStoreInvoice {
     pub metadata: Option<serde_json::Value>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct StoreInvoiceFilter {
+    pub status: Option<CheckoutStatus>,
+    pub from: Option<chrono::NaiveDateTime>,
+    pub to: Option<chrono::NaiveDateTime>,
+    /// Matches invoices whose metadata contains this JSON object.
+    pub metadata: Option<serde_json::Value>,
+    pub sort: SortOrder,
+    pub cursor: Option<Cursor>,
+    pub limit: i64,
+}
+
 impl StoreInvoiceRepository {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
@@ -138,6 +152,105 @@ impl StoreInvoiceRepository {
 
         Ok(invoice)
     }
+
+    pub async fn get_by_checkout_uuid(
+        &self,
+        checkout_uuid: &str,
+    ) -> Result<Option<StoreInvoice>, Error> {
+        let invoice = sqlx::query_as::<_, StoreInvoice>(
+            "SELECT * FROM store_invoices WHERE checkout_uuid = $1 AND deleted_at IS NULL",
+        )
+        .bind(checkout_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(invoice)
+    }
+
+    pub async fn get_by_uuid(
+        &self,
+        store_uuid: &str,
+        uuid: &str,
+    ) -> Result<Option<StoreInvoice>, Error> {
+        let invoice = sqlx::query_as::<_, StoreInvoice>(
+            "SELECT * FROM store_invoices WHERE uuid = $1 AND store_uuid = $2 AND deleted_at IS NULL",
+        )
+        .bind(uuid)
+        .bind(store_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(invoice)
+    }
+
+    /// Returns a page of invoices ordered by `(created_at, uuid)`, starting after the cursor.
+    pub async fn get_all(
+        &self,
+        store_uuid: &str,
+        filter: &StoreInvoiceFilter,
+    ) -> Result<Vec<StoreInvoice>, Error> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT store_invoices.* FROM store_invoices
+            INNER JOIN checkouts ON checkouts.uuid = store_invoices.checkout_uuid
+            WHERE store_invoices.deleted_at IS NULL AND store_invoices.store_uuid = ",
+        );
+        query.push_bind(store_uuid);
+
+        if let Some(status) = &filter.status {
+            query
+                .push(" AND checkouts.status = ")
+                .push_bind(status.clone());
+        }
+
+        if let Some(from) = filter.from {
+            query
+                .push(" AND store_invoices.created_at >= ")
+                .push_bind(from);
+        }
+
+        if let Some(to) = filter.to {
+            query
+                .push(" AND store_invoices.created_at < ")
+                .push_bind(to);
+        }
+
+        if let Some(metadata) = &filter.metadata {
+            query
+                .push(" AND store_invoices.metadata @> ")
+                .push_bind(metadata.clone());
+        }
+
+        let (comparison, direction) = match filter.sort {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+
+        if let Some(cursor) = &filter.cursor {
+            query
+                .push(format!(
+                    " AND (store_invoices.created_at, store_invoices.uuid) {} (",
+                    comparison
+                ))
+                .push_bind(cursor.created_at)
+                .push(", ")
+                .push_bind(cursor.uuid.clone())
+                .push(")");
+        }
+
+        query
+            .push(format!(
+                " ORDER BY store_invoices.created_at {0}, store_invoices.uuid {0} LIMIT ",
+                direction
+            ))
+            .push_bind(filter.limit);
+
+        let invoices = query
+            .build_query_as::<StoreInvoice>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(invoices)
+    }
 }
 
 #[cfg(test)]
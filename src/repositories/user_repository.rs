@@ -14,6 +14,12 @@ pub struct UserRepository {
     pool: PgPool,
 }
 
+/// A password reset, with the sessions it revoked.
+pub struct PasswordReset {
+    pub user: User,
+    pub revoked_sessions: Vec<String>,
+}
+
 pub struct CreateUser {
     pub email: Option<String>,
     pub password: Option<String>,
@@ -61,6 +67,54 @@ impl UserRepository {
         Ok(user)
     }
 
+    pub async fn get_by_uuid(&self, uuid: &str) -> Result<Option<User>, UserRepositoryError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT *
+            FROM users
+            WHERE uuid = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn get_user_by_npub(&self, npub: &str) -> Result<Option<User>, UserRepositoryError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT *
+            FROM users
+            WHERE npub = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(npub)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    pub async fn get_user_by_identifier(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<User>, UserRepositoryError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT *
+            FROM users
+            WHERE identifier = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(identifier)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
     pub async fn get_user_password(&self, email: &str) -> Result<String, UserRepositoryError> {
         let row = sqlx::query(
             r#"
@@ -77,6 +131,334 @@ impl UserRepository {
         Ok(password)
     }
 
+    pub async fn set_email_verified(&self, uuid: &str) -> Result<User, UserRepositoryError> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET email_verified_at = COALESCE(email_verified_at, NOW()), updated_at = NOW()
+            WHERE uuid = $1
+            RETURNING *
+            "#,
+        )
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Swaps the password hash, unless it was changed since `old_hash` was
+    /// read.
+    pub async fn replace_password_hash(
+        &self,
+        uuid: &str,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<bool, UserRepositoryError> {
+        let rows = sqlx::query(
+            r#"
+            UPDATE users
+            SET password = $3, updated_at = NOW()
+            WHERE uuid = $1 AND password = $2
+            "#,
+        )
+        .bind(uuid)
+        .bind(old_hash)
+        .bind(new_hash)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows > 0)
+    }
+
+    /// Stores the hash of a password reset token for the user.
+    pub async fn create_password_reset(
+        &self,
+        user_uuid: &str,
+        token_hash: &str,
+        expiry_seconds: u64,
+    ) -> Result<(), UserRepositoryError> {
+        sqlx::query(
+            r#"
+            INSERT INTO password_reset_tokens (token_hash, user_uuid, expires_at)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3))
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_uuid)
+        .bind(expiry_seconds as f64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Uses up the reset token, dropping the user's other reset tokens too.
+    /// Returns the user to set a new password for with `reset_password`, or
+    /// None if the token was never issued, has expired or was already used.
+    pub async fn consume_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<String>, UserRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let user_uuid = sqlx::query_scalar::<_, String>(
+            r#"
+            DELETE FROM password_reset_tokens
+            WHERE token_hash = $1 AND expires_at >= NOW()
+            RETURNING user_uuid
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(user_uuid) = &user_uuid {
+            sqlx::query("DELETE FROM password_reset_tokens WHERE user_uuid = $1")
+                .bind(user_uuid)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(user_uuid)
+    }
+
+    /// Sets a new password for a user whose reset token was consumed,
+    /// revoking their sessions and tokens.
+    pub async fn reset_password(
+        &self,
+        user_uuid: &str,
+        password_hash: &str,
+    ) -> Result<PasswordReset, UserRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        // The reset link was emailed, so following it proves the address.
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET password = $2,
+                email_verified_at = COALESCE(email_verified_at, NOW()),
+                sessions_revoked_at = NOW(),
+                updated_at = NOW()
+            WHERE uuid = $1
+            RETURNING *
+            "#,
+        )
+        .bind(user_uuid)
+        .bind(password_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let revoked_sessions = sqlx::query_scalar::<_, String>(
+            r#"
+            UPDATE sessions SET revoked_at = NOW()
+            WHERE user_uuid = $1 AND revoked_at IS NULL
+            RETURNING uuid
+            "#,
+        )
+        .bind(user_uuid)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(PasswordReset {
+            user,
+            revoked_sessions,
+        })
+    }
+
+    /// Starts TOTP enrolment with a new secret. Returns false if two-factor
+    /// authentication is already on.
+    pub async fn set_pending_totp_secret(
+        &self,
+        uuid: &str,
+        secret: &str,
+    ) -> Result<bool, UserRepositoryError> {
+        let rows = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_secret = $2, updated_at = NOW()
+            WHERE uuid = $1 AND totp_enabled_at IS NULL
+            "#,
+        )
+        .bind(uuid)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows > 0)
+    }
+
+    /// Turns on two-factor authentication with a pending secret, after a
+    /// first code for `step`, and replaces the user's recovery codes.
+    pub async fn enable_totp(
+        &self,
+        uuid: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<bool, UserRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let rows = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_enabled_at = NOW(), totp_last_step = $2, updated_at = NOW()
+            WHERE uuid = $1 AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL
+            "#,
+        )
+        .bind(uuid)
+        .bind(step)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        if rows == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_uuid = $1")
+            .bind(uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO recovery_codes (code_hash, user_uuid)
+            SELECT UNNEST($2::VARCHAR[]), $1
+            "#,
+        )
+        .bind(uuid)
+        .bind(recovery_code_hashes)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Turns off two-factor authentication and drops the recovery codes.
+    pub async fn disable_totp(&self, uuid: &str) -> Result<(), UserRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL,
+                updated_at = NOW()
+            WHERE uuid = $1
+            "#,
+        )
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_uuid = $1")
+            .bind(uuid)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Records that a code for `step` was accepted. Returns false if one for
+    /// that step or a later one already was.
+    pub async fn use_totp_step(&self, uuid: &str, step: i64) -> Result<bool, UserRepositoryError> {
+        let rows = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_last_step = $2
+            WHERE uuid = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+            "#,
+        )
+        .bind(uuid)
+        .bind(step)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows > 0)
+    }
+
+    /// Counts a wrong two-factor code. The `max_failures`th one revokes the
+    /// user's pending logins and starts the count over. Returns whether it
+    /// did.
+    pub async fn record_mfa_failure(
+        &self,
+        uuid: &str,
+        max_failures: i32,
+    ) -> Result<bool, UserRepositoryError> {
+        let revoked = sqlx::query_scalar::<_, bool>(
+            r#"
+            UPDATE users
+            SET mfa_failures = CASE WHEN mfa_failures + 1 >= $2 THEN 0 ELSE mfa_failures + 1 END,
+                mfa_tokens_revoked_at = CASE
+                    WHEN mfa_failures + 1 >= $2 THEN NOW() ELSE mfa_tokens_revoked_at
+                END
+            WHERE uuid = $1
+            RETURNING mfa_failures = 0
+            "#,
+        )
+        .bind(uuid)
+        .bind(max_failures)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(revoked)
+    }
+
+    /// Ends a pending two-factor login that was completed, revoking the
+    /// user's pending logins so its token can't be used again. Returns false
+    /// if they were revoked since `tokens_revoked_at`, i.e. the token was
+    /// already used.
+    pub async fn complete_mfa_login(
+        &self,
+        uuid: &str,
+        tokens_revoked_at: Option<chrono::NaiveDateTime>,
+    ) -> Result<bool, UserRepositoryError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET mfa_failures = 0, mfa_tokens_revoked_at = NOW()
+            WHERE uuid = $1 AND mfa_tokens_revoked_at IS NOT DISTINCT FROM $2
+            "#,
+        )
+        .bind(uuid)
+        .bind(tokens_revoked_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Uses up a recovery code. Returns false if the user has no such code.
+    pub async fn use_recovery_code(
+        &self,
+        uuid: &str,
+        code_hash: &str,
+    ) -> Result<bool, UserRepositoryError> {
+        let rows = sqlx::query(
+            r#"
+            DELETE FROM recovery_codes
+            WHERE user_uuid = $1 AND code_hash = $2
+            "#,
+        )
+        .bind(uuid)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rows > 0)
+    }
+
     pub async fn delete(&self, uuid: &str) -> Result<(), UserRepositoryError> {
         sqlx::query(
             r#"
@@ -0,0 +1,538 @@
+use sqlx::{Error, PgPool};
+use uuid::Uuid;
+
+use crate::models::webhook::{
+    Webhook, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryStatus,
+};
+
+#[derive(Debug, Clone)]
+pub struct WebhookRepository {
+    pool: PgPool,
+}
+
+pub struct CreateWebhook {
+    pub store_uuid: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+pub struct UpdateWebhook {
+    pub url: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+}
+
+impl WebhookRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(&self, webhook: CreateWebhook) -> Result<Webhook, Error> {
+        let uuid = Uuid::new_v4().to_string();
+        let webhook = sqlx::query_as::<_, Webhook>(
+            r#"
+            INSERT INTO webhooks (uuid, store_uuid, url, secret, events)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#,
+        )
+        .bind(&uuid)
+        .bind(webhook.store_uuid)
+        .bind(webhook.url)
+        .bind(webhook.secret)
+        .bind(webhook.events)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn get_all(&self, store_uuid: &str) -> Result<Vec<Webhook>, Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE store_uuid = $1 AND deleted_at IS NULL ORDER BY created_at ASC",
+        )
+        .bind(store_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn count(&self, store_uuid: &str) -> Result<i64, Error> {
+        let count: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM webhooks WHERE store_uuid = $1 AND deleted_at IS NULL",
+        )
+        .bind(store_uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count.0)
+    }
+
+    pub async fn get_by_uuid(
+        &self,
+        store_uuid: &str,
+        webhook_uuid: &str,
+    ) -> Result<Option<Webhook>, Error> {
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE uuid = $1 AND store_uuid = $2 AND deleted_at IS NULL",
+        )
+        .bind(webhook_uuid)
+        .bind(store_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn get_one(&self, webhook_uuid: &str) -> Result<Option<Webhook>, Error> {
+        let webhook = sqlx::query_as::<_, Webhook>(
+            "SELECT * FROM webhooks WHERE uuid = $1 AND deleted_at IS NULL",
+        )
+        .bind(webhook_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    /// Enabled webhooks of a store that listen for `event`.
+    pub async fn get_subscribed(
+        &self,
+        store_uuid: &str,
+        event: &str,
+    ) -> Result<Vec<Webhook>, Error> {
+        let webhooks = sqlx::query_as::<_, Webhook>(
+            r#"
+            SELECT * FROM webhooks
+            WHERE store_uuid = $1 AND $2 = ANY(events) AND enabled AND deleted_at IS NULL
+            "#,
+        )
+        .bind(store_uuid)
+        .bind(event)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    pub async fn update(
+        &self,
+        store_uuid: &str,
+        webhook_uuid: &str,
+        data: UpdateWebhook,
+    ) -> Result<Option<Webhook>, Error> {
+        let webhook = sqlx::query_as::<_, Webhook>(
+            r#"
+            UPDATE webhooks SET url = $1, events = $2, enabled = $3, updated_at = NOW()
+            WHERE uuid = $4 AND store_uuid = $5 AND deleted_at IS NULL
+            RETURNING *
+            "#,
+        )
+        .bind(data.url)
+        .bind(data.events)
+        .bind(data.enabled)
+        .bind(webhook_uuid)
+        .bind(store_uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(webhook)
+    }
+
+    pub async fn delete(&self, store_uuid: &str, webhook_uuid: &str) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "UPDATE webhooks SET deleted_at = NOW() WHERE uuid = $1 AND store_uuid = $2 AND deleted_at IS NULL",
+        )
+        .bind(webhook_uuid)
+        .bind(store_uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn hard_delete(&self, webhook_uuid: &str) -> Result<bool, Error> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE uuid = $1")
+            .bind(webhook_uuid)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookDeliveryRepository {
+    pool: PgPool,
+}
+
+pub struct CreateWebhookDeliveryAttempt {
+    pub delivery_uuid: String,
+    pub response_code: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i64,
+}
+
+impl WebhookDeliveryRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn enqueue(
+        &self,
+        webhook_uuid: &str,
+        event: &str,
+        payload: serde_json::Value,
+    ) -> Result<WebhookDelivery, Error> {
+        let uuid = Uuid::new_v4().to_string();
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            INSERT INTO webhook_deliveries (uuid, webhook_uuid, event, payload)
+            VALUES ($1, $2, $3, $4)
+            RETURNING *
+            "#,
+        )
+        .bind(&uuid)
+        .bind(webhook_uuid)
+        .bind(event)
+        .bind(payload)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(delivery)
+    }
+
+    /// Whether `event` was already queued to the webhook for the checkout, at
+    /// or after `since` if given.
+    pub async fn has_checkout_delivery(
+        &self,
+        webhook_uuid: &str,
+        event: &str,
+        checkout_uuid: &str,
+        since: Option<chrono::NaiveDateTime>,
+    ) -> Result<bool, Error> {
+        let exists = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM webhook_deliveries
+                WHERE webhook_uuid = $1 AND event = $2
+                    AND payload->'data'->'checkout'->>'uuid' = $3
+                    AND ($4::timestamp IS NULL OR created_at >= $4)
+            )
+            "#,
+        )
+        .bind(webhook_uuid)
+        .bind(event)
+        .bind(checkout_uuid)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    /// Claims up to `limit` due deliveries by pushing their next attempt
+    /// `lease_seconds` into the future, so concurrent workers skip them.
+    pub async fn claim_due(
+        &self,
+        limit: i64,
+        lease_seconds: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            UPDATE webhook_deliveries
+            SET next_attempt_at = NOW() + $1 * INTERVAL '1 second', updated_at = NOW()
+            WHERE uuid IN (
+                SELECT uuid FROM webhook_deliveries
+                WHERE status = $2 AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at ASC
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(lease_seconds)
+        .bind(WebhookDeliveryStatus::Pending)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    pub async fn record_attempt(
+        &self,
+        attempt: CreateWebhookDeliveryAttempt,
+    ) -> Result<WebhookDeliveryAttempt, Error> {
+        let uuid = Uuid::new_v4().to_string();
+        let attempt = sqlx::query_as::<_, WebhookDeliveryAttempt>(
+            r#"
+            INSERT INTO webhook_delivery_attempts (uuid, delivery_uuid, response_code, response_body, error, duration_ms)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(&uuid)
+        .bind(attempt.delivery_uuid)
+        .bind(attempt.response_code)
+        .bind(attempt.response_body)
+        .bind(attempt.error)
+        .bind(attempt.duration_ms)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(attempt)
+    }
+
+    pub async fn mark_delivered(&self, uuid: &str) -> Result<WebhookDelivery, Error> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $1, attempts = attempts + 1, delivered_at = NOW(), updated_at = NOW()
+            WHERE uuid = $2
+            RETURNING *
+            "#,
+        )
+        .bind(WebhookDeliveryStatus::Delivered)
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(delivery)
+    }
+
+    /// Counts a failed attempt and either schedules the next one or, when
+    /// `retry_in_seconds` is `None`, gives up on the delivery.
+    pub async fn mark_attempt_failed(
+        &self,
+        uuid: &str,
+        retry_in_seconds: Option<i64>,
+    ) -> Result<WebhookDelivery, Error> {
+        let status = match retry_in_seconds {
+            Some(_) => WebhookDeliveryStatus::Pending,
+            None => WebhookDeliveryStatus::Failed,
+        };
+
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $1,
+                attempts = attempts + 1,
+                next_attempt_at = NOW() + COALESCE($2, 0) * INTERVAL '1 second',
+                updated_at = NOW()
+            WHERE uuid = $3
+            RETURNING *
+            "#,
+        )
+        .bind(status)
+        .bind(retry_in_seconds)
+        .bind(uuid)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(delivery)
+    }
+
+    pub async fn get_all_by_webhook(
+        &self,
+        webhook_uuid: &str,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, Error> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT * FROM webhook_deliveries
+            WHERE webhook_uuid = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(webhook_uuid)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    pub async fn get_attempts(
+        &self,
+        delivery_uuids: &[String],
+    ) -> Result<Vec<WebhookDeliveryAttempt>, Error> {
+        let attempts = sqlx::query_as::<_, WebhookDeliveryAttempt>(
+            r#"
+            SELECT * FROM webhook_delivery_attempts
+            WHERE delivery_uuid = ANY($1)
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(delivery_uuids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(attempts)
+    }
+
+    /// When the checkout updates the enqueuer has processed run until, if
+    /// it ever ran.
+    pub async fn get_cursor(&self) -> Result<Option<chrono::NaiveDateTime>, Error> {
+        let cursor = sqlx::query_scalar::<_, chrono::NaiveDateTime>(
+            "SELECT processed_until FROM webhook_enqueuer_cursor",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(cursor)
+    }
+
+    /// Moves the enqueuer's cursor forward to `processed_until`. It never
+    /// moves back.
+    pub async fn save_cursor(&self, processed_until: chrono::NaiveDateTime) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_enqueuer_cursor (processed_until)
+            VALUES ($1)
+            ON CONFLICT (id) DO UPDATE
+            SET processed_until = GREATEST(webhook_enqueuer_cursor.processed_until, $1)
+            "#,
+        )
+        .bind(processed_until)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        models::webhook::WebhookDeliveryStatus,
+        repositories::store_repository::StoreRepository,
+    };
+
+    use super::{
+        CreateWebhook, CreateWebhookDeliveryAttempt, UpdateWebhook, WebhookDeliveryRepository,
+        WebhookRepository,
+    };
+
+    #[tokio::test]
+    async fn test_webhook_crud_and_deliveries() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let store_repo = StoreRepository::new(pool.clone());
+        let store = store_repo.create(&user.uuid, "test store").await.unwrap();
+        let repo = WebhookRepository::new(pool.clone());
+        let delivery_repo = WebhookDeliveryRepository::new(pool.clone());
+
+        let webhook = repo
+            .create(CreateWebhook {
+                store_uuid: store.uuid.clone(),
+                url: "https://example.com/hook".to_string(),
+                secret: "secret".to_string(),
+                events: vec!["checkout.paid".to_string()],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(webhook.store_uuid, store.uuid);
+        assert!(webhook.enabled);
+
+        let subscribed = repo
+            .get_subscribed(&store.uuid, "checkout.paid")
+            .await
+            .unwrap();
+        assert_eq!(subscribed.len(), 1);
+
+        let subscribed = repo
+            .get_subscribed(&store.uuid, "checkout.expired")
+            .await
+            .unwrap();
+        assert!(subscribed.is_empty());
+
+        let webhook = repo
+            .update(
+                &store.uuid,
+                &webhook.uuid,
+                UpdateWebhook {
+                    url: "https://example.com/hook2".to_string(),
+                    events: vec!["checkout.expired".to_string()],
+                    enabled: true,
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(webhook.url, "https://example.com/hook2");
+        assert_eq!(repo.count(&store.uuid).await.unwrap(), 1);
+
+        let delivery = delivery_repo
+            .enqueue(
+                &webhook.uuid,
+                "checkout.expired",
+                serde_json::json!({"test": "test"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+
+        let claimed = delivery_repo.claim_due(100, 60).await.unwrap();
+        assert!(claimed.iter().any(|d| d.uuid == delivery.uuid));
+
+        let claimed = delivery_repo.claim_due(100, 60).await.unwrap();
+        assert!(claimed.iter().all(|d| d.uuid != delivery.uuid));
+
+        delivery_repo
+            .record_attempt(CreateWebhookDeliveryAttempt {
+                delivery_uuid: delivery.uuid.clone(),
+                response_code: Some(500),
+                response_body: None,
+                error: None,
+                duration_ms: 10,
+            })
+            .await
+            .unwrap();
+
+        let delivery = delivery_repo
+            .mark_attempt_failed(&delivery.uuid, Some(30))
+            .await
+            .unwrap();
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(delivery.attempts, 1);
+
+        let delivery = delivery_repo.mark_delivered(&delivery.uuid).await.unwrap();
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 2);
+
+        let attempts = delivery_repo
+            .get_attempts(std::slice::from_ref(&delivery.uuid))
+            .await
+            .unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].response_code, Some(500));
+
+        sqlx::query("DELETE FROM webhook_delivery_attempts WHERE delivery_uuid = $1")
+            .bind(&delivery.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM webhook_deliveries WHERE uuid = $1")
+            .bind(&delivery.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(repo.delete(&store.uuid, &webhook.uuid).await.unwrap());
+        assert!(repo.hard_delete(&webhook.uuid).await.unwrap());
+
+        let _ = store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::helpers::pagination::Cursor;
+use crate::models::withdrawal::{Withdrawal, WithdrawalKind, WithdrawalStatus};
+use crate::repositories::ledger_repository::{
+    AccountKey, LedgerRepository, LedgerRepositoryError, NewLedgerTransaction,
+};
+
+#[derive(Error, Debug)]
+pub enum WithdrawalRepositoryError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    #[error("Ledger error: {0}")]
+    LedgerError(#[from] LedgerRepositoryError),
+    #[error("Insufficient balance, {available} sats available")]
+    InsufficientBalance { available: i64 },
+    #[error("A withdrawal for this invoice already exists")]
+    PaymentHashTaken,
+}
+
+#[derive(Debug, Clone)]
+pub struct WithdrawalRepository {
+    pool: PgPool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateWithdrawal {
+    pub user_uuid: String,
+    pub store_uuid: Option<String>,
+    pub kind: WithdrawalKind,
+    pub destination: String,
+    pub payment_request: Option<String>,
+    pub payment_hash: Option<String>,
+    pub amount: i64,
+    pub fee_limit: i64,
+    pub idempotency_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum CreatedWithdrawal {
+    Created(Withdrawal),
+    /// A withdrawal with the same idempotency key already existed.
+    Existing(Withdrawal),
+}
+
+impl WithdrawalRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates the withdrawal and posts the ledger transaction that reserves
+    /// its funds from `source`, failing if the account can't cover
+    /// `amount + fee_limit` or another withdrawal that hasn't failed pays
+    /// the same invoice.
+    pub async fn create(
+        &self,
+        req: CreateWithdrawal,
+        source: &AccountKey,
+        reservation: impl FnOnce(&Withdrawal) -> NewLedgerTransaction,
+    ) -> Result<CreatedWithdrawal, WithdrawalRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let withdrawal = sqlx::query_as::<_, Withdrawal>(
+            r#"
+            INSERT INTO withdrawals
+            (uuid, user_uuid, store_uuid, kind, destination, payment_request, payment_hash, amount, fee_limit, idempotency_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (user_uuid, idempotency_key) DO NOTHING
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&req.user_uuid)
+        .bind(&req.store_uuid)
+        .bind(req.kind)
+        .bind(&req.destination)
+        .bind(&req.payment_request)
+        .bind(&req.payment_hash)
+        .bind(req.amount)
+        .bind(req.fee_limit)
+        .bind(&req.idempotency_key)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db)
+                if db.constraint() == Some("withdrawals_payment_hash_idx") =>
+            {
+                WithdrawalRepositoryError::PaymentHashTaken
+            }
+            e => e.into(),
+        })?;
+
+        let withdrawal = match withdrawal {
+            Some(withdrawal) => withdrawal,
+            None => {
+                tx.rollback().await?;
+
+                let existing = self
+                    .get_by_idempotency_key(&req.user_uuid, &req.idempotency_key)
+                    .await?
+                    .ok_or(sqlx::Error::RowNotFound)?;
+                return Ok(CreatedWithdrawal::Existing(existing));
+            }
+        };
+
+        let available = LedgerRepository::lock_balance_with(&mut tx, source).await?;
+        if available < withdrawal.amount + withdrawal.fee_limit {
+            tx.rollback().await?;
+            return Err(WithdrawalRepositoryError::InsufficientBalance { available });
+        }
+
+        LedgerRepository::post_with(&mut tx, reservation(&withdrawal)).await?;
+        tx.commit().await?;
+
+        Ok(CreatedWithdrawal::Created(withdrawal))
+    }
+
+    /// Moves a pending withdrawal to its final status and posts the ledger
+    /// transaction that settles or reverts its reservation.
+    pub async fn finalize(
+        &self,
+        uuid: &str,
+        update: FinalizeWithdrawal,
+        transaction: NewLedgerTransaction,
+    ) -> Result<Withdrawal, WithdrawalRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let withdrawal = sqlx::query_as::<_, Withdrawal>(
+            r#"
+            UPDATE withdrawals SET
+                status = $1,
+                fee_amount = $2,
+                payment_hash = COALESCE($3, payment_hash),
+                payment_preimage = $4,
+                failure_reason = $5,
+                completed_at = CASE WHEN $1 = 'completed'::withdrawal_status THEN NOW() END,
+                updated_at = NOW()
+            WHERE uuid = $6 AND status = 'pending'
+            RETURNING *
+            "#,
+        )
+        .bind(update.status)
+        .bind(update.fee_amount)
+        .bind(&update.payment_hash)
+        .bind(&update.payment_preimage)
+        .bind(&update.failure_reason)
+        .bind(uuid)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        LedgerRepository::post_with(&mut tx, transaction).await?;
+        tx.commit().await?;
+
+        Ok(withdrawal)
+    }
+
+    /// Lightning withdrawals left pending for longer than `older_than_seconds`,
+    /// because their payment outcome was unknown or the process stopped
+    /// before it was recorded.
+    pub async fn get_stale_lightning(
+        &self,
+        older_than_seconds: i64,
+        limit: i64,
+    ) -> Result<Vec<Withdrawal>, sqlx::Error> {
+        let withdrawals = sqlx::query_as::<_, Withdrawal>(
+            r#"
+            SELECT * FROM withdrawals
+            WHERE kind = 'lightning' AND status = 'pending'
+                AND updated_at < NOW() - make_interval(secs => $1)
+            ORDER BY created_at ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(older_than_seconds as f64)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Claims up to `limit` pending on-chain withdrawals, oldest first, into
+    /// the payout batch `batch_uuid`.
+    pub async fn claim_onchain_batch(
+        &self,
+        limit: i64,
+        batch_uuid: &str,
+    ) -> Result<Vec<Withdrawal>, sqlx::Error> {
+        let withdrawals = sqlx::query_as::<_, Withdrawal>(
+            r#"
+            UPDATE withdrawals SET status = 'processing', batch_uuid = $2, updated_at = NOW()
+            WHERE uuid IN (
+                SELECT uuid FROM withdrawals
+                WHERE kind = 'onchain' AND status = 'pending'
+                ORDER BY created_at ASC
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *
+            "#,
+        )
+        .bind(limit)
+        .bind(batch_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Payout batches claimed more than `older_than_seconds` ago that are
+    /// still processing, so their send failed or was never recorded.
+    pub async fn get_stale_onchain_batches(
+        &self,
+        older_than_seconds: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let batches = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT batch_uuid FROM withdrawals
+            WHERE kind = 'onchain' AND status = 'processing' AND batch_uuid IS NOT NULL
+                AND updated_at < NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(older_than_seconds as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(batches)
+    }
+
+    /// Withdrawals of a payout batch that is still processing.
+    pub async fn get_onchain_batch(
+        &self,
+        batch_uuid: &str,
+    ) -> Result<Vec<Withdrawal>, sqlx::Error> {
+        let withdrawals = sqlx::query_as::<_, Withdrawal>(
+            "SELECT * FROM withdrawals WHERE batch_uuid = $1 AND status = 'processing'",
+        )
+        .bind(batch_uuid)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Marks a broadcast batch as completed and posts each payout's settlement.
+    pub async fn complete_onchain_batch(
+        &self,
+        uuids: &[String],
+        txid: &str,
+        settlements: Vec<NewLedgerTransaction>,
+    ) -> Result<Vec<Withdrawal>, WithdrawalRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let withdrawals = sqlx::query_as::<_, Withdrawal>(
+            r#"
+            UPDATE withdrawals SET
+                status = 'completed',
+                txid = $1,
+                fee_amount = fee_limit,
+                failure_reason = NULL,
+                completed_at = NOW(),
+                updated_at = NOW()
+            WHERE uuid = ANY($2) AND status = 'processing'
+            RETURNING *
+            "#,
+        )
+        .bind(txid)
+        .bind(uuids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for settlement in settlements {
+            LedgerRepository::post_with(&mut tx, settlement).await?;
+        }
+        tx.commit().await?;
+
+        Ok(withdrawals)
+    }
+
+    /// Puts a payout batch back in the queue for the next run. Only for
+    /// batches the wallet has no transaction for.
+    pub async fn release_onchain_batch(
+        &self,
+        batch_uuid: &str,
+        reason: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE withdrawals SET
+                status = 'pending',
+                batch_uuid = NULL,
+                failure_reason = $1,
+                updated_at = NOW()
+            WHERE batch_uuid = $2 AND status = 'processing'
+            "#,
+        )
+        .bind(reason)
+        .bind(batch_uuid)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_by_idempotency_key(
+        &self,
+        user_uuid: &str,
+        idempotency_key: &str,
+    ) -> Result<Option<Withdrawal>, sqlx::Error> {
+        let withdrawal = sqlx::query_as::<_, Withdrawal>(
+            "SELECT * FROM withdrawals WHERE user_uuid = $1 AND idempotency_key = $2",
+        )
+        .bind(user_uuid)
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(withdrawal)
+    }
+
+    pub async fn get_by_uuid(
+        &self,
+        user_uuid: &str,
+        uuid: &str,
+    ) -> Result<Option<Withdrawal>, sqlx::Error> {
+        let withdrawal = sqlx::query_as::<_, Withdrawal>(
+            "SELECT * FROM withdrawals WHERE user_uuid = $1 AND uuid = $2",
+        )
+        .bind(user_uuid)
+        .bind(uuid)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(withdrawal)
+    }
+
+    /// Newest first page of the user's withdrawals, after the cursor.
+    pub async fn get_all(
+        &self,
+        user_uuid: &str,
+        cursor: Option<&Cursor>,
+        limit: i64,
+    ) -> Result<Vec<Withdrawal>, sqlx::Error> {
+        let mut query =
+            QueryBuilder::<Postgres>::new("SELECT * FROM withdrawals WHERE user_uuid = ");
+        query.push_bind(user_uuid);
+
+        if let Some(cursor) = cursor {
+            query
+                .push(" AND (created_at, uuid) < (")
+                .push_bind(cursor.created_at)
+                .push(", ")
+                .push_bind(cursor.uuid.clone())
+                .push(")");
+        }
+
+        query
+            .push(" ORDER BY created_at DESC, uuid DESC LIMIT ")
+            .push_bind(limit);
+
+        let withdrawals = query
+            .build_query_as::<Withdrawal>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(withdrawals)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FinalizeWithdrawal {
+    pub status: WithdrawalStatus,
+    pub fee_amount: Option<i64>,
+    pub payment_hash: Option<String>,
+    pub payment_preimage: Option<String>,
+    pub failure_reason: Option<String>,
+}
@@ -0,0 +1,501 @@
+use std::sync::Arc;
+
+use rand::RngCore;
+use thiserror::Error;
+
+use crate::{
+    config::{AuthConfig, EmailConfig},
+    helpers::{crypto::sha256_hex, password::hash_password, signed_token},
+    mailer::{Email, Mailer},
+    models::user::User,
+    repositories::user_repository::{
+        CreateUser, PasswordReset, UserRepository, UserRepositoryError,
+    },
+    services::signing_key_service::SigningKeyService,
+};
+
+/// Purpose of email verification tokens.
+const VERIFY_EMAIL: &str = "verify_email";
+
+#[derive(Error, Debug)]
+pub enum AccountError {
+    #[error("Invalid email format")]
+    InvalidEmail,
+    #[error("Password must be at least {0} characters")]
+    PasswordTooShort(usize),
+    #[error("Password must contain lowercase and uppercase letters and a digit")]
+    WeakPassword,
+    #[error("An account with this email already exists")]
+    EmailTaken,
+    #[error("Token is invalid or expired")]
+    InvalidToken,
+    #[error("User not found")]
+    NotFound,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<UserRepositoryError> for AccountError {
+    fn from(e: UserRepositoryError) -> Self {
+        AccountError::Internal(e.into())
+    }
+}
+
+/// Lowercases the email and checks it looks like an address.
+pub fn validate_email(email: &str) -> Result<String, AccountError> {
+    let email = email.trim().to_lowercase();
+
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+        }
+        None => false,
+    };
+
+    if !valid || email.len() > 255 || email.chars().any(char::is_whitespace) {
+        return Err(AccountError::InvalidEmail);
+    }
+
+    Ok(email)
+}
+
+/// Checks the password against the configured length and strength rules.
+pub fn validate_password(password: &str, config: &AuthConfig) -> Result<(), AccountError> {
+    if password.chars().count() < config.min_password_length {
+        return Err(AccountError::PasswordTooShort(config.min_password_length));
+    }
+
+    let strong = password.chars().any(|c| c.is_lowercase())
+        && password.chars().any(|c| c.is_uppercase())
+        && password.chars().any(|c| c.is_ascii_digit());
+    if config.require_strong_password && !strong {
+        return Err(AccountError::WeakPassword);
+    }
+
+    Ok(())
+}
+
+/// Email and password accounts: registration, email verification and
+/// password resets.
+#[derive(Clone)]
+pub struct AccountService {
+    auth: AuthConfig,
+    email: EmailConfig,
+    users: UserRepository,
+    mailer: Arc<dyn Mailer>,
+    keys: SigningKeyService,
+}
+
+impl AccountService {
+    pub fn new(
+        auth: AuthConfig,
+        email: EmailConfig,
+        users: UserRepository,
+        mailer: Arc<dyn Mailer>,
+        keys: SigningKeyService,
+    ) -> Self {
+        Self {
+            auth,
+            email,
+            users,
+            mailer,
+            keys,
+        }
+    }
+
+    /// Creates an unverified user and emails them a verification link.
+    pub async fn register(&self, email: &str, password: &str) -> Result<User, AccountError> {
+        let email = validate_email(email)?;
+        validate_password(password, &self.auth)?;
+
+        let created = self
+            .users
+            .create(&CreateUser {
+                email: Some(email),
+                password: Some(hash_password(password, &self.auth).await?),
+                npub: None,
+                identifier: None,
+            })
+            .await;
+
+        let user = match created {
+            Ok(user) => user,
+            Err(UserRepositoryError::DatabaseError(e))
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                return Err(AccountError::EmailTaken)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        // The account exists either way, the user can ask for another link.
+        if let Err(e) = self.send_verification(&user).await {
+            eprintln!(
+                "failed to send verification email to {}: {:?}",
+                user.uuid, e
+            );
+        }
+
+        Ok(user)
+    }
+
+    /// Emails the user a link to verify their address. Does nothing for
+    /// users who are already verified.
+    pub async fn send_verification(&self, user: &User) -> Result<(), AccountError> {
+        let email = match &user.email {
+            Some(email) if !user.is_verified() => email,
+            _ => return Ok(()),
+        };
+
+        let token = signed_token::sign(
+            &self.keys,
+            VERIFY_EMAIL,
+            &user.uuid,
+            &signed_token::fingerprint(email),
+            self.email.verification_expiry_seconds,
+        )
+        .await?;
+        let link = format!(
+            "{}/verify-email?token={}",
+            self.email.app_url.trim_end_matches('/'),
+            token
+        );
+
+        self.mailer
+            .send(Email {
+                to: email.clone(),
+                subject: "Verify your email".to_string(),
+                body: format!(
+                    "Welcome to Nodeless!\n\nConfirm your email address by opening this link:\n\n{}\n\nThe link expires in {} hours.",
+                    link,
+                    self.email.verification_expiry_seconds / 3600
+                ),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Resends the verification link to the user.
+    pub async fn resend_verification(&self, user_uuid: &str) -> Result<(), AccountError> {
+        let user = self
+            .users
+            .get_by_uuid(user_uuid)
+            .await?
+            .ok_or(AccountError::NotFound)?;
+
+        self.send_verification(&user).await
+    }
+
+    /// Marks the email the token was sent to as verified.
+    pub async fn verify_email(&self, token: &str) -> Result<User, AccountError> {
+        let claims = signed_token::verify(&self.keys, VERIFY_EMAIL, token)
+            .await
+            .map_err(|_| AccountError::InvalidToken)?;
+
+        let user = self
+            .users
+            .get_by_uuid(&claims.sub)
+            .await?
+            .ok_or(AccountError::InvalidToken)?;
+
+        // Tokens are for the address they were sent to.
+        let email = user.email.as_deref().ok_or(AccountError::InvalidToken)?;
+        if signed_token::fingerprint(email) != claims.fingerprint {
+            return Err(AccountError::InvalidToken);
+        }
+
+        Ok(self.users.set_email_verified(&user.uuid).await?)
+    }
+
+    /// Emails a single-use password reset link if an account has the email.
+    /// Callers shouldn't tell whether one did.
+    pub async fn forgot_password(&self, email: &str) -> Result<(), AccountError> {
+        let email = validate_email(email)?;
+        let user = match self.users.get_user_by_email(&email).await {
+            Ok(user) if user.deleted_at.is_none() => user,
+            _ => return Ok(()),
+        };
+
+        let mut token = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut token);
+        let token = hex::encode(token);
+
+        // Only the hash is stored, so a leaked table can't reset passwords.
+        self.users
+            .create_password_reset(
+                &user.uuid,
+                &sha256_hex(&token),
+                self.email.password_reset_expiry_seconds,
+            )
+            .await?;
+
+        let link = format!(
+            "{}/reset-password?token={}",
+            self.email.app_url.trim_end_matches('/'),
+            token
+        );
+
+        self.mailer
+            .send(Email {
+                to: email,
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Someone asked to reset the password of your Nodeless account.\n\nChoose a new password by opening this link:\n\n{}\n\nThe link expires in {} minutes and can only be used once. If you didn't ask for this, you can ignore this email.",
+                    link,
+                    self.email.password_reset_expiry_seconds / 60
+                ),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Sets a new password with a token from `forgot_password`, logging the
+    /// user out everywhere.
+    pub async fn reset_password(
+        &self,
+        token: &str,
+        password: &str,
+    ) -> Result<PasswordReset, AccountError> {
+        validate_password(password, &self.auth)?;
+
+        // The token is checked before the costly hash.
+        let user_uuid = self
+            .users
+            .consume_password_reset_token(&sha256_hex(token))
+            .await?
+            .ok_or(AccountError::InvalidToken)?;
+        let password_hash = hash_password(password, &self.auth).await?;
+
+        Ok(self
+            .users
+            .reset_password(&user_uuid, &password_hash)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{validate_email, validate_password, AccountError, AccountService};
+    use crate::{
+        config::{AuthConfig, EmailConfig},
+        helpers::password::{verify_password, PasswordCheck},
+        helpers::{
+            format::random_text,
+            signed_token,
+            tests::{create_test_pool, create_test_signing_keys},
+        },
+        mailer::{fake::FakeMailer, Email},
+        repositories::user_repository::UserRepository,
+        services::signing_key_service::SigningKeyService,
+    };
+
+    fn auth_config() -> AuthConfig {
+        AuthConfig {
+            min_password_length: 8,
+            require_strong_password: true,
+            enable_email_auth: true,
+            enable_nost_auth: true,
+            enable_identifier_auth: true,
+            jwt_expiry_seconds: 3600,
+            refresh_token_expiry_seconds: 3600,
+            challenge_expiry_seconds: 300,
+            mfa_token_expiry_seconds: 300,
+            user_cache_seconds: 30,
+            password_memory_kib: 1024,
+            password_iterations: 1,
+            password_parallelism: 1,
+            signing_key_algorithm: "EdDSA".to_string(),
+            signing_keys_kept: 3,
+            signing_key_refresh_seconds: 60,
+        }
+    }
+
+    fn account_service(
+        users: UserRepository,
+        mailer: Arc<FakeMailer>,
+        keys: SigningKeyService,
+    ) -> AccountService {
+        AccountService::new(
+            auth_config(),
+            EmailConfig {
+                from: "Nodeless <noreply@nodeless.io>".to_string(),
+                smtp_host: "localhost".to_string(),
+                smtp_port: 587,
+                app_url: "https://nodeless.io/".to_string(),
+                verification_expiry_seconds: 3600,
+                password_reset_expiry_seconds: 3600,
+            },
+            users,
+            mailer,
+            keys,
+        )
+    }
+
+    /// The token from the link in an email.
+    fn link_token(email: &Email) -> &str {
+        email
+            .body
+            .split("token=")
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .next()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_credentials() {
+        assert_eq!(
+            validate_email(" Satoshi@Nodeless.io ").unwrap(),
+            "satoshi@nodeless.io"
+        );
+        for email in [
+            "satoshi",
+            "@nodeless.io",
+            "satoshi@nodeless",
+            "sat oshi@nodeless.io",
+        ] {
+            assert!(validate_email(email).is_err(), "{}", email);
+        }
+
+        let config = auth_config();
+        assert!(validate_password("Correct1horse", &config).is_ok());
+        assert!(matches!(
+            validate_password("Short1", &config),
+            Err(AccountError::PasswordTooShort(8))
+        ));
+        assert!(matches!(
+            validate_password("alllowercase1", &config),
+            Err(AccountError::WeakPassword)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_register_and_verify_email() {
+        let pool = create_test_pool().await;
+        let users = UserRepository::new(pool.clone());
+        let mailer = Arc::new(FakeMailer::new());
+        let keys = create_test_signing_keys().await;
+        let service = account_service(users.clone(), mailer.clone(), keys.clone());
+        let email = format!("{}@nodeless.io", random_text(10).await).to_lowercase();
+
+        let user = service.register(&email, "Correct1horse").await.unwrap();
+        assert_eq!(user.email.as_deref(), Some(email.as_str()));
+        assert!(!user.is_verified());
+        assert!(matches!(
+            service
+                .register(&email.to_uppercase(), "Correct1horse")
+                .await,
+            Err(AccountError::EmailTaken)
+        ));
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, email);
+        let token = link_token(&sent[0]);
+
+        // Tokens for another purpose or another address don't verify.
+        let reset = signed_token::sign(
+            &keys,
+            "reset_password",
+            &user.uuid,
+            &signed_token::fingerprint(&email),
+            60,
+        )
+        .await
+        .unwrap();
+        let other = signed_token::sign(
+            &keys,
+            "verify_email",
+            &user.uuid,
+            &signed_token::fingerprint("other@nodeless.io"),
+            60,
+        )
+        .await
+        .unwrap();
+        for token in [reset.as_str(), other.as_str(), "garbage"] {
+            assert!(matches!(
+                service.verify_email(token).await,
+                Err(AccountError::InvalidToken)
+            ));
+        }
+
+        let user = service.verify_email(token).await.unwrap();
+        assert!(user.is_verified());
+
+        // Verified users aren't sent another link.
+        service.resend_verification(&user.uuid).await.unwrap();
+        assert_eq!(mailer.sent().len(), 1);
+
+        users.hard_delete(&user.uuid).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_password_reset() {
+        let pool = create_test_pool().await;
+        let users = UserRepository::new(pool.clone());
+        let mailer = Arc::new(FakeMailer::new());
+        let service = account_service(
+            users.clone(),
+            mailer.clone(),
+            create_test_signing_keys().await,
+        );
+        let email = format!("{}@nodeless.io", random_text(10).await).to_lowercase();
+        let user = service.register(&email, "Correct1horse").await.unwrap();
+
+        // Unknown emails are quietly ignored.
+        service.forgot_password("nobody@nodeless.io").await.unwrap();
+        assert_eq!(mailer.sent().len(), 1);
+
+        service.forgot_password(&email).await.unwrap();
+        service.forgot_password(&email).await.unwrap();
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 3);
+        let first = link_token(&sent[1]);
+        let second = link_token(&sent[2]);
+
+        assert!(matches!(
+            service.reset_password(first, "weak").await,
+            Err(AccountError::PasswordTooShort(8))
+        ));
+        assert!(matches!(
+            service.reset_password("garbage", "Battery2staple").await,
+            Err(AccountError::InvalidToken)
+        ));
+
+        let reset = service
+            .reset_password(first, "Battery2staple")
+            .await
+            .unwrap();
+        assert!(reset.user.sessions_revoked_at.is_some());
+        assert!(reset.user.is_verified());
+        assert_eq!(
+            verify_password(
+                "Battery2staple",
+                reset.user.password.as_deref(),
+                &auth_config()
+            )
+            .await,
+            PasswordCheck::Valid
+        );
+
+        // Tokens are single use, and the reset used up the other one too.
+        for token in [first, second] {
+            assert!(matches!(
+                service.reset_password(token, "Battery3staple").await,
+                Err(AccountError::InvalidToken)
+            ));
+        }
+
+        users.hard_delete(&user.uuid).await.unwrap();
+    }
+}
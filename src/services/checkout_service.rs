@@ -1,17 +1,18 @@
 use crate::{
+    helpers::qr::qr_data_uri,
+    lightning::{AddInvoice, Invoice, LightningBackend},
     models::checkout::Checkout,
     repositories::checkout_repository::{CheckoutRepository, CreateCheckout},
+    services::fee_service::FeeService,
 };
-use anyhow::Result;
-use lightning_cluster::{
-    cluster::{Cluster, ClusterAddInvoice},
-    lnd::AddInvoiceResponse,
-};
-use std::fs;
+use anyhow::{anyhow, Result};
+use moka::future::Cache;
+use std::{sync::Arc, time::Duration};
 use tokio::try_join;
 
 pub struct CheckoutService {
-    pub cluster: Cluster,
+    pub lightning: Arc<dyn LightningBackend>,
+    pub fees: FeeService,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +21,8 @@ pub struct CreateCheckoutService {
     pub amount: i64,
     pub expiry: i64,
     pub memo: Option<String>,
+    /// Hex SHA-256 the invoice should commit to instead of the memo.
+    pub description_hash: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -30,9 +33,48 @@ pub struct CheckoutResponse {
     pub qr_ln: String,
 }
 
+/// (unified, bitcoin, lightning) QR codes of a checkout, as data URIs.
+pub type QrCodes = (String, String, String);
+
+/// Rendered QR codes of recent checkouts, so loading a checkout page
+/// doesn't render them again. A checkout's payment details never change.
+#[derive(Clone)]
+pub struct QrCodeCache {
+    codes: Arc<Cache<String, QrCodes>>,
+}
+
+impl QrCodeCache {
+    pub fn new(max_checkouts: u64, ttl: Duration) -> Self {
+        Self {
+            codes: Arc::new(
+                Cache::builder()
+                    .max_capacity(max_checkouts)
+                    .time_to_live(ttl)
+                    .build(),
+            ),
+        }
+    }
+
+    /// The checkout's QR codes, rendered on first use. Concurrent requests
+    /// for the same checkout share one rendering.
+    pub async fn get(&self, checkout: &Checkout) -> Result<QrCodes> {
+        self.codes
+            .try_get_with(
+                checkout.uuid.clone(),
+                CheckoutService::get_qr_codes(&checkout.bitcoin_address, &checkout.payment_request),
+            )
+            .await
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    pub fn contains(&self, checkout_uuid: &str) -> bool {
+        self.codes.contains_key(checkout_uuid)
+    }
+}
+
 impl CheckoutService {
-    pub fn new(cluster: Cluster) -> Self {
-        Self { cluster }
+    pub fn new(lightning: Arc<dyn LightningBackend>, fees: FeeService) -> Self {
+        Self { lightning, fees }
     }
 
     pub async fn create(
@@ -40,23 +82,22 @@ impl CheckoutService {
         data: CreateCheckoutService,
         repo: CheckoutRepository,
     ) -> Result<CheckoutResponse> {
+        let fee = self.fees.fee_for(&data.user_uuid, data.amount).await?;
+
         let (ln_pr, bitcoin_addr) =
             try_join!(self.get_ln_pr(data.clone()), self.get_bitcoin_addr())?;
 
-        let unified = format! {"bitcoin:{}?lightning={}", bitcoin_addr, ln_pr.payment_request};
-
-        let (unified_qr, bitcoin_qr, ln_qr) = try_join!(
-            Self::get_qr(unified.as_str()),
-            Self::get_qr(bitcoin_addr.as_str()),
-            Self::get_qr(ln_pr.payment_request.as_str())
-        )?;
+        let (unified_qr, bitcoin_qr, ln_qr) =
+            Self::get_qr_codes(&bitcoin_addr, &ln_pr.payment_request).await?;
 
         let create_checkout = CreateCheckout {
             user_uuid: data.user_uuid,
             amount: data.amount,
             bitcoin_address: bitcoin_addr,
             payment_request: ln_pr.payment_request,
+            payment_hash: Some(ln_pr.payment_hash),
             expiry_seconds: data.expiry,
+            fee: Some(fee),
         };
 
         let checkout = repo.create(create_checkout).await?;
@@ -71,61 +112,80 @@ impl CheckoutService {
         Ok(response)
     }
 
-    async fn get_ln_pr(&self, data: CreateCheckoutService) -> Result<AddInvoiceResponse> {
+    async fn get_ln_pr(&self, data: CreateCheckoutService) -> Result<Invoice> {
         let mut memo = String::from("");
         match data.memo {
             Some(m) => memo = m,
             None => memo = dotenvy::var("APP_NAME").unwrap().to_string(),
         };
 
-        let pr_req = ClusterAddInvoice {
-            pubkey: None,
-            value: data.amount,
+        let pr_req = AddInvoice {
+            amount: data.amount,
             expiry: data.expiry,
             memo: memo,
+            description_hash: data.description_hash,
         };
 
-        let lightning_request = self.cluster.add_invoice(pr_req, None).await?;
+        let lightning_request = self.lightning.add_invoice(pr_req).await?;
 
         Ok(lightning_request)
     }
 
     async fn get_bitcoin_addr(&self) -> Result<String, anyhow::Error> {
-        let addr = self.cluster.next_address(None).await?;
+        let addr = self.lightning.next_address().await?;
 
         Ok(addr)
     }
 
-    async fn get_qr(url: &str) -> Result<String> {
-        let qr_name = format!("/tmp/{}.png", &url[..6]);
-        qrcode_gen::qr_image(url, qr_name.as_str());
-        let buffer = fs::read(qr_name.as_str())?;
-        let base64_image = base64::encode(&buffer);
-        fs::remove_file(qr_name)?;
-        Ok(format!("data:image/png;base64,{}", base64_image))
+    /// Returns the (unified, bitcoin, lightning) QR codes for a checkout.
+    pub async fn get_qr_codes(bitcoin_addr: &str, payment_request: &str) -> Result<QrCodes> {
+        let unified = format! {"bitcoin:{}?lightning={}", bitcoin_addr, payment_request};
+
+        let qr_codes = try_join!(
+            qr_data_uri(unified.as_str()),
+            qr_data_uri(bitcoin_addr),
+            qr_data_uri(payment_request)
+        )?;
+
+        Ok(qr_codes)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use crate::{
-        helpers::tests::{create_test_cluster, create_test_pool, create_test_user},
-        repositories::checkout_repository::CheckoutRepository,
-        services::checkout_service::{CheckoutService, CreateCheckoutService},
+        config::PricingConfig,
+        helpers::tests::{create_test_pool, create_test_user},
+        lightning::fake::FakeLightningBackend,
+        repositories::{checkout_repository::CheckoutRepository, fee_repository::FeeRepository},
+        services::{
+            checkout_service::{CheckoutService, CreateCheckoutService},
+            fee_service::FeeService,
+        },
     };
 
     #[tokio::test]
     pub async fn test_create_checkout_service() {
-        let cluster = create_test_cluster().await;
         let pool = create_test_pool().await;
         let user = create_test_user().await.unwrap();
 
-        let service = CheckoutService::new(cluster);
+        let pricing = PricingConfig {
+            base_fee_sat: 100,
+            fee_rate_percent: 1,
+        };
+        let fee_repo = FeeRepository::new(pool.clone());
+        let service = CheckoutService::new(
+            Arc::new(FakeLightningBackend::new()),
+            FeeService::new(&pricing, fee_repo.clone()),
+        );
         let data = CreateCheckoutService {
             user_uuid: user.uuid,
             amount: 1000,
             expiry: 3600,
             memo: None,
+            description_hash: None,
         };
         let repo = CheckoutRepository::new(pool);
         let response = service.create(data, repo).await;
@@ -136,6 +196,19 @@ mod tests {
                 assert_eq!(response.checkout.expiry_seconds, 3600);
                 assert!(response.checkout.bitcoin_address.len() > 0);
                 assert!(response.checkout.payment_request.len() > 0);
+                assert_eq!(response.checkout.fee_amount, 110);
+                assert_eq!(response.checkout.net_amount, 890);
+
+                let entry = fee_repo
+                    .get_ledger_entry(&response.checkout.uuid)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(entry.gross_amount, 1000);
+                assert_eq!(entry.fee_amount, 110);
+                assert_eq!(entry.fee_rate_bps, 100);
+                // Not collected until the checkout is paid and credited.
+                assert!(entry.credited_at.is_none());
             }
             Err(e) => panic!("Failed to create checkout: {:?}", e),
         }
@@ -0,0 +1,75 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::models::checkout::{Checkout, CheckoutStatus};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckoutEventKind {
+    Created,
+    PendingConfirmation,
+    Paid,
+    Overpaid,
+    Underpaid,
+    Expired,
+    LatePayment,
+}
+
+impl CheckoutEventKind {
+    pub fn from_status(status: &CheckoutStatus) -> Option<Self> {
+        match status {
+            CheckoutStatus::New => None,
+            CheckoutStatus::PendingConfirmation => Some(CheckoutEventKind::PendingConfirmation),
+            CheckoutStatus::Paid => Some(CheckoutEventKind::Paid),
+            CheckoutStatus::Overpaid => Some(CheckoutEventKind::Overpaid),
+            CheckoutStatus::Underpaid => Some(CheckoutEventKind::Underpaid),
+            CheckoutStatus::Expired => Some(CheckoutEventKind::Expired),
+        }
+    }
+}
+
+impl Display for CheckoutEventKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckoutEventKind::Created => write!(f, "created"),
+            CheckoutEventKind::PendingConfirmation => write!(f, "pending_confirmation"),
+            CheckoutEventKind::Paid => write!(f, "paid"),
+            CheckoutEventKind::Overpaid => write!(f, "overpaid"),
+            CheckoutEventKind::Underpaid => write!(f, "underpaid"),
+            CheckoutEventKind::Expired => write!(f, "expired"),
+            CheckoutEventKind::LatePayment => write!(f, "late_payment"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckoutEvent {
+    pub kind: CheckoutEventKind,
+    pub checkout: Checkout,
+}
+
+/// In-process fan-out of checkout lifecycle events. Subscribers that fall too
+/// far behind miss events, so anything that must not lose one should also
+/// reconcile from the database.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<CheckoutEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, kind: CheckoutEventKind, checkout: Checkout) {
+        // An error only means nobody is listening right now.
+        let _ = self.sender.send(CheckoutEvent { kind, checkout });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<CheckoutEvent> {
+        self.sender.subscribe()
+    }
+}
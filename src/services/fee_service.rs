@@ -0,0 +1,129 @@
+use anyhow::Result;
+
+use crate::{
+    config::PricingConfig,
+    models::fee::{CheckoutFee, UserFeeOverride},
+    repositories::fee_repository::FeeRepository,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeePolicy {
+    pub base_fee_sat: i64,
+    /// Percentage fee in basis points, 1% = 100.
+    pub fee_rate_bps: i64,
+}
+
+impl From<&PricingConfig> for FeePolicy {
+    fn from(pricing: &PricingConfig) -> Self {
+        Self {
+            base_fee_sat: pricing.base_fee_sat as i64,
+            fee_rate_bps: pricing.fee_rate_percent as i64 * 100,
+        }
+    }
+}
+
+impl FeePolicy {
+    /// Replaces the parts of the policy the user has an override for.
+    pub fn with_override(self, fee_override: &UserFeeOverride) -> Self {
+        Self {
+            base_fee_sat: fee_override.base_fee_sat.unwrap_or(self.base_fee_sat),
+            fee_rate_bps: fee_override.fee_rate_bps.unwrap_or(self.fee_rate_bps),
+        }
+    }
+
+    /// `base + amount * rate`, rounded down and never more than the amount itself.
+    pub fn apply(&self, amount: i64) -> CheckoutFee {
+        let rate_fee = (amount as i128 * self.fee_rate_bps as i128 / 10_000) as i64;
+        let fee_amount = (self.base_fee_sat + rate_fee).clamp(0, amount.max(0));
+
+        CheckoutFee {
+            fee_amount,
+            net_amount: amount - fee_amount,
+            base_fee_sat: self.base_fee_sat,
+            fee_rate_bps: self.fee_rate_bps,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeeService {
+    pub default_policy: FeePolicy,
+    pub repo: FeeRepository,
+}
+
+impl FeeService {
+    pub fn new(pricing: &PricingConfig, repo: FeeRepository) -> Self {
+        Self {
+            default_policy: FeePolicy::from(pricing),
+            repo,
+        }
+    }
+
+    pub async fn policy_for(&self, user_uuid: &str) -> Result<FeePolicy> {
+        let policy = match self.repo.get_override(user_uuid).await? {
+            Some(fee_override) => self.default_policy.with_override(&fee_override),
+            None => self.default_policy,
+        };
+
+        Ok(policy)
+    }
+
+    pub async fn fee_for(&self, user_uuid: &str, amount: i64) -> Result<CheckoutFee> {
+        Ok(self.policy_for(user_uuid).await?.apply(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeePolicy;
+    use crate::{config::PricingConfig, models::fee::UserFeeOverride};
+
+    #[test]
+    fn test_fee_policy() {
+        let policy = FeePolicy::from(&PricingConfig {
+            base_fee_sat: 100,
+            fee_rate_percent: 1,
+        });
+        assert_eq!(policy.fee_rate_bps, 100);
+
+        let fee = policy.apply(10_000);
+        assert_eq!(fee.fee_amount, 200);
+        assert_eq!(fee.net_amount, 9_800);
+
+        // Rounds down.
+        assert_eq!(policy.apply(1_999).fee_amount, 119);
+
+        // Never more than the checkout itself.
+        let fee = policy.apply(50);
+        assert_eq!(fee.fee_amount, 50);
+        assert_eq!(fee.net_amount, 0);
+    }
+
+    #[test]
+    fn test_fee_policy_override() {
+        let policy = FeePolicy {
+            base_fee_sat: 100,
+            fee_rate_bps: 100,
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let fee_override = UserFeeOverride {
+            uuid: "uuid".to_string(),
+            user_uuid: "user".to_string(),
+            base_fee_sat: Some(0),
+            fee_rate_bps: None,
+            note: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let policy = policy.with_override(&fee_override);
+        assert_eq!(
+            policy,
+            FeePolicy {
+                base_fee_sat: 0,
+                fee_rate_bps: 100,
+            }
+        );
+        assert_eq!(policy.apply(10_000).fee_amount, 100);
+    }
+}
@@ -0,0 +1,518 @@
+use anyhow::Result;
+
+use crate::{
+    models::{
+        checkout::{Checkout, CheckoutStatus},
+        ledger::{LedgerTransaction, LedgerTransactionKind},
+        nodeless_address::NodelessAddress,
+    },
+    repositories::{
+        ledger_repository::{AccountKey, LedgerRepository, NewLedgerEntry, NewLedgerTransaction},
+        nodeless_address_repository::NodelessAddressRepository,
+        store_repository::StoreInvoiceRepository,
+    },
+};
+
+/// Checkouts reconciled per sweep.
+const RECONCILE_BATCH_SIZE: i64 = 100;
+
+/// The first credit for a checkout is referenced by the checkout itself. Funds
+/// confirmed after that are credited as a delta, referenced by the checkout
+/// and the confirmed total it brings the checkout up to.
+pub fn checkout_credit_reference(checkout: &Checkout) -> String {
+    if checkout.amount_credited == 0 {
+        checkout.uuid.clone()
+    } else {
+        format!("{}:{}", checkout.uuid, checkout.amount_confirmed)
+    }
+}
+
+/// Confirmed funds for a checkout that were not credited yet move out of the
+/// hot wallet into the merchant's account, minus what is left of the fee,
+/// which goes to the platform. Payments to store invoices are credited to the
+/// store, everything else to the user.
+pub fn checkout_payment_transaction(
+    checkout: &Checkout,
+    store_uuid: Option<&str>,
+) -> NewLedgerTransaction {
+    let gross = checkout.amount_confirmed - checkout.amount_credited;
+    let fee = (checkout.fee_amount - checkout.amount_credited).clamp(0, gross);
+    let merchant = match store_uuid {
+        Some(store_uuid) => AccountKey::store(&checkout.user_uuid, store_uuid),
+        None => AccountKey::user(&checkout.user_uuid),
+    };
+
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::CheckoutPayment,
+        reference_uuid: checkout_credit_reference(checkout),
+        description: Some(format!("Payment for checkout {}", checkout.uuid)),
+        entries: vec![
+            NewLedgerEntry {
+                account: AccountKey::hot_wallet(),
+                amount: -gross,
+            },
+            NewLedgerEntry {
+                account: merchant,
+                amount: gross - fee,
+            },
+            NewLedgerEntry {
+                account: AccountKey::platform_fees(),
+                amount: fee,
+            },
+        ],
+    }
+}
+
+/// Confirmed funds for a handle purchase go to the platform, up to what is
+/// left of the price. Anything paid on top is credited back to the buyer.
+pub fn handle_purchase_transaction(
+    checkout: &Checkout,
+    address: &NodelessAddress,
+) -> NewLedgerTransaction {
+    let gross = checkout.amount_confirmed - checkout.amount_credited;
+    let price = (address.price - checkout.amount_credited).clamp(0, gross);
+
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::CheckoutPayment,
+        reference_uuid: checkout_credit_reference(checkout),
+        description: Some(format!("Purchase of nodeless address {}", address.handle)),
+        entries: vec![
+            NewLedgerEntry {
+                account: AccountKey::hot_wallet(),
+                amount: -gross,
+            },
+            NewLedgerEntry {
+                account: AccountKey::user(&checkout.user_uuid),
+                amount: gross - price,
+            },
+            NewLedgerEntry {
+                account: AccountKey::platform_fees(),
+                amount: price,
+            },
+        ],
+    }
+}
+
+/// Whether the checkout is paid, late payments included, with confirmed funds.
+pub fn is_paid(checkout: &Checkout) -> bool {
+    let paid = matches!(
+        checkout.status,
+        CheckoutStatus::Paid | CheckoutStatus::Overpaid
+    ) || checkout.late_payment_at.is_some();
+
+    paid && checkout.amount_confirmed > 0 && checkout.deleted_at.is_none()
+}
+
+/// Whether the checkout has confirmed funds the merchant wasn't credited for.
+pub fn is_payable(checkout: &Checkout) -> bool {
+    is_paid(checkout) && checkout.amount_confirmed > checkout.amount_credited
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerService {
+    pub ledger_repo: LedgerRepository,
+    pub store_invoice_repo: StoreInvoiceRepository,
+    pub nodeless_address_repo: NodelessAddressRepository,
+}
+
+impl LedgerService {
+    pub fn new(
+        ledger_repo: LedgerRepository,
+        store_invoice_repo: StoreInvoiceRepository,
+        nodeless_address_repo: NodelessAddressRepository,
+    ) -> Self {
+        Self {
+            ledger_repo,
+            store_invoice_repo,
+            nodeless_address_repo,
+        }
+    }
+
+    /// Posts the confirmed funds of a paid checkout that were not credited
+    /// yet. Returns `None` if there are none, or if they were already posted
+    /// from a fresher copy of the checkout.
+    pub async fn post_checkout_payment(
+        &self,
+        checkout: &Checkout,
+    ) -> Result<Option<LedgerTransaction>> {
+        if !is_payable(checkout) {
+            return Ok(None);
+        }
+
+        // Handle purchases paid in full are activated, so the platform keeps
+        // the price. Late or short payments don't buy the handle and are
+        // credited to the buyer like any other checkout.
+        let purchase = self
+            .nodeless_address_repo
+            .get_by_checkout_uuid(&checkout.uuid)
+            .await?
+            .filter(|address| {
+                address.deleted_at.is_none()
+                    && matches!(
+                        checkout.status,
+                        CheckoutStatus::Paid | CheckoutStatus::Overpaid
+                    )
+            });
+        if let Some(address) = purchase {
+            let transaction = handle_purchase_transaction(checkout, &address);
+            return Ok(self
+                .ledger_repo
+                .post_checkout_credit(checkout, transaction)
+                .await?);
+        }
+
+        let store_invoice = self
+            .store_invoice_repo
+            .get_by_checkout_uuid(&checkout.uuid)
+            .await?;
+        let transaction = checkout_payment_transaction(
+            checkout,
+            store_invoice
+                .as_ref()
+                .map(|invoice| invoice.store_uuid.as_str()),
+        );
+
+        Ok(self
+            .ledger_repo
+            .post_checkout_credit(checkout, transaction)
+            .await?)
+    }
+
+    /// Posts paid checkouts that were missed, e.g. while the server was down,
+    /// and funds that confirmed after the checkout was first credited.
+    pub async fn reconcile(&self) -> Result<Vec<LedgerTransaction>> {
+        let checkouts = self
+            .ledger_repo
+            .get_unposted_checkouts(RECONCILE_BATCH_SIZE)
+            .await?;
+        let mut posted = Vec::new();
+
+        for checkout in &checkouts {
+            if let Some(transaction) = self.post_checkout_payment(checkout).await? {
+                posted.push(transaction);
+            }
+        }
+
+        Ok(posted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checkout_payment_transaction, handle_purchase_transaction, LedgerService};
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        models::{checkout::CheckoutStatus, fee::CheckoutFee, ledger::LedgerAccountKind},
+        repositories::{
+            checkout_repository::{CheckoutRepository, CreateCheckout},
+            fee_repository::FeeRepository,
+            ledger_repository::{AccountKey, LedgerRepository},
+            nodeless_address_repository::NodelessAddressRepository,
+            store_repository::{CreateStoreInvoice, StoreInvoiceRepository, StoreRepository},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_post_checkout_payment() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+        let store_repo = StoreRepository::new(pool.clone());
+        let store_invoice_repo = StoreInvoiceRepository::new(pool.clone());
+        let ledger = LedgerService::new(
+            LedgerRepository::new(pool.clone()),
+            store_invoice_repo.clone(),
+            NodelessAddressRepository::new(pool.clone()),
+        );
+
+        let create = || CreateCheckout {
+            user_uuid: user.uuid.clone(),
+            amount: 10_000,
+            bitcoin_address: "test address".to_string(),
+            payment_request: "test payment request".to_string(),
+            payment_hash: None,
+            expiry_seconds: 3600,
+            fee: Some(CheckoutFee {
+                fee_amount: 200,
+                net_amount: 9_800,
+                base_fee_sat: 100,
+                fee_rate_bps: 100,
+            }),
+        };
+
+        let store = store_repo.create(&user.uuid, "Ledger store").await.unwrap();
+        let store_checkout = checkout_repo.create(create()).await.unwrap();
+        store_invoice_repo
+            .create(CreateStoreInvoice {
+                store_uuid: store.uuid.clone(),
+                checkout_uuid: store_checkout.uuid.clone(),
+                metadata: None,
+            })
+            .await
+            .unwrap();
+        let checkout = checkout_repo.create(create()).await.unwrap();
+
+        // Unpaid checkouts are not posted, and their fee isn't collected.
+        let fee_repo = FeeRepository::new(pool.clone());
+        assert!(ledger
+            .post_checkout_payment(&checkout)
+            .await
+            .unwrap()
+            .is_none());
+        let fee_entry = fee_repo
+            .get_ledger_entry(&checkout.uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(fee_entry.credited_at.is_none());
+
+        let store_checkout = checkout_repo
+            .set_payment_received(
+                &store_checkout.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Overpaid,
+                12_000,
+                12_000,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let checkout = checkout_repo
+            .set_payment_received(
+                &checkout.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Paid,
+                10_000,
+                10_000,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let store_payment = ledger
+            .post_checkout_payment(&store_checkout)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(store_payment.reference_uuid, store_checkout.uuid);
+        assert!(ledger
+            .post_checkout_payment(&store_checkout)
+            .await
+            .unwrap()
+            .is_none());
+
+        let unposted = ledger
+            .ledger_repo
+            .get_unposted_checkouts(1000)
+            .await
+            .unwrap();
+        assert!(unposted.iter().any(|c| c.uuid == checkout.uuid));
+        assert!(unposted.iter().all(|c| c.uuid != store_checkout.uuid));
+        let payment = ledger
+            .post_checkout_payment(&checkout)
+            .await
+            .unwrap()
+            .unwrap();
+        let fee_entry = fee_repo
+            .get_ledger_entry(&checkout.uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(fee_entry.credited_at.is_some());
+
+        let balances = ledger.ledger_repo.get_balances(&user.uuid).await.unwrap();
+        let store_balance = balances
+            .iter()
+            .find(|b| b.kind == LedgerAccountKind::Store)
+            .unwrap();
+        assert_eq!(store_balance.store_uuid, Some(store.uuid.clone()));
+        assert_eq!(store_balance.balance, 11_800);
+        assert_eq!(
+            ledger
+                .ledger_repo
+                .get_account_balance(&AccountKey::user(&user.uuid))
+                .await
+                .unwrap(),
+            9_800
+        );
+
+        // A stale copy of the checkout doesn't credit it twice.
+        let stale = checkout.clone();
+        let checkout = checkout_repo
+            .set_late_payment(&checkout.uuid, 10_500, 10_000)
+            .await
+            .unwrap();
+        assert!(ledger
+            .post_checkout_payment(&stale)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Unconfirmed funds on top are only credited once they confirm, and
+        // then as a delta with its own reference.
+        assert!(ledger
+            .post_checkout_payment(&checkout)
+            .await
+            .unwrap()
+            .is_none());
+        let checkout = checkout_repo
+            .set_late_payment(&checkout.uuid, 10_500, 10_500)
+            .await
+            .unwrap();
+        let top_up = ledger
+            .post_checkout_payment(&checkout)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(top_up.reference_uuid, format!("{}:10500", checkout.uuid));
+        assert_eq!(
+            ledger
+                .ledger_repo
+                .get_account_balance(&AccountKey::user(&user.uuid))
+                .await
+                .unwrap(),
+            10_300
+        );
+
+        for transaction in [&store_payment, &payment, &top_up] {
+            sqlx::query("DELETE FROM ledger_entries WHERE transaction_uuid = $1")
+                .bind(&transaction.uuid)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("DELETE FROM ledger_transactions WHERE uuid = $1")
+                .bind(&transaction.uuid)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("DELETE FROM ledger_accounts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM store_invoices WHERE store_uuid = $1")
+            .bind(&store.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+
+    #[test]
+    fn test_checkout_payment_transaction_balances() {
+        let now = chrono::Utc::now().naive_utc();
+        let checkout = crate::models::checkout::Checkout {
+            uuid: "checkout".to_string(),
+            user_uuid: "user".to_string(),
+            amount: 1_000,
+            status: CheckoutStatus::Paid,
+            bitcoin_address: String::new(),
+            payment_request: String::new(),
+            payment_hash: None,
+            amount_received: 50,
+            amount_confirmed: 50,
+            amount_credited: 0,
+            fee_amount: 110,
+            net_amount: 890,
+            expiry_seconds: 3600,
+            created_at: now,
+            updated_at: now,
+            expired_at: None,
+            late_payment_at: None,
+            deleted_at: None,
+        };
+
+        // A fee larger than what was received is capped at the received amount.
+        let transaction = checkout_payment_transaction(&checkout, None);
+        let amounts: Vec<i64> = transaction.entries.iter().map(|e| e.amount).collect();
+        assert_eq!(amounts, vec![-50, 0, 50]);
+        assert_eq!(transaction.entries[1].account, AccountKey::user("user"));
+    }
+
+    #[test]
+    fn test_checkout_payment_transaction_credits_confirmed_delta() {
+        let now = chrono::Utc::now().naive_utc();
+        let checkout = crate::models::checkout::Checkout {
+            uuid: "checkout".to_string(),
+            user_uuid: "user".to_string(),
+            amount: 1_000,
+            status: CheckoutStatus::Underpaid,
+            bitcoin_address: String::new(),
+            payment_request: String::new(),
+            payment_hash: None,
+            amount_received: 2_000,
+            amount_confirmed: 600,
+            amount_credited: 50,
+            fee_amount: 110,
+            net_amount: 890,
+            expiry_seconds: 3600,
+            created_at: now,
+            updated_at: now,
+            expired_at: Some(now),
+            late_payment_at: Some(now),
+            deleted_at: None,
+        };
+
+        // Only the confirmed funds not credited yet are posted, with the rest
+        // of the fee, under a reference of their own.
+        let transaction = checkout_payment_transaction(&checkout, None);
+        let amounts: Vec<i64> = transaction.entries.iter().map(|e| e.amount).collect();
+        assert_eq!(amounts, vec![-550, 490, 60]);
+        assert_eq!(transaction.reference_uuid, "checkout:600");
+    }
+
+    #[test]
+    fn test_handle_purchase_transaction_balances() {
+        let now = chrono::Utc::now().naive_utc();
+        let checkout = crate::models::checkout::Checkout {
+            uuid: "checkout".to_string(),
+            user_uuid: "user".to_string(),
+            amount: 1_000,
+            status: CheckoutStatus::Overpaid,
+            bitcoin_address: String::new(),
+            payment_request: String::new(),
+            payment_hash: None,
+            amount_received: 1_200,
+            amount_confirmed: 1_200,
+            amount_credited: 0,
+            fee_amount: 110,
+            net_amount: 890,
+            expiry_seconds: 900,
+            created_at: now,
+            updated_at: now,
+            expired_at: None,
+            late_payment_at: None,
+            deleted_at: None,
+        };
+        let address = crate::models::nodeless_address::NodelessAddress {
+            uuid: "address".to_string(),
+            user_uuid: "user".to_string(),
+            handle: "satoshi".to_string(),
+            npub: None,
+            price: 1_000,
+            status: crate::models::nodeless_address::NodelessAddressStatus::Pending,
+            checkout_uuid: Some("checkout".to_string()),
+            reserved_until: None,
+            activated_at: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        };
+
+        // The price goes to the platform and the overpayment back to the buyer.
+        let transaction = handle_purchase_transaction(&checkout, &address);
+        let amounts: Vec<i64> = transaction.entries.iter().map(|e| e.amount).collect();
+        assert_eq!(amounts, vec![-1_200, 200, 1_000]);
+        assert_eq!(transaction.entries[1].account, AccountKey::user("user"));
+    }
+}
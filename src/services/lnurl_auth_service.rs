@@ -0,0 +1,319 @@
+use rand::RngCore;
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    helpers::{crypto::sha256_hex, lnurl, qr::qr_data_uri},
+    repositories::{
+        auth_challenge_repository::AuthChallengeRepository,
+        user_repository::{CreateUser, UserRepository, UserRepositoryError},
+    },
+};
+
+/// Challenges issued for LNURL-auth.
+const CHALLENGE_METHOD: &str = "lnurl-auth";
+
+#[derive(Error, Debug)]
+pub enum LnurlAuthError {
+    #[error("Invalid linking key")]
+    InvalidKey,
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Challenge is invalid, expired or already used")]
+    InvalidChallenge,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for LnurlAuthError {
+    fn from(e: sqlx::Error) -> Self {
+        LnurlAuthError::Internal(e.into())
+    }
+}
+
+impl From<UserRepositoryError> for LnurlAuthError {
+    fn from(e: UserRepositoryError) -> Self {
+        LnurlAuthError::Internal(e.into())
+    }
+}
+
+/// What the browser shows the user to scan with their wallet.
+#[derive(Debug, Clone, Serialize)]
+pub struct LnurlAuthLogin {
+    /// Polled with to pick up the login once the wallet has signed it.
+    pub k1: String,
+    /// Proves the poll comes from the browser that started the login. Unlike
+    /// `k1` it isn't part of the LNURL, so the wallet never sees it.
+    pub poll_secret: String,
+    pub lnurl: String,
+    pub qr: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LnurlAuthStatus {
+    /// The wallet hasn't signed the challenge yet.
+    Pending,
+    /// The wallet signed the challenge for this user.
+    Authenticated(String),
+}
+
+/// Checks the wallet's DER signature over `k1` with its linking key, and
+/// returns the linking key as compressed hex.
+pub fn verify_signature(k1: &str, sig: &str, key: &str) -> Result<String, LnurlAuthError> {
+    let key = hex::decode(key)
+        .ok()
+        .and_then(|key| PublicKey::from_slice(&key).ok())
+        .ok_or(LnurlAuthError::InvalidKey)?;
+    let message = hex::decode(k1)
+        .ok()
+        .and_then(|k1| Message::from_slice(&k1).ok())
+        .ok_or(LnurlAuthError::InvalidChallenge)?;
+    let mut sig = hex::decode(sig)
+        .ok()
+        .and_then(|sig| Signature::from_der(&sig).ok())
+        .ok_or(LnurlAuthError::InvalidSignature)?;
+    // Some wallets don't normalize their signatures, which libsecp256k1
+    // rejects.
+    sig.normalize_s();
+
+    Secp256k1::new()
+        .verify_ecdsa(&message, &sig, &key)
+        .map_err(|_| LnurlAuthError::InvalidSignature)?;
+
+    Ok(hex::encode(key.serialize()))
+}
+
+/// Passwordless login with a lightning wallet (LUD-04). The browser gets an
+/// LNURL to scan, the wallet signs its k1 with a linking key, and the
+/// browser polls until the login is ready. Users are matched on their
+/// linking key, stored as `users.identifier`, and signed up on first login.
+#[derive(Debug, Clone)]
+pub struct LnurlAuthService {
+    challenge_expiry_seconds: i64,
+    callback_base_url: String,
+    challenges: AuthChallengeRepository,
+    users: UserRepository,
+}
+
+impl LnurlAuthService {
+    pub fn new(
+        challenge_expiry_seconds: i64,
+        callback_base_url: String,
+        challenges: AuthChallengeRepository,
+        users: UserRepository,
+    ) -> Self {
+        Self {
+            challenge_expiry_seconds,
+            callback_base_url,
+            challenges,
+            users,
+        }
+    }
+
+    pub async fn login(&self) -> Result<LnurlAuthLogin, LnurlAuthError> {
+        let mut poll_secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut poll_secret);
+        let poll_secret = hex::encode(poll_secret);
+
+        let challenge = self
+            .challenges
+            .create_polled(
+                CHALLENGE_METHOD,
+                self.challenge_expiry_seconds,
+                &sha256_hex(&poll_secret),
+            )
+            .await?;
+
+        let url = format!(
+            "{}/lnurl-auth/callback?tag=login&k1={}&action=login",
+            self.callback_base_url.trim_end_matches('/'),
+            challenge.challenge
+        );
+        let lnurl = lnurl::encode(&url)?;
+        let qr = qr_data_uri(&lnurl).await?;
+
+        Ok(LnurlAuthLogin {
+            k1: challenge.challenge,
+            poll_secret,
+            lnurl,
+            qr,
+            expires_at: challenge.expires_at,
+        })
+    }
+
+    /// Handles the wallet's callback, logging the linking key's user in.
+    pub async fn callback(&self, k1: &str, sig: &str, key: &str) -> Result<(), LnurlAuthError> {
+        let key = verify_signature(k1, sig, key)?;
+
+        // Don't sign up users for challenges we never issued.
+        match self.challenges.get(CHALLENGE_METHOD, k1).await? {
+            Some(challenge) if challenge.authenticated_at.is_none() => {}
+            _ => return Err(LnurlAuthError::InvalidChallenge),
+        }
+
+        let user_uuid = self.get_or_create_user(&key).await?;
+
+        if !self
+            .challenges
+            .authenticate(CHALLENGE_METHOD, k1, &user_uuid)
+            .await?
+        {
+            return Err(LnurlAuthError::InvalidChallenge);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the user the challenge was signed for, using it up, or
+    /// `Pending` while the wallet hasn't signed it. Only the browser holding
+    /// the login's poll secret can pick it up.
+    pub async fn poll(
+        &self,
+        k1: &str,
+        poll_secret: &str,
+    ) -> Result<LnurlAuthStatus, LnurlAuthError> {
+        let poll_secret_hash = sha256_hex(poll_secret);
+
+        if let Some(user_uuid) = self
+            .challenges
+            .consume_authenticated(CHALLENGE_METHOD, k1, &poll_secret_hash)
+            .await?
+        {
+            return Ok(LnurlAuthStatus::Authenticated(user_uuid));
+        }
+
+        match self.challenges.get(CHALLENGE_METHOD, k1).await? {
+            Some(challenge) if challenge.poll_secret_hash == Some(poll_secret_hash) => {
+                Ok(LnurlAuthStatus::Pending)
+            }
+            _ => Err(LnurlAuthError::InvalidChallenge),
+        }
+    }
+
+    async fn get_or_create_user(&self, key: &str) -> Result<String, LnurlAuthError> {
+        if let Some(user) = self.users.get_user_by_identifier(key).await? {
+            return Ok(user.uuid);
+        }
+
+        let created = self
+            .users
+            .create(&CreateUser {
+                email: None,
+                password: None,
+                npub: None,
+                identifier: Some(key.to_string()),
+            })
+            .await;
+
+        match created {
+            Ok(user) => Ok(user.uuid),
+            // Signed up by a concurrent login with the same key.
+            Err(UserRepositoryError::DatabaseError(e))
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                self.users
+                    .get_user_by_identifier(key)
+                    .await?
+                    .map(|user| user.uuid)
+                    .ok_or(LnurlAuthError::InvalidChallenge)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+    use super::{LnurlAuthError, LnurlAuthService, LnurlAuthStatus};
+    use crate::{
+        helpers::tests::create_test_pool,
+        repositories::{
+            auth_challenge_repository::AuthChallengeRepository, user_repository::UserRepository,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_lnurl_auth_login() {
+        let pool = create_test_pool().await;
+        let users = UserRepository::new(pool.clone());
+        let service = LnurlAuthService::new(
+            60,
+            "https://nodeless.io/".to_string(),
+            AuthChallengeRepository::new(pool.clone()),
+            users.clone(),
+        );
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&rand::random::<[u8; 32]>()).unwrap();
+        let key = hex::encode(PublicKey::from_secret_key(&secp, &secret).serialize());
+        let sign = |k1: &str| {
+            let message = Message::from_slice(&hex::decode(k1).unwrap()).unwrap();
+            hex::encode(&*secp.sign_ecdsa(&message, &secret).serialize_der())
+        };
+
+        let login = service.login().await.unwrap();
+        assert!(login.lnurl.starts_with("LNURL1"));
+        assert!(!login.lnurl.contains(&login.poll_secret));
+        assert_eq!(
+            service.poll(&login.k1, &login.poll_secret).await.unwrap(),
+            LnurlAuthStatus::Pending
+        );
+
+        // Signatures over another challenge, or by another key, are rejected.
+        let other = hex::encode([1u8; 32]);
+        assert!(matches!(
+            service.callback(&login.k1, &sign(&other), &key).await,
+            Err(LnurlAuthError::InvalidSignature)
+        ));
+        assert!(matches!(
+            service.callback(&other, &sign(&other), &key).await,
+            Err(LnurlAuthError::InvalidChallenge)
+        ));
+        assert!(matches!(
+            service.callback(&login.k1, &sign(&login.k1), "02ab").await,
+            Err(LnurlAuthError::InvalidKey)
+        ));
+
+        service
+            .callback(&login.k1, &sign(&login.k1), &key)
+            .await
+            .unwrap();
+        assert!(matches!(
+            service.callback(&login.k1, &sign(&login.k1), &key).await,
+            Err(LnurlAuthError::InvalidChallenge)
+        ));
+
+        let user = users.get_user_by_identifier(&key).await.unwrap().unwrap();
+        // Knowing the k1 alone doesn't pick up the login.
+        assert!(matches!(
+            service.poll(&login.k1, &other).await,
+            Err(LnurlAuthError::InvalidChallenge)
+        ));
+        assert_eq!(
+            service.poll(&login.k1, &login.poll_secret).await.unwrap(),
+            LnurlAuthStatus::Authenticated(user.uuid.clone())
+        );
+        // The login can only be picked up once.
+        assert!(matches!(
+            service.poll(&login.k1, &login.poll_secret).await,
+            Err(LnurlAuthError::InvalidChallenge)
+        ));
+
+        // Logging in again with the same key finds the same user.
+        let login = service.login().await.unwrap();
+        service
+            .callback(&login.k1, &sign(&login.k1), &key)
+            .await
+            .unwrap();
+        assert_eq!(
+            service.poll(&login.k1, &login.poll_secret).await.unwrap(),
+            LnurlAuthStatus::Authenticated(user.uuid.clone())
+        );
+
+        users.hard_delete(&user.uuid).await.unwrap();
+    }
+}
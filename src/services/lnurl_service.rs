@@ -0,0 +1,435 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    config::LnurlConfig,
+    helpers::nostr::npub_to_hex,
+    models::{checkout::Checkout, nodeless_address::NodelessAddress},
+    repositories::{
+        checkout_repository::CheckoutRepository,
+        nodeless_address_repository::{
+            NodelessAddressPaymentRepository, NodelessAddressRepository,
+        },
+    },
+    services::{
+        checkout_service::{CheckoutService, CreateCheckoutService},
+        zap_service::validate_zap_request,
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum LnurlError {
+    #[error("Lightning address not found")]
+    NotFound,
+    #[error("Amount must be a whole number of sats between {min} and {max} sats")]
+    InvalidAmount { min: i64, max: i64 },
+    #[error("Comment is longer than {0} characters")]
+    CommentTooLong(usize),
+    #[error("Lightning address does not accept zaps")]
+    ZapsNotAllowed,
+    #[error("{0}")]
+    InvalidZapRequest(String),
+    #[error("Too many requests, try again in a minute")]
+    TooManyRequests,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for LnurlError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => LnurlError::NotFound,
+            e => LnurlError::Internal(e.into()),
+        }
+    }
+}
+
+/// LUD-06 pay request, served at `/.well-known/lnurlp/{handle}` (LUD-16).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayRequest {
+    pub callback: String,
+    /// Millisats.
+    pub min_sendable: i64,
+    /// Millisats.
+    pub max_sendable: i64,
+    pub metadata: String,
+    /// LUD-12, omitted when comments are disabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_allowed: Option<usize>,
+    /// NIP-57, set when the address has an npub to zap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allows_nostr: Option<bool>,
+    /// Pubkey zap receipts are signed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nostr_pubkey: Option<String>,
+    pub tag: &'static str,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LnurlPayment {
+    /// Millisats.
+    pub amount: i64,
+    pub comment: Option<String>,
+    /// NIP-57 zap request, as sent in the callback's `nostr` parameter.
+    pub zap_request: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PayCallback {
+    pub pr: String,
+    pub routes: Vec<serde_json::Value>,
+}
+
+/// LNURL errors are reported in the body, not through the status code.
+#[derive(Debug, Clone, Serialize)]
+pub struct LnurlErrorResponse {
+    pub status: &'static str,
+    pub reason: String,
+}
+
+impl From<&LnurlError> for LnurlErrorResponse {
+    fn from(e: &LnurlError) -> Self {
+        let reason = match e {
+            LnurlError::Internal(_) => "Failed to create invoice".to_string(),
+            e => e.to_string(),
+        };
+
+        LnurlErrorResponse {
+            status: "ERROR",
+            reason,
+        }
+    }
+}
+
+pub struct LnurlService {
+    config: LnurlConfig,
+    /// Pubkey zap receipts are signed with.
+    nostr_pubkey: String,
+    addresses: NodelessAddressRepository,
+    payments: NodelessAddressPaymentRepository,
+    checkouts: CheckoutRepository,
+}
+
+impl LnurlService {
+    pub fn new(
+        config: LnurlConfig,
+        nostr_pubkey: String,
+        addresses: NodelessAddressRepository,
+        payments: NodelessAddressPaymentRepository,
+        checkouts: CheckoutRepository,
+    ) -> Self {
+        Self {
+            config,
+            nostr_pubkey,
+            addresses,
+            payments,
+            checkouts,
+        }
+    }
+
+    pub async fn pay_request(&self, handle: &str) -> Result<PayRequest, LnurlError> {
+        let address = self.get_address(handle).await?;
+        let allows_nostr = self.zap_recipient(&address).is_some();
+
+        Ok(PayRequest {
+            callback: format!(
+                "{}/lnurlp/{}/callback",
+                self.config.callback_base_url.trim_end_matches('/'),
+                address.handle
+            ),
+            min_sendable: self.config.min_sendable_sat * 1_000,
+            max_sendable: self.config.max_sendable_sat * 1_000,
+            metadata: self.metadata(&address),
+            comment_allowed: Some(self.config.comment_allowed).filter(|&len| len > 0),
+            allows_nostr: allows_nostr.then_some(true),
+            nostr_pubkey: allows_nostr.then(|| self.nostr_pubkey.clone()),
+            tag: "payRequest",
+        })
+    }
+
+    /// Issues an invoice for the payment, paid to the owner of the address.
+    /// The checkout has no store invoice, so once paid it is credited to the
+    /// user's own balance.
+    pub async fn pay(
+        &self,
+        handle: &str,
+        payment: LnurlPayment,
+        checkout_service: CheckoutService,
+    ) -> Result<(PayCallback, Checkout), LnurlError> {
+        let address = self.get_address(handle).await?;
+
+        let min = self.config.min_sendable_sat;
+        let max = self.config.max_sendable_sat;
+        let amount = payment.amount;
+        if amount % 1_000 != 0 || amount < min * 1_000 || amount > max * 1_000 {
+            return Err(LnurlError::InvalidAmount { min, max });
+        }
+
+        let comment = payment
+            .comment
+            .as_deref()
+            .filter(|comment| !comment.is_empty());
+        if let Some(comment) = comment {
+            if comment.chars().count() > self.config.comment_allowed {
+                return Err(LnurlError::CommentTooLong(self.config.comment_allowed));
+            }
+        }
+
+        // The invoice commits to the metadata, or for zaps to the zap request.
+        let (memo, description) = match &payment.zap_request {
+            Some(zap_request) => {
+                let recipient = self
+                    .zap_recipient(&address)
+                    .ok_or(LnurlError::ZapsNotAllowed)?;
+                validate_zap_request(
+                    zap_request,
+                    amount,
+                    &recipient,
+                    chrono::Utc::now().timestamp(),
+                )
+                .map_err(|e| LnurlError::InvalidZapRequest(e.to_string()))?;
+
+                (
+                    format!("Zap to {}", self.identifier(&address)),
+                    zap_request.clone(),
+                )
+            }
+            None => (self.description(&address), self.metadata(&address)),
+        };
+
+        let checkout = checkout_service
+            .create(
+                CreateCheckoutService {
+                    user_uuid: address.user_uuid.clone(),
+                    amount: amount / 1_000,
+                    expiry: self.config.invoice_expiry_seconds,
+                    memo: Some(memo),
+                    description_hash: Some(hex::encode(Sha256::digest(description.as_bytes()))),
+                },
+                self.checkouts.clone(),
+            )
+            .await?
+            .checkout;
+
+        self.payments
+            .create(
+                &address.uuid,
+                &checkout.uuid,
+                comment,
+                payment.zap_request.as_deref(),
+            )
+            .await?;
+
+        Ok((
+            PayCallback {
+                pr: checkout.payment_request.clone(),
+                routes: vec![],
+            },
+            checkout,
+        ))
+    }
+
+    async fn get_address(&self, handle: &str) -> Result<NodelessAddress, LnurlError> {
+        self.addresses
+            .get_active_by_handle(&handle.to_lowercase())
+            .await?
+            .ok_or(LnurlError::NotFound)
+    }
+
+    /// Hex pubkey zaps to the address are for, if it has an npub.
+    fn zap_recipient(&self, address: &NodelessAddress) -> Option<String> {
+        address
+            .npub
+            .as_deref()
+            .and_then(|npub| npub_to_hex(npub).ok())
+    }
+
+    fn identifier(&self, address: &NodelessAddress) -> String {
+        format!("{}@{}", address.handle, self.config.domain)
+    }
+
+    fn description(&self, address: &NodelessAddress) -> String {
+        format!("Payment to {}", self.identifier(address))
+    }
+
+    fn metadata(&self, address: &NodelessAddress) -> String {
+        serde_json::json!([
+            ["text/plain", self.description(address)],
+            ["text/identifier", self.identifier(address)],
+        ])
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Arc;
+
+    use sha2::{Digest, Sha256};
+    use sqlx::PgPool;
+    use uuid::Uuid;
+
+    use super::{LnurlError, LnurlPayment, LnurlService};
+    use crate::{
+        config::{LnurlConfig, PricingConfig},
+        helpers::{
+            bolt11,
+            tests::{create_test_pool, create_test_user, delete_test_user},
+        },
+        lightning::fake::FakeLightningBackend,
+        models::nodeless_address::NodelessAddress,
+        repositories::{
+            checkout_repository::CheckoutRepository,
+            fee_repository::FeeRepository,
+            nodeless_address_repository::{
+                CreateNodelessAddress, NodelessAddressPaymentRepository, NodelessAddressRepository,
+            },
+        },
+        services::{checkout_service::CheckoutService, fee_service::FeeService},
+    };
+
+    pub fn test_service(pool: &PgPool, nostr_pubkey: &str) -> LnurlService {
+        LnurlService::new(
+            LnurlConfig {
+                domain: "nodeless.io".to_string(),
+                callback_base_url: "https://nodeless.io/".to_string(),
+                min_sendable_sat: 1,
+                max_sendable_sat: 100_000,
+                comment_allowed: 10,
+                invoice_expiry_seconds: 600,
+            },
+            nostr_pubkey.to_string(),
+            NodelessAddressRepository::new(pool.clone()),
+            NodelessAddressPaymentRepository::new(pool.clone()),
+            CheckoutRepository::new(pool.clone()),
+        )
+    }
+
+    pub fn checkout_service(pool: &PgPool) -> CheckoutService {
+        CheckoutService::new(
+            Arc::new(FakeLightningBackend::new()),
+            FeeService::new(
+                &PricingConfig {
+                    base_fee_sat: 0,
+                    fee_rate_percent: 0,
+                },
+                FeeRepository::new(pool.clone()),
+            ),
+        )
+    }
+
+    pub async fn create_test_address(
+        pool: &PgPool,
+        user_uuid: &str,
+        npub: Option<String>,
+    ) -> NodelessAddress {
+        NodelessAddressRepository::new(pool.clone())
+            .create(CreateNodelessAddress {
+                user_uuid: user_uuid.to_string(),
+                handle: format!("test{}", &Uuid::new_v4().simple().to_string()[..12]),
+                npub,
+                price: 1000,
+            })
+            .await
+            .unwrap()
+    }
+
+    pub async fn cleanup(pool: &PgPool, user_uuid: &str, address_uuid: &str) {
+        NodelessAddressRepository::new(pool.clone())
+            .hard_delete(address_uuid)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+            .bind(user_uuid)
+            .execute(pool)
+            .await
+            .unwrap();
+        delete_test_user(user_uuid).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lightning_address_payment() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let address = create_test_address(&pool, &user.uuid, None).await;
+        let handle = address.handle.clone();
+        let service = test_service(&pool, &"ab".repeat(32));
+
+        let pay_request = service.pay_request(&handle.to_uppercase()).await.unwrap();
+        assert_eq!(
+            pay_request.callback,
+            format!("https://nodeless.io/lnurlp/{}/callback", handle)
+        );
+        assert_eq!(pay_request.min_sendable, 1_000);
+        assert_eq!(pay_request.max_sendable, 100_000_000);
+        assert_eq!(pay_request.comment_allowed, Some(10));
+        assert_eq!(pay_request.allows_nostr, None);
+        let metadata: Vec<Vec<String>> = serde_json::from_str(&pay_request.metadata).unwrap();
+        assert_eq!(
+            metadata[1],
+            vec![
+                "text/identifier".to_string(),
+                format!("{}@nodeless.io", handle)
+            ]
+        );
+
+        assert!(matches!(
+            service.pay_request("no-such-handle").await,
+            Err(LnurlError::NotFound)
+        ));
+
+        let payment = |amount: i64, comment: Option<&str>| LnurlPayment {
+            amount,
+            comment: comment.map(str::to_string),
+            zap_request: None,
+        };
+
+        let (callback, checkout) = service
+            .pay(
+                &handle,
+                payment(21_000, Some("thanks")),
+                checkout_service(&pool),
+            )
+            .await
+            .unwrap();
+        assert_eq!(checkout.user_uuid, user.uuid);
+        assert_eq!(checkout.amount, 21);
+        assert_eq!(bolt11::invoice_amount_sat(&callback.pr).unwrap(), Some(21));
+        assert_eq!(
+            bolt11::invoice_description_hash(&callback.pr).unwrap(),
+            Some(hex::encode(Sha256::digest(pay_request.metadata.as_bytes())))
+        );
+
+        let stored = NodelessAddressPaymentRepository::new(pool.clone())
+            .get_by_checkout_uuid(&checkout.uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.nodeless_address_uuid, address.uuid);
+        assert_eq!(stored.comment.as_deref(), Some("thanks"));
+        assert_eq!(stored.zap_request, None);
+
+        for amount in [0, 21_500, 100_001_000] {
+            assert!(matches!(
+                service
+                    .pay(&handle, payment(amount, None), checkout_service(&pool))
+                    .await,
+                Err(LnurlError::InvalidAmount { .. })
+            ));
+        }
+        assert!(matches!(
+            service
+                .pay(
+                    &handle,
+                    payment(21_000, Some("far too long")),
+                    checkout_service(&pool)
+                )
+                .await,
+            Err(LnurlError::CommentTooLong(10))
+        ));
+
+        cleanup(&pool, &user.uuid, &address.uuid).await;
+    }
+}
@@ -1,2 +1,15 @@
+pub mod account_service;
 pub mod checkout_service;
+pub mod event_bus;
+pub mod fee_service;
+pub mod ledger_service;
+pub mod lnurl_auth_service;
+pub mod lnurl_service;
+pub mod nodeless_address_service;
+pub mod nostr_auth_service;
+pub mod session_service;
+pub mod signing_key_service;
 pub mod store_service;
+pub mod totp_service;
+pub mod withdrawal_service;
+pub mod zap_service;
@@ -0,0 +1,535 @@
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    config::NodelessAddressesConfig,
+    helpers::nostr::npub_to_hex,
+    models::{
+        checkout::Checkout,
+        nodeless_address::{NodelessAddress, NodelessAddressStatus},
+    },
+    repositories::{
+        checkout_repository::CheckoutRepository,
+        nodeless_address_repository::{
+            NodelessAddressRepository, Reservation, UpdateNodelessAddress,
+        },
+    },
+    services::checkout_service::{CheckoutService, CreateCheckoutService},
+};
+
+/// Pending purchases activated per sweep.
+const ACTIVATION_BATCH_SIZE: i64 = 100;
+
+#[derive(Error, Debug)]
+pub enum NodelessAddressError {
+    #[error("{0}")]
+    InvalidHandle(String),
+    #[error("Handle is not available")]
+    Unavailable,
+    #[error("Too many handles are waiting for payment, pay or let them expire first")]
+    TooManyPending,
+    #[error("Nodeless address is not active yet")]
+    NotActive,
+    #[error("Invalid npub")]
+    InvalidNpub,
+    #[error("Npub is already used by another address")]
+    NpubTaken,
+    #[error("Nodeless address not found")]
+    NotFound,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for NodelessAddressError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => NodelessAddressError::NotFound,
+            e => NodelessAddressError::Internal(e.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HandleAvailability {
+    pub handle: String,
+    pub available: bool,
+    /// Price in sats.
+    pub price: i64,
+    /// Why the handle can't be bought, if it can't.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodelessAddressPurchase {
+    pub address: NodelessAddress,
+    pub checkout: Checkout,
+    pub qr_unified: String,
+    pub qr_bitcoin: String,
+    pub qr_ln: String,
+}
+
+/// Normalizes a handle to lowercase and checks it can be used as the local
+/// part of a lightning address: letters, digits, `.`, `-` and `_`, starting
+/// and ending with a letter or digit, and not on the reserved list.
+pub fn validate_handle(
+    handle: &str,
+    config: &NodelessAddressesConfig,
+) -> Result<String, NodelessAddressError> {
+    let handle = handle.trim().to_lowercase();
+    let len = handle.chars().count();
+
+    if len < config.min_handle_length || len > config.max_handle_length {
+        return Err(NodelessAddressError::InvalidHandle(format!(
+            "Handle must be between {} and {} characters",
+            config.min_handle_length, config.max_handle_length
+        )));
+    }
+
+    if !handle
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_'))
+    {
+        return Err(NodelessAddressError::InvalidHandle(
+            "Handle may only contain letters, digits, '.', '-' and '_'".to_string(),
+        ));
+    }
+
+    let is_alphanumeric = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    if !is_alphanumeric(handle.chars().next()) || !is_alphanumeric(handle.chars().last()) {
+        return Err(NodelessAddressError::InvalidHandle(
+            "Handle must start and end with a letter or digit".to_string(),
+        ));
+    }
+
+    if config
+        .reserved_handles
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(&handle))
+    {
+        return Err(NodelessAddressError::InvalidHandle(
+            "Handle is reserved".to_string(),
+        ));
+    }
+
+    Ok(handle)
+}
+
+/// Sells handles through checkouts. A handle is reserved as a pending
+/// address while its checkout is paid, and activated once it is paid in full.
+#[derive(Clone)]
+pub struct NodelessAddressService {
+    config: NodelessAddressesConfig,
+    addresses: NodelessAddressRepository,
+    checkouts: CheckoutRepository,
+}
+
+impl NodelessAddressService {
+    pub fn new(
+        config: NodelessAddressesConfig,
+        addresses: NodelessAddressRepository,
+        checkouts: CheckoutRepository,
+    ) -> Self {
+        Self {
+            config,
+            addresses,
+            checkouts,
+        }
+    }
+
+    pub async fn availability(
+        &self,
+        handle: &str,
+    ) -> Result<HandleAvailability, NodelessAddressError> {
+        let (handle, reason) = match validate_handle(handle, &self.config) {
+            Ok(handle) => {
+                let taken = self.addresses.is_handle_taken(&handle).await?;
+                (
+                    handle,
+                    taken.then(|| NodelessAddressError::Unavailable.to_string()),
+                )
+            }
+            Err(NodelessAddressError::InvalidHandle(reason)) => {
+                (handle.trim().to_lowercase(), Some(reason))
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(HandleAvailability {
+            handle,
+            available: reason.is_none(),
+            price: self.config.price_sat,
+            reason,
+        })
+    }
+
+    /// Reserves the handle for the user and creates the checkout that pays
+    /// for it. The reservation lasts as long as the checkout.
+    pub async fn purchase(
+        &self,
+        user_uuid: &str,
+        handle: &str,
+        checkout_service: CheckoutService,
+    ) -> Result<NodelessAddressPurchase, NodelessAddressError> {
+        let handle = validate_handle(handle, &self.config)?;
+        let reserved_until = chrono::Utc::now().naive_utc()
+            + chrono::Duration::seconds(self.config.reservation_seconds);
+
+        let reservation = self
+            .addresses
+            .reserve(
+                user_uuid,
+                &handle,
+                self.config.price_sat,
+                reserved_until,
+                self.config.max_pending_purchases,
+            )
+            .await?;
+        let address = match reservation {
+            Reservation::Reserved(address) => address,
+            Reservation::Taken => return Err(NodelessAddressError::Unavailable),
+            Reservation::TooManyPending => return Err(NodelessAddressError::TooManyPending),
+        };
+
+        let checkout = checkout_service
+            .create(
+                CreateCheckoutService {
+                    user_uuid: user_uuid.to_string(),
+                    amount: address.price,
+                    expiry: self.config.reservation_seconds,
+                    memo: Some(format!("Nodeless address {}", handle)),
+                    description_hash: None,
+                },
+                self.checkouts.clone(),
+            )
+            .await;
+
+        let checkout = match checkout {
+            Ok(checkout) => checkout,
+            Err(e) => {
+                // Release the handle rather than hold it without a way to pay.
+                self.addresses.hard_delete(&address.uuid).await?;
+                return Err(e.into());
+            }
+        };
+
+        let address = self
+            .addresses
+            .set_checkout(&address.uuid, &checkout.checkout.uuid)
+            .await?;
+
+        Ok(NodelessAddressPurchase {
+            address,
+            checkout: checkout.checkout,
+            qr_unified: checkout.qr_unified,
+            qr_bitcoin: checkout.qr_bitcoin,
+            qr_ln: checkout.qr_ln,
+        })
+    }
+
+    /// Activates the address bought with the checkout, if it was paid in full.
+    pub async fn activate(
+        &self,
+        checkout: &Checkout,
+    ) -> Result<Option<NodelessAddress>, NodelessAddressError> {
+        let mut activated = self
+            .addresses
+            .activate_paid(Some(&checkout.uuid), 1)
+            .await?;
+
+        Ok(activated.pop())
+    }
+
+    /// Activates paid purchases that were missed, e.g. while the server was
+    /// down, and releases the handles of reservations that expired unpaid.
+    pub async fn reconcile(&self) -> Result<Vec<NodelessAddress>, NodelessAddressError> {
+        let activated = self
+            .addresses
+            .activate_paid(None, ACTIVATION_BATCH_SIZE)
+            .await?;
+        self.addresses.delete_expired_reservations().await?;
+
+        Ok(activated)
+    }
+
+    /// Sets or clears the npub of one of the user's active addresses.
+    pub async fn update_npub(
+        &self,
+        user_uuid: &str,
+        uuid: &str,
+        npub: Option<String>,
+    ) -> Result<NodelessAddress, NodelessAddressError> {
+        let npub = npub
+            .map(|npub| npub.trim().to_string())
+            .filter(|npub| !npub.is_empty());
+        if let Some(npub) = &npub {
+            npub_to_hex(npub).map_err(|_| NodelessAddressError::InvalidNpub)?;
+        }
+
+        let address = self
+            .addresses
+            .get_by_user_and_uuid(user_uuid, uuid)
+            .await?
+            .ok_or(NodelessAddressError::NotFound)?;
+        // The npub is set once the handle is paid for, so pending ones can't
+        // claim it.
+        if address.status != NodelessAddressStatus::Active {
+            return Err(NodelessAddressError::NotActive);
+        }
+
+        self.addresses
+            .update(&address.uuid, UpdateNodelessAddress { npub, price: None })
+            .await
+            .map_err(|e| match e.as_database_error() {
+                Some(db) if db.is_unique_violation() => NodelessAddressError::NpubTaken,
+                _ => e.into(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_handle, NodelessAddressError, NodelessAddressService};
+    use crate::{
+        config::NodelessAddressesConfig,
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        models::{checkout::CheckoutStatus, nodeless_address::NodelessAddressStatus},
+        repositories::{
+            checkout_repository::CheckoutRepository,
+            nodeless_address_repository::{NodelessAddressRepository, Reservation},
+        },
+        services::lnurl_service::tests::checkout_service,
+    };
+
+    fn test_config() -> NodelessAddressesConfig {
+        NodelessAddressesConfig {
+            price_sat: 1000,
+            min_handle_length: 3,
+            max_handle_length: 32,
+            reservation_seconds: 900,
+            reserved_handles: vec!["admin".to_string()],
+            max_pending_purchases: 2,
+        }
+    }
+
+    #[test]
+    fn test_validate_handle() {
+        let config = test_config();
+
+        assert_eq!(validate_handle(" Satoshi ", &config).unwrap(), "satoshi");
+        assert_eq!(validate_handle("s.n-4_k", &config).unwrap(), "s.n-4_k");
+
+        for handle in [
+            "ab",
+            &"a".repeat(33),
+            "sat oshi",
+            "sätoshi",
+            ".sat",
+            "sat-",
+            "Admin",
+        ] {
+            assert!(
+                matches!(
+                    validate_handle(handle, &config),
+                    Err(NodelessAddressError::InvalidHandle(_))
+                ),
+                "{}",
+                handle
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purchase_handle() {
+        let pool = create_test_pool().await;
+        let buyer = create_test_user().await.unwrap();
+        let other = create_test_user().await.unwrap();
+        let addresses = NodelessAddressRepository::new(pool.clone());
+        let checkouts = CheckoutRepository::new(pool.clone());
+        let service =
+            NodelessAddressService::new(test_config(), addresses.clone(), checkouts.clone());
+        let handle = format!("buy{}", &buyer.uuid.replace('-', "")[..12]);
+
+        assert!(service.availability(&handle).await.unwrap().available);
+        assert!(!service.availability("admin").await.unwrap().available);
+
+        let purchase = service
+            .purchase(&buyer.uuid, &handle.to_uppercase(), checkout_service(&pool))
+            .await
+            .unwrap();
+        assert_eq!(purchase.address.handle, handle);
+        assert_eq!(purchase.address.status, NodelessAddressStatus::Pending);
+        assert_eq!(purchase.checkout.amount, 1000);
+        assert_eq!(
+            purchase.address.checkout_uuid.as_deref(),
+            Some(purchase.checkout.uuid.as_str())
+        );
+
+        // The reservation holds the handle, but it doesn't resolve yet.
+        assert!(!service.availability(&handle).await.unwrap().available);
+        assert!(matches!(
+            service
+                .purchase(&other.uuid, &handle, checkout_service(&pool))
+                .await,
+            Err(NodelessAddressError::Unavailable)
+        ));
+        assert!(addresses
+            .get_active_by_handle(&handle)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            service
+                .update_npub(&buyer.uuid, &purchase.address.uuid, None)
+                .await,
+            Err(NodelessAddressError::NotActive)
+        ));
+
+        // Buyers can only hold a few handles while they are unpaid.
+        let second = service
+            .purchase(
+                &buyer.uuid,
+                &format!("{}b", handle),
+                checkout_service(&pool),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            service
+                .purchase(
+                    &buyer.uuid,
+                    &format!("{}c", handle),
+                    checkout_service(&pool)
+                )
+                .await,
+            Err(NodelessAddressError::TooManyPending)
+        ));
+        addresses.hard_delete(&second.address.uuid).await.unwrap();
+
+        // Underpaid checkouts don't activate the handle.
+        let checkout = checkouts
+            .set_payment_received(
+                &purchase.checkout.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Underpaid,
+                500,
+                500,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(service.activate(&checkout).await.unwrap().is_none());
+
+        let checkout = checkouts
+            .set_payment_received(
+                &purchase.checkout.uuid,
+                CheckoutStatus::Underpaid,
+                CheckoutStatus::Paid,
+                1000,
+                1000,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let address = service.activate(&checkout).await.unwrap().unwrap();
+        assert_eq!(address.status, NodelessAddressStatus::Active);
+        assert!(address.activated_at.is_some());
+        assert!(addresses
+            .get_active_by_handle(&handle)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(service.activate(&checkout).await.unwrap().is_none());
+
+        assert!(matches!(
+            service
+                .update_npub(&buyer.uuid, &address.uuid, Some("npub1nope".to_string()))
+                .await,
+            Err(NodelessAddressError::InvalidNpub)
+        ));
+        assert!(matches!(
+            service.update_npub(&other.uuid, &address.uuid, None).await,
+            Err(NodelessAddressError::NotFound)
+        ));
+
+        assert!(addresses
+            .delete_by_user(&buyer.uuid, &address.uuid)
+            .await
+            .unwrap());
+        // Deleted handles are not released.
+        assert!(!service.availability(&handle).await.unwrap().available);
+        assert!(addresses
+            .get_all_by_user(&buyer.uuid)
+            .await
+            .unwrap()
+            .is_empty());
+
+        addresses.hard_delete(&address.uuid).await.unwrap();
+        for user in [&buyer, &other] {
+            sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+                .bind(&user.uuid)
+                .execute(&pool)
+                .await
+                .unwrap();
+            delete_test_user(&user.uuid).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_reservation_is_released() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let addresses = NodelessAddressRepository::new(pool.clone());
+        let service = NodelessAddressService::new(
+            test_config(),
+            addresses.clone(),
+            CheckoutRepository::new(pool.clone()),
+        );
+        let handle = format!("exp{}", &user.uuid.replace('-', "")[..12]);
+
+        let reserve_expired = |handle: String| {
+            let addresses = addresses.clone();
+            let user_uuid = user.uuid.clone();
+            async move {
+                let reservation = addresses
+                    .reserve(
+                        &user_uuid,
+                        &handle,
+                        1000,
+                        chrono::Utc::now().naive_utc() - chrono::Duration::seconds(1),
+                        test_config().max_pending_purchases,
+                    )
+                    .await
+                    .unwrap();
+                match reservation {
+                    Reservation::Reserved(address) => address,
+                    other => panic!("unexpected reservation: {:?}", other),
+                }
+            }
+        };
+
+        let expired = reserve_expired(handle.clone()).await;
+        assert!(service.availability(&handle).await.unwrap().available);
+
+        let purchase = service
+            .purchase(&user.uuid, &handle, checkout_service(&pool))
+            .await
+            .unwrap();
+        assert_ne!(purchase.address.uuid, expired.uuid);
+        assert!(addresses.get_by_uuid(&expired.uuid).await.is_err());
+
+        // Expired reservations don't count against the cap, and the sweep
+        // deletes them without waiting for their handle to be bought again.
+        let other = reserve_expired(format!("{}x", handle)).await;
+        service.reconcile().await.unwrap();
+        assert!(addresses.get_by_uuid(&other.uuid).await.is_err());
+
+        addresses.hard_delete(&purchase.address.uuid).await.unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use thiserror::Error;
+
+use crate::{
+    helpers::nostr::{hex_to_npub, NostrEvent},
+    models::{auth_challenge::AuthChallenge, user::User},
+    repositories::{
+        auth_challenge_repository::AuthChallengeRepository,
+        user_repository::{CreateUser, UserRepository, UserRepositoryError},
+    },
+};
+
+/// NIP-98 HTTP auth event kind, signed by the client to log in.
+pub const NOSTR_AUTH_KIND: u32 = 27235;
+/// Challenges issued for nostr login.
+const CHALLENGE_METHOD: &str = "nostr";
+/// Allowed clock difference between the client and the server.
+const MAX_EVENT_AGE_SECONDS: i64 = 60;
+/// Method of the login request, which login events must name.
+const LOGIN_METHOD: &str = "POST";
+
+#[derive(Error, Debug)]
+pub enum NostrAuthError {
+    #[error("{0}")]
+    InvalidEvent(String),
+    #[error("Challenge is invalid, expired or already used")]
+    InvalidChallenge,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for NostrAuthError {
+    fn from(e: sqlx::Error) -> Self {
+        NostrAuthError::Internal(e.into())
+    }
+}
+
+impl From<UserRepositoryError> for NostrAuthError {
+    fn from(e: UserRepositoryError) -> Self {
+        NostrAuthError::Internal(e.into())
+    }
+}
+
+/// Checks the event is a fresh, correctly signed NIP-98 event for a login
+/// request to `login_url`, and returns the challenge it signs.
+pub fn validate_login_event<'a>(
+    event: &'a NostrEvent,
+    login_url: &str,
+    now: i64,
+) -> Result<&'a str, NostrAuthError> {
+    let invalid = |reason: &str| NostrAuthError::InvalidEvent(reason.to_string());
+
+    if event.kind != NOSTR_AUTH_KIND {
+        return Err(invalid("Login event must be of kind 27235"));
+    }
+
+    if (now - event.created_at).abs() > MAX_EVENT_AGE_SECONDS {
+        return Err(invalid("Login event is too old or from the future"));
+    }
+
+    event
+        .verify()
+        .map_err(|_| invalid("Login event signature is invalid"))?;
+
+    // Events signed for another request can't be used to log in here.
+    let url = event
+        .tag_value("u")
+        .ok_or_else(|| invalid("Login event has no u tag"))?;
+    if url.trim_end_matches('/') != login_url.trim_end_matches('/') {
+        return Err(invalid("Login event is for another url"));
+    }
+    let method = event
+        .tag_value("method")
+        .ok_or_else(|| invalid("Login event has no method tag"))?;
+    if !method.eq_ignore_ascii_case(LOGIN_METHOD) {
+        return Err(invalid("Login event is for another method"));
+    }
+
+    event
+        .tag_value("challenge")
+        .ok_or_else(|| invalid("Login event has no challenge tag"))
+}
+
+/// Logs users in by having them sign a server-issued challenge with their
+/// nostr key. Users are matched on their npub, and signed up on first login.
+#[derive(Debug, Clone)]
+pub struct NostrAuthService {
+    challenge_expiry_seconds: i64,
+    login_url: String,
+    challenges: AuthChallengeRepository,
+    users: UserRepository,
+}
+
+impl NostrAuthService {
+    pub fn new(
+        challenge_expiry_seconds: i64,
+        login_url: String,
+        challenges: AuthChallengeRepository,
+        users: UserRepository,
+    ) -> Self {
+        Self {
+            challenge_expiry_seconds,
+            login_url,
+            challenges,
+            users,
+        }
+    }
+
+    pub async fn challenge(&self) -> Result<AuthChallenge, NostrAuthError> {
+        Ok(self
+            .challenges
+            .create(CHALLENGE_METHOD, self.challenge_expiry_seconds)
+            .await?)
+    }
+
+    pub async fn login(&self, event: &NostrEvent) -> Result<User, NostrAuthError> {
+        let challenge =
+            validate_login_event(event, &self.login_url, chrono::Utc::now().timestamp())?;
+
+        if !self.challenges.consume(CHALLENGE_METHOD, challenge).await? {
+            return Err(NostrAuthError::InvalidChallenge);
+        }
+
+        let npub = hex_to_npub(&event.pubkey)?;
+        if let Some(user) = self.users.get_user_by_npub(&npub).await? {
+            return Ok(user);
+        }
+
+        let created = self
+            .users
+            .create(&CreateUser {
+                email: None,
+                password: None,
+                npub: Some(npub.clone()),
+                identifier: None,
+            })
+            .await;
+
+        match created {
+            Ok(user) => Ok(user),
+            // Signed up by a concurrent login with the same key.
+            Err(UserRepositoryError::DatabaseError(e))
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                self.users
+                    .get_user_by_npub(&npub)
+                    .await?
+                    .ok_or(NostrAuthError::InvalidChallenge)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NostrAuthError, NostrAuthService, NOSTR_AUTH_KIND};
+    use crate::{
+        helpers::{
+            nostr::{hex_to_npub, keypair_from_hex, NostrEvent},
+            tests::create_test_pool,
+        },
+        repositories::{
+            auth_challenge_repository::AuthChallengeRepository, user_repository::UserRepository,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_nostr_login() {
+        let pool = create_test_pool().await;
+        let users = UserRepository::new(pool.clone());
+        let login_url = "https://nodeless.io/auth/nostr";
+        let service = NostrAuthService::new(
+            60,
+            login_url.to_string(),
+            AuthChallengeRepository::new(pool.clone()),
+            users.clone(),
+        );
+        let keys = keypair_from_hex(&hex::encode(rand::random::<[u8; 32]>())).unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let sign_for = |challenge: &str, kind: u32, created_at: i64, url: &str, method: &str| {
+            NostrEvent::sign(
+                &keys,
+                created_at,
+                kind,
+                vec![
+                    vec!["challenge".to_string(), challenge.to_string()],
+                    vec!["u".to_string(), url.to_string()],
+                    vec!["method".to_string(), method.to_string()],
+                ],
+                String::new(),
+            )
+            .unwrap()
+        };
+        let sign = |challenge: &str, kind: u32, created_at: i64| {
+            sign_for(challenge, kind, created_at, login_url, "POST")
+        };
+
+        let challenge = service.challenge().await.unwrap().challenge;
+
+        for event in [
+            sign(&challenge, 1, now),
+            sign(&challenge, NOSTR_AUTH_KIND, now - 600),
+            sign_for(
+                &challenge,
+                NOSTR_AUTH_KIND,
+                now,
+                "https://example.com/login",
+                "POST",
+            ),
+            sign_for(&challenge, NOSTR_AUTH_KIND, now, login_url, "GET"),
+        ] {
+            assert!(matches!(
+                service.login(&event).await,
+                Err(NostrAuthError::InvalidEvent(_))
+            ));
+        }
+        let mut forged = sign(&challenge, NOSTR_AUTH_KIND, now);
+        forged.pubkey =
+            "dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659".to_string();
+        assert!(matches!(
+            service.login(&forged).await,
+            Err(NostrAuthError::InvalidEvent(_))
+        ));
+        assert!(matches!(
+            service
+                .login(&sign("not issued", NOSTR_AUTH_KIND, now))
+                .await,
+            Err(NostrAuthError::InvalidChallenge)
+        ));
+
+        // First login signs the user up.
+        let event = sign(&challenge, NOSTR_AUTH_KIND, now);
+        let user = service.login(&event).await.unwrap();
+        assert_eq!(user.npub, Some(hex_to_npub(&event.pubkey).unwrap()));
+        assert_eq!(user.email, None);
+
+        // Challenges can't be replayed.
+        assert!(matches!(
+            service.login(&event).await,
+            Err(NostrAuthError::InvalidChallenge)
+        ));
+
+        let challenge = service.challenge().await.unwrap().challenge;
+        let again = service
+            .login(&sign(&challenge, NOSTR_AUTH_KIND, now))
+            .await
+            .unwrap();
+        assert_eq!(again.uuid, user.uuid);
+
+        users.hard_delete(&user.uuid).await.unwrap();
+    }
+}
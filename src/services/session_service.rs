@@ -0,0 +1,234 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    helpers::crypto::sha256_hex,
+    middleware::jwt_middleware::{generate_jwt_token, AuthCache},
+    repositories::session_repository::{CreateSession, RefreshOutcome, SessionRepository},
+    services::signing_key_service::SigningKeyService,
+};
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Refresh token is invalid or expired")]
+    InvalidRefreshToken,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for SessionError {
+    fn from(e: sqlx::Error) -> Self {
+        SessionError::Internal(e.into())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenPair {
+    /// Short-lived access token for the `Authorization` header.
+    pub token: String,
+    /// Exchanged at `/auth/refresh` for a new pair. Each one works once.
+    pub refresh_token: String,
+    /// Seconds until `token` expires.
+    pub expires_in: u64,
+}
+
+fn generate_refresh_token() -> String {
+    let mut token = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token);
+    hex::encode(token)
+}
+
+/// Logins as server-side sessions. Each issues short-lived access tokens
+/// tied to the session, renewed with refresh tokens that rotate on every
+/// use.
+#[derive(Debug, Clone)]
+pub struct SessionService {
+    access_token_expiry_seconds: u64,
+    refresh_token_expiry_seconds: u64,
+    sessions: SessionRepository,
+    keys: SigningKeyService,
+    cache: AuthCache,
+}
+
+impl SessionService {
+    pub fn new(
+        access_token_expiry_seconds: u64,
+        refresh_token_expiry_seconds: u64,
+        sessions: SessionRepository,
+        keys: SigningKeyService,
+        cache: AuthCache,
+    ) -> Self {
+        Self {
+            access_token_expiry_seconds,
+            refresh_token_expiry_seconds,
+            sessions,
+            keys,
+            cache,
+        }
+    }
+
+    /// Starts a session for a user who just logged in.
+    pub async fn start(
+        &self,
+        user_uuid: &str,
+        device: Option<String>,
+        ip_hash: String,
+    ) -> Result<TokenPair, SessionError> {
+        let refresh_token = generate_refresh_token();
+        let session = self
+            .sessions
+            .create(CreateSession {
+                user_uuid: user_uuid.to_string(),
+                device,
+                ip_hash,
+                refresh_token_hash: sha256_hex(&refresh_token),
+                expiry_seconds: self.refresh_token_expiry_seconds,
+            })
+            .await?;
+
+        self.token_pair(&session.user_uuid, &session.uuid, refresh_token)
+            .await
+    }
+
+    /// Swaps a refresh token for a new pair. Reusing a refresh token ends
+    /// its session.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        ip_hash: &str,
+    ) -> Result<TokenPair, SessionError> {
+        let new_refresh_token = generate_refresh_token();
+        let outcome = self
+            .sessions
+            .rotate(
+                &sha256_hex(refresh_token),
+                &sha256_hex(&new_refresh_token),
+                ip_hash,
+                self.refresh_token_expiry_seconds,
+            )
+            .await?;
+
+        match outcome {
+            RefreshOutcome::Rotated(session) => {
+                self.token_pair(&session.user_uuid, &session.uuid, new_refresh_token)
+                    .await
+            }
+            RefreshOutcome::Reused(session_uuid) => {
+                eprintln!("refresh token reused, session revoked");
+                // Access tokens for the session stop working right away.
+                self.cache.invalidate_session(&session_uuid).await;
+                Err(SessionError::InvalidRefreshToken)
+            }
+            RefreshOutcome::Invalid => Err(SessionError::InvalidRefreshToken),
+        }
+    }
+
+    async fn token_pair(
+        &self,
+        user_uuid: &str,
+        session_uuid: &str,
+        refresh_token: String,
+    ) -> Result<TokenPair, SessionError> {
+        let token = generate_jwt_token(
+            &self.keys,
+            user_uuid,
+            session_uuid,
+            self.access_token_expiry_seconds,
+        )
+        .await
+        .map_err(anyhow::Error::from)?;
+
+        Ok(TokenPair {
+            token,
+            refresh_token,
+            expires_in: self.access_token_expiry_seconds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use jsonwebtoken::Algorithm;
+
+    use super::{SessionError, SessionService};
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        middleware::jwt_middleware::{verify_jwt_token, AuthCache},
+        repositories::{
+            session_repository::SessionRepository, signing_key_repository::SigningKeyRepository,
+        },
+        services::signing_key_service::SigningKeyService,
+    };
+
+    #[tokio::test]
+    async fn test_refresh_token_rotation() {
+        let user = create_test_user().await.unwrap();
+        let pool = create_test_pool().await;
+        let repo = SessionRepository::new(pool.clone());
+        let keys = SigningKeyService::new(
+            Algorithm::EdDSA,
+            3,
+            Duration::from_secs(60),
+            SigningKeyRepository::new(pool),
+        );
+        keys.load().await.unwrap();
+        let cache = AuthCache::new(Duration::from_secs(60));
+        let service = SessionService::new(60, 3600, repo.clone(), keys.clone(), cache.clone());
+
+        let first = service
+            .start(&user.uuid, Some("test".to_string()), "ip".to_string())
+            .await
+            .unwrap();
+        let claims = verify_jwt_token(&keys, &first.token).await.unwrap().claims;
+        assert_eq!(claims.sub, user.uuid);
+        let session_uuid = claims.sid.unwrap();
+
+        let second = service.refresh(&first.refresh_token, "ip").await.unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+        assert_eq!(
+            verify_jwt_token(&keys, &second.token)
+                .await
+                .unwrap()
+                .claims
+                .sid,
+            Some(session_uuid.clone())
+        );
+        assert!(repo.is_active(&session_uuid).await.unwrap());
+
+        // Replaying a used refresh token kills the session, and with it the
+        // refresh token issued in its place.
+        cache.sessions.insert(session_uuid.clone(), true).await;
+        assert!(matches!(
+            service.refresh(&first.refresh_token, "ip").await,
+            Err(SessionError::InvalidRefreshToken)
+        ));
+        assert!(!repo.is_active(&session_uuid).await.unwrap());
+        assert!(cache.sessions.get(&session_uuid).is_none());
+        assert!(matches!(
+            service.refresh(&second.refresh_token, "ip").await,
+            Err(SessionError::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            service.refresh("garbage", "ip").await,
+            Err(SessionError::InvalidRefreshToken)
+        ));
+
+        // Other sessions are unaffected, until revoked.
+        let other = service
+            .start(&user.uuid, None, "ip".to_string())
+            .await
+            .unwrap();
+        let sessions = repo.get_active_by_user(&user.uuid).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(repo.revoke(&user.uuid, &sessions[0].uuid).await.unwrap());
+        assert!(matches!(
+            service.refresh(&other.refresh_token, "ip").await,
+            Err(SessionError::InvalidRefreshToken)
+        ));
+
+        delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, Header, TokenData, Validation};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    helpers::signing_key::{self, Jwk},
+    models::signing_key::SigningKey,
+    repositories::signing_key_repository::{CreateSigningKey, SigningKeyRepository},
+};
+
+/// How soon a token with an unknown `kid` can make the keys reload, in case
+/// another instance rotated them.
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long clients may cache the JWKS.
+pub const JWKS_MAX_AGE: Duration = Duration::from_secs(300);
+
+#[derive(Error, Debug)]
+pub enum SigningKeyError {
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for SigningKeyError {
+    fn from(e: sqlx::Error) -> Self {
+        SigningKeyError::Internal(e.into())
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for SigningKeyError {
+    fn from(_: jsonwebtoken::errors::Error) -> Self {
+        SigningKeyError::InvalidToken
+    }
+}
+
+/// The JWKS served at `/.well-known/jwks.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+struct SigningCandidate {
+    signs_from: DateTime<Utc>,
+    kid: String,
+    algorithm: Algorithm,
+    key: jsonwebtoken::EncodingKey,
+}
+
+struct Keyring {
+    /// Newest first, down to the first key that signed already when loaded.
+    signing: Vec<SigningCandidate>,
+    verifying: HashMap<String, (Algorithm, jsonwebtoken::DecodingKey)>,
+    jwks: Vec<Jwk>,
+    loaded_at: Instant,
+}
+
+impl Keyring {
+    fn empty() -> Self {
+        Self {
+            signing: Vec::new(),
+            verifying: HashMap::new(),
+            jwks: Vec::new(),
+            loaded_at: Instant::now(),
+        }
+    }
+
+    /// Builds the keyring from keys ordered newest first.
+    fn from_keys(keys: &[SigningKey]) -> anyhow::Result<Self> {
+        let mut keyring = Self::empty();
+
+        for key in keys {
+            let algorithm = signing_key::parse_algorithm(&key.algorithm)?;
+            let public_key = base64::decode_config(&key.public_key, base64::URL_SAFE_NO_PAD)?;
+
+            if !keyring.has_signed_by(Utc::now()) {
+                let pkcs8 = signing_key::decrypt(&key.kid, &key.private_key)?;
+                keyring.signing.push(SigningCandidate {
+                    signs_from: key.signs_from,
+                    kid: key.kid.clone(),
+                    algorithm,
+                    key: signing_key::encoding_key(algorithm, &pkcs8),
+                });
+            }
+            keyring.verifying.insert(
+                key.kid.clone(),
+                (algorithm, signing_key::decoding_key(algorithm, &public_key)),
+            );
+            keyring
+                .jwks
+                .push(signing_key::jwk(&key.kid, algorithm, &public_key));
+        }
+
+        Ok(keyring)
+    }
+
+    fn has_signed_by(&self, at: DateTime<Utc>) -> bool {
+        self.signing.iter().any(|key| key.signs_from <= at)
+    }
+
+    /// The newest key that signs at `at`.
+    fn signer(&self, at: DateTime<Utc>) -> Option<&SigningCandidate> {
+        self.signing.iter().find(|key| key.signs_from <= at)
+    }
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field(
+                "signing",
+                &self.signing.iter().map(|key| &key.kid).collect::<Vec<_>>(),
+            )
+            .field("verifying", &self.verifying.keys())
+            .finish()
+    }
+}
+
+/// Asymmetric keys access tokens are signed with. The newest key signs and
+/// the ones before it keep verifying, so rotating doesn't log anyone out.
+/// Keys live in the database, shared by every instance, and are reloaded
+/// now and then to pick up rotations. Rotated keys are published in the
+/// JWKS before they start signing, so clients with a cached copy can
+/// verify the tokens they sign.
+#[derive(Debug, Clone)]
+pub struct SigningKeyService {
+    algorithm: Algorithm,
+    keys_kept: i64,
+    refresh_interval: Duration,
+    keys: SigningKeyRepository,
+    keyring: Arc<RwLock<Keyring>>,
+}
+
+impl SigningKeyService {
+    /// `algorithm` is used for the first key, and `keys_kept` is how many
+    /// keys verify tokens, the signing one included.
+    pub fn new(
+        algorithm: Algorithm,
+        keys_kept: i64,
+        refresh_interval: Duration,
+        keys: SigningKeyRepository,
+    ) -> Self {
+        Self {
+            algorithm,
+            keys_kept: keys_kept.max(1),
+            refresh_interval,
+            keys,
+            keyring: Arc::new(RwLock::new(Keyring::empty())),
+        }
+    }
+
+    /// Loads the keys, generating the first one if there are none.
+    pub async fn load(&self) -> Result<(), SigningKeyError> {
+        if !self.reload().await? {
+            self.add_key(self.algorithm, Duration::ZERO).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Swaps in the kept keys from the database. Returns false if there
+    /// are none.
+    async fn reload(&self) -> Result<bool, SigningKeyError> {
+        let keys = self.keys.get_latest(self.keys_kept).await?;
+        if keys.is_empty() {
+            return Ok(false);
+        }
+
+        let keyring = Keyring::from_keys(&keys)?;
+        *self.keyring.write().unwrap() = keyring;
+
+        Ok(true)
+    }
+
+    /// Generates a key that is published in the JWKS now and signs once
+    /// every instance picked it up and cached copies without it expired.
+    /// Keys older than the kept ones are deleted, and tokens they signed
+    /// stop verifying.
+    pub async fn rotate(&self, algorithm: Algorithm) -> Result<SigningKey, SigningKeyError> {
+        self.add_key(algorithm, self.refresh_interval + JWKS_MAX_AGE)
+            .await
+    }
+
+    async fn add_key(
+        &self,
+        algorithm: Algorithm,
+        signs_in: Duration,
+    ) -> Result<SigningKey, SigningKeyError> {
+        let material = signing_key::generate(algorithm)?;
+        let mut kid = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut kid);
+        let kid = hex::encode(kid);
+
+        let key = self
+            .keys
+            .create(CreateSigningKey {
+                private_key: signing_key::encrypt(&kid, &material.pkcs8)?,
+                public_key: base64::encode_config(&material.public_key, base64::URL_SAFE_NO_PAD),
+                algorithm: signing_key::algorithm_name(algorithm).to_string(),
+                kid,
+                signs_in_seconds: signs_in.as_secs(),
+            })
+            .await?;
+        self.keys.prune(self.keys_kept).await?;
+        self.reload().await?;
+
+        Ok(key)
+    }
+
+    fn loaded_within(&self, interval: Duration) -> bool {
+        self.keyring.read().unwrap().loaded_at.elapsed() < interval
+    }
+
+    /// Picks up keys rotated by other instances. The loaded keys stay in
+    /// use if the database can't be reached.
+    async fn refresh(&self) {
+        if self.loaded_within(self.refresh_interval) {
+            return;
+        }
+        if let Err(e) = self.reload().await {
+            eprintln!("Failed to reload signing keys: {}", e);
+        }
+    }
+
+    /// Signs `claims` with the newest key that signs already, named in the
+    /// `kid` header.
+    pub async fn sign<T: Serialize>(&self, claims: &T) -> Result<String, SigningKeyError> {
+        self.refresh().await;
+
+        let keyring = self.keyring.read().unwrap();
+        let signer = keyring
+            .signer(Utc::now())
+            .ok_or_else(|| anyhow::anyhow!("No signing key loaded"))?;
+
+        let mut header = Header::new(signer.algorithm);
+        header.kid = Some(signer.kid.clone());
+
+        Ok(encode(&header, claims, &signer.key).map_err(anyhow::Error::from)?)
+    }
+
+    /// Checks a token's signature, with the key its `kid` header names,
+    /// and its expiry.
+    pub async fn verify<T: DeserializeOwned>(
+        &self,
+        token: &str,
+    ) -> Result<TokenData<T>, SigningKeyError> {
+        let kid = decode_header(token)?
+            .kid
+            .ok_or(SigningKeyError::InvalidToken)?;
+
+        let known = self.keyring.read().unwrap().verifying.contains_key(&kid);
+        if !known && !self.loaded_within(MIN_RELOAD_INTERVAL) {
+            self.reload().await?;
+        }
+
+        let keyring = self.keyring.read().unwrap();
+        let (algorithm, key) = keyring
+            .verifying
+            .get(&kid)
+            .ok_or(SigningKeyError::InvalidToken)?;
+
+        Ok(decode::<T>(token, key, &Validation::new(*algorithm))?)
+    }
+
+    /// Public keys of every key that verifies tokens.
+    pub async fn jwks(&self) -> JwkSet {
+        self.refresh().await;
+
+        JwkSet {
+            keys: self.keyring.read().unwrap().jwks.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use jsonwebtoken::{decode_header, Algorithm};
+
+    use super::{SigningKeyError, SigningKeyService};
+    use crate::{
+        helpers::tests::create_test_pool,
+        middleware::jwt_middleware::{generate_jwt_token, verify_jwt_token},
+        repositories::signing_key_repository::SigningKeyRepository,
+    };
+
+    #[tokio::test]
+    async fn test_signing_key_rotation() {
+        let repo = SigningKeyRepository::new(create_test_pool().await);
+        let service =
+            SigningKeyService::new(Algorithm::EdDSA, 10, Duration::from_secs(60), repo.clone());
+        service.load().await.unwrap();
+
+        let before = generate_jwt_token(&service, "user", "session", 60)
+            .await
+            .unwrap();
+        let key = service
+            .add_key(Algorithm::ES256, Duration::ZERO)
+            .await
+            .unwrap();
+        let after = generate_jwt_token(&service, "user", "session", 60)
+            .await
+            .unwrap();
+
+        let header = decode_header(&after).unwrap();
+        assert_eq!(header.alg, Algorithm::ES256);
+        assert_eq!(header.kid, Some(key.kid.clone()));
+        assert_ne!(decode_header(&before).unwrap().kid, header.kid);
+
+        // Tokens signed before the rotation still verify.
+        for token in [&before, &after] {
+            let claims = verify_jwt_token(&service, token).await.unwrap().claims;
+            assert_eq!(claims.sub, "user");
+        }
+        assert!(matches!(
+            verify_jwt_token(&service, &format!("{}x", after)).await,
+            Err(SigningKeyError::InvalidToken)
+        ));
+
+        let jwks = service.jwks().await;
+        assert_eq!(jwks.keys[0].kid, key.kid);
+        assert_eq!(jwks.keys[0].kty, "EC");
+        assert!(jwks.keys.len() >= 2);
+
+        // An instance keeping only the newest key, which picked up the
+        // rotation, rejects the older token.
+        let newest_only =
+            SigningKeyService::new(Algorithm::EdDSA, 1, Duration::from_secs(60), repo);
+        newest_only.load().await.unwrap();
+        assert!(verify_jwt_token(&newest_only, &after).await.is_ok());
+        assert!(matches!(
+            verify_jwt_token(&newest_only, &before).await,
+            Err(SigningKeyError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rotated_key_is_published_before_signing() {
+        let repo = SigningKeyRepository::new(create_test_pool().await);
+        let service = SigningKeyService::new(Algorithm::EdDSA, 10, Duration::from_secs(60), repo);
+        service.load().await.unwrap();
+
+        let key = service.rotate(Algorithm::ES256).await.unwrap();
+        assert!(service
+            .jwks()
+            .await
+            .keys
+            .iter()
+            .any(|jwk| jwk.kid == key.kid));
+
+        let token = generate_jwt_token(&service, "user", "session", 60)
+            .await
+            .unwrap();
+        assert_ne!(decode_header(&token).unwrap().kid, Some(key.kid));
+        assert!(verify_jwt_token(&service, &token).await.is_ok());
+    }
+}
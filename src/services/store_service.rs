@@ -2,11 +2,12 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use super::checkout_service::{CheckoutService, CreateCheckoutService};
+use crate::helpers::pagination::Cursor;
 use crate::models::checkout::Checkout;
 use crate::models::store::StoreInvoice;
 use crate::repositories::checkout_repository::CheckoutRepository;
 use crate::repositories::store_repository::{
-    CreateStoreInvoice, StoreInvoiceRepository, StoreRepository,
+    CreateStoreInvoice, StoreInvoiceFilter, StoreInvoiceRepository, StoreRepository,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +19,19 @@ pub struct CreateStoreInvoiceResponse {
     pub qr_ln: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreInvoiceDetails {
+    #[serde(flatten)]
+    pub invoice: StoreInvoice,
+    pub checkout: Checkout,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreInvoicePage {
+    pub invoices: Vec<StoreInvoiceDetails>,
+    pub next_cursor: Option<Cursor>,
+}
+
 pub struct StoreService {
     pub store_repo: StoreRepository,
     pub store_invoice_repo: StoreInvoiceRepository,
@@ -66,30 +80,114 @@ impl StoreService {
 
         Ok(response)
     }
+
+    pub async fn get_invoice(
+        &self,
+        store_uuid: &str,
+        invoice_uuid: &str,
+    ) -> Result<Option<StoreInvoiceDetails>> {
+        let invoice = match self
+            .store_invoice_repo
+            .get_by_uuid(store_uuid, invoice_uuid)
+            .await?
+        {
+            Some(invoice) => invoice,
+            None => return Ok(None),
+        };
+
+        let checkout = self
+            .checkout_repo
+            .get_by_uuid(invoice.checkout_uuid.clone())
+            .await?;
+
+        Ok(Some(StoreInvoiceDetails { invoice, checkout }))
+    }
+
+    pub async fn list_invoices(
+        &self,
+        store_uuid: &str,
+        filter: StoreInvoiceFilter,
+    ) -> Result<StoreInvoicePage> {
+        // Fetch one extra row to know whether there is a next page.
+        let limit = filter.limit;
+        let filter = StoreInvoiceFilter {
+            limit: limit + 1,
+            ..filter
+        };
+
+        let mut invoices = self.store_invoice_repo.get_all(store_uuid, &filter).await?;
+        let has_more = invoices.len() as i64 > limit;
+        invoices.truncate(limit as usize);
+
+        let checkout_uuids: Vec<String> = invoices
+            .iter()
+            .map(|invoice| invoice.checkout_uuid.clone())
+            .collect();
+        let checkouts = self.checkout_repo.get_by_uuids(&checkout_uuids).await?;
+
+        let next_cursor = match invoices.last() {
+            Some(last) if has_more => Some(Cursor::new(last.created_at, &last.uuid)),
+            _ => None,
+        };
+
+        let invoices = invoices
+            .into_iter()
+            .filter_map(|invoice| {
+                let checkout = checkouts
+                    .iter()
+                    .find(|checkout| checkout.uuid == invoice.checkout_uuid)?
+                    .clone();
+                Some(StoreInvoiceDetails { invoice, checkout })
+            })
+            .collect();
+
+        Ok(StoreInvoicePage {
+            invoices,
+            next_cursor,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use crate::services::store_service::StoreService;
     use crate::{
-        helpers::tests::{create_test_cluster, create_test_pool, create_test_user},
+        config::PricingConfig,
+        helpers::pagination::SortOrder,
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        lightning::fake::FakeLightningBackend,
+        models::checkout::CheckoutStatus,
         repositories::{
-            checkout_repository::CheckoutRepository,
-            store_repository::{StoreInvoiceRepository, StoreRepository},
+            checkout_repository::{CheckoutRepository, CreateCheckout},
+            fee_repository::FeeRepository,
+            store_repository::{
+                CreateStoreInvoice, StoreInvoiceFilter, StoreInvoiceRepository, StoreRepository,
+            },
+        },
+        services::{
+            checkout_service::{CheckoutService, CreateCheckoutService},
+            fee_service::FeeService,
         },
-        services::checkout_service::{CheckoutService, CreateCheckoutService},
     };
 
     #[tokio::test]
     async fn create_store_invoice() {
         let pool = create_test_pool().await;
         let user = create_test_user().await.unwrap();
-        let cluster = create_test_cluster().await;
         let store_repo: StoreRepository = StoreRepository::new(pool.clone());
         let store = store_repo.create(&user.uuid, "Test Store").await.unwrap();
         let metadata = Some({ serde_json::json!({"test": "test"}) });
 
-        let checkout_service = CheckoutService::new(cluster);
+        let pricing = PricingConfig {
+            base_fee_sat: 100,
+            fee_rate_percent: 1,
+        };
+        let checkout_service = CheckoutService::new(
+            Arc::new(FakeLightningBackend::new()),
+            FeeService::new(&pricing, FeeRepository::new(pool.clone())),
+        );
 
         let store_service = StoreService::new(
             StoreRepository::new(pool.clone()),
@@ -102,10 +200,16 @@ mod tests {
             amount: 1000,
             expiry: 3600,
             memo: None,
+            description_hash: None,
         };
 
         let invoice = store_service
-            .create_invoice(&store.uuid, metadata.clone(), checkout_data, checkout_service)
+            .create_invoice(
+                &store.uuid,
+                metadata.clone(),
+                checkout_data,
+                checkout_service,
+            )
             .await;
 
         match invoice {
@@ -120,4 +224,129 @@ mod tests {
             Err(e) => panic!("Failed to create invoice: {:?}", e),
         }
     }
+
+    #[tokio::test]
+    async fn list_store_invoices() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let store_repo = StoreRepository::new(pool.clone());
+        let store = store_repo.create(&user.uuid, "Test Store").await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+        let store_invoice_repo = StoreInvoiceRepository::new(pool.clone());
+
+        let mut invoice_uuids = vec![];
+        for i in 0..3 {
+            let checkout = checkout_repo
+                .create(CreateCheckout {
+                    user_uuid: user.uuid.clone(),
+                    amount: 1000 + i,
+                    bitcoin_address: "test address".to_string(),
+                    payment_request: "test payment request".to_string(),
+                    payment_hash: None,
+                    expiry_seconds: 3600,
+                    fee: None,
+                })
+                .await
+                .unwrap();
+
+            if i == 0 {
+                checkout_repo
+                    .set_status(&checkout.uuid, CheckoutStatus::Paid)
+                    .await
+                    .unwrap();
+            }
+
+            let invoice = store_invoice_repo
+                .create(CreateStoreInvoice {
+                    store_uuid: store.uuid.clone(),
+                    checkout_uuid: checkout.uuid,
+                    metadata: Some(serde_json::json!({"order_id": i.to_string(), "shop": "test"})),
+                })
+                .await
+                .unwrap();
+            invoice_uuids.push(invoice.uuid);
+        }
+
+        let store_service = StoreService::new(
+            store_repo.clone(),
+            checkout_repo.clone(),
+            store_invoice_repo.clone(),
+        );
+
+        let filter = StoreInvoiceFilter {
+            sort: SortOrder::Asc,
+            limit: 2,
+            ..Default::default()
+        };
+        let page = store_service
+            .list_invoices(&store.uuid, filter.clone())
+            .await
+            .unwrap();
+        assert_eq!(page.invoices.len(), 2);
+        assert_eq!(page.invoices[0].invoice.uuid, invoice_uuids[0]);
+        assert_eq!(page.invoices[0].checkout.amount, 1000);
+
+        let page = store_service
+            .list_invoices(
+                &store.uuid,
+                StoreInvoiceFilter {
+                    cursor: page.next_cursor,
+                    ..filter.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.invoices.len(), 1);
+        assert_eq!(page.invoices[0].invoice.uuid, invoice_uuids[2]);
+        assert!(page.next_cursor.is_none());
+
+        let page = store_service
+            .list_invoices(
+                &store.uuid,
+                StoreInvoiceFilter {
+                    status: Some(CheckoutStatus::Paid),
+                    ..filter.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.invoices.len(), 1);
+        assert_eq!(page.invoices[0].checkout.status, CheckoutStatus::Paid);
+
+        let page = store_service
+            .list_invoices(
+                &store.uuid,
+                StoreInvoiceFilter {
+                    metadata: Some(serde_json::json!({"order_id": "1"})),
+                    ..filter.clone()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.invoices.len(), 1);
+        assert_eq!(page.invoices[0].invoice.uuid, invoice_uuids[1]);
+
+        let invoice = store_service
+            .get_invoice(&store.uuid, &invoice_uuids[2])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(invoice.checkout.amount, 1002);
+
+        sqlx::query("DELETE FROM store_invoices WHERE store_uuid = $1")
+            .bind(&store.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let _ = store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
 }
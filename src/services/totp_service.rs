@@ -0,0 +1,421 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::RngCore;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    helpers::{crypto::sha256_hex, qr::qr_data_uri, signed_token, totp},
+    models::user::User,
+    repositories::user_repository::{UserRepository, UserRepositoryError},
+    services::signing_key_service::SigningKeyService,
+};
+
+/// Purpose of the tokens password logins get while their second factor is
+/// pending.
+const MFA_PENDING: &str = "mfa_pending";
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Wrong codes a user can submit before their pending logins are revoked
+/// and they have to enter their password again.
+const MAX_MFA_FAILURES: i32 = 5;
+
+#[derive(Error, Debug)]
+pub enum TotpError {
+    #[error("Two-factor authentication is only available for email logins")]
+    NotAvailable,
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("Two-factor authentication is not enabled")]
+    NotEnabled,
+    #[error("Invalid code")]
+    InvalidCode,
+    #[error("Login is invalid or expired")]
+    InvalidToken,
+    #[error("Too many wrong codes, log in again")]
+    TooManyAttempts,
+    #[error("User not found")]
+    NotFound,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<UserRepositoryError> for TotpError {
+    fn from(e: UserRepositoryError) -> Self {
+        TotpError::Internal(e.into())
+    }
+}
+
+/// What the user adds to their authenticator app.
+#[derive(Debug, Clone, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+    pub qr: String,
+}
+
+/// Returned to password logins of users with two-factor authentication,
+/// in place of a JWT until they submit a code.
+#[derive(Debug, Clone, Serialize)]
+pub struct MfaPending {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+}
+
+/// Pending logins die with the user's sessions, e.g. on a password reset,
+/// once one is completed, and after too many wrong codes.
+fn session_fingerprint(user: &User) -> String {
+    let sessions_revoked_at = user
+        .sessions_revoked_at
+        .map(|revoked_at| revoked_at.naive_utc().to_string())
+        .unwrap_or_default();
+    let state = match user.mfa_tokens_revoked_at {
+        Some(mfa_tokens_revoked_at) => {
+            format!("{}|{}", sessions_revoked_at, mfa_tokens_revoked_at)
+        }
+        None => sessions_revoked_at,
+    };
+    signed_token::fingerprint(&state)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Recovery codes are compared without case and separators.
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    sha256_hex(&code.to_lowercase())
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// TOTP two-factor authentication for email logins, with single-use
+/// recovery codes for when the authenticator is lost.
+#[derive(Debug, Clone)]
+pub struct TotpService {
+    issuer: String,
+    mfa_token_expiry_seconds: u64,
+    users: UserRepository,
+    keys: SigningKeyService,
+}
+
+impl TotpService {
+    pub fn new(
+        issuer: String,
+        mfa_token_expiry_seconds: u64,
+        users: UserRepository,
+        keys: SigningKeyService,
+    ) -> Self {
+        Self {
+            issuer,
+            mfa_token_expiry_seconds,
+            users,
+            keys,
+        }
+    }
+
+    async fn get_user(&self, user_uuid: &str) -> Result<User, TotpError> {
+        self.users
+            .get_by_uuid(user_uuid)
+            .await?
+            .ok_or(TotpError::NotFound)
+    }
+
+    /// Starts enrolment with a new secret. It takes effect once `confirm`
+    /// gets a code for it.
+    pub async fn enroll(&self, user_uuid: &str) -> Result<TotpEnrollment, TotpError> {
+        let user = self.get_user(user_uuid).await?;
+        let email = match (&user.email, &user.password) {
+            (Some(email), Some(_)) => email,
+            _ => return Err(TotpError::NotAvailable),
+        };
+        if user.has_totp() {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        let secret = totp::generate_secret();
+        if !self
+            .users
+            .set_pending_totp_secret(&user.uuid, &secret)
+            .await?
+        {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        let otpauth_uri = totp::otpauth_uri(&self.issuer, email, &secret);
+        let qr = qr_data_uri(&otpauth_uri).await?;
+
+        Ok(TotpEnrollment {
+            secret,
+            otpauth_uri,
+            qr,
+        })
+    }
+
+    /// Turns two-factor authentication on with a first code from the
+    /// authenticator, and returns recovery codes. They aren't stored in the
+    /// clear, so this is the only time the user sees them.
+    pub async fn confirm(&self, user_uuid: &str, code: &str) -> Result<Vec<String>, TotpError> {
+        let user = self.get_user(user_uuid).await?;
+        if user.has_totp() {
+            return Err(TotpError::AlreadyEnabled);
+        }
+        let secret = user.totp_secret.as_deref().ok_or(TotpError::NotEnabled)?;
+        let step = totp::verify(secret, code, now()).ok_or(TotpError::InvalidCode)?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+        if !self
+            .users
+            .enable_totp(&user.uuid, step as i64, &hashes)
+            .await?
+        {
+            return Err(TotpError::AlreadyEnabled);
+        }
+
+        Ok(codes)
+    }
+
+    /// Turns two-factor authentication off. Needs a current code from the
+    /// authenticator. Wrong codes count towards the same limit as logins,
+    /// and the one that reaches it returns `TooManyAttempts`.
+    pub async fn disable(&self, user_uuid: &str, code: &str) -> Result<(), TotpError> {
+        let user = self.get_user(user_uuid).await?;
+        if !self.verify_totp(&user, code).await? {
+            if self
+                .users
+                .record_mfa_failure(&user.uuid, MAX_MFA_FAILURES)
+                .await?
+            {
+                return Err(TotpError::TooManyAttempts);
+            }
+            return Err(TotpError::InvalidCode);
+        }
+
+        self.users.disable_totp(&user.uuid).await?;
+
+        Ok(())
+    }
+
+    /// Issues the token a password login continues with in `login`.
+    pub async fn mfa_pending(&self, user: &User) -> Result<MfaPending, TotpError> {
+        let mfa_token = signed_token::sign(
+            &self.keys,
+            MFA_PENDING,
+            &user.uuid,
+            &session_fingerprint(user),
+            self.mfa_token_expiry_seconds,
+        )
+        .await?;
+
+        Ok(MfaPending {
+            mfa_required: true,
+            mfa_token,
+        })
+    }
+
+    /// Finishes a password login with a TOTP or recovery code, returning the
+    /// user to issue a JWT for. The token can only be used once, and too many
+    /// wrong codes revoke it.
+    pub async fn login(&self, mfa_token: &str, code: &str) -> Result<User, TotpError> {
+        let claims = signed_token::verify(&self.keys, MFA_PENDING, mfa_token)
+            .await
+            .map_err(|_| TotpError::InvalidToken)?;
+        let user = self
+            .users
+            .get_by_uuid(&claims.sub)
+            .await?
+            .ok_or(TotpError::InvalidToken)?;
+
+        if session_fingerprint(&user) != claims.fingerprint {
+            return Err(TotpError::InvalidToken);
+        }
+
+        if self.verify_totp(&user, code).await?
+            || self
+                .users
+                .use_recovery_code(&user.uuid, &hash_recovery_code(code))
+                .await?
+        {
+            if !self
+                .users
+                .complete_mfa_login(&user.uuid, user.mfa_tokens_revoked_at)
+                .await?
+            {
+                return Err(TotpError::InvalidToken);
+            }
+            return Ok(user);
+        }
+
+        if self
+            .users
+            .record_mfa_failure(&user.uuid, MAX_MFA_FAILURES)
+            .await?
+        {
+            return Err(TotpError::InvalidToken);
+        }
+
+        Err(TotpError::InvalidCode)
+    }
+
+    /// Checks a code from the authenticator, which can't be used again.
+    async fn verify_totp(&self, user: &User, code: &str) -> Result<bool, TotpError> {
+        let secret = match &user.totp_secret {
+            Some(secret) if user.has_totp() => secret,
+            _ => return Err(TotpError::NotEnabled),
+        };
+
+        match totp::verify(secret, code, now()) {
+            Some(step) => Ok(self.users.use_totp_step(&user.uuid, step as i64).await?),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base32::Alphabet;
+
+    use super::{now, TotpError, TotpService, MAX_MFA_FAILURES};
+    use crate::{
+        helpers::{
+            tests::{
+                create_test_pool, create_test_signing_keys, create_test_user, delete_test_user,
+            },
+            totp,
+        },
+        repositories::user_repository::UserRepository,
+    };
+
+    fn code(secret: &str, time: u64) -> String {
+        let secret = base32::decode(Alphabet::RFC4648 { padding: false }, secret).unwrap();
+        totp::code_at(&secret, time / totp::STEP_SECONDS)
+    }
+
+    #[tokio::test]
+    async fn test_totp_login() {
+        let user = create_test_user().await.unwrap();
+        let pool = create_test_pool().await;
+        let service = TotpService::new(
+            "Nodeless.io".to_string(),
+            60,
+            UserRepository::new(pool),
+            create_test_signing_keys().await,
+        );
+
+        let enrollment = service.enroll(&user.uuid).await.unwrap();
+        assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+        assert!(matches!(
+            service.confirm(&user.uuid, "000000x").await,
+            Err(TotpError::InvalidCode)
+        ));
+
+        // Codes from the previous step are still accepted, which leaves the
+        // current and next ones for the checks below.
+        let previous = now() - totp::STEP_SECONDS;
+        let codes = service
+            .confirm(&user.uuid, &code(&enrollment.secret, previous))
+            .await
+            .unwrap();
+        assert_eq!(codes.len(), 10);
+        assert!(matches!(
+            service.enroll(&user.uuid).await,
+            Err(TotpError::AlreadyEnabled)
+        ));
+
+        let user = service.get_user(&user.uuid).await.unwrap();
+        let pending = service.mfa_pending(&user).await.unwrap();
+        assert!(matches!(
+            service.login(&pending.mfa_token, "000000x").await,
+            Err(TotpError::InvalidCode)
+        ));
+        assert!(matches!(
+            service.login("garbage", &codes[0]).await,
+            Err(TotpError::InvalidToken)
+        ));
+
+        // Recovery codes work once, in any case.
+        let logged_in = service
+            .login(&pending.mfa_token, &codes[0].to_uppercase())
+            .await
+            .unwrap();
+        assert_eq!(logged_in.uuid, user.uuid);
+        // A completed login's token can't be used again.
+        assert!(matches!(
+            service.login(&pending.mfa_token, &codes[1]).await,
+            Err(TotpError::InvalidToken)
+        ));
+        let user = service.get_user(&logged_in.uuid).await.unwrap();
+        let pending = service.mfa_pending(&user).await.unwrap();
+        assert!(matches!(
+            service.login(&pending.mfa_token, &codes[0]).await,
+            Err(TotpError::InvalidCode)
+        ));
+
+        // So do authenticator codes, and not for a step already used.
+        let current = code(&enrollment.secret, now());
+        let logged_in = service.login(&pending.mfa_token, &current).await.unwrap();
+        let user = service.get_user(&logged_in.uuid).await.unwrap();
+        let pending = service.mfa_pending(&user).await.unwrap();
+        assert!(matches!(
+            service.login(&pending.mfa_token, &current).await,
+            Err(TotpError::InvalidCode)
+        ));
+        assert!(matches!(
+            service
+                .disable(&user.uuid, &code(&enrollment.secret, previous))
+                .await,
+            Err(TotpError::InvalidCode)
+        ));
+
+        // Too many wrong codes, counting the one to disable, revoke the
+        // pending login, but not the ones issued after.
+        for _ in 3..MAX_MFA_FAILURES {
+            assert!(matches!(
+                service.login(&pending.mfa_token, "000000x").await,
+                Err(TotpError::InvalidCode)
+            ));
+        }
+        assert!(matches!(
+            service.login(&pending.mfa_token, "000000x").await,
+            Err(TotpError::InvalidToken)
+        ));
+        assert!(matches!(
+            service.login(&pending.mfa_token, &codes[1]).await,
+            Err(TotpError::InvalidToken)
+        ));
+        let user = service.get_user(&user.uuid).await.unwrap();
+        let pending = service.mfa_pending(&user).await.unwrap();
+        service.login(&pending.mfa_token, &codes[1]).await.unwrap();
+
+        // Wrong codes to disable it are limited the same way.
+        for _ in 1..MAX_MFA_FAILURES {
+            assert!(matches!(
+                service.disable(&user.uuid, "000000x").await,
+                Err(TotpError::InvalidCode)
+            ));
+        }
+        assert!(matches!(
+            service.disable(&user.uuid, "000000x").await,
+            Err(TotpError::TooManyAttempts)
+        ));
+        assert!(service.get_user(&user.uuid).await.unwrap().has_totp());
+
+        let next = code(&enrollment.secret, now() + totp::STEP_SECONDS);
+        service.disable(&user.uuid, &next).await.unwrap();
+        assert!(!service.get_user(&user.uuid).await.unwrap().has_totp());
+
+        delete_test_user(&user.uuid).await.unwrap();
+    }
+}
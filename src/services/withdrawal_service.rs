@@ -0,0 +1,965 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use lightning_cluster::cluster::NodeNetwork;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    config::WithdrawalsConfig,
+    helpers::{bitcoin_address, bolt11, lightning_address},
+    lightning::{LightningBackend, Payment, PaymentError, PaymentStatus},
+    models::{
+        ledger::LedgerTransactionKind,
+        withdrawal::{Withdrawal, WithdrawalKind, WithdrawalStatus},
+    },
+    repositories::{
+        ledger_repository::{AccountKey, NewLedgerEntry, NewLedgerTransaction},
+        withdrawal_repository::{
+            CreateWithdrawal, CreatedWithdrawal, FinalizeWithdrawal, WithdrawalRepository,
+            WithdrawalRepositoryError,
+        },
+    },
+};
+
+/// Outputs below this are non-standard and won't be relayed.
+const DUST_LIMIT_SAT: i64 = 546;
+
+/// Most pending lightning withdrawals looked up per reconciliation run.
+const RECONCILE_BATCH_SIZE: i64 = 100;
+
+#[derive(Error, Debug)]
+pub enum WithdrawalError {
+    #[error("{0}")]
+    InvalidDestination(String),
+    #[error("Amount is required when paying a lightning address")]
+    AmountRequired,
+    #[error("Amount does not match the invoice amount")]
+    AmountMismatch,
+    #[error("Minimum withdrawal is {0} sats")]
+    BelowMinimum(i64),
+    #[error("Insufficient balance, {available} sats available and {required} sats required")]
+    InsufficientBalance { available: i64, required: i64 },
+    #[error("This invoice is already being paid")]
+    AlreadyPaying,
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<WithdrawalRepositoryError> for WithdrawalError {
+    fn from(e: WithdrawalRepositoryError) -> Self {
+        WithdrawalError::Internal(e.into())
+    }
+}
+
+impl From<sqlx::Error> for WithdrawalError {
+    fn from(e: sqlx::Error) -> Self {
+        WithdrawalError::Internal(e.into())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateLightningWithdrawal {
+    pub user_uuid: String,
+    pub store_uuid: Option<String>,
+    /// BOLT11 invoice or lightning address.
+    pub destination: String,
+    /// Required for lightning addresses, optional for invoices.
+    pub amount: Option<i64>,
+    pub idempotency_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateOnchainWithdrawal {
+    pub user_uuid: String,
+    pub store_uuid: Option<String>,
+    pub address: String,
+    pub amount: i64,
+    pub idempotency_key: String,
+}
+
+/// Account the withdrawal is paid from.
+pub fn source_account(withdrawal: &Withdrawal) -> AccountKey {
+    match &withdrawal.store_uuid {
+        Some(store_uuid) => AccountKey::store(&withdrawal.user_uuid, store_uuid),
+        None => AccountKey::user(&withdrawal.user_uuid),
+    }
+}
+
+/// Holds the amount plus the full fee limit until the payment resolves.
+pub fn reservation_transaction(withdrawal: &Withdrawal) -> NewLedgerTransaction {
+    let reserved = withdrawal.amount + withdrawal.fee_limit;
+
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::Withdrawal,
+        reference_uuid: withdrawal.uuid.clone(),
+        description: Some(format!("Withdrawal {}", withdrawal.uuid)),
+        entries: vec![
+            NewLedgerEntry {
+                account: source_account(withdrawal),
+                amount: -reserved,
+            },
+            NewLedgerEntry {
+                account: AccountKey::withdrawal_reserve(),
+                amount: reserved,
+            },
+        ],
+    }
+}
+
+/// Pays the amount and actual fee out of the hot wallet and returns the
+/// unused part of the fee limit.
+pub fn settlement_transaction(withdrawal: &Withdrawal, fee: i64) -> NewLedgerTransaction {
+    let fee = fee.clamp(0, withdrawal.fee_limit);
+
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::WithdrawalSettlement,
+        reference_uuid: withdrawal.uuid.clone(),
+        description: Some(format!("Withdrawal {} paid", withdrawal.uuid)),
+        entries: vec![
+            NewLedgerEntry {
+                account: AccountKey::withdrawal_reserve(),
+                amount: -(withdrawal.amount + withdrawal.fee_limit),
+            },
+            NewLedgerEntry {
+                account: AccountKey::hot_wallet(),
+                amount: withdrawal.amount + fee,
+            },
+            NewLedgerEntry {
+                account: source_account(withdrawal),
+                amount: withdrawal.fee_limit - fee,
+            },
+        ],
+    }
+}
+
+/// Returns the whole reservation to the source account.
+pub fn reversal_transaction(withdrawal: &Withdrawal) -> NewLedgerTransaction {
+    let reserved = withdrawal.amount + withdrawal.fee_limit;
+
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::WithdrawalReversal,
+        reference_uuid: withdrawal.uuid.clone(),
+        description: Some(format!("Withdrawal {} failed", withdrawal.uuid)),
+        entries: vec![
+            NewLedgerEntry {
+                account: AccountKey::withdrawal_reserve(),
+                amount: -reserved,
+            },
+            NewLedgerEntry {
+                account: source_account(withdrawal),
+                amount: reserved,
+            },
+        ],
+    }
+}
+
+/// Outputs for a payout batch, one per address with the amounts of payouts
+/// to the same address combined.
+pub fn batch_outputs(batch: &[Withdrawal]) -> Vec<(String, i64)> {
+    let mut outputs: Vec<(String, i64)> = Vec::new();
+
+    for withdrawal in batch {
+        match outputs
+            .iter_mut()
+            .find(|(address, _)| *address == withdrawal.destination)
+        {
+            Some((_, amount)) => *amount += withdrawal.amount,
+            None => outputs.push((withdrawal.destination.clone(), withdrawal.amount)),
+        }
+    }
+
+    outputs
+}
+
+/// Pays the amount out of the hot wallet. The flat fee goes to the platform,
+/// which covers the miner fee of the batch.
+pub fn onchain_settlement_transaction(withdrawal: &Withdrawal) -> NewLedgerTransaction {
+    NewLedgerTransaction {
+        kind: LedgerTransactionKind::WithdrawalSettlement,
+        reference_uuid: withdrawal.uuid.clone(),
+        description: Some(format!("Withdrawal {} paid", withdrawal.uuid)),
+        entries: vec![
+            NewLedgerEntry {
+                account: AccountKey::withdrawal_reserve(),
+                amount: -(withdrawal.amount + withdrawal.fee_limit),
+            },
+            NewLedgerEntry {
+                account: AccountKey::hot_wallet(),
+                amount: withdrawal.amount,
+            },
+            NewLedgerEntry {
+                account: AccountKey::platform_fees(),
+                amount: withdrawal.fee_limit,
+            },
+        ],
+    }
+}
+
+#[derive(Clone)]
+pub struct WithdrawalService {
+    pub lightning: Arc<dyn LightningBackend>,
+    pub repo: WithdrawalRepository,
+    pub config: WithdrawalsConfig,
+    /// Network destinations must be on, parsed from the cluster config at
+    /// startup.
+    pub network: NodeNetwork,
+}
+
+impl WithdrawalService {
+    pub fn new(
+        lightning: Arc<dyn LightningBackend>,
+        repo: WithdrawalRepository,
+        config: WithdrawalsConfig,
+        network: NodeNetwork,
+    ) -> Self {
+        Self {
+            lightning,
+            repo,
+            config,
+            network,
+        }
+    }
+
+    /// `base + amount * rate`, the most we pay in routing fees.
+    pub fn fee_limit(&self, amount: i64) -> i64 {
+        let rate_fee =
+            (amount as i128 * self.config.lightning_fee_limit_rate_bps as i128 / 10_000) as i64;
+
+        (self.config.lightning_fee_limit_base_sat + rate_fee).max(0)
+    }
+
+    /// Pays a lightning invoice or address from the user's balance. Retrying
+    /// with the same idempotency key returns the original withdrawal instead
+    /// of paying again. If the node can't say whether the payment went
+    /// through, the withdrawal stays pending for `reconcile_lightning`.
+    pub async fn withdraw_lightning(
+        &self,
+        req: CreateLightningWithdrawal,
+    ) -> Result<Withdrawal, WithdrawalError> {
+        if let Some(existing) = self
+            .repo
+            .get_by_idempotency_key(&req.user_uuid, &req.idempotency_key)
+            .await?
+        {
+            return Ok(existing);
+        }
+
+        let (payment_request, amount, payment_hash) = self.resolve_destination(&req).await?;
+
+        if amount < self.config.min_lightning_withdrawal_sat {
+            return Err(WithdrawalError::BelowMinimum(
+                self.config.min_lightning_withdrawal_sat,
+            ));
+        }
+
+        let fee_limit = self.fee_limit(amount);
+        let create = CreateWithdrawal {
+            user_uuid: req.user_uuid.clone(),
+            store_uuid: req.store_uuid.clone(),
+            kind: WithdrawalKind::Lightning,
+            destination: req.destination.trim().to_string(),
+            payment_request: Some(payment_request.clone()),
+            payment_hash: Some(payment_hash),
+            amount,
+            fee_limit,
+            idempotency_key: req.idempotency_key.clone(),
+        };
+        let withdrawal = match self.reserve(create).await? {
+            CreatedWithdrawal::Created(withdrawal) => withdrawal,
+            CreatedWithdrawal::Existing(withdrawal) => return Ok(withdrawal),
+        };
+
+        let finalized = match self
+            .lightning
+            .pay_invoice(&payment_request, fee_limit)
+            .await
+        {
+            Ok(payment) => self.complete_lightning(&withdrawal, payment).await,
+            Err(PaymentError::Failed(reason)) => self.fail_lightning(&withdrawal, reason).await,
+            Err(PaymentError::Unknown(e)) => {
+                eprintln!(
+                    "withdrawal {} outcome unknown, leaving it pending: {:?}",
+                    withdrawal.uuid, e
+                );
+                return Ok(withdrawal);
+            }
+        };
+
+        Ok(finalized?)
+    }
+
+    /// Settles or reverses lightning withdrawals left pending, either because
+    /// the node couldn't say whether the payment went through or because the
+    /// process stopped before recording it. Returns how many were finalized.
+    pub async fn reconcile_lightning(&self) -> Result<usize> {
+        let pending = self
+            .repo
+            .get_stale_lightning(
+                self.config.lightning_reconcile_after_seconds,
+                RECONCILE_BATCH_SIZE,
+            )
+            .await?;
+
+        let mut finalized = 0;
+        for withdrawal in pending {
+            match self.reconcile_lightning_withdrawal(&withdrawal).await {
+                Ok(true) => finalized += 1,
+                Ok(false) => {}
+                Err(e) => eprintln!(
+                    "failed to reconcile withdrawal {}: {:?}",
+                    withdrawal.uuid, e
+                ),
+            }
+        }
+
+        Ok(finalized)
+    }
+
+    async fn reconcile_lightning_withdrawal(&self, withdrawal: &Withdrawal) -> Result<bool> {
+        let invoice = bolt11::decode(withdrawal.payment_request.as_deref().unwrap_or_default())?;
+        let payment_hash = withdrawal
+            .payment_hash
+            .clone()
+            .or(invoice.payment_hash.clone())
+            .ok_or_else(|| anyhow!("Withdrawal {} has no payment hash", withdrawal.uuid))?;
+
+        match self.lightning.lookup_payment(&payment_hash).await? {
+            PaymentStatus::Succeeded(payment) => {
+                self.complete_lightning(withdrawal, payment).await?;
+            }
+            PaymentStatus::Failed(reason) => {
+                self.fail_lightning(withdrawal, reason).await?;
+            }
+            // Never sent, and an expired invoice can't be paid anymore.
+            PaymentStatus::NotFound if invoice.is_expired(chrono::Utc::now().timestamp()) => {
+                self.fail_lightning(withdrawal, "Payment was not sent".to_string())
+                    .await?;
+            }
+            PaymentStatus::NotFound | PaymentStatus::InFlight => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    async fn complete_lightning(
+        &self,
+        withdrawal: &Withdrawal,
+        payment: Payment,
+    ) -> Result<Withdrawal, WithdrawalRepositoryError> {
+        let settlement = settlement_transaction(withdrawal, payment.fee);
+        let update = FinalizeWithdrawal {
+            status: WithdrawalStatus::Completed,
+            fee_amount: Some(payment.fee.clamp(0, withdrawal.fee_limit)),
+            payment_hash: Some(payment.payment_hash),
+            payment_preimage: Some(payment.preimage),
+            failure_reason: None,
+        };
+
+        self.repo
+            .finalize(&withdrawal.uuid, update, settlement)
+            .await
+    }
+
+    async fn fail_lightning(
+        &self,
+        withdrawal: &Withdrawal,
+        reason: String,
+    ) -> Result<Withdrawal, WithdrawalRepositoryError> {
+        let update = FinalizeWithdrawal {
+            status: WithdrawalStatus::Failed,
+            fee_amount: None,
+            payment_hash: None,
+            payment_preimage: None,
+            failure_reason: Some(reason),
+        };
+
+        self.repo
+            .finalize(&withdrawal.uuid, update, reversal_transaction(withdrawal))
+            .await
+    }
+
+    /// Queues an on-chain payout for the next batch. With
+    /// `onchain_fee_from_amount` the flat fee comes out of `amount`, otherwise
+    /// it is charged on top.
+    pub async fn withdraw_onchain(
+        &self,
+        req: CreateOnchainWithdrawal,
+    ) -> Result<Withdrawal, WithdrawalError> {
+        if let Some(existing) = self
+            .repo
+            .get_by_idempotency_key(&req.user_uuid, &req.idempotency_key)
+            .await?
+        {
+            return Ok(existing);
+        }
+
+        let address = req.address.trim();
+        bitcoin_address::validate(address, &self.network)
+            .map_err(|e| WithdrawalError::InvalidDestination(e.to_string()))?;
+
+        if req.amount < self.config.min_onchain_withdrawal_sat {
+            return Err(WithdrawalError::BelowMinimum(
+                self.config.min_onchain_withdrawal_sat,
+            ));
+        }
+
+        let fee = self.config.onchain_fee_sat.max(0);
+        let amount = if self.config.onchain_fee_from_amount {
+            req.amount - fee
+        } else {
+            req.amount
+        };
+
+        if amount < DUST_LIMIT_SAT {
+            return Err(WithdrawalError::BelowMinimum(fee + DUST_LIMIT_SAT));
+        }
+
+        let create = CreateWithdrawal {
+            user_uuid: req.user_uuid,
+            store_uuid: req.store_uuid,
+            kind: WithdrawalKind::Onchain,
+            destination: address.to_string(),
+            payment_request: None,
+            payment_hash: None,
+            amount,
+            fee_limit: fee,
+            idempotency_key: req.idempotency_key,
+        };
+
+        match self.reserve(create).await? {
+            CreatedWithdrawal::Created(withdrawal) | CreatedWithdrawal::Existing(withdrawal) => {
+                Ok(withdrawal)
+            }
+        }
+    }
+
+    /// Sends the oldest queued on-chain payouts in a single transaction,
+    /// labelled with a new batch uuid. Returns the txid and the completed
+    /// withdrawals, or `None` if nothing was queued. A failed send leaves the
+    /// payouts claimed, as the transaction may have been broadcast anyway,
+    /// until `reconcile_onchain` checks the wallet.
+    pub async fn send_onchain_batch(&self) -> Result<Option<(String, Vec<Withdrawal>)>> {
+        let batch_uuid = Uuid::new_v4().to_string();
+        let mut batch = self
+            .repo
+            .claim_onchain_batch(self.config.onchain_max_batch_size, &batch_uuid)
+            .await?;
+        batch.sort_by(|a, b| (a.created_at, &a.uuid).cmp(&(b.created_at, &b.uuid)));
+
+        if batch.is_empty() {
+            return Ok(None);
+        }
+
+        let outputs = batch_outputs(&batch);
+        let txid = self
+            .lightning
+            .send_many(&outputs, self.config.onchain_target_conf, &batch_uuid)
+            .await?;
+        let completed = self.complete_onchain_batch(&batch, &txid).await?;
+
+        Ok(Some((txid, completed)))
+    }
+
+    /// Resolves payout batches still processing after
+    /// `onchain_reconcile_after_seconds`: completes those the wallet has a
+    /// transaction for and puts the rest back in the queue. Returns how many
+    /// batches were resolved.
+    pub async fn reconcile_onchain(&self) -> Result<usize> {
+        let batches = self
+            .repo
+            .get_stale_onchain_batches(self.config.onchain_reconcile_after_seconds)
+            .await?;
+
+        let mut resolved = 0;
+        for batch_uuid in batches {
+            match self.reconcile_onchain_batch(&batch_uuid).await {
+                Ok(()) => resolved += 1,
+                Err(e) => eprintln!("failed to reconcile payout batch {}: {:?}", batch_uuid, e),
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    async fn reconcile_onchain_batch(&self, batch_uuid: &str) -> Result<()> {
+        match self.lightning.find_transaction(batch_uuid).await? {
+            Some(txid) => {
+                let batch = self.repo.get_onchain_batch(batch_uuid).await?;
+                self.complete_onchain_batch(&batch, &txid).await?;
+            }
+            None => {
+                self.repo
+                    .release_onchain_batch(batch_uuid, "Payout batch was not sent")
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn complete_onchain_batch(
+        &self,
+        batch: &[Withdrawal],
+        txid: &str,
+    ) -> Result<Vec<Withdrawal>, WithdrawalRepositoryError> {
+        let uuids: Vec<String> = batch.iter().map(|w| w.uuid.clone()).collect();
+        let settlements = batch.iter().map(onchain_settlement_transaction).collect();
+
+        self.repo
+            .complete_onchain_batch(&uuids, txid, settlements)
+            .await
+    }
+
+    async fn reserve(
+        &self,
+        create: CreateWithdrawal,
+    ) -> Result<CreatedWithdrawal, WithdrawalError> {
+        let required = create.amount + create.fee_limit;
+        let source = match &create.store_uuid {
+            Some(store_uuid) => AccountKey::store(&create.user_uuid, store_uuid),
+            None => AccountKey::user(&create.user_uuid),
+        };
+
+        match self
+            .repo
+            .create(create, &source, reservation_transaction)
+            .await
+        {
+            Err(WithdrawalRepositoryError::InsufficientBalance { available }) => {
+                Err(WithdrawalError::InsufficientBalance {
+                    available,
+                    required,
+                })
+            }
+            Err(WithdrawalRepositoryError::PaymentHashTaken) => Err(WithdrawalError::AlreadyPaying),
+            result => Ok(result?),
+        }
+    }
+
+    /// Turns the destination into an invoice to pay, the amount it pays and
+    /// its payment hash. The invoice must be for our network and not yet
+    /// expired.
+    async fn resolve_destination(
+        &self,
+        req: &CreateLightningWithdrawal,
+    ) -> Result<(String, i64, String), WithdrawalError> {
+        let destination = req.destination.trim();
+
+        let payment_request = if lightning_address::parse(destination).is_some() {
+            let amount = req.amount.ok_or(WithdrawalError::AmountRequired)?;
+            if amount < self.config.min_lightning_withdrawal_sat {
+                return Err(WithdrawalError::BelowMinimum(
+                    self.config.min_lightning_withdrawal_sat,
+                ));
+            }
+
+            let timeout = Duration::from_secs(self.config.lightning_address_timeout_seconds);
+            lightning_address::fetch_invoice(destination, amount, timeout)
+                .await
+                .map_err(|e| WithdrawalError::InvalidDestination(e.to_string()))?
+        } else {
+            destination.to_string()
+        };
+
+        let invoice = bolt11::decode(&payment_request)
+            .map_err(|e| WithdrawalError::InvalidDestination(e.to_string()))?;
+        invoice
+            .check_payable(&self.network, chrono::Utc::now().timestamp())
+            .map_err(|e| WithdrawalError::InvalidDestination(e.to_string()))?;
+
+        let invoice_amount = invoice.amount_sat.ok_or_else(|| {
+            WithdrawalError::InvalidDestination(
+                "Invoices without an amount are not supported".to_string(),
+            )
+        })?;
+
+        let payment_hash = invoice.payment_hash.ok_or_else(|| {
+            WithdrawalError::InvalidDestination("Invoice has no payment hash".to_string())
+        })?;
+
+        match req.amount {
+            Some(amount) if amount != invoice_amount => Err(WithdrawalError::AmountMismatch),
+            _ => Ok((payment_request, invoice_amount, payment_hash)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lightning_cluster::cluster::NodeNetwork;
+    use sqlx::PgPool;
+
+    use super::{
+        CreateLightningWithdrawal, CreateOnchainWithdrawal, WithdrawalError, WithdrawalService,
+    };
+    use crate::{
+        config::WithdrawalsConfig,
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        lightning::{
+            fake::{fake_address, FakeLightningBackend},
+            AddInvoice, LightningBackend,
+        },
+        models::{ledger::LedgerTransactionKind, withdrawal::WithdrawalStatus},
+        repositories::{
+            ledger_repository::{
+                AccountKey, LedgerRepository, NewLedgerEntry, NewLedgerTransaction,
+            },
+            withdrawal_repository::WithdrawalRepository,
+        },
+    };
+
+    fn test_service(pool: &PgPool, backend: Arc<FakeLightningBackend>) -> WithdrawalService {
+        WithdrawalService::new(
+            backend,
+            WithdrawalRepository::new(pool.clone()),
+            WithdrawalsConfig {
+                min_lightning_withdrawal_sat: 1,
+                lightning_fee_limit_base_sat: 10,
+                lightning_fee_limit_rate_bps: 100,
+                lightning_address_timeout_seconds: 1,
+                lightning_reconcile_after_seconds: 0,
+                min_onchain_withdrawal_sat: 10_000,
+                onchain_fee_sat: 500,
+                onchain_fee_from_amount: true,
+                onchain_target_conf: 6,
+                onchain_max_batch_size: 100,
+                onchain_reconcile_after_seconds: 0,
+            },
+            NodeNetwork::Testnet,
+        )
+    }
+
+    /// Credits the user's account and returns the transaction reference.
+    async fn deposit(ledger: &LedgerRepository, user_uuid: &str, amount: i64) -> String {
+        let reference_uuid = uuid::Uuid::new_v4().to_string();
+        ledger
+            .post(NewLedgerTransaction {
+                kind: LedgerTransactionKind::CheckoutPayment,
+                reference_uuid: reference_uuid.clone(),
+                description: None,
+                entries: vec![
+                    NewLedgerEntry {
+                        account: AccountKey::hot_wallet(),
+                        amount: -amount,
+                    },
+                    NewLedgerEntry {
+                        account: AccountKey::user(user_uuid),
+                        amount,
+                    },
+                ],
+            })
+            .await
+            .unwrap();
+
+        reference_uuid
+    }
+
+    async fn cleanup(pool: &PgPool, user_uuid: &str, deposit_uuid: &str) {
+        sqlx::query(
+            r#"
+            DELETE FROM ledger_entries WHERE transaction_uuid IN (
+                SELECT uuid FROM ledger_transactions WHERE reference_uuid = $1
+                    OR reference_uuid IN (SELECT uuid FROM withdrawals WHERE user_uuid = $2)
+            )
+            "#,
+        )
+        .bind(deposit_uuid)
+        .bind(user_uuid)
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"
+            DELETE FROM ledger_transactions WHERE reference_uuid = $1
+                OR reference_uuid IN (SELECT uuid FROM withdrawals WHERE user_uuid = $2)
+            "#,
+        )
+        .bind(deposit_uuid)
+        .bind(user_uuid)
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query("DELETE FROM ledger_accounts WHERE user_uuid = $1")
+            .bind(user_uuid)
+            .execute(pool)
+            .await
+            .unwrap();
+        let _ = delete_test_user(user_uuid).await.unwrap();
+    }
+
+    async fn invoice(backend: &FakeLightningBackend, amount: i64) -> String {
+        // Fake invoices are stamped in 2023, so they need an expiry that
+        // outlives the tests.
+        backend
+            .add_invoice(AddInvoice {
+                memo: "withdrawal".to_string(),
+                amount,
+                expiry: 1_000_000_000,
+                description_hash: None,
+            })
+            .await
+            .unwrap()
+            .payment_request
+    }
+
+    fn withdrawal_request(user_uuid: &str) -> CreateLightningWithdrawal {
+        CreateLightningWithdrawal {
+            user_uuid: user_uuid.to_string(),
+            store_uuid: None,
+            destination: String::new(),
+            amount: None,
+            idempotency_key: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lightning_withdrawal() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let ledger = LedgerRepository::new(pool.clone());
+        let backend = Arc::new(FakeLightningBackend::new());
+        let service = test_service(&pool, backend.clone());
+
+        let deposit_uuid = deposit(&ledger, &user.uuid, 10_000).await;
+        let balance = || async {
+            ledger
+                .get_account_balance(&AccountKey::user(&user.uuid))
+                .await
+        };
+
+        // 5000 sats with a fee limit of 10 + 1%, of which 7 are spent.
+        backend.set_payment_fee(7);
+        let request = CreateLightningWithdrawal {
+            user_uuid: user.uuid.clone(),
+            store_uuid: None,
+            destination: invoice(&backend, 5_000).await,
+            amount: None,
+            idempotency_key: "first".to_string(),
+        };
+        let withdrawal = service.withdraw_lightning(request.clone()).await.unwrap();
+        assert_eq!(withdrawal.status, WithdrawalStatus::Completed);
+        assert_eq!(withdrawal.fee_limit, 60);
+        assert_eq!(withdrawal.fee_amount, Some(7));
+        assert_eq!(balance().await.unwrap(), 10_000 - 5_007);
+
+        // Retrying with the same key doesn't pay twice.
+        let retried = service.withdraw_lightning(request.clone()).await.unwrap();
+        assert_eq!(retried.uuid, withdrawal.uuid);
+        assert_eq!(backend.payments().len(), 1);
+
+        // Nor does submitting the same invoice under another key.
+        let again = service
+            .withdraw_lightning(CreateLightningWithdrawal {
+                idempotency_key: "first again".to_string(),
+                ..request
+            })
+            .await;
+        assert!(matches!(again, Err(WithdrawalError::AlreadyPaying)));
+        assert_eq!(backend.payments().len(), 1);
+        assert_eq!(balance().await.unwrap(), 10_000 - 5_007);
+
+        // Failed payments release the reservation.
+        backend.set_payment_error(Some("no route"));
+        let failed = service
+            .withdraw_lightning(CreateLightningWithdrawal {
+                destination: invoice(&backend, 1_000).await,
+                idempotency_key: "second".to_string(),
+                ..withdrawal_request(&user.uuid)
+            })
+            .await
+            .unwrap();
+        assert_eq!(failed.status, WithdrawalStatus::Failed);
+        assert_eq!(failed.failure_reason, Some("no route".to_string()));
+        assert_eq!(balance().await.unwrap(), 4_993);
+
+        backend.set_payment_error(None);
+        let too_large = service
+            .withdraw_lightning(CreateLightningWithdrawal {
+                destination: invoice(&backend, 4_950).await,
+                idempotency_key: "third".to_string(),
+                ..withdrawal_request(&user.uuid)
+            })
+            .await;
+        assert!(matches!(
+            too_large,
+            Err(WithdrawalError::InsufficientBalance {
+                available: 4_993,
+                required: 5_009
+            })
+        ));
+
+        let mismatch = service
+            .withdraw_lightning(CreateLightningWithdrawal {
+                destination: invoice(&backend, 100).await,
+                amount: Some(200),
+                idempotency_key: "fourth".to_string(),
+                ..withdrawal_request(&user.uuid)
+            })
+            .await;
+        assert!(matches!(mismatch, Err(WithdrawalError::AmountMismatch)));
+        assert_eq!(backend.payments().len(), 1);
+
+        // Payments that time out stay pending until the node has an answer.
+        backend.set_payment_in_flight(true);
+        let mut in_flight = Vec::new();
+        for key in ["fifth", "sixth"] {
+            let withdrawal = service
+                .withdraw_lightning(CreateLightningWithdrawal {
+                    destination: invoice(&backend, 1_000).await,
+                    idempotency_key: key.to_string(),
+                    ..withdrawal_request(&user.uuid)
+                })
+                .await
+                .unwrap();
+            assert_eq!(withdrawal.status, WithdrawalStatus::Pending);
+            in_flight.push(withdrawal);
+        }
+        backend.set_payment_in_flight(false);
+        assert_eq!(balance().await.unwrap(), 4_993 - 2 * 1_020);
+
+        service.reconcile_lightning().await.unwrap();
+        let status = |uuid: String| {
+            let repo = service.repo.clone();
+            let user_uuid = user.uuid.clone();
+            async move {
+                repo.get_by_uuid(&user_uuid, &uuid)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .status
+            }
+        };
+        assert_eq!(
+            status(in_flight[0].uuid.clone()).await,
+            WithdrawalStatus::Pending
+        );
+
+        backend.resolve_payment(in_flight[0].payment_hash.as_ref().unwrap(), None);
+        backend.resolve_payment(
+            in_flight[1].payment_hash.as_ref().unwrap(),
+            Some("FAILURE_REASON_NO_ROUTE"),
+        );
+        service.reconcile_lightning().await.unwrap();
+        assert_eq!(
+            status(in_flight[0].uuid.clone()).await,
+            WithdrawalStatus::Completed
+        );
+        assert_eq!(
+            status(in_flight[1].uuid.clone()).await,
+            WithdrawalStatus::Failed
+        );
+        assert_eq!(balance().await.unwrap(), 4_993 - 1_007);
+
+        cleanup(&pool, &user.uuid, &deposit_uuid).await;
+    }
+
+    #[tokio::test]
+    async fn test_onchain_withdrawal_batch() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let ledger = LedgerRepository::new(pool.clone());
+        let backend = Arc::new(FakeLightningBackend::new());
+        let service = test_service(&pool, backend.clone());
+
+        let deposit_uuid = deposit(&ledger, &user.uuid, 100_000).await;
+        let address = fake_address(&[1; 20]);
+        let other_address = fake_address(&[2; 20]);
+        let request = |address: &str, amount, key: &str| CreateOnchainWithdrawal {
+            user_uuid: user.uuid.clone(),
+            store_uuid: None,
+            address: address.to_string(),
+            amount,
+            idempotency_key: key.to_string(),
+        };
+
+        let mainnet = service
+            .withdraw_onchain(request(
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                20_000,
+                "a",
+            ))
+            .await;
+        assert!(matches!(
+            mainnet,
+            Err(WithdrawalError::InvalidDestination(_))
+        ));
+
+        let too_small = service
+            .withdraw_onchain(request(&address, 5_000, "b"))
+            .await;
+        assert!(matches!(
+            too_small,
+            Err(WithdrawalError::BelowMinimum(10_000))
+        ));
+
+        // The fee comes out of the amount, so the payout is 500 sats less.
+        let mut queued = Vec::new();
+        for (address, amount, key) in [
+            (&address, 20_000, "c"),
+            (&address, 30_000, "d"),
+            (&other_address, 40_000, "e"),
+        ] {
+            let withdrawal = service
+                .withdraw_onchain(request(address, amount, key))
+                .await
+                .unwrap();
+            assert_eq!(withdrawal.status, WithdrawalStatus::Pending);
+            assert_eq!(withdrawal.amount, amount - 500);
+            queued.push(withdrawal.uuid);
+        }
+        let balance = ledger
+            .get_account_balance(&AccountKey::user(&user.uuid))
+            .await
+            .unwrap();
+        assert_eq!(balance, 10_000);
+
+        // A failed send keeps the payouts claimed until the wallet shows
+        // nothing was sent.
+        backend.set_payment_error(Some("insufficient funds"));
+        assert!(service.send_onchain_batch().await.is_err());
+        backend.set_payment_error(None);
+        assert!(service.send_onchain_batch().await.unwrap().is_none());
+        service.reconcile_onchain().await.unwrap();
+
+        let (txid, batch) = service.send_onchain_batch().await.unwrap().unwrap();
+        let sends = backend.onchain_sends();
+        assert_eq!(sends.len(), 1);
+        assert_eq!(
+            sends[0],
+            vec![(address.clone(), 49_000), (other_address.clone(), 39_500)]
+        );
+        for uuid in &queued {
+            let withdrawal = batch.iter().find(|w| &w.uuid == uuid).unwrap();
+            assert_eq!(withdrawal.status, WithdrawalStatus::Completed);
+            assert_eq!(withdrawal.txid, Some(txid.clone()));
+        }
+        assert!(service.send_onchain_batch().await.unwrap().is_none());
+
+        // A send that errors after broadcasting is completed from the wallet
+        // instead of being paid again.
+        let late = service
+            .withdraw_onchain(request(&address, 20_000, "f"))
+            .await
+            .unwrap();
+        backend.set_payment_in_flight(true);
+        assert!(service.send_onchain_batch().await.is_err());
+        backend.set_payment_in_flight(false);
+        service.reconcile_onchain().await.unwrap();
+        assert_eq!(backend.onchain_sends().len(), 2);
+        assert!(service.send_onchain_batch().await.unwrap().is_none());
+
+        let late = service
+            .repo
+            .get_by_uuid(&user.uuid, &late.uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(late.status, WithdrawalStatus::Completed);
+        assert!(late.txid.is_some());
+
+        cleanup(&pool, &user.uuid, &deposit_uuid).await;
+    }
+}
@@ -0,0 +1,494 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
+use secp256k1::KeyPair;
+
+use crate::{
+    helpers::nostr::NostrEvent,
+    models::{checkout::Checkout, nodeless_address::NodelessAddressPayment},
+    nostr::RelayClient,
+    repositories::{
+        checkout_repository::CheckoutRepository,
+        nodeless_address_repository::NodelessAddressPaymentRepository,
+    },
+    services::ledger_service::is_paid,
+};
+
+pub const ZAP_REQUEST_KIND: u32 = 9734;
+pub const ZAP_RECEIPT_KIND: u32 = 9735;
+
+/// Most relays a receipt is published to, whatever the zap request lists.
+const MAX_ZAP_RELAYS: usize = 10;
+
+/// How far a zap request's `created_at` may be from now. Wallets sign it
+/// right before asking for the invoice.
+const MAX_ZAP_REQUEST_AGE_SECONDS: i64 = 600;
+
+/// Checks a zap request sent to the LNURL-pay callback for `amount` millisats
+/// to `recipient` at `now`, per NIP-57 appendix D.
+pub fn validate_zap_request(
+    raw: &str,
+    amount: i64,
+    recipient: &str,
+    now: i64,
+) -> Result<NostrEvent> {
+    let event: NostrEvent =
+        serde_json::from_str(raw).map_err(|_| anyhow!("Zap request is not a nostr event"))?;
+
+    if event.kind != ZAP_REQUEST_KIND {
+        return Err(anyhow!(
+            "Zap request must be a kind {} event",
+            ZAP_REQUEST_KIND
+        ));
+    }
+    event
+        .verify()
+        .map_err(|_| anyhow!("Zap request signature is invalid"))?;
+    if (now - event.created_at).abs() > MAX_ZAP_REQUEST_AGE_SECONDS {
+        return Err(anyhow!("Zap request created_at is too far from now"));
+    }
+
+    let recipients: Vec<_> = event.tags("p").collect();
+    if recipients.len() != 1 {
+        return Err(anyhow!("Zap request must have exactly one p tag"));
+    }
+    if recipients[0].first().map(String::as_str) != Some(recipient) {
+        return Err(anyhow!("Zap request is for a different pubkey"));
+    }
+    if event.tags("e").count() > 1 {
+        return Err(anyhow!("Zap request must have at most one e tag"));
+    }
+    if event.tags("P").count() > 1 {
+        return Err(anyhow!("Zap request must have at most one P tag"));
+    }
+
+    if let Some(zap_amount) = event.tag_value("amount") {
+        if zap_amount.parse::<i64>().ok() != Some(amount) {
+            return Err(anyhow!("Zap request amount does not match the amount"));
+        }
+    }
+
+    if let Some(coordinate) = event.tag_value("a") {
+        if !is_event_coordinate(coordinate) {
+            return Err(anyhow!("Zap request has an invalid a tag"));
+        }
+    }
+
+    Ok(event)
+}
+
+/// `<kind>:<pubkey>:<d tag>`, as used by `a` tags.
+fn is_event_coordinate(coordinate: &str) -> bool {
+    let mut parts = coordinate.splitn(3, ':');
+
+    let kind = parts.next().map(|kind| kind.parse::<u32>().is_ok());
+    let pubkey = parts
+        .next()
+        .map(|pubkey| pubkey.len() == 64 && pubkey.chars().all(|c| c.is_ascii_hexdigit()));
+
+    kind == Some(true) && pubkey == Some(true) && parts.next().is_some()
+}
+
+/// Relays listed in the zap request's `relays` tag.
+pub fn zap_relays(zap_request: &NostrEvent) -> Vec<String> {
+    let mut relays: Vec<String> = vec![];
+
+    for relay in zap_request.tags("relays").flatten() {
+        if !relays.contains(relay) {
+            relays.push(relay.clone());
+        }
+    }
+    relays.truncate(MAX_ZAP_RELAYS);
+
+    relays
+}
+
+/// Builds the kind 9735 receipt for a paid zap request.
+pub fn zap_receipt(
+    keys: &KeyPair,
+    zap_request: &NostrEvent,
+    raw_zap_request: &str,
+    bolt11: &str,
+    paid_at: i64,
+) -> Result<NostrEvent> {
+    let tag = |name: &str, value: &str| vec![name.to_string(), value.to_string()];
+
+    let recipient = zap_request
+        .tag_value("p")
+        .ok_or_else(|| anyhow!("Zap request has no p tag"))?;
+    let mut tags = vec![tag("p", recipient)];
+    for name in ["e", "a"] {
+        if let Some(value) = zap_request.tag_value(name) {
+            tags.push(tag(name, value));
+        }
+    }
+    tags.push(tag("P", &zap_request.pubkey));
+    tags.push(tag("bolt11", bolt11));
+    tags.push(tag("description", raw_zap_request));
+
+    NostrEvent::sign(keys, paid_at, ZAP_RECEIPT_KIND, tags, String::new())
+}
+
+/// Publishes zap receipts for paid lightning address zaps.
+#[derive(Clone)]
+pub struct ZapService {
+    keys: KeyPair,
+    relays: Arc<dyn RelayClient>,
+    /// Used when the zap request doesn't list any relays.
+    default_relays: Vec<String>,
+    payments: NodelessAddressPaymentRepository,
+    checkouts: CheckoutRepository,
+}
+
+impl ZapService {
+    pub fn new(
+        keys: KeyPair,
+        relays: Arc<dyn RelayClient>,
+        default_relays: Vec<String>,
+        payments: NodelessAddressPaymentRepository,
+        checkouts: CheckoutRepository,
+    ) -> Self {
+        Self {
+            keys,
+            relays,
+            default_relays,
+            payments,
+            checkouts,
+        }
+    }
+
+    /// Hex pubkey zap receipts are signed with.
+    pub fn pubkey(&self) -> String {
+        self.keys.x_only_public_key().0.to_string()
+    }
+
+    /// Publishes the receipt for a paid checkout, if it paid a zap whose
+    /// receipt hasn't been published yet.
+    pub async fn publish_receipt(&self, checkout: &Checkout) -> Result<()> {
+        if !is_paid(checkout) {
+            return Ok(());
+        }
+
+        match self.payments.get_by_checkout_uuid(&checkout.uuid).await? {
+            Some(payment)
+                if payment.zap_request.is_some() && payment.zap_receipt_published_at.is_none() =>
+            {
+                self.publish(&payment, checkout).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Retries receipts that no relay accepted, or that were missed.
+    pub async fn reconcile(&self) -> Result<()> {
+        let payments = self.payments.get_unpublished_zaps(100).await?;
+        if payments.is_empty() {
+            return Ok(());
+        }
+
+        let uuids: Vec<String> = payments.iter().map(|p| p.checkout_uuid.clone()).collect();
+        let checkouts = self.checkouts.get_by_uuids(&uuids).await?;
+
+        for payment in payments {
+            let checkout = checkouts.iter().find(|c| c.uuid == payment.checkout_uuid);
+
+            if let Some(checkout) = checkout {
+                if let Err(e) = self.publish(&payment, checkout).await {
+                    eprintln!(
+                        "failed to publish zap receipt for {}: {:?}",
+                        checkout.uuid, e
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn publish(&self, payment: &NodelessAddressPayment, checkout: &Checkout) -> Result<()> {
+        let raw = payment
+            .zap_request
+            .as_deref()
+            .ok_or_else(|| anyhow!("Payment is not a zap"))?;
+        let zap_request: NostrEvent = serde_json::from_str(raw)?;
+        let paid_at = checkout.late_payment_at.unwrap_or(checkout.updated_at);
+
+        let receipt = zap_receipt(
+            &self.keys,
+            &zap_request,
+            raw,
+            &checkout.payment_request,
+            paid_at.and_utc().timestamp(),
+        )?;
+
+        let mut relays = zap_relays(&zap_request);
+        if relays.is_empty() {
+            relays = self.default_relays.clone();
+        }
+
+        let results = join_all(
+            relays
+                .iter()
+                .map(|relay| self.relays.publish(relay, &receipt)),
+        )
+        .await;
+
+        let mut accepted = false;
+        for (relay, result) in relays.iter().zip(results) {
+            match result {
+                Ok(()) => accepted = true,
+                Err(e) => eprintln!("failed to publish zap receipt to {}: {:?}", relay, e),
+            }
+        }
+
+        if !accepted {
+            return Err(anyhow!("No relay accepted the zap receipt"));
+        }
+
+        self.payments
+            .set_zap_receipt_published(&payment.uuid, &receipt.id)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bech32::{ToBase32, Variant};
+    use secp256k1::KeyPair;
+    use sha2::{Digest, Sha256};
+    use sqlx::PgPool;
+
+    use super::{validate_zap_request, zap_relays, ZapService, ZAP_RECEIPT_KIND, ZAP_REQUEST_KIND};
+    use crate::{
+        helpers::{
+            bolt11,
+            nostr::{keypair_from_hex, NostrEvent},
+            tests::{create_test_pool, create_test_user},
+        },
+        models::checkout::CheckoutStatus,
+        nostr::fake::FakeRelayClient,
+        repositories::{
+            checkout_repository::CheckoutRepository,
+            nodeless_address_repository::NodelessAddressPaymentRepository,
+        },
+        services::lnurl_service::{
+            tests::{checkout_service, cleanup, create_test_address, test_service},
+            LnurlError, LnurlPayment,
+        },
+    };
+
+    fn keys(byte: u8) -> KeyPair {
+        keypair_from_hex(&hex::encode([byte; 32])).unwrap()
+    }
+
+    fn pubkey(keys: &KeyPair) -> String {
+        keys.x_only_public_key().0.to_string()
+    }
+
+    fn npub(keys: &KeyPair) -> String {
+        let pubkey = keys.x_only_public_key().0.serialize();
+        bech32::encode("npub", pubkey.to_base32(), Variant::Bech32).unwrap()
+    }
+
+    fn zap_request(sender: &KeyPair, tags: Vec<Vec<&str>>) -> String {
+        let tags = tags
+            .into_iter()
+            .map(|tag| tag.into_iter().map(str::to_string).collect())
+            .collect();
+        let event = NostrEvent::sign(
+            sender,
+            chrono::Utc::now().timestamp(),
+            ZAP_REQUEST_KIND,
+            tags,
+            "Great post".to_string(),
+        )
+        .unwrap();
+
+        serde_json::to_string(&event).unwrap()
+    }
+
+    fn zap_service(pool: &PgPool, relays: Arc<FakeRelayClient>) -> ZapService {
+        ZapService::new(
+            keys(3),
+            relays,
+            vec!["wss://default.relay".to_string()],
+            NodelessAddressPaymentRepository::new(pool.clone()),
+            CheckoutRepository::new(pool.clone()),
+        )
+    }
+
+    #[test]
+    fn test_validate_zap_request() {
+        let now = chrono::Utc::now().timestamp();
+        let sender = keys(1);
+        let recipient = pubkey(&keys(2));
+        let relays = vec![
+            "relays",
+            "wss://relay.one",
+            "wss://relay.two",
+            "wss://relay.one",
+        ];
+
+        let valid = zap_request(
+            &sender,
+            vec![
+                vec!["p", &recipient],
+                vec!["amount", "21000"],
+                relays.clone(),
+            ],
+        );
+        let event = validate_zap_request(&valid, 21_000, &recipient, now).unwrap();
+        assert_eq!(
+            zap_relays(&event),
+            vec!["wss://relay.one", "wss://relay.two"]
+        );
+
+        assert!(validate_zap_request(&valid, 42_000, &recipient, now).is_err());
+        assert!(validate_zap_request(&valid, 21_000, &pubkey(&keys(4)), now).is_err());
+        assert!(validate_zap_request("not json", 21_000, &recipient, now).is_err());
+        assert!(validate_zap_request(&valid, 21_000, &recipient, now + 700).is_err());
+        assert!(validate_zap_request(&valid, 21_000, &recipient, now - 700).is_err());
+
+        let tampered = valid.replace("Great post", "Bad post");
+        assert!(validate_zap_request(&tampered, 21_000, &recipient, now).is_err());
+
+        let two_recipients = zap_request(
+            &sender,
+            vec![vec!["p", &recipient], vec!["p", &recipient], relays.clone()],
+        );
+        assert!(validate_zap_request(&two_recipients, 21_000, &recipient, now).is_err());
+
+        let bad_coordinate = zap_request(
+            &sender,
+            vec![vec!["p", &recipient], vec!["a", "30023:nothex:post"]],
+        );
+        assert!(validate_zap_request(&bad_coordinate, 21_000, &recipient, now).is_err());
+
+        let wrong_kind = NostrEvent::sign(
+            &sender,
+            now,
+            1,
+            vec![vec!["p".to_string(), recipient.clone()]],
+            String::new(),
+        )
+        .unwrap();
+        let wrong_kind = serde_json::to_string(&wrong_kind).unwrap();
+        assert!(validate_zap_request(&wrong_kind, 21_000, &recipient, now).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_zap_receipt_published() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let sender = keys(1);
+        let recipient = keys(2);
+        let address = create_test_address(&pool, &user.uuid, Some(npub(&recipient))).await;
+        let relays = Arc::new(FakeRelayClient::new());
+        let zaps = zap_service(&pool, relays.clone());
+        let lnurl = test_service(&pool, &zaps.pubkey());
+
+        let pay_request = lnurl.pay_request(&address.handle).await.unwrap();
+        assert_eq!(pay_request.allows_nostr, Some(true));
+        assert_eq!(pay_request.nostr_pubkey, Some(pubkey(&keys(3))));
+
+        let raw = zap_request(
+            &sender,
+            vec![
+                vec!["p", &pubkey(&recipient)],
+                vec!["e", &"cd".repeat(32)],
+                vec!["amount", "21000"],
+                vec!["relays", "wss://relay.one", "wss://relay.two"],
+            ],
+        );
+        let (callback, checkout) = lnurl
+            .pay(
+                &address.handle,
+                LnurlPayment {
+                    amount: 21_000,
+                    comment: None,
+                    zap_request: Some(raw.clone()),
+                },
+                checkout_service(&pool),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            bolt11::invoice_description_hash(&callback.pr).unwrap(),
+            Some(hex::encode(Sha256::digest(raw.as_bytes())))
+        );
+
+        // Nothing is published until the invoice is paid.
+        zaps.publish_receipt(&checkout).await.unwrap();
+        assert!(relays.published().is_empty());
+
+        let checkout = CheckoutRepository::new(pool.clone())
+            .set_payment_received(
+                &checkout.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Paid,
+                21,
+                21,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        relays.set_offline("wss://relay.two", true);
+        zaps.publish_receipt(&checkout).await.unwrap();
+
+        let published = relays.published();
+        assert_eq!(published.len(), 1);
+        let (relay, receipt) = &published[0];
+        assert_eq!(relay, "wss://relay.one");
+        assert!(receipt.verify().is_ok());
+        assert_eq!(receipt.kind, ZAP_RECEIPT_KIND);
+        assert_eq!(receipt.pubkey, zaps.pubkey());
+        assert_eq!(receipt.tag_value("p"), Some(pubkey(&recipient).as_str()));
+        assert_eq!(receipt.tag_value("e"), Some("cd".repeat(32).as_str()));
+        assert_eq!(receipt.tag_value("P"), Some(pubkey(&sender).as_str()));
+        assert_eq!(receipt.tag_value("bolt11"), Some(callback.pr.as_str()));
+        assert_eq!(receipt.tag_value("description"), Some(raw.as_str()));
+
+        let payment = NodelessAddressPaymentRepository::new(pool.clone())
+            .get_by_checkout_uuid(&checkout.uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(payment.zap_receipt_id.as_deref(), Some(receipt.id.as_str()));
+
+        // Published receipts aren't sent again.
+        zaps.publish_receipt(&checkout).await.unwrap();
+        zaps.reconcile().await.unwrap();
+        assert_eq!(relays.published().len(), 1);
+
+        cleanup(&pool, &user.uuid, &address.uuid).await;
+    }
+
+    #[tokio::test]
+    async fn test_zap_requires_npub() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let address = create_test_address(&pool, &user.uuid, None).await;
+        let lnurl = test_service(&pool, &pubkey(&keys(3)));
+
+        let raw = zap_request(&keys(1), vec![vec!["p", &pubkey(&keys(2))]]);
+        let result = lnurl
+            .pay(
+                &address.handle,
+                LnurlPayment {
+                    amount: 21_000,
+                    comment: None,
+                    zap_request: Some(raw),
+                },
+                checkout_service(&pool),
+            )
+            .await;
+        assert!(matches!(result, Err(LnurlError::ZapsNotAllowed)));
+
+        cleanup(&pool, &user.uuid, &address.uuid).await;
+    }
+}
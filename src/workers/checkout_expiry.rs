@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::{
+    models::checkout::Checkout,
+    repositories::checkout_repository::CheckoutRepository,
+    services::event_bus::{CheckoutEventKind, EventBus},
+};
+
+pub struct CheckoutExpirySweeper {
+    pub checkout_repo: CheckoutRepository,
+    pub events: EventBus,
+    pub interval: Duration,
+}
+
+impl CheckoutExpirySweeper {
+    pub fn new(checkout_repo: CheckoutRepository, events: EventBus, interval: Duration) -> Self {
+        Self {
+            checkout_repo,
+            events,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.tick().await {
+                eprintln!("checkout expiry sweeper failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Expires every stale checkout once and returns the ones that expired.
+    pub async fn tick(&self) -> Result<Vec<Checkout>> {
+        let expired = self.checkout_repo.expire_stale().await?;
+
+        for checkout in &expired {
+            self.events
+                .publish(CheckoutEventKind::Expired, checkout.clone());
+        }
+
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::CheckoutExpirySweeper;
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        models::checkout::CheckoutStatus,
+        repositories::checkout_repository::{CheckoutRepository, CreateCheckout},
+        services::event_bus::{CheckoutEventKind, EventBus},
+    };
+
+    #[tokio::test]
+    async fn test_expire_stale_checkouts() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+
+        let create = |expiry_seconds| CreateCheckout {
+            user_uuid: user.uuid.clone(),
+            amount: 1000,
+            bitcoin_address: "test address".to_string(),
+            payment_request: "test payment request".to_string(),
+            payment_hash: None,
+            expiry_seconds,
+            fee: None,
+        };
+        let stale = checkout_repo.create(create(0)).await.unwrap();
+        let fresh = checkout_repo.create(create(3600)).await.unwrap();
+        let underpaid = checkout_repo.create(create(0)).await.unwrap();
+        checkout_repo
+            .set_payment_received(
+                &underpaid.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Underpaid,
+                400,
+                400,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let events = EventBus::new(16);
+        let mut receiver = events.subscribe();
+        let sweeper =
+            CheckoutExpirySweeper::new(checkout_repo.clone(), events, Duration::from_secs(1));
+
+        let expired = sweeper.tick().await.unwrap();
+        let swept = expired.iter().find(|c| c.uuid == stale.uuid).unwrap();
+        assert_eq!(swept.status, CheckoutStatus::Expired);
+        assert!(swept.expired_at.is_some());
+        assert!(swept.late_payment_at.is_none());
+        assert!(expired.iter().all(|c| c.uuid != fresh.uuid));
+        // Its confirmed funds are flagged so they are still credited.
+        let underpaid_expired = expired.iter().find(|c| c.uuid == underpaid.uuid).unwrap();
+        assert!(underpaid_expired.late_payment_at.is_some());
+
+        // A watcher that loaded the checkout before it expired can't revive it.
+        assert!(checkout_repo
+            .set_payment_received(
+                &underpaid.uuid,
+                CheckoutStatus::Underpaid,
+                CheckoutStatus::Paid,
+                1000,
+                1000,
+            )
+            .await
+            .unwrap()
+            .is_none());
+
+        let event = loop {
+            let event = receiver.recv().await.unwrap();
+            if event.checkout.uuid == stale.uuid {
+                break event;
+            }
+        };
+        assert_eq!(event.kind, CheckoutEventKind::Expired);
+
+        let fresh = checkout_repo.get_by_uuid(fresh.uuid).await.unwrap();
+        assert_eq!(fresh.status, CheckoutStatus::New);
+
+        sqlx::query("DELETE FROM checkouts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
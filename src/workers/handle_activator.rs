@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::{
+    event_bus::{CheckoutEventKind, EventBus},
+    nodeless_address_service::NodelessAddressService,
+};
+
+/// Activates purchased handles as their checkouts are paid, and periodically
+/// activates any the event bus dropped.
+pub struct HandleActivator {
+    pub events: EventBus,
+    pub addresses: NodelessAddressService,
+    pub interval: Duration,
+}
+
+impl HandleActivator {
+    pub fn new(events: EventBus, addresses: NodelessAddressService, interval: Duration) -> Self {
+        Self {
+            events,
+            addresses,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut receiver = self.events.subscribe();
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        if !matches!(
+                            event.kind,
+                            CheckoutEventKind::Paid | CheckoutEventKind::Overpaid
+                        ) {
+                            continue;
+                        }
+
+                        if let Err(e) = self.addresses.activate(&event.checkout).await {
+                            eprintln!(
+                                "failed to activate handle for checkout {}: {:?}",
+                                event.checkout.uuid, e
+                            );
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("handle activator lagged, {} events dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {
+                    if let Err(e) = self.addresses.reconcile().await {
+                        eprintln!("handle activation reconciliation failed: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::{
+    event_bus::{CheckoutEventKind, EventBus},
+    ledger_service::LedgerService,
+};
+
+/// Posts checkout payments to the ledger as they are paid, and periodically
+/// reconciles any the event bus dropped.
+pub struct LedgerPoster {
+    pub events: EventBus,
+    pub ledger: LedgerService,
+    pub interval: Duration,
+}
+
+impl LedgerPoster {
+    pub fn new(events: EventBus, ledger: LedgerService, interval: Duration) -> Self {
+        Self {
+            events,
+            ledger,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut receiver = self.events.subscribe();
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        if !matches!(
+                            event.kind,
+                            CheckoutEventKind::Paid
+                                | CheckoutEventKind::Overpaid
+                                | CheckoutEventKind::LatePayment
+                        ) {
+                            continue;
+                        }
+
+                        if let Err(e) = self.ledger.post_checkout_payment(&event.checkout).await {
+                            eprintln!(
+                                "failed to post checkout {} to the ledger: {:?}",
+                                event.checkout.uuid, e
+                            );
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("ledger poster lagged, {} events dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {
+                    if let Err(e) = self.ledger.reconcile().await {
+                        eprintln!("ledger reconciliation failed: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
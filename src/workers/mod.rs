@@ -0,0 +1,9 @@
+pub mod checkout_expiry;
+pub mod handle_activator;
+pub mod ledger_poster;
+pub mod payment_watcher;
+pub mod payout_batcher;
+pub mod session_sweeper;
+pub mod webhook_dispatcher;
+pub mod withdrawal_reconciler;
+pub mod zap_publisher;
@@ -0,0 +1,447 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    lightning::LightningBackend,
+    models::checkout::{Checkout, CheckoutStatus},
+    repositories::checkout_repository::CheckoutRepository,
+    services::event_bus::{CheckoutEventKind, EventBus},
+};
+
+/// Funds seen for a single checkout, in sats.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaymentReceived {
+    pub lightning: i64,
+    pub onchain_confirmed: i64,
+    pub onchain_unconfirmed: i64,
+}
+
+impl PaymentReceived {
+    pub fn confirmed(&self) -> i64 {
+        self.lightning + self.onchain_confirmed
+    }
+
+    pub fn total(&self) -> i64 {
+        self.confirmed() + self.onchain_unconfirmed
+    }
+}
+
+/// Decides which status a checkout should be in given what has been received.
+/// Returns `None` while nothing has arrived yet.
+pub fn resolve_status(amount: i64, received: &PaymentReceived) -> Option<CheckoutStatus> {
+    let confirmed = received.confirmed();
+
+    if confirmed >= amount {
+        if confirmed == amount {
+            return Some(CheckoutStatus::Paid);
+        }
+        return Some(CheckoutStatus::Overpaid);
+    }
+
+    if received.onchain_unconfirmed > 0 {
+        return Some(CheckoutStatus::PendingConfirmation);
+    }
+
+    if confirmed > 0 {
+        return Some(CheckoutStatus::Underpaid);
+    }
+
+    None
+}
+
+pub struct PaymentWatcher {
+    pub lightning: Arc<dyn LightningBackend>,
+    pub checkout_repo: CheckoutRepository,
+    pub events: EventBus,
+    pub interval: Duration,
+    pub late_payment_window_seconds: i64,
+}
+
+impl PaymentWatcher {
+    pub fn new(
+        lightning: Arc<dyn LightningBackend>,
+        checkout_repo: CheckoutRepository,
+        events: EventBus,
+        interval: Duration,
+        late_payment_window_seconds: i64,
+    ) -> Self {
+        Self {
+            lightning,
+            checkout_repo,
+            events,
+            interval,
+            late_payment_window_seconds,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.tick().await {
+                eprintln!("payment watcher failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Checks every unsettled checkout once and returns the ones that changed.
+    /// Checkouts older than the late payment window are no longer watched.
+    pub async fn tick(&self) -> Result<Vec<Checkout>> {
+        let checkouts = self
+            .checkout_repo
+            .get_unsettled(self.late_payment_window_seconds)
+            .await?;
+        let expired = self
+            .checkout_repo
+            .get_recently_expired(self.late_payment_window_seconds)
+            .await?;
+
+        // Every address is looked up in one go rather than per checkout.
+        let addresses: Vec<String> = checkouts
+            .iter()
+            .chain(expired.iter())
+            .map(|checkout| checkout.bitcoin_address.clone())
+            .collect();
+        let onchain = self.lightning.addresses_received(&addresses).await?;
+        let onchain_received = |checkout: &Checkout| {
+            onchain
+                .get(&checkout.bitcoin_address)
+                .copied()
+                .unwrap_or((0, 0))
+        };
+
+        let mut updated = Vec::new();
+
+        for checkout in checkouts {
+            match self.check(&checkout, onchain_received(&checkout)).await {
+                Ok(Some(checkout)) => updated.push(checkout),
+                Ok(None) => {}
+                Err(e) => eprintln!("failed to check checkout {}: {:?}", checkout.uuid, e),
+            }
+        }
+
+        for checkout in expired {
+            match self
+                .check_late_payment(&checkout, onchain_received(&checkout))
+                .await
+            {
+                Ok(Some(checkout)) => updated.push(checkout),
+                Ok(None) => {}
+                Err(e) => eprintln!(
+                    "failed to check expired checkout {}: {:?}",
+                    checkout.uuid, e
+                ),
+            }
+        }
+
+        Ok(updated)
+    }
+
+    async fn check(&self, checkout: &Checkout, onchain: (i64, i64)) -> Result<Option<Checkout>> {
+        let lightning = match &checkout.payment_hash {
+            Some(payment_hash) => self.lightning.settled_amount(payment_hash).await?,
+            None => None,
+        };
+        let lightning = lightning.unwrap_or(0);
+        // Funds seen before are never taken back, in case the node reports
+        // less than it did, e.g. while it resyncs.
+        let onchain_confirmed = onchain.0.max(checkout.amount_confirmed - lightning);
+        let received = PaymentReceived {
+            lightning,
+            onchain_confirmed,
+            onchain_unconfirmed: onchain
+                .1
+                .max(checkout.amount_received - lightning - onchain_confirmed),
+        };
+
+        let status = match resolve_status(checkout.amount, &received) {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+
+        if status == checkout.status
+            && received.total() == checkout.amount_received
+            && received.confirmed() == checkout.amount_confirmed
+        {
+            return Ok(None);
+        }
+
+        // Skipped if the checkout expired since it was loaded. Anything it
+        // received is then picked up as a late payment.
+        let status_changed = status != checkout.status;
+        let checkout = match self
+            .checkout_repo
+            .set_payment_received(
+                &checkout.uuid,
+                checkout.status.clone(),
+                status,
+                received.total(),
+                received.confirmed(),
+            )
+            .await?
+        {
+            Some(checkout) => checkout,
+            None => return Ok(None),
+        };
+
+        if status_changed {
+            if let Some(kind) = CheckoutEventKind::from_status(&checkout.status) {
+                self.events.publish(kind, checkout.clone());
+            }
+        }
+
+        Ok(Some(checkout))
+    }
+
+    /// An expired checkout keeps its status, but anything that lands on its
+    /// address is recorded and announced so it can be resolved manually. It is
+    /// only credited once confirmed.
+    async fn check_late_payment(
+        &self,
+        checkout: &Checkout,
+        (confirmed, unconfirmed): (i64, i64),
+    ) -> Result<Option<Checkout>> {
+        let received_more = confirmed + unconfirmed > checkout.amount_received;
+        if !received_more && confirmed <= checkout.amount_confirmed {
+            return Ok(None);
+        }
+
+        let checkout = self
+            .checkout_repo
+            .set_late_payment(
+                &checkout.uuid,
+                (confirmed + unconfirmed).max(checkout.amount_received),
+                confirmed,
+            )
+            .await?;
+
+        if received_more || checkout.amount_confirmed > checkout.amount_credited {
+            self.events
+                .publish(CheckoutEventKind::LatePayment, checkout.clone());
+        }
+
+        Ok(Some(checkout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{resolve_status, PaymentReceived, PaymentWatcher};
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        lightning::{fake::FakeLightningBackend, AddInvoice, LightningBackend},
+        models::checkout::CheckoutStatus,
+        repositories::{
+            checkout_repository::{CheckoutRepository, CreateCheckout},
+            ledger_repository::{AccountKey, LedgerRepository},
+            nodeless_address_repository::NodelessAddressRepository,
+            store_repository::StoreInvoiceRepository,
+        },
+        services::{
+            event_bus::{CheckoutEventKind, EventBus},
+            ledger_service::LedgerService,
+        },
+        workers::checkout_expiry::CheckoutExpirySweeper,
+    };
+
+    #[test]
+    fn test_resolve_status() {
+        let received = |lightning, onchain_confirmed, onchain_unconfirmed| PaymentReceived {
+            lightning,
+            onchain_confirmed,
+            onchain_unconfirmed,
+        };
+
+        assert_eq!(resolve_status(1000, &received(0, 0, 0)), None);
+        assert_eq!(
+            resolve_status(1000, &received(1000, 0, 0)),
+            Some(CheckoutStatus::Paid)
+        );
+        assert_eq!(
+            resolve_status(1000, &received(0, 1500, 0)),
+            Some(CheckoutStatus::Overpaid)
+        );
+        assert_eq!(
+            resolve_status(1000, &received(0, 400, 0)),
+            Some(CheckoutStatus::Underpaid)
+        );
+        assert_eq!(
+            resolve_status(1000, &received(0, 0, 1000)),
+            Some(CheckoutStatus::PendingConfirmation)
+        );
+        assert_eq!(
+            resolve_status(1000, &received(1000, 0, 500)),
+            Some(CheckoutStatus::Paid)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_watcher_transitions() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+        let lightning = Arc::new(FakeLightningBackend::new());
+        let invoice = lightning
+            .add_invoice(AddInvoice {
+                memo: "test".to_string(),
+                amount: 1000,
+                expiry: 3600,
+                description_hash: None,
+            })
+            .await
+            .unwrap();
+
+        let checkout = checkout_repo
+            .create(CreateCheckout {
+                user_uuid: user.uuid.clone(),
+                amount: 1000,
+                bitcoin_address: format!("tb1q{}", user.uuid),
+                payment_request: invoice.payment_request,
+                payment_hash: Some(invoice.payment_hash.clone()),
+                expiry_seconds: 3600,
+                fee: None,
+            })
+            .await
+            .unwrap();
+
+        lightning.receive_onchain(&checkout.bitcoin_address, 0, 1000);
+
+        let events = EventBus::new(16);
+        let mut receiver = events.subscribe();
+        let watcher = PaymentWatcher::new(
+            lightning.clone(),
+            checkout_repo.clone(),
+            events,
+            Duration::from_secs(1),
+            3600,
+        );
+
+        let updated = watcher.tick().await.unwrap();
+        let pending = updated.iter().find(|c| c.uuid == checkout.uuid).unwrap();
+        assert_eq!(pending.status, CheckoutStatus::PendingConfirmation);
+        assert_eq!(pending.amount_received, 1000);
+        assert_eq!(pending.amount_confirmed, 0);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.kind, CheckoutEventKind::PendingConfirmation);
+        assert_eq!(event.checkout.uuid, checkout.uuid);
+
+        lightning.receive_onchain(&checkout.bitcoin_address, 1000, 0);
+        lightning.settle(&invoice.payment_hash, Some(500));
+
+        let updated = watcher.tick().await.unwrap();
+        let overpaid = updated.iter().find(|c| c.uuid == checkout.uuid).unwrap();
+        assert_eq!(overpaid.status, CheckoutStatus::Overpaid);
+        assert_eq!(overpaid.amount_received, 1500);
+        assert_eq!(overpaid.amount_confirmed, 1500);
+
+        // The node reporting less, e.g. after the output was spent, doesn't
+        // take the funds back.
+        lightning.receive_onchain(&checkout.bitcoin_address, 0, 0);
+        let updated = watcher.tick().await.unwrap();
+        assert!(updated.iter().all(|c| c.uuid != checkout.uuid));
+        let checkout = checkout_repo.get_by_uuid(checkout.uuid).await.unwrap();
+        assert_eq!(checkout.status, CheckoutStatus::Overpaid);
+        assert_eq!(checkout.amount_received, 1500);
+
+        sqlx::query("DELETE FROM checkouts WHERE uuid = $1")
+            .bind(&checkout.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_underpaid_checkout_is_credited() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+        let lightning = Arc::new(FakeLightningBackend::new());
+        let events = EventBus::new(16);
+        let ledger_repo = LedgerRepository::new(pool.clone());
+        let ledger = LedgerService::new(
+            ledger_repo.clone(),
+            StoreInvoiceRepository::new(pool.clone()),
+            NodelessAddressRepository::new(pool.clone()),
+        );
+
+        let checkout = checkout_repo
+            .create(CreateCheckout {
+                user_uuid: user.uuid.clone(),
+                amount: 1000,
+                bitcoin_address: format!("tb1q{}", user.uuid),
+                payment_request: "test payment request".to_string(),
+                payment_hash: None,
+                expiry_seconds: 0,
+                fee: None,
+            })
+            .await
+            .unwrap();
+
+        let watcher = PaymentWatcher::new(
+            lightning.clone(),
+            checkout_repo.clone(),
+            events.clone(),
+            Duration::from_secs(1),
+            3600,
+        );
+        lightning.receive_onchain(&checkout.bitcoin_address, 400, 0);
+        watcher.tick().await.unwrap();
+
+        // The partial payment confirmed before the checkout expired, so it is
+        // still credited.
+        let sweeper =
+            CheckoutExpirySweeper::new(checkout_repo.clone(), events, Duration::from_secs(1));
+        let expired = sweeper.tick().await.unwrap();
+        let expired = expired.iter().find(|c| c.uuid == checkout.uuid).unwrap();
+        assert_eq!(expired.status, CheckoutStatus::Expired);
+        assert_eq!(expired.amount_confirmed, 400);
+        assert!(expired.late_payment_at.is_some());
+
+        let updated = watcher.tick().await.unwrap();
+        assert!(updated.iter().all(|c| c.uuid != checkout.uuid));
+
+        let payment = ledger
+            .post_checkout_payment(expired)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            ledger_repo
+                .get_account_balance(&AccountKey::user(&user.uuid))
+                .await
+                .unwrap(),
+            400
+        );
+
+        sqlx::query("DELETE FROM ledger_entries WHERE transaction_uuid = $1")
+            .bind(&payment.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM ledger_transactions WHERE uuid = $1")
+            .bind(&payment.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM ledger_accounts WHERE user_uuid = $1")
+            .bind(&user.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE uuid = $1")
+            .bind(&checkout.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use crate::services::withdrawal_service::WithdrawalService;
+
+/// Periodically sends queued on-chain payouts as one transaction per batch.
+pub struct PayoutBatcher {
+    pub withdrawals: WithdrawalService,
+    pub interval: Duration,
+}
+
+impl PayoutBatcher {
+    pub fn new(withdrawals: WithdrawalService, interval: Duration) -> Self {
+        Self {
+            withdrawals,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            match self.withdrawals.send_onchain_batch().await {
+                Ok(Some((txid, batch))) => {
+                    eprintln!("sent {} on-chain payouts in {}", batch.len(), txid);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("payout batcher failed: {:?}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::repositories::session_repository::SessionRepository;
+
+/// How long expired sessions are kept around, e.g. to tell a reused
+/// refresh token apart from an unknown one.
+const EXPIRED_SESSION_RETENTION_SECONDS: u64 = 7 * 24 * 3600;
+
+pub struct SessionSweeper {
+    pub sessions: SessionRepository,
+    pub interval: Duration,
+}
+
+impl SessionSweeper {
+    pub fn new(sessions: SessionRepository, interval: Duration) -> Self {
+        Self { sessions, interval }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.tick().await {
+                eprintln!("session sweeper failed: {:?}", e);
+            }
+        }
+    }
+
+    /// Deletes sessions that expired over a week ago and returns how many.
+    pub async fn tick(&self) -> Result<u64> {
+        Ok(self
+            .sessions
+            .delete_expired(EXPIRED_SESSION_RETENTION_SECONDS)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SessionSweeper;
+    use crate::{
+        helpers::tests::{create_test_pool, create_test_user, delete_test_user},
+        repositories::session_repository::{CreateSession, SessionRepository},
+    };
+
+    #[tokio::test]
+    async fn test_delete_long_expired_sessions() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let sessions = SessionRepository::new(pool.clone());
+
+        let create = |token: &str| CreateSession {
+            user_uuid: user.uuid.clone(),
+            device: None,
+            ip_hash: "test ip hash".to_string(),
+            refresh_token_hash: format!("{}-{}", token, uuid::Uuid::new_v4()),
+            expiry_seconds: 3600,
+        };
+        let stale = sessions.create(create("stale")).await.unwrap();
+        let recent = sessions.create(create("recent")).await.unwrap();
+
+        for (session, days) in [(&stale, 8), (&recent, 1)] {
+            sqlx::query(
+                "UPDATE sessions SET expires_at = NOW() - make_interval(days => $1) WHERE uuid = $2",
+            )
+            .bind(days)
+            .bind(&session.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let sweeper = SessionSweeper::new(sessions, Duration::from_secs(1));
+        assert!(sweeper.tick().await.unwrap() >= 1);
+
+        let remaining: Vec<String> =
+            sqlx::query_scalar("SELECT uuid FROM sessions WHERE user_uuid = $1")
+                .bind(&user.uuid)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, vec![recent.uuid]);
+
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
@@ -0,0 +1,626 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    config::WebhooksConfig,
+    helpers::{crypto::sha256_hmac, outbound::pinned_client},
+    models::{
+        checkout::Checkout,
+        store::StoreInvoice,
+        webhook::{Webhook, WebhookDelivery},
+    },
+    repositories::{
+        checkout_repository::CheckoutRepository,
+        store_repository::StoreInvoiceRepository,
+        webhook_repository::{
+            CreateWebhookDeliveryAttempt, WebhookDeliveryRepository, WebhookRepository,
+        },
+    },
+    services::event_bus::{CheckoutEvent, CheckoutEventKind, EventBus},
+};
+
+/// Events a webhook can subscribe to.
+pub const WEBHOOK_EVENTS: [&str; 7] = [
+    "checkout.created",
+    "checkout.pending_confirmation",
+    "checkout.paid",
+    "checkout.overpaid",
+    "checkout.underpaid",
+    "checkout.expired",
+    "checkout.late_payment",
+];
+
+pub fn event_name(kind: &CheckoutEventKind) -> String {
+    format!("checkout.{}", kind)
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload {
+    pub event: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub data: WebhookPayloadData,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookPayloadData {
+    pub invoice: StoreInvoice,
+    pub checkout: Checkout,
+}
+
+/// Signature sent in `X-Nodeless-Signature`. The timestamp is part of the
+/// signed content so receivers can reject replayed deliveries.
+pub fn sign_payload(secret: &str, timestamp: u64, body: &str) -> String {
+    sha256_hmac(format!("{}.{}", timestamp, body).as_str(), secret)
+}
+
+/// Seconds to wait before the next attempt after `attempts` failed ones.
+pub fn backoff_seconds(attempts: i32, initial: i64, max: i64) -> i64 {
+    let factor = 2i64.saturating_pow(attempts.max(0) as u32);
+    initial.saturating_mul(factor).min(max)
+}
+
+/// How far before its saved cursor the enqueuer catches up on startup.
+const CURSOR_OVERLAP_SECONDS: i64 = 60;
+
+/// Bytes of a webhook response body kept with the delivery attempt.
+const MAX_RESPONSE_BODY_BYTES: usize = 1024;
+
+/// Turns checkout events for store invoices into queued webhook deliveries.
+pub struct WebhookEnqueuer {
+    pub events: EventBus,
+    pub checkout_repo: CheckoutRepository,
+    pub store_invoice_repo: StoreInvoiceRepository,
+    pub webhook_repo: WebhookRepository,
+    pub delivery_repo: WebhookDeliveryRepository,
+}
+
+impl WebhookEnqueuer {
+    pub fn new(
+        events: EventBus,
+        checkout_repo: CheckoutRepository,
+        store_invoice_repo: StoreInvoiceRepository,
+        webhook_repo: WebhookRepository,
+        delivery_repo: WebhookDeliveryRepository,
+    ) -> Self {
+        Self {
+            events,
+            checkout_repo,
+            store_invoice_repo,
+            webhook_repo,
+            delivery_repo,
+        }
+    }
+
+    pub async fn run(self) {
+        // Subscribe before catching up, so nothing published meanwhile is
+        // missed.
+        let mut receiver = self.events.subscribe();
+        // Checkouts updated since this are covered by an event or a catch-up.
+        let mut since = loop {
+            match self.start().await {
+                Ok(since) => break since,
+                Err(e) => {
+                    eprintln!("webhook enqueuer failed to start: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        };
+
+        loop {
+            match receiver.recv().await {
+                Ok(event) => match self.enqueue(&event).await {
+                    Ok(_) => since = self.advance(since, event.checkout.updated_at).await,
+                    Err(e) => eprintln!(
+                        "failed to enqueue webhooks for checkout {}: {:?}",
+                        event.checkout.uuid, e
+                    ),
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    eprintln!(
+                        "webhook enqueuer lagged, {} events dropped, catching up",
+                        skipped
+                    );
+
+                    match self.catch_up(since).await {
+                        Ok(latest) => since = self.advance(since, latest).await,
+                        Err(e) => eprintln!("webhook enqueuer failed to catch up: {:?}", e),
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    /// Queues the events missed while the enqueuer wasn't running, e.g.
+    /// because it crashed between a checkout update and its event. Returns
+    /// the cursor to continue from.
+    pub async fn start(&self) -> Result<chrono::NaiveDateTime> {
+        let since = match self.delivery_repo.get_cursor().await? {
+            // Updates aren't published in the order they are stamped, so
+            // look back a little. Events already queued are skipped.
+            Some(cursor) => {
+                let latest = self
+                    .catch_up(cursor - chrono::Duration::seconds(CURSOR_OVERLAP_SECONDS))
+                    .await?;
+                latest.max(cursor)
+            }
+            None => chrono::Utc::now().naive_utc(),
+        };
+
+        self.delivery_repo.save_cursor(since).await?;
+
+        Ok(since)
+    }
+
+    async fn advance(
+        &self,
+        since: chrono::NaiveDateTime,
+        processed: chrono::NaiveDateTime,
+    ) -> chrono::NaiveDateTime {
+        let since = since.max(processed);
+        if let Err(e) = self.delivery_repo.save_cursor(since).await {
+            eprintln!("webhook enqueuer failed to save its cursor: {:?}", e);
+        }
+
+        since
+    }
+
+    pub async fn enqueue(&self, event: &CheckoutEvent) -> Result<Vec<WebhookDelivery>> {
+        self.enqueue_event(event, false).await
+    }
+
+    /// Queues the events of store checkouts updated at or after `since` that
+    /// weren't queued yet, e.g. because the event bus dropped them. Only the
+    /// checkout's current status can be recovered, not every step on the
+    /// way. Returns when the latest of them was updated.
+    pub async fn catch_up(&self, since: chrono::NaiveDateTime) -> Result<chrono::NaiveDateTime> {
+        let checkouts = self
+            .checkout_repo
+            .get_store_checkouts_updated_since(since)
+            .await?;
+        let mut latest = since;
+
+        for checkout in checkouts {
+            latest = latest.max(checkout.updated_at);
+
+            let mut kinds = Vec::new();
+            if checkout.created_at >= since {
+                kinds.push(CheckoutEventKind::Created);
+            }
+            if let Some(kind) = CheckoutEventKind::from_status(&checkout.status) {
+                kinds.push(kind);
+            }
+            if checkout.late_payment_at.is_some_and(|at| at >= since) {
+                kinds.push(CheckoutEventKind::LatePayment);
+            }
+
+            for kind in kinds {
+                let event = CheckoutEvent {
+                    kind,
+                    checkout: checkout.clone(),
+                };
+                self.enqueue_event(&event, true).await?;
+            }
+        }
+
+        Ok(latest)
+    }
+
+    /// Queues the event to every subscribed webhook, skipping the webhooks it
+    /// was already queued to if `skip_queued`. Late payments can repeat, so
+    /// those only count if queued after the checkout's latest one.
+    async fn enqueue_event(
+        &self,
+        event: &CheckoutEvent,
+        skip_queued: bool,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let invoice = match self
+            .store_invoice_repo
+            .get_by_checkout_uuid(&event.checkout.uuid)
+            .await?
+        {
+            Some(invoice) => invoice,
+            None => return Ok(Vec::new()),
+        };
+
+        let event_name = event_name(&event.kind);
+        let webhooks = self
+            .webhook_repo
+            .get_subscribed(&invoice.store_uuid, &event_name)
+            .await?;
+
+        if webhooks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let payload = serde_json::to_value(WebhookPayload {
+            event: event_name.clone(),
+            created_at: chrono::Utc::now().naive_utc(),
+            data: WebhookPayloadData {
+                invoice,
+                checkout: event.checkout.clone(),
+            },
+        })?;
+        let queued_since = match event.kind {
+            CheckoutEventKind::LatePayment => event.checkout.late_payment_at,
+            _ => None,
+        };
+
+        let mut deliveries = Vec::new();
+        for webhook in webhooks {
+            if skip_queued
+                && self
+                    .delivery_repo
+                    .has_checkout_delivery(
+                        &webhook.uuid,
+                        &event_name,
+                        &event.checkout.uuid,
+                        queued_since,
+                    )
+                    .await?
+            {
+                continue;
+            }
+
+            let delivery = self
+                .delivery_repo
+                .enqueue(&webhook.uuid, &event_name, payload.clone())
+                .await?;
+            deliveries.push(delivery);
+        }
+
+        Ok(deliveries)
+    }
+}
+
+/// Sends queued deliveries and reschedules failed ones with exponential backoff.
+pub struct WebhookDeliverer {
+    pub webhook_repo: WebhookRepository,
+    pub delivery_repo: WebhookDeliveryRepository,
+    pub config: WebhooksConfig,
+}
+
+impl WebhookDeliverer {
+    pub fn new(
+        webhook_repo: WebhookRepository,
+        delivery_repo: WebhookDeliveryRepository,
+        config: WebhooksConfig,
+    ) -> Self {
+        Self {
+            webhook_repo,
+            delivery_repo,
+            config,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.delivery_interval_seconds));
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.tick().await {
+                eprintln!("webhook deliverer failed: {:?}", e);
+            }
+        }
+    }
+
+    pub async fn tick(&self) -> Result<()> {
+        // Hold claimed deliveries for longer than a request can take.
+        let lease_seconds = self.config.request_timeout_seconds as i64 * 2;
+        let deliveries = self.delivery_repo.claim_due(50, lease_seconds).await?;
+
+        for delivery in deliveries {
+            if let Err(e) = self.deliver(&delivery).await {
+                eprintln!("failed to deliver webhook {}: {:?}", delivery.uuid, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn deliver(&self, delivery: &WebhookDelivery) -> Result<()> {
+        let webhook = match self.webhook_repo.get_one(&delivery.webhook_uuid).await? {
+            Some(webhook) if webhook.enabled => webhook,
+            _ => {
+                self.record_attempt(delivery, None, None, Some("webhook disabled".into()), 0)
+                    .await?;
+                self.delivery_repo
+                    .mark_attempt_failed(&delivery.uuid, None)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let started = Instant::now();
+        let result = self.send(&webhook, delivery).await;
+        let duration_ms = started.elapsed().as_millis() as i64;
+
+        let success = match result {
+            Ok((status, body)) => {
+                self.record_attempt(delivery, Some(status as i32), Some(body), None, duration_ms)
+                    .await?;
+                (200..300).contains(&status)
+            }
+            Err(e) => {
+                self.record_attempt(delivery, None, None, Some(e.to_string()), duration_ms)
+                    .await?;
+                false
+            }
+        };
+
+        if success {
+            self.delivery_repo.mark_delivered(&delivery.uuid).await?;
+            return Ok(());
+        }
+
+        let retry_in = if delivery.attempts + 1 >= self.config.max_attempts {
+            None
+        } else {
+            Some(backoff_seconds(
+                delivery.attempts,
+                self.config.initial_backoff_seconds,
+                self.config.max_backoff_seconds,
+            ))
+        };
+
+        self.delivery_repo
+            .mark_attempt_failed(&delivery.uuid, retry_in)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send(&self, webhook: &Webhook, delivery: &WebhookDelivery) -> Result<(u16, String)> {
+        let body = serde_json::to_string(&delivery.payload)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let signature = sign_payload(&webhook.secret, timestamp, &body);
+
+        // The url is resolved on every delivery, so a host that was public
+        // when the webhook was saved can't be pointed inward later.
+        let url = reqwest::Url::parse(&webhook.url)?;
+        let client = pinned_client(
+            &url,
+            Duration::from_secs(self.config.request_timeout_seconds),
+        )
+        .await?;
+
+        let mut response = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Nodeless-Event", &delivery.event)
+            .header("X-Nodeless-Delivery", &delivery.uuid)
+            .header("X-Nodeless-Timestamp", timestamp.to_string())
+            .header("X-Nodeless-Signature", signature)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status().as_u16();
+        // The endpoint controls the body, so only read what is kept.
+        let mut body = Vec::new();
+        while body.len() < MAX_RESPONSE_BODY_BYTES {
+            match response.chunk().await? {
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        body.truncate(MAX_RESPONSE_BODY_BYTES);
+        let body = String::from_utf8_lossy(&body).into_owned();
+
+        Ok((status, body))
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        response_code: Option<i32>,
+        response_body: Option<String>,
+        error: Option<String>,
+        duration_ms: i64,
+    ) -> Result<()> {
+        self.delivery_repo
+            .record_attempt(CreateWebhookDeliveryAttempt {
+                delivery_uuid: delivery.uuid.clone(),
+                response_code,
+                response_body,
+                error,
+                duration_ms,
+            })
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backoff_seconds, sign_payload, WebhookEnqueuer};
+    use crate::{
+        helpers::{
+            crypto::sha256_hmac,
+            tests::{create_test_pool, create_test_user, delete_test_user},
+        },
+        models::checkout::CheckoutStatus,
+        repositories::{
+            checkout_repository::{CheckoutRepository, CreateCheckout},
+            store_repository::{CreateStoreInvoice, StoreInvoiceRepository, StoreRepository},
+            webhook_repository::{CreateWebhook, WebhookDeliveryRepository, WebhookRepository},
+        },
+        services::event_bus::{CheckoutEvent, CheckoutEventKind, EventBus},
+    };
+
+    #[test]
+    fn test_backoff_seconds() {
+        assert_eq!(backoff_seconds(0, 30, 3600), 30);
+        assert_eq!(backoff_seconds(1, 30, 3600), 60);
+        assert_eq!(backoff_seconds(3, 30, 3600), 240);
+        assert_eq!(backoff_seconds(10, 30, 3600), 3600);
+        assert_eq!(backoff_seconds(100, 30, 3600), 3600);
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        let signature = sign_payload("secret", 1691000000, "{}");
+        assert_eq!(signature, sha256_hmac("1691000000.{}", "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_store_invoice_event() {
+        let pool = create_test_pool().await;
+        let user = create_test_user().await.unwrap();
+        let store_repo = StoreRepository::new(pool.clone());
+        let store = store_repo.create(&user.uuid, "test store").await.unwrap();
+        let checkout_repo = CheckoutRepository::new(pool.clone());
+        let store_invoice_repo = StoreInvoiceRepository::new(pool.clone());
+        let webhook_repo = WebhookRepository::new(pool.clone());
+        let delivery_repo = WebhookDeliveryRepository::new(pool.clone());
+
+        let checkout = checkout_repo
+            .create(CreateCheckout {
+                user_uuid: user.uuid.clone(),
+                amount: 1000,
+                bitcoin_address: "test address".to_string(),
+                payment_request: "test payment request".to_string(),
+                payment_hash: None,
+                expiry_seconds: 3600,
+                fee: None,
+            })
+            .await
+            .unwrap();
+        let invoice = store_invoice_repo
+            .create(CreateStoreInvoice {
+                store_uuid: store.uuid.clone(),
+                checkout_uuid: checkout.uuid.clone(),
+                metadata: None,
+            })
+            .await
+            .unwrap();
+        let webhook = webhook_repo
+            .create(CreateWebhook {
+                store_uuid: store.uuid.clone(),
+                url: "https://example.com/hook".to_string(),
+                secret: "secret".to_string(),
+                events: vec!["checkout.paid".to_string()],
+            })
+            .await
+            .unwrap();
+
+        let enqueuer = WebhookEnqueuer::new(
+            EventBus::new(16),
+            checkout_repo.clone(),
+            store_invoice_repo,
+            webhook_repo.clone(),
+            delivery_repo,
+        );
+
+        let deliveries = enqueuer
+            .enqueue(&CheckoutEvent {
+                kind: CheckoutEventKind::Expired,
+                checkout: checkout.clone(),
+            })
+            .await
+            .unwrap();
+        assert!(deliveries.is_empty());
+
+        let deliveries = enqueuer
+            .enqueue(&CheckoutEvent {
+                kind: CheckoutEventKind::Paid,
+                checkout: checkout.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].webhook_uuid, webhook.uuid);
+        assert_eq!(deliveries[0].event, "checkout.paid");
+        assert_eq!(
+            deliveries[0].payload["data"]["invoice"]["uuid"],
+            serde_json::json!(invoice.uuid)
+        );
+
+        // Catching up after a lag skips events that were already queued, and
+        // queues the ones the event bus dropped.
+        let paid = checkout_repo
+            .set_payment_received(
+                &checkout.uuid,
+                CheckoutStatus::New,
+                CheckoutStatus::Paid,
+                1000,
+                1000,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let latest = enqueuer.catch_up(paid.updated_at).await.unwrap();
+        assert_eq!(latest, paid.updated_at);
+        let queued = |enqueuer: &WebhookEnqueuer| {
+            let delivery_repo = enqueuer.delivery_repo.clone();
+            let webhook_uuid = webhook.uuid.clone();
+            async move {
+                delivery_repo
+                    .get_all_by_webhook(&webhook_uuid, 100)
+                    .await
+                    .unwrap()
+                    .len()
+            }
+        };
+        assert_eq!(queued(&enqueuer).await, 1);
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_uuid = $1")
+            .bind(&webhook.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        enqueuer.catch_up(paid.updated_at).await.unwrap();
+        assert_eq!(queued(&enqueuer).await, 1);
+
+        // On startup, events missed since the saved cursor are queued.
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_uuid = $1")
+            .bind(&webhook.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM webhook_enqueuer_cursor")
+            .execute(&pool)
+            .await
+            .unwrap();
+        enqueuer
+            .delivery_repo
+            .save_cursor(paid.updated_at)
+            .await
+            .unwrap();
+        let since = enqueuer.start().await.unwrap();
+        assert!(since >= paid.updated_at);
+        assert_eq!(queued(&enqueuer).await, 1);
+        assert_eq!(
+            enqueuer.delivery_repo.get_cursor().await.unwrap(),
+            Some(since)
+        );
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_uuid = $1")
+            .bind(&webhook.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        webhook_repo.hard_delete(&webhook.uuid).await.unwrap();
+        sqlx::query("DELETE FROM store_invoices WHERE uuid = $1")
+            .bind(&invoice.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM checkouts WHERE uuid = $1")
+            .bind(&checkout.uuid)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let _ = store_repo
+            .hard_delete(&user.uuid, &store.uuid)
+            .await
+            .unwrap();
+        let _ = delete_test_user(&user.uuid).await.unwrap();
+    }
+}
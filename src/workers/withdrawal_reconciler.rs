@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use crate::services::withdrawal_service::WithdrawalService;
+
+/// Periodically settles or reverses lightning withdrawals, and completes or
+/// requeues on-chain payout batches, whose outcome wasn't recorded when they
+/// were sent.
+pub struct WithdrawalReconciler {
+    pub withdrawals: WithdrawalService,
+    pub interval: Duration,
+}
+
+impl WithdrawalReconciler {
+    pub fn new(withdrawals: WithdrawalService, interval: Duration) -> Self {
+        Self {
+            withdrawals,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            interval.tick().await;
+
+            match self.withdrawals.reconcile_lightning().await {
+                Ok(0) => {}
+                Ok(count) => eprintln!("reconciled {} lightning withdrawals", count),
+                Err(e) => eprintln!("withdrawal reconciliation failed: {:?}", e),
+            }
+
+            match self.withdrawals.reconcile_onchain().await {
+                Ok(0) => {}
+                Ok(count) => eprintln!("reconciled {} payout batches", count),
+                Err(e) => eprintln!("payout batch reconciliation failed: {:?}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::services::{
+    event_bus::{CheckoutEventKind, EventBus},
+    zap_service::ZapService,
+};
+
+/// Publishes zap receipts as zaps are paid, and periodically retries any
+/// that no relay accepted or the event bus dropped.
+pub struct ZapPublisher {
+    pub events: EventBus,
+    pub zaps: ZapService,
+    pub interval: Duration,
+}
+
+impl ZapPublisher {
+    pub fn new(events: EventBus, zaps: ZapService, interval: Duration) -> Self {
+        Self {
+            events,
+            zaps,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        let mut receiver = self.events.subscribe();
+        let mut interval = tokio::time::interval(self.interval);
+
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        if !matches!(
+                            event.kind,
+                            CheckoutEventKind::Paid
+                                | CheckoutEventKind::Overpaid
+                                | CheckoutEventKind::LatePayment
+                        ) {
+                            continue;
+                        }
+
+                        if let Err(e) = self.zaps.publish_receipt(&event.checkout).await {
+                            eprintln!(
+                                "failed to publish zap receipt for {}: {:?}",
+                                event.checkout.uuid, e
+                            );
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("zap publisher lagged, {} events dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = interval.tick() => {
+                    if let Err(e) = self.zaps.reconcile().await {
+                        eprintln!("zap receipt reconciliation failed: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}